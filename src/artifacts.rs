@@ -0,0 +1,130 @@
+//! JSONL files exchanged between the `enrich`, `check`, `plan` and `emit` subcommands.
+//!
+//! Each step writes one JSON object per line so curators can inspect or hand-fix a file before
+//! handing it to the next step. `check` writes the same line format as the checkpoint journal,
+//! so a journal from an interrupted full run can be fed to `plan` directly.
+use crate::enrichment::EnrichedData;
+use crate::error::{CrateError, Result};
+use crate::wikidata::checker::WikidataInfo;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+/// Line written by `enrich`: the enriched fields plus the CSV row they came from.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EnrichedRow {
+    pub row: usize,
+    #[serde(flatten)]
+    pub data: EnrichedData,
+}
+
+/// Line written by `plan`: a checked record with the creation decisions `emit` will apply.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlannedRecord {
+    pub row: usize,
+    pub enriched: EnrichedData,
+    pub wikidata: WikidataInfo,
+    pub create_chemical: bool,
+    pub create_reference: bool,
+    #[serde(default)]
+    pub create_taxon: bool,
+}
+
+/// Writes `items` as JSON lines, replacing any existing file.
+pub fn write_jsonl<'a, T, I>(path: &Path, items: I) -> Result<()>
+where
+    T: Serialize + 'a,
+    I: IntoIterator<Item = &'a T>,
+{
+    let mut writer = BufWriter::new(File::create(path)?);
+    for item in items {
+        serde_json::to_writer(&mut writer, item).map_err(std::io::Error::from)?;
+        writer.write_all(b"\n")?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Reads a JSONL file. Unlike the checkpoint journal, a malformed line is an error: these files
+/// are meant to be edited by hand, and silently dropping a row would hide the mistake.
+pub fn read_jsonl<T: DeserializeOwned>(path: &Path) -> Result<Vec<T>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut items = Vec::new();
+    for (line_number, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let item = serde_json::from_str(&line).map_err(|e| CrateError::ArtifactParseError {
+            path: path.display().to_string(),
+            line: line_number + 1,
+            message: e.to_string(),
+        })?;
+        items.push(item);
+    }
+    Ok(items)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn enriched(smiles: &str) -> EnrichedData {
+        EnrichedData {
+            chemical_entity_name: "Compound".to_string(),
+            input_smiles: smiles.to_string(),
+            sanitized_smiles: smiles.to_string(),
+            taxon_name: "Coffea arabica".to_string(),
+            reference_doi: "10.1000/test".to_string(),
+            canonical_smiles: Some(smiles.to_string()),
+            molecular_formula: Some("CH4".to_string()),
+            exact_mass: Some(16.0313),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_enriched_rows_are_flat_and_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("enriched.jsonl");
+        let rows = vec![EnrichedRow {
+            row: 2,
+            data: enriched("C"),
+        }];
+        write_jsonl(&path, &rows).unwrap();
+
+        let line = fs::read_to_string(&path).unwrap();
+        let value: serde_json::Value = serde_json::from_str(line.trim()).unwrap();
+        assert_eq!(value["row"], 2);
+        assert_eq!(value["input_smiles"], "C");
+
+        let read: Vec<EnrichedRow> = read_jsonl(&path).unwrap();
+        assert_eq!(read.len(), 1);
+        assert_eq!(read[0].data.exact_mass, Some(16.0313));
+    }
+
+    #[test]
+    fn test_malformed_line_reports_its_position() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("plan.jsonl");
+        let record = PlannedRecord {
+            row: 2,
+            enriched: enriched("C"),
+            wikidata: WikidataInfo::default(),
+            create_chemical: true,
+            create_reference: false,
+            create_taxon: false,
+        };
+        let good = serde_json::to_string(&record).unwrap();
+        fs::write(&path, format!("{}\n{{\"row\": 3,}}\n", good)).unwrap();
+
+        let result = read_jsonl::<PlannedRecord>(&path);
+        assert!(matches!(
+            result,
+            Err(CrateError::ArtifactParseError { line: 2, .. })
+        ));
+    }
+}
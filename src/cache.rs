@@ -0,0 +1,510 @@
+//! Lookup cache shared by the enrichment, SPARQL and Crossref helpers.
+//!
+//! Without `--cache-dir` the cache only lives for the current run. With a cache directory,
+//! every stored entry is appended to `<dir>/<kind>.jsonl`, so reruns of the same CSV (e.g. the
+//! second QuickStatements round) skip lookups that were already answered. The appends happen on
+//! a background thread, so storing an entry never blocks the async workers on file I/O.
+use crate::error::Result;
+use chrono::Utc;
+use log::{info, warn};
+use once_cell::sync::Lazy;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+
+const MINUTE: i64 = 60;
+const DAY: i64 = 24 * 60 * MINUTE;
+
+/// Kinds of cached lookups; each has its own file and expiry rules.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CacheKind {
+    /// `enrich_structure` results keyed by enricher name and input SMILES.
+    Enrichment,
+    /// Crossref metadata keyed by lowercase DOI.
+    Crossref,
+    /// Wikidata reference QIDs keyed by lowercase DOI.
+    ReferenceQid,
+    /// Wikidata journal QIDs keyed by journal title.
+    JournalLabel,
+    /// Wikidata journal QIDs keyed by ISSN.
+    JournalIssn,
+    /// Cross-database identifiers keyed by database and InChIKey.
+    Identifiers,
+    /// Wikidata chemical QIDs keyed by InChIKey.
+    ChemicalQid,
+    /// Wikidata P225 matches keyed by taxon name.
+    TaxonName,
+}
+
+impl CacheKind {
+    pub const ALL: [CacheKind; 8] = [
+        CacheKind::Enrichment,
+        CacheKind::Crossref,
+        CacheKind::ReferenceQid,
+        CacheKind::JournalLabel,
+        CacheKind::JournalIssn,
+        CacheKind::Identifiers,
+        CacheKind::ChemicalQid,
+        CacheKind::TaxonName,
+    ];
+
+    /// Short name used for the cache file and in `cache stats`.
+    pub fn name(self) -> &'static str {
+        match self {
+            CacheKind::Enrichment => "enrichment",
+            CacheKind::Crossref => "crossref",
+            CacheKind::ReferenceQid => "reference_qid",
+            CacheKind::JournalLabel => "journal_label",
+            CacheKind::JournalIssn => "journal_issn",
+            CacheKind::Identifiers => "identifiers",
+            CacheKind::ChemicalQid => "chemical_qid",
+            CacheKind::TaxonName => "taxon_name",
+        }
+    }
+
+    /// Lifetime of an entry holding a value, in seconds.
+    fn ttl(self) -> i64 {
+        match self {
+            CacheKind::Enrichment => 30 * DAY,
+            CacheKind::Crossref => 180 * DAY,
+            CacheKind::ReferenceQid
+            | CacheKind::JournalLabel
+            | CacheKind::JournalIssn
+            | CacheKind::Identifiers
+            | CacheKind::ChemicalQid
+            | CacheKind::TaxonName => 30 * DAY,
+        }
+    }
+
+    /// Lifetime of a negative ("not found") entry, in seconds. Missing Wikidata items are
+    /// often created between two runs, so those expire quickly.
+    fn negative_ttl(self) -> i64 {
+        match self {
+            CacheKind::Enrichment => 0,
+            CacheKind::Crossref => DAY,
+            CacheKind::ReferenceQid | CacheKind::ChemicalQid | CacheKind::TaxonName => 10 * MINUTE,
+            CacheKind::JournalLabel | CacheKind::JournalIssn | CacheKind::Identifiers => DAY,
+        }
+    }
+
+    fn file_path(self, dir: &Path) -> PathBuf {
+        dir.join(format!("{}.jsonl", self.name()))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CacheEntry {
+    key: String,
+    stored_at: i64,
+    value: Value,
+}
+
+impl CacheEntry {
+    fn is_fresh(&self, kind: CacheKind, now: i64) -> bool {
+        let ttl = if self.value.is_null() {
+            kind.negative_ttl()
+        } else {
+            kind.ttl()
+        };
+        now - self.stored_at < ttl
+    }
+}
+
+/// Per-kind entry counts reported by `lotus-o3 cache stats`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheStats {
+    pub kind: CacheKind,
+    pub entries: usize,
+    pub negative: usize,
+    pub expired: usize,
+    pub bytes: u64,
+}
+
+/// In-memory lookup tables, optionally backed by append-only files.
+#[derive(Debug, Default)]
+pub struct LookupCache {
+    dir: Option<PathBuf>,
+    tables: HashMap<CacheKind, HashMap<String, CacheEntry>>,
+    writer: Option<CacheWriter>,
+}
+
+impl LookupCache {
+    /// Creates a cache that is discarded at the end of the run.
+    pub fn in_memory() -> Self {
+        Self::default()
+    }
+
+    /// Opens (or creates) a cache directory and loads all unexpired entries.
+    pub fn open(dir: &Path) -> Result<Self> {
+        fs::create_dir_all(dir)?;
+        let now = Utc::now().timestamp();
+        let mut tables = HashMap::new();
+        for kind in CacheKind::ALL {
+            let table: HashMap<String, CacheEntry> = read_entries(&kind.file_path(dir))?
+                .into_iter()
+                .filter(|entry| entry.is_fresh(kind, now))
+                .map(|entry| (entry.key.clone(), entry))
+                .collect();
+            tables.insert(kind, table);
+        }
+        Ok(Self {
+            dir: Some(dir.to_path_buf()),
+            tables,
+            writer: Some(CacheWriter::spawn()),
+        })
+    }
+
+    /// Returns `Some(None)` for a cached negative result and `None` on a miss.
+    pub fn get<T: DeserializeOwned>(&self, kind: CacheKind, key: &str) -> Option<Option<T>> {
+        let entry = self.tables.get(&kind)?.get(key)?;
+        if !entry.is_fresh(kind, Utc::now().timestamp()) {
+            return None;
+        }
+        if entry.value.is_null() {
+            return Some(None);
+        }
+        serde_json::from_value(entry.value.clone()).ok().map(Some)
+    }
+
+    /// Stores a value (or a negative result) and queues it for the cache file, if any.
+    pub fn put<T: Serialize>(&mut self, kind: CacheKind, key: &str, value: Option<&T>) {
+        let value = match value.map(serde_json::to_value).transpose() {
+            Ok(value) => value.unwrap_or(Value::Null),
+            Err(err) => {
+                warn!(
+                    "Failed to serialize {} cache entry {}: {}",
+                    kind.name(),
+                    key,
+                    err
+                );
+                return;
+            }
+        };
+        let entry = CacheEntry {
+            key: key.to_string(),
+            stored_at: Utc::now().timestamp(),
+            value,
+        };
+        if let (Some(dir), Some(writer)) = (&self.dir, &self.writer) {
+            match serde_json::to_string(&entry) {
+                Ok(line) => writer.append(kind.file_path(dir), line),
+                Err(err) => warn!(
+                    "Failed to persist {} cache entry {}: {}",
+                    kind.name(),
+                    key,
+                    err
+                ),
+            }
+        }
+        self.tables
+            .entry(kind)
+            .or_default()
+            .insert(key.to_string(), entry);
+    }
+
+    /// Waits until every queued entry has been written to its cache file.
+    pub fn flush(&self) {
+        if let Some(writer) = &self.writer {
+            writer.flush();
+        }
+    }
+
+    /// Rewrites the cache files so they only contain the latest unexpired entry per key.
+    pub fn compact(&self) -> Result<()> {
+        let Some(dir) = &self.dir else {
+            return Ok(());
+        };
+        self.flush();
+        let now = Utc::now().timestamp();
+        for kind in CacheKind::ALL {
+            let path = kind.file_path(dir);
+            let mut writer = BufWriter::new(File::create(&path)?);
+            if let Some(table) = self.tables.get(&kind) {
+                for entry in table.values().filter(|entry| entry.is_fresh(kind, now)) {
+                    serde_json::to_writer(&mut writer, entry).map_err(std::io::Error::from)?;
+                    writer.write_all(b"\n")?;
+                }
+            }
+            writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+static CACHE: Lazy<Mutex<LookupCache>> = Lazy::new(|| Mutex::new(LookupCache::in_memory()));
+
+/// Points the process-wide cache at a directory (or keeps it in memory when `None`).
+pub fn configure(dir: Option<&Path>) -> Result<()> {
+    let cache = match dir {
+        Some(dir) => {
+            let cache = LookupCache::open(dir)?;
+            info!(
+                "Loaded lookup cache from {} ({} entries)",
+                dir.display(),
+                cache.tables.values().map(HashMap::len).sum::<usize>()
+            );
+            cache
+        }
+        None => LookupCache::in_memory(),
+    };
+    if let Ok(mut global) = CACHE.lock() {
+        *global = cache;
+    }
+    Ok(())
+}
+
+/// Looks up a cached value in the process-wide cache.
+pub fn lookup<T: DeserializeOwned>(kind: CacheKind, key: &str) -> Option<Option<T>> {
+    CACHE.lock().ok().and_then(|cache| cache.get(kind, key))
+}
+
+/// Stores a value (or a negative result) in the process-wide cache.
+pub fn store<T: Serialize>(kind: CacheKind, key: &str, value: Option<&T>) {
+    if let Ok(mut cache) = CACHE.lock() {
+        cache.put(kind, key, value);
+    }
+}
+
+/// Compacts the on-disk cache at the end of a run.
+pub fn persist() -> Result<()> {
+    match CACHE.lock() {
+        Ok(cache) => cache.compact(),
+        Err(_) => Ok(()),
+    }
+}
+
+/// Deletes all cache files in `dir`, returning how many were removed.
+pub fn clear(dir: &Path) -> Result<usize> {
+    let mut removed = 0;
+    for kind in CacheKind::ALL {
+        let path = kind.file_path(dir);
+        if path.exists() {
+            fs::remove_file(&path)?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+/// Summarizes the cache files in `dir` without modifying them.
+pub fn stats(dir: &Path) -> Result<Vec<CacheStats>> {
+    let now = Utc::now().timestamp();
+    let mut stats = Vec::new();
+    for kind in CacheKind::ALL {
+        let path = kind.file_path(dir);
+        let bytes = fs::metadata(&path).map(|meta| meta.len()).unwrap_or(0);
+        let mut latest: HashMap<String, CacheEntry> = HashMap::new();
+        for entry in read_entries(&path)? {
+            latest.insert(entry.key.clone(), entry);
+        }
+        let expired = latest
+            .values()
+            .filter(|entry| !entry.is_fresh(kind, now))
+            .count();
+        let negative = latest
+            .values()
+            .filter(|entry| entry.is_fresh(kind, now) && entry.value.is_null())
+            .count();
+        stats.push(CacheStats {
+            kind,
+            entries: latest.len() - expired,
+            negative,
+            expired,
+            bytes,
+        });
+    }
+    Ok(stats)
+}
+
+fn read_entries(path: &Path) -> Result<Vec<CacheEntry>> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let reader = BufReader::new(File::open(path)?);
+    let mut entries = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str::<CacheEntry>(&line) {
+            Ok(entry) => entries.push(entry),
+            Err(err) => warn!("Skipping corrupt cache line in {}: {}", path.display(), err),
+        }
+    }
+    Ok(entries)
+}
+
+enum WriterMessage {
+    Append(PathBuf, String),
+    Flush(Sender<()>),
+}
+
+/// Handle to the thread that appends serialized entries to the cache files. The thread exits
+/// once the handle is dropped and the queue is drained.
+#[derive(Debug)]
+struct CacheWriter {
+    sender: Sender<WriterMessage>,
+}
+
+impl CacheWriter {
+    fn spawn() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || run_writer(receiver));
+        Self { sender }
+    }
+
+    fn append(&self, path: PathBuf, line: String) {
+        if self.sender.send(WriterMessage::Append(path, line)).is_err() {
+            warn!("Cache writer stopped; entry not persisted");
+        }
+    }
+
+    fn flush(&self) {
+        let (done, wait) = mpsc::channel();
+        if self.sender.send(WriterMessage::Flush(done)).is_ok() {
+            let _ = wait.recv();
+        }
+    }
+}
+
+// Writes every queued line, then flushes the files each time the queue runs empty.
+fn run_writer(receiver: Receiver<WriterMessage>) {
+    let mut files: HashMap<PathBuf, BufWriter<File>> = HashMap::new();
+    while let Ok(message) = receiver.recv() {
+        let mut next = Some(message);
+        while let Some(message) = next {
+            match message {
+                WriterMessage::Append(path, line) => append_line(&mut files, path, &line),
+                WriterMessage::Flush(done) => {
+                    flush_files(&mut files);
+                    // Compaction rewrites the files next; reopen them afterwards.
+                    files.clear();
+                    let _ = done.send(());
+                }
+            }
+            next = receiver.try_recv().ok();
+        }
+        flush_files(&mut files);
+    }
+    flush_files(&mut files);
+}
+
+fn append_line(files: &mut HashMap<PathBuf, BufWriter<File>>, path: PathBuf, line: &str) {
+    let writer = match files.entry(path) {
+        Entry::Occupied(slot) => slot.into_mut(),
+        Entry::Vacant(slot) => {
+            match OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(slot.key())
+            {
+                Ok(file) => slot.insert(BufWriter::new(file)),
+                Err(err) => {
+                    warn!(
+                        "Failed to open cache file {}: {}",
+                        slot.key().display(),
+                        err
+                    );
+                    return;
+                }
+            }
+        }
+    };
+    if let Err(err) = writer
+        .write_all(line.as_bytes())
+        .and_then(|()| writer.write_all(b"\n"))
+    {
+        warn!("Failed to append to cache file: {}", err);
+    }
+}
+
+fn flush_files(files: &mut HashMap<PathBuf, BufWriter<File>>) {
+    for (path, writer) in files.iter_mut() {
+        if let Err(err) = writer.flush() {
+            warn!("Failed to flush cache file {}: {}", path.display(), err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_entries_survive_reopen() {
+        let dir = tempdir().unwrap();
+        let mut cache = LookupCache::open(dir.path()).unwrap();
+        cache.put(CacheKind::ReferenceQid, "10.1/a", Some(&"Q1".to_string()));
+        cache.put::<String>(CacheKind::Crossref, "10.1/missing", None);
+        cache.flush();
+
+        let reopened = LookupCache::open(dir.path()).unwrap();
+        assert_eq!(
+            reopened.get::<String>(CacheKind::ReferenceQid, "10.1/a"),
+            Some(Some("Q1".to_string()))
+        );
+        assert_eq!(
+            reopened.get::<String>(CacheKind::Crossref, "10.1/missing"),
+            Some(None)
+        );
+        assert_eq!(
+            reopened.get::<String>(CacheKind::Crossref, "10.1/other"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_negative_wikidata_hits_expire_first() {
+        let stale = Utc::now().timestamp() - 2 * 60 * MINUTE;
+        let negative = CacheEntry {
+            key: "10.1/a".to_string(),
+            stored_at: stale,
+            value: Value::Null,
+        };
+        let positive = CacheEntry {
+            value: Value::String("Q1".to_string()),
+            ..negative.clone()
+        };
+        let now = Utc::now().timestamp();
+        assert!(!negative.is_fresh(CacheKind::ReferenceQid, now));
+        assert!(positive.is_fresh(CacheKind::ReferenceQid, now));
+        assert!(negative.is_fresh(CacheKind::Crossref, now));
+    }
+
+    #[test]
+    fn test_stats_and_clear() {
+        let dir = tempdir().unwrap();
+        let mut cache = LookupCache::open(dir.path()).unwrap();
+        cache.put(CacheKind::JournalIssn, "1234-5678", Some(&"Q5".to_string()));
+        cache.put(CacheKind::JournalIssn, "1234-5678", Some(&"Q6".to_string()));
+        cache.put::<String>(CacheKind::JournalIssn, "0000-0000", None);
+        cache.compact().unwrap();
+
+        let issn = stats(dir.path())
+            .unwrap()
+            .into_iter()
+            .find(|s| s.kind == CacheKind::JournalIssn)
+            .unwrap();
+        assert_eq!(issn.entries, 2);
+        assert_eq!(issn.negative, 1);
+        assert_eq!(issn.expired, 0);
+
+        assert_eq!(clear(dir.path()).unwrap(), CacheKind::ALL.len());
+        assert!(
+            stats(dir.path())
+                .unwrap()
+                .iter()
+                .all(|s| s.entries == 0 && s.bytes == 0)
+        );
+    }
+}
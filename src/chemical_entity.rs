@@ -1,3 +1,15 @@
 //! Namespace for chemical-entity specific helpers.
 
+pub mod comparison;
+pub mod composition;
+pub mod descriptors;
+pub mod elements;
+pub mod enricher;
+pub mod identifiers;
+pub mod inchikey;
+pub mod np_api;
+pub mod precomputed;
+pub mod pubchem;
+pub mod smiles;
+pub mod stereo;
 pub mod structure;
@@ -0,0 +1,592 @@
+//! Structural comparison of two notations of the same chemical: SMILES graphs (with or without
+//! their stereo marks), InChI layers and written formulae. Used to tell real disagreements with
+//! the statements of an existing Wikidata item from mere differences in notation (Kekulé or
+//! aromatic rings, another atom order, subscript digits).
+//!
+//! Each function returns `None` when both values describe the same structure, otherwise a short
+//! description of what differs, read as "ours vs theirs".
+use crate::chemical_entity::composition::{Composition, parse_formula};
+use crate::chemical_entity::smiles::{BondDirection, Molecule, parse_smiles};
+use crate::chemical_entity::stereo::{
+    StereoLayers, StereoRelation, all_distinct, constitution_classes, is_stereocentre,
+    is_stereogenic_double_bond, neighbours,
+};
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Range;
+
+/// Compares two SMILES as graphs. With `stereo`, stereocentres and double-bond geometries are
+/// compared too; without it, stereo marks in `theirs` are reported since canonical SMILES
+/// should not carry any.
+///
+/// Atoms are matched through symmetry classes of both structures together, so the stereo of
+/// centres whose neighbours are symmetry-equivalent (e.g. cis/trans ring substituents) is not
+/// compared. Explicit `[H]` atoms are first folded into their neighbour's hydrogen count.
+pub fn smiles_difference(ours: &str, theirs: &str, stereo: bool) -> Option<String> {
+    let Ok(their_molecule) = parse_smiles(theirs) else {
+        return Some("not a valid SMILES".to_string());
+    };
+    let Ok(our_molecule) = parse_smiles(ours) else {
+        return (ours != theirs).then(|| "different SMILES".to_string());
+    };
+    let (our_molecule, their_molecule) = (
+        fold_explicit_hydrogens(&our_molecule),
+        fold_explicit_hydrogens(&their_molecule),
+    );
+    let split = our_molecule.atoms.len();
+    let union = disjoint_union(&our_molecule, &their_molecule);
+    let neighbours = neighbours(&union);
+    let classes = constitution_classes(&union, &neighbours);
+    let mut our_classes = classes[..split].to_vec();
+    let mut their_classes = classes[split..].to_vec();
+    our_classes.sort_unstable();
+    their_classes.sort_unstable();
+    if our_classes != their_classes {
+        return Some(constitution_difference(&our_molecule, &their_molecule));
+    }
+    if !stereo {
+        return has_stereo_marks(&their_molecule)
+            .then(|| "stereo marks in a canonical SMILES".to_string());
+    }
+    let ours = stereo_elements(&union, &neighbours, &classes, 0..split);
+    let theirs = stereo_elements(&union, &neighbours, &classes, split..union.atoms.len());
+    stereo_difference(&ours, &theirs)
+}
+
+/// Compares two InChIs layer by layer; stereo layers are compared through [`StereoLayers`].
+pub fn inchi_difference(ours: &str, theirs: &str) -> Option<String> {
+    let (ours, theirs) = (ours.trim(), theirs.trim());
+    if ours == theirs {
+        return None;
+    }
+    let Some(their_layers) = InchiLayers::parse(theirs) else {
+        return Some("not a valid InChI".to_string());
+    };
+    let Some(our_layers) = InchiLayers::parse(ours) else {
+        return Some("different InChI".to_string());
+    };
+    let mut differences = Vec::new();
+    if our_layers.version != their_layers.version {
+        differences.push(format!(
+            "InChI version {} vs {}",
+            our_layers.version, their_layers.version
+        ));
+    }
+    if our_layers.formula != their_layers.formula {
+        differences.push(format!(
+            "formula {} vs {}",
+            our_layers.formula, their_layers.formula
+        ));
+    }
+    for (prefix, name) in [
+        ('c', "connectivity"),
+        ('h', "hydrogens"),
+        ('q', "charge"),
+        ('p', "protonation"),
+    ] {
+        if our_layers.get(0, prefix) != their_layers.get(0, prefix) {
+            differences.push(format!("different {} (/{})", name, prefix));
+        }
+    }
+    if ['b', 't', 'm', 's']
+        .iter()
+        .any(|&prefix| our_layers.get(0, prefix) != their_layers.get(0, prefix))
+    {
+        let relation = StereoLayers::from_inchi(theirs)
+            .zip(StereoLayers::from_inchi(ours))
+            .map(|(theirs, ours)| theirs.relation_to(&ours));
+        differences.push(
+            match relation {
+                Some(StereoRelation::Same) => "different stereo type (/s)",
+                Some(StereoRelation::MoreSpecific) => "stereo more specific on Wikidata",
+                Some(StereoRelation::LessSpecific) => "stereo less specific on Wikidata",
+                Some(StereoRelation::Different) | None => "different stereo",
+            }
+            .to_string(),
+        );
+    }
+    for (section, name) in [
+        (ISOTOPIC_SECTION, "isotopic layer"),
+        (FIXED_H_SECTION, "fixed-H layer"),
+        (RECONNECTED_SECTION, "reconnected layer"),
+    ] {
+        if our_layers.section(section) != their_layers.section(section) {
+            differences.push(format!("different {}", name));
+        }
+    }
+    if differences.is_empty() {
+        differences.push("different InChI".to_string());
+    }
+    Some(differences.join(", "))
+}
+
+/// Compares the element counts of two written formulae such as `C8H10N4O2` (charges are not
+/// compared).
+pub fn formula_difference(ours: &str, theirs: &str) -> Option<String> {
+    let Some(their_counts) = parse_formula(theirs) else {
+        return Some("not a readable formula".to_string());
+    };
+    let Some(our_counts) = parse_formula(ours) else {
+        return (ours != theirs).then(|| "different formula".to_string());
+    };
+    if our_counts == their_counts {
+        return None;
+    }
+    let elements: BTreeSet<&String> = our_counts.keys().chain(their_counts.keys()).collect();
+    let differences: Vec<String> = elements
+        .into_iter()
+        .filter_map(|element| {
+            let ours = our_counts.get(element).copied().unwrap_or(0);
+            let theirs = their_counts.get(element).copied().unwrap_or(0);
+            (ours != theirs).then(|| format!("{} {} vs {}", element, ours, theirs))
+        })
+        .collect();
+    Some(differences.join(", "))
+}
+
+/// Both structures in one graph, `theirs` numbered after `ours`, so that symmetry classes are
+/// shared between them.
+fn disjoint_union(ours: &Molecule, theirs: &Molecule) -> Molecule {
+    let offset = ours.atoms.len();
+    let mut union = ours.clone();
+    union.atoms.extend(theirs.atoms.iter().cloned());
+    union.bonds.extend(theirs.bonds.iter().map(|bond| {
+        let mut bond = bond.clone();
+        bond.atoms = (bond.atoms.0 + offset, bond.atoms.1 + offset);
+        bond
+    }));
+    union
+}
+
+/// `molecule` with plain `[H]` atoms folded into the hydrogen count of their neighbour, so that
+/// `N[C@]([H])(C)C` and `N[C@H](C)C` give the same graph. The chirality mark of the neighbour is
+/// inverted when the hydrogen was written an odd number of places away from where an implicit
+/// one is read (right after the preceding atom).
+fn fold_explicit_hydrogens(molecule: &Molecule) -> Molecule {
+    let neighbours = neighbours(molecule);
+    let folded: Vec<bool> = (0..molecule.atoms.len())
+        .map(|atom| {
+            let entry = &molecule.atoms[atom];
+            entry.element == "H"
+                && entry.isotope.is_none()
+                && entry.charge == 0
+                && entry.hydrogens.unwrap_or(0) == 0
+                && entry.chirality.is_none()
+                && matches!(
+                    neighbours[atom].as_slice(),
+                    [(other, _)] if molecule.atoms[*other].element != "H"
+                )
+        })
+        .collect();
+    if !folded.contains(&true) {
+        return molecule.clone();
+    }
+
+    let mut atoms = molecule.atoms.clone();
+    for (atom, entry) in atoms.iter_mut().enumerate() {
+        let hydrogen_bonds: Vec<usize> = neighbours[atom]
+            .iter()
+            .filter(|&&(other, _)| folded[other])
+            .map(|&(_, bond)| bond)
+            .collect();
+        if hydrogen_bonds.is_empty() || !entry.bracket {
+            continue;
+        }
+        entry.hydrogens = Some(entry.hydrogens.unwrap_or(0) + hydrogen_bonds.len() as u8);
+        let [hydrogen_bond] = hydrogen_bonds[..] else {
+            continue;
+        };
+        let mut written: Vec<(usize, usize)> = neighbours[atom]
+            .iter()
+            .map(|&(_, bond)| (written_position(molecule, atom, bond), bond))
+            .collect();
+        written.sort_unstable();
+        let from = written
+            .iter()
+            .position(|&(_, bond)| bond == hydrogen_bond)
+            .expect("the hydrogen is a neighbour");
+        let preceded = written
+            .iter()
+            .any(|&(position, bond)| position == 0 && bond != hydrogen_bond);
+        if from.abs_diff(usize::from(preceded)) % 2 == 1 {
+            entry.chirality = entry.chirality.as_deref().map(|mark| {
+                match mark {
+                    "@" => "@@",
+                    "@@" => "@",
+                    "@TH1" => "@TH2",
+                    "@TH2" => "@TH1",
+                    other => other,
+                }
+                .to_string()
+            });
+        }
+    }
+
+    let mut index = vec![None; atoms.len()];
+    let mut kept = Vec::new();
+    for (atom, entry) in atoms.into_iter().enumerate() {
+        if !folded[atom] {
+            index[atom] = Some(kept.len());
+            kept.push(entry);
+        }
+    }
+    let bonds = molecule
+        .bonds
+        .iter()
+        .filter_map(|bond| {
+            let mut bond = bond.clone();
+            bond.atoms = (index[bond.atoms.0]?, index[bond.atoms.1]?);
+            Some(bond)
+        })
+        .collect();
+    Molecule { atoms: kept, bonds }
+}
+
+fn constitution_difference(ours: &Molecule, theirs: &Molecule) -> String {
+    let formula = |molecule| Composition::from_molecule(molecule).map(|c| c.hill_formula());
+    match (formula(ours), formula(theirs)) {
+        (Some(ours), Some(theirs)) if ours != theirs => format!("formula {} vs {}", ours, theirs),
+        _ => "same formula, different connectivity".to_string(),
+    }
+}
+
+fn has_stereo_marks(molecule: &Molecule) -> bool {
+    molecule.atoms.iter().any(|atom| atom.chirality.is_some())
+        || molecule.bonds.iter().any(|bond| bond.direction.is_some())
+}
+
+/// A stereo element, keyed by the symmetry classes of its atoms.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum StereoElement {
+    Centre(usize),
+    DoubleBond(usize, usize),
+}
+
+/// Parities of the stereo elements among `atoms`, `None` for elements left undefined. Centre
+/// parities are read with the neighbours ordered by class, double-bond parities (`true` for
+/// trans) between the highest-class substituents of both ends.
+fn stereo_elements(
+    molecule: &Molecule,
+    neighbours: &[Vec<(usize, usize)>],
+    classes: &[usize],
+    atoms: Range<usize>,
+) -> BTreeMap<StereoElement, Vec<Option<bool>>> {
+    let mut elements: BTreeMap<StereoElement, Vec<Option<bool>>> = BTreeMap::new();
+    for atom in atoms.clone() {
+        let parity = if molecule.atoms[atom].chirality.is_some() {
+            match centre_parity(molecule, neighbours, classes, atom) {
+                Some(parity) => Some(parity),
+                None => continue,
+            }
+        } else if is_stereocentre(molecule, neighbours, classes, atom) {
+            None
+        } else {
+            continue;
+        };
+        elements
+            .entry(StereoElement::Centre(classes[atom]))
+            .or_default()
+            .push(parity);
+    }
+    for (index, bond) in molecule.bonds.iter().enumerate() {
+        if !atoms.contains(&bond.atoms.0)
+            || !is_stereogenic_double_bond(molecule, neighbours, classes, index)
+        {
+            continue;
+        }
+        let (a, b) = (classes[bond.atoms.0], classes[bond.atoms.1]);
+        elements
+            .entry(StereoElement::DoubleBond(a.min(b), a.max(b)))
+            .or_default()
+            .push(double_bond_parity(molecule, neighbours, classes, index));
+    }
+    elements
+}
+
+/// Position of `bond` in the SMILES neighbour order of `atom`: the preceding atom first, then
+/// the ring bonds in digit order, then branches and the next atom.
+fn written_position(molecule: &Molecule, atom: usize, bond: usize) -> usize {
+    let bond = &molecule.bonds[bond];
+    match bond.ring_closure {
+        Some((opening, _)) if bond.atoms.0 == atom => opening,
+        Some((_, closing)) => closing,
+        None if bond.atoms.1 == atom => 0,
+        None => molecule.atoms[bond.atoms.1].position,
+    }
+}
+
+/// Chirality of `atom` with its neighbours ordered by class (`true` for clockwise), or `None`
+/// for marks that do not describe a tetrahedral centre with distinct neighbours.
+fn centre_parity(
+    molecule: &Molecule,
+    neighbours: &[Vec<(usize, usize)>],
+    classes: &[usize],
+    atom: usize,
+) -> Option<bool> {
+    let entry = &molecule.atoms[atom];
+    let clockwise = match entry.chirality.as_deref()? {
+        "@" | "@TH1" => false,
+        "@@" | "@TH2" => true,
+        _ => return None,
+    };
+    let mut around: Vec<(usize, Option<usize>)> = neighbours[atom]
+        .iter()
+        .map(|&(other, bond)| (written_position(molecule, atom, bond), Some(classes[other])))
+        .collect();
+    // A hydrogen (or lone pair) inside the brackets comes right after the preceding atom.
+    match (around.len(), molecule.hydrogen_count(atom)) {
+        (4, 0) => {}
+        (3, 0 | 1) => around.push((entry.position, None)),
+        _ => return None,
+    }
+    around.sort_unstable();
+    let written: Vec<Option<usize>> = around.into_iter().map(|(_, class)| class).collect();
+    if !all_distinct(&written) {
+        return None;
+    }
+    let inversions = (0..written.len())
+        .flat_map(|i| (i + 1..written.len()).map(move |j| (i, j)))
+        .filter(|&(i, j)| written[i] > written[j])
+        .count();
+    Some(clockwise ^ (inversions % 2 == 1))
+}
+
+/// Whether the highest-class substituents of both ends of double bond `index` are trans, or
+/// `None` when the directional bonds do not define the geometry.
+fn double_bond_parity(
+    molecule: &Molecule,
+    neighbours: &[Vec<(usize, usize)>],
+    classes: &[usize],
+    index: usize,
+) -> Option<bool> {
+    // Whether the reference substituent of `end` lies "up".
+    let side = |end: usize| {
+        let substituents: Vec<&(usize, usize)> = neighbours[end]
+            .iter()
+            .filter(|&&(_, bond)| bond != index)
+            .collect();
+        let &&(reference, _) = substituents
+            .iter()
+            .max_by_key(|&&&(other, _)| classes[other])?;
+        substituents.iter().find_map(|&&(other, bond)| {
+            let bond = &molecule.bonds[bond];
+            // `atoms.1` lies on the `direction` side of `atoms.0`.
+            let up = bond.direction? == BondDirection::Up;
+            let up = if bond.atoms.0 == end { up } else { !up };
+            Some(if other == reference { up } else { !up })
+        })
+    };
+    let (a, b) = molecule.bonds[index].atoms;
+    Some(side(a)? != side(b)?)
+}
+
+fn stereo_difference(
+    ours: &BTreeMap<StereoElement, Vec<Option<bool>>>,
+    theirs: &BTreeMap<StereoElement, Vec<Option<bool>>>,
+) -> Option<String> {
+    let tally = |parities: Option<&Vec<Option<bool>>>| {
+        let parities = parities.map(Vec::as_slice).unwrap_or_default();
+        let count = |parity| parities.iter().filter(|&&p| p == Some(parity)).count();
+        (count(false), count(true))
+    };
+    let mut inverted_centres = 0;
+    let mut flipped_bonds = 0;
+    let mut undefined_on_wikidata = 0;
+    let mut defined_only_on_wikidata = 0;
+    let elements: BTreeSet<&StereoElement> = ours.keys().chain(theirs.keys()).collect();
+    for element in elements {
+        let (our_false, our_true) = tally(ours.get(element));
+        let (their_false, their_true) = tally(theirs.get(element));
+        let (our_defined, their_defined) = (our_false + our_true, their_false + their_true);
+        let matching = our_false.min(their_false) + our_true.min(their_true);
+        let opposite = our_defined.min(their_defined) - matching;
+        match element {
+            StereoElement::Centre(_) => inverted_centres += opposite,
+            StereoElement::DoubleBond(..) => flipped_bonds += opposite,
+        }
+        undefined_on_wikidata += our_defined.saturating_sub(their_defined);
+        defined_only_on_wikidata += their_defined.saturating_sub(our_defined);
+    }
+    let mut differences = Vec::new();
+    if inverted_centres > 0 {
+        differences.push(format!(
+            "{} inverted",
+            counted(inverted_centres, "stereocentre")
+        ));
+    }
+    if flipped_bonds > 0 {
+        differences.push(format!(
+            "{} with the opposite geometry",
+            counted(flipped_bonds, "double bond")
+        ));
+    }
+    if undefined_on_wikidata > 0 {
+        differences.push(format!(
+            "{} undefined on Wikidata",
+            counted(undefined_on_wikidata, "stereo element")
+        ));
+    }
+    if defined_only_on_wikidata > 0 {
+        differences.push(format!(
+            "{} defined only on Wikidata",
+            counted(defined_only_on_wikidata, "stereo element")
+        ));
+    }
+    (!differences.is_empty()).then(|| differences.join(", "))
+}
+
+fn counted(count: usize, noun: &str) -> String {
+    if count == 1 {
+        format!("1 {}", noun)
+    } else {
+        format!("{} {}s", count, noun)
+    }
+}
+
+const ISOTOPIC_SECTION: usize = 1;
+const FIXED_H_SECTION: usize = 2;
+const RECONNECTED_SECTION: usize = 3;
+
+/// Layers of an InChI keyed by section (main, isotopic, fixed-H, reconnected) and prefix.
+struct InchiLayers<'a> {
+    version: &'a str,
+    formula: &'a str,
+    layers: BTreeMap<(usize, char), &'a str>,
+}
+
+impl<'a> InchiLayers<'a> {
+    fn parse(inchi: &'a str) -> Option<Self> {
+        let mut parts = inchi.strip_prefix("InChI=")?.split('/');
+        let version = parts.next().filter(|version| version.starts_with('1'))?;
+        let formula = parts.next().unwrap_or_default();
+        let mut section = 0;
+        let mut layers = BTreeMap::new();
+        for layer in parts {
+            let prefix = layer.chars().next()?;
+            section = match prefix {
+                'i' => section.max(ISOTOPIC_SECTION),
+                'f' => FIXED_H_SECTION,
+                'r' => RECONNECTED_SECTION,
+                _ => section,
+            };
+            layers.insert((section, prefix), &layer[prefix.len_utf8()..]);
+        }
+        Some(Self {
+            version,
+            formula,
+            layers,
+        })
+    }
+
+    fn get(&self, section: usize, prefix: char) -> Option<&'a str> {
+        self.layers.get(&(section, prefix)).copied()
+    }
+
+    fn section(&self, section: usize) -> Vec<(&(usize, char), &&'a str)> {
+        self.layers
+            .range((section, char::MIN)..=(section, char::MAX))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_smiles_difference_is_structural() {
+        // Notation only: Kekulé vs aromatic rings, atom order, ring-bond directions.
+        assert_eq!(smiles_difference("Oc1ccccc1", "OC1=CC=CC=C1", false), None);
+        assert_eq!(
+            smiles_difference("C[C@@H](C(=O)O)N", "N[C@@H](C)C(=O)O", true),
+            None
+        );
+        assert_eq!(
+            smiles_difference("OC(=O)[C@@H]1CCCN1", "C1C[C@H](NC1)C(=O)O", true),
+            None
+        );
+        assert_eq!(smiles_difference("C/C=C/C", r"C\C=C\C", true), None);
+        // Explicit hydrogens: the written position of [H] decides the handedness.
+        assert_eq!(
+            smiles_difference("N[C@]([H])(C)C(=O)O", "N[C@H](C)C(=O)O", true),
+            None
+        );
+        assert_eq!(
+            smiles_difference("N[C@@](C)([H])C(=O)O", "N[C@H](C)C(=O)O", true),
+            None
+        );
+        assert_eq!(
+            smiles_difference("[H][C@](N)(C)C(=O)O", "[C@H](N)(C)C(=O)O", true),
+            None
+        );
+        assert_eq!(
+            smiles_difference("N[C@]([H])(C)C(=O)O", "N[C@@H](C)C(=O)O", true).as_deref(),
+            Some("1 stereocentre inverted")
+        );
+        assert_eq!(smiles_difference(r"F/C=C\F", "F/C=C1.F/1", true), None);
+
+        assert_eq!(
+            smiles_difference("C[C@@H](C(=O)O)N", "C[C@H](C(=O)O)N", true).as_deref(),
+            Some("1 stereocentre inverted")
+        );
+        assert_eq!(
+            smiles_difference("C[C@@H](C(=O)O)N", "CC(C(=O)O)N", true).as_deref(),
+            Some("1 stereo element undefined on Wikidata")
+        );
+        assert_eq!(
+            smiles_difference("C/C=C/C", r"C/C=C\C", true).as_deref(),
+            Some("1 double bond with the opposite geometry")
+        );
+        assert_eq!(
+            smiles_difference("CC(C(=O)O)N", "C[C@H](C(=O)O)N", false).as_deref(),
+            Some("stereo marks in a canonical SMILES")
+        );
+        assert_eq!(
+            smiles_difference(
+                "CN1C=NC2=C1C(=O)N(C(=O)N2C)C",
+                "CN1C=NC2=C1C(=O)NC(=O)N2C",
+                false
+            )
+            .as_deref(),
+            Some("formula C8H10N4O2 vs C7H8N4O2")
+        );
+        assert_eq!(smiles_difference("CCOC", "COCC", false), None);
+        assert_eq!(
+            smiles_difference("CCCO", "CC(C)O", false).as_deref(),
+            Some("same formula, different connectivity")
+        );
+        assert_eq!(
+            smiles_difference("CCO", "C(C", false).as_deref(),
+            Some("not a valid SMILES")
+        );
+    }
+
+    #[test]
+    fn test_inchi_and_formula_differences() {
+        const ALANINE: &str = "InChI=1S/C3H7NO2/c1-2(4)3(5)6/h2H,4H2,1H3,(H,5,6)";
+        let l_alanine = format!("{}/t2-/m0/s1", ALANINE);
+        let d_alanine = format!("{}/t2-/m1/s1", ALANINE);
+        assert_eq!(inchi_difference(&l_alanine, &l_alanine), None);
+        assert_eq!(
+            inchi_difference(&l_alanine, &d_alanine).as_deref(),
+            Some("different stereo")
+        );
+        assert_eq!(
+            inchi_difference(&l_alanine, ALANINE).as_deref(),
+            Some("stereo less specific on Wikidata")
+        );
+        assert_eq!(
+            inchi_difference(ALANINE, "InChI=1S/C3H6NO2/c1-2(4)3(5)6/h2H,4H2,1H3").as_deref(),
+            Some("formula C3H7NO2 vs C3H6NO2, different hydrogens (/h)")
+        );
+
+        assert_eq!(formula_difference("C8H10N4O2", "C8H10N4O2"), None);
+        assert_eq!(
+            formula_difference("C8H10N4O2", "C8H9N4O2").as_deref(),
+            Some("H 10 vs 9")
+        );
+        assert_eq!(
+            formula_difference("C8H10N4O2", "caffeine").as_deref(),
+            Some("not a readable formula")
+        );
+    }
+}
@@ -0,0 +1,231 @@
+//! Elemental composition derived locally from a SMILES: Hill formula and monoisotopic mass.
+//!
+//! Enrichment backends report `molecular_formula` and `exact_mass` on their own; these helpers
+//! recompute both from the sanitized SMILES so missing values can be filled in and disagreeing
+//! ones kept out of P274/P2067.
+use crate::chemical_entity::elements::{ELECTRON_MASS, isotope_mass, monoisotopic_mass};
+use crate::chemical_entity::smiles::{Molecule, parse_smiles};
+use crate::chemical_entity::structure::ChemicalStructureData;
+use log::debug;
+use std::collections::BTreeMap;
+
+/// Largest accepted difference (in dalton) between a reported and the computed exact mass.
+pub const MASS_TOLERANCE: f64 = 0.002;
+
+/// Atom counts, net charge and monoisotopic mass of a structure.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Composition {
+    pub counts: BTreeMap<&'static str, u32>,
+    pub charge: i32,
+    /// `None` when an atom carries an isotope label missing from the isotope table.
+    pub monoisotopic_mass: Option<f64>,
+}
+
+impl Composition {
+    /// Composition of a parsed SMILES, including implicit hydrogens. `None` for structures with
+    /// wildcard atoms or elements without a known mass.
+    pub fn from_molecule(molecule: &Molecule) -> Option<Self> {
+        let mut counts: BTreeMap<&'static str, u32> = BTreeMap::new();
+        let mut charge = 0i32;
+        let mut mass = Some(0.0);
+        let hydrogen = monoisotopic_mass("H")?;
+        for (index, atom) in molecule.atoms.iter().enumerate() {
+            if atom.element == "*" {
+                return None;
+            }
+            let atom_mass = match atom.isotope {
+                Some(number) => isotope_mass(atom.element, number),
+                None => Some(monoisotopic_mass(atom.element)?),
+            };
+            let hydrogens = molecule.hydrogen_count(index);
+            *counts.entry(atom.element).or_default() += 1;
+            if hydrogens > 0 {
+                *counts.entry("H").or_default() += u32::from(hydrogens);
+            }
+            charge += i32::from(atom.charge);
+            mass = mass
+                .zip(atom_mass)
+                .map(|(total, atom_mass)| total + atom_mass + f64::from(hydrogens) * hydrogen);
+        }
+        Some(Self {
+            counts,
+            charge,
+            monoisotopic_mass: mass.map(|mass| mass - f64::from(charge) * ELECTRON_MASS),
+        })
+    }
+
+    /// Composition of `smiles`, or `None` if it cannot be parsed or contains unknown atoms.
+    pub fn from_smiles(smiles: &str) -> Option<Self> {
+        Self::from_molecule(&parse_smiles(smiles).ok()?)
+    }
+
+    /// Hill formula: C and H first when carbon is present, everything else alphabetically, with
+    /// the net charge appended (`H4N+`, `C2H3O2-`).
+    pub fn hill_formula(&self) -> String {
+        let mut symbols: Vec<&str> = self.counts.keys().copied().collect();
+        if self.counts.contains_key("C") {
+            symbols.sort_by_key(|symbol| match *symbol {
+                "C" => (0, *symbol),
+                "H" => (1, *symbol),
+                _ => (2, *symbol),
+            });
+        }
+        let mut formula = String::new();
+        for symbol in symbols {
+            formula.push_str(symbol);
+            match self.counts[symbol] {
+                1 => {}
+                count => formula.push_str(&count.to_string()),
+            }
+        }
+        match self.charge {
+            0 => {}
+            1 => formula.push('+'),
+            -1 => formula.push('-'),
+            charge => formula.push_str(&format!("{:+}", charge)),
+        }
+        formula
+    }
+}
+
+/// Element counts of a written formula such as `C8H10N4O2`; a trailing charge is ignored.
+pub fn parse_formula(formula: &str) -> Option<BTreeMap<String, u32>> {
+    let formula = formula.trim();
+    let formula = formula
+        .find(['+', '-'])
+        .map_or(formula, |sign| &formula[..sign]);
+    let mut counts = BTreeMap::new();
+    let mut chars = formula.chars().peekable();
+    while let Some(first) = chars.next() {
+        if !first.is_ascii_uppercase() {
+            return None;
+        }
+        let mut symbol = first.to_string();
+        while let Some(next) = chars.next_if(char::is_ascii_lowercase) {
+            symbol.push(next);
+        }
+        let mut digits = String::new();
+        while let Some(next) = chars.next_if(char::is_ascii_digit) {
+            digits.push(next);
+        }
+        let count = if digits.is_empty() {
+            1
+        } else {
+            digits.parse().ok()?
+        };
+        *counts.entry(symbol).or_default() += count;
+    }
+    (!counts.is_empty()).then_some(counts)
+}
+
+/// Fills in a missing formula or mass from the sanitized SMILES and drops reported values that
+/// disagree with it. Returns one warning per dropped value.
+pub fn cross_check_composition(structure: &mut ChemicalStructureData) -> Vec<String> {
+    let Some(local) = Composition::from_smiles(&structure.sanitized_smiles) else {
+        debug!(
+            "No local composition for {}; keeping the reported formula and mass",
+            structure.sanitized_smiles
+        );
+        return Vec::new();
+    };
+    let mut warnings = Vec::new();
+    let local_formula = local.hill_formula();
+
+    match structure.molecular_formula.as_deref() {
+        None => structure.molecular_formula = Some(local_formula.clone()),
+        Some(reported) => {
+            let local_counts: BTreeMap<String, u32> = local
+                .counts
+                .iter()
+                .map(|(symbol, count)| (symbol.to_string(), *count))
+                .collect();
+            if parse_formula(reported).as_ref() != Some(&local_counts) {
+                warnings.push(format!(
+                    "Reported molecular formula {} disagrees with {} derived from the SMILES; P274 not written.",
+                    reported, local_formula
+                ));
+                structure.molecular_formula = None;
+            }
+        }
+    }
+
+    match (structure.exact_mass, local.monoisotopic_mass) {
+        (None, local_mass) => structure.exact_mass = local_mass,
+        (Some(reported), Some(local_mass)) if (reported - local_mass).abs() > MASS_TOLERANCE => {
+            warnings.push(format!(
+                "Reported exact mass {:.5} Da differs from {:.5} Da derived from the SMILES by more than {} Da; P2067 not written.",
+                reported, local_mass, MASS_TOLERANCE
+            ));
+            structure.exact_mass = None;
+        }
+        _ => {}
+    }
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn structure(smiles: &str, formula: Option<&str>, mass: Option<f64>) -> ChemicalStructureData {
+        ChemicalStructureData {
+            sanitized_smiles: smiles.to_string(),
+            smiles_were_sanitized: false,
+            canonical_smiles: Some(smiles.to_string()),
+            isomeric_smiles: None,
+            inchi: None,
+            inchikey: None,
+            molecular_formula: formula.map(str::to_string),
+            exact_mass: mass,
+            other_descriptors: None,
+        }
+    }
+
+    #[test]
+    fn test_hill_formula_and_mass() {
+        let caffeine = Composition::from_smiles("Cn1cnc2c1c(=O)n(C)c(=O)n2C").unwrap();
+        assert_eq!(caffeine.hill_formula(), "C8H10N4O2");
+        assert!((caffeine.monoisotopic_mass.unwrap() - 194.080376).abs() < 1e-5);
+
+        let thiophene = Composition::from_smiles("c1ccsc1").unwrap();
+        assert_eq!(thiophene.hill_formula(), "C4H4S");
+        let pyrrole = Composition::from_smiles("c1cc[nH]c1").unwrap();
+        assert_eq!(pyrrole.hill_formula(), "C4H5N");
+        assert_eq!(
+            Composition::from_smiles("[NH4+]").unwrap().hill_formula(),
+            "H4N+"
+        );
+        assert_eq!(
+            Composition::from_smiles("OS(=O)(=O)O")
+                .unwrap()
+                .hill_formula(),
+            "H2O4S"
+        );
+        let labelled = Composition::from_smiles("[13CH4]").unwrap();
+        assert_eq!(labelled.hill_formula(), "CH4");
+        assert!((labelled.monoisotopic_mass.unwrap() - 17.034655).abs() < 1e-5);
+        assert_eq!(
+            parse_formula("C8H10N4O2").unwrap(),
+            parse_formula("H10C8O2N4").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_cross_check_fills_and_flags() {
+        let mut missing = structure("CCO", None, None);
+        assert!(cross_check_composition(&mut missing).is_empty());
+        assert_eq!(missing.molecular_formula.as_deref(), Some("C2H6O"));
+        assert!((missing.exact_mass.unwrap() - 46.041865).abs() < 1e-5);
+
+        let mut agreeing = structure("CCO", Some("C2H6O"), Some(46.0419));
+        assert!(cross_check_composition(&mut agreeing).is_empty());
+        assert_eq!(agreeing.exact_mass, Some(46.0419));
+
+        let mut disputed = structure("CCO", Some("C2H4O"), Some(44.0262));
+        let warnings = cross_check_composition(&mut disputed);
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[0].contains("C2H4O disagrees with C2H6O"));
+        assert_eq!(disputed.molecular_formula, None);
+        assert_eq!(disputed.exact_mass, None);
+    }
+}
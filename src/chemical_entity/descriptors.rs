@@ -0,0 +1,279 @@
+//! Structure tables computed ahead of time (for example with RDKit): the `--descriptors` overlay
+//! and the `precomputed` enricher backend both read this format.
+//!
+//! The TSV has a header row and is keyed by input SMILES (`smiles`, `input_smiles` or
+//! `chemical_entity_smiles`), by input row number (`row` or `row_id`, the CSV line number used
+//! in diagnostics and step files), or both; a row number wins over the SMILES. Structure columns
+//! are `canonical_smiles`, `isomeric_smiles`, `inchi`, `inchikey` (required on every row),
+//! `molecular_formula` and `exact_mass`. Values are validated when the table is loaded, including
+//! that each InChIKey matches its row's InChI. A row without either SMILES column falls back to
+//! the input SMILES, which is validated when the row is looked up.
+use crate::chemical_entity::inchikey::inchikey_from_inchi;
+use crate::chemical_entity::smiles::validate_smiles;
+use crate::chemical_entity::structure::{
+    ChemicalStructureData, is_valid_inchikey, validate_smiles_pair,
+};
+use crate::error::{CrateError, Result};
+use csv::ReaderBuilder;
+use std::collections::HashMap;
+use std::path::Path;
+
+const INPUT_SMILES_HEADERS: [&str; 3] = ["smiles", "input_smiles", "chemical_entity_smiles"];
+const ROW_HEADERS: [&str; 2] = ["row", "row_id"];
+
+/// Structure values of one table row.
+#[derive(Debug, Clone, PartialEq)]
+struct DescriptorEntry {
+    canonical_smiles: Option<String>,
+    isomeric_smiles: Option<String>,
+    inchi: Option<String>,
+    inchikey: String,
+    molecular_formula: Option<String>,
+    exact_mass: Option<f64>,
+}
+
+impl DescriptorEntry {
+    fn to_structure(&self, input_smiles: &str) -> Result<ChemicalStructureData> {
+        let sanitized_smiles = match self
+            .isomeric_smiles
+            .clone()
+            .or_else(|| self.canonical_smiles.clone())
+        {
+            Some(smiles) => smiles,
+            None => {
+                // The table vouches for nothing here, so hold the input to the same rules.
+                validate_smiles(input_smiles)?;
+                let has_stereo = input_smiles.contains(['@', '/', '\\']);
+                let input = Some(input_smiles.to_string());
+                let (canonical, isomeric) = if has_stereo {
+                    (None, input)
+                } else {
+                    (input, None)
+                };
+                validate_smiles_pair(canonical, isomeric)?;
+                input_smiles.to_string()
+            }
+        };
+        Ok(ChemicalStructureData {
+            smiles_were_sanitized: sanitized_smiles != input_smiles,
+            sanitized_smiles,
+            canonical_smiles: self.canonical_smiles.clone(),
+            isomeric_smiles: self.isomeric_smiles.clone(),
+            inchi: self.inchi.clone(),
+            inchikey: Some(self.inchikey.clone()),
+            molecular_formula: self.molecular_formula.clone(),
+            exact_mass: self.exact_mass,
+            other_descriptors: None,
+        })
+    }
+}
+
+/// Precomputed structures indexed by input row and by input SMILES.
+#[derive(Debug, Default)]
+pub struct DescriptorTable {
+    by_row: HashMap<usize, DescriptorEntry>,
+    by_smiles: HashMap<String, DescriptorEntry>,
+}
+
+fn table_error(path: &Path, message: impl Into<String>) -> CrateError {
+    CrateError::DescriptorTableError {
+        path: path.display().to_string(),
+        message: message.into(),
+    }
+}
+
+fn find_column(headers: &[String], names: &[&str]) -> Option<usize> {
+    names
+        .iter()
+        .find_map(|name| headers.iter().position(|header| header == name))
+}
+
+impl DescriptorTable {
+    /// Loads a tab-separated descriptor table, rejecting rows with invalid SMILES or InChIKeys.
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut reader = ReaderBuilder::new()
+            .delimiter(b'\t')
+            .flexible(true)
+            .from_path(path)?;
+        let headers: Vec<String> = reader
+            .headers()?
+            .iter()
+            .map(|header| header.trim().to_lowercase())
+            .collect();
+        let input = find_column(&headers, &INPUT_SMILES_HEADERS);
+        let row = find_column(&headers, &ROW_HEADERS);
+        if input.is_none() && row.is_none() {
+            return Err(table_error(
+                path,
+                format!(
+                    "needs a row ({}) or input SMILES ({}) column",
+                    ROW_HEADERS.join(", "),
+                    INPUT_SMILES_HEADERS.join(", ")
+                ),
+            ));
+        }
+        let inchikey = find_column(&headers, &["inchikey"])
+            .ok_or_else(|| table_error(path, "needs an inchikey column"))?;
+        let canonical = find_column(&headers, &["canonical_smiles"]);
+        let isomeric = find_column(&headers, &["isomeric_smiles"]);
+        let inchi = find_column(&headers, &["inchi"]);
+        let formula = find_column(&headers, &["molecular_formula"]);
+        let mass = find_column(&headers, &["exact_mass"]);
+
+        let mut table = Self::default();
+        for (index, record) in reader.records().enumerate() {
+            let record = record?;
+            // Header is line 1.
+            let line = index + 2;
+            let line_error =
+                |message: String| table_error(path, format!("line {}: {}", line, message));
+            let cell = |column: Option<usize>| {
+                column
+                    .and_then(|index| record.get(index))
+                    .map(str::trim)
+                    .filter(|value| !value.is_empty())
+                    .map(str::to_string)
+            };
+            let smiles = cell(input);
+            let row_id = match cell(row) {
+                Some(value) => Some(
+                    value
+                        .parse::<usize>()
+                        .map_err(|_| line_error(format!("invalid row number '{}'", value)))?,
+                ),
+                None => None,
+            };
+            if smiles.is_none() && row_id.is_none() {
+                continue;
+            }
+            let key =
+                cell(Some(inchikey)).ok_or_else(|| line_error("inchikey is empty".to_string()))?;
+            if !is_valid_inchikey(&key) {
+                return Err(line_error(format!("invalid InChIKey '{}'", key)));
+            }
+            let inchi = cell(inchi);
+            if let Some(ref inchi) = inchi {
+                match inchikey_from_inchi(inchi) {
+                    Ok(computed) if computed == key => {}
+                    Ok(computed) => {
+                        return Err(line_error(format!(
+                            "InChIKey '{}' does not match {} computed from the InChI",
+                            key, computed
+                        )));
+                    }
+                    Err(err) => return Err(line_error(err.to_string())),
+                }
+            }
+            let exact_mass = match cell(mass) {
+                Some(value) => Some(
+                    value
+                        .parse::<f64>()
+                        .map_err(|_| line_error(format!("invalid exact_mass '{}'", value)))?,
+                ),
+                None => None,
+            };
+            let (canonical_smiles, isomeric_smiles) =
+                validate_smiles_pair(cell(canonical), cell(isomeric))
+                    .map_err(|err| line_error(err.to_string()))?;
+            let entry = DescriptorEntry {
+                canonical_smiles,
+                isomeric_smiles,
+                inchi,
+                inchikey: key,
+                molecular_formula: cell(formula),
+                exact_mass,
+            };
+            if let Some(row_id) = row_id {
+                table.by_row.insert(row_id, entry);
+            } else if let Some(smiles) = smiles {
+                table.by_smiles.insert(smiles, entry);
+            }
+        }
+        Ok(table)
+    }
+
+    /// Structure for input row `row` (if the table lists it) or else for `smiles`. Fails when the
+    /// row has no SMILES of its own and the input SMILES is invalid.
+    pub fn lookup(
+        &self,
+        row: Option<usize>,
+        smiles: &str,
+    ) -> Option<Result<ChemicalStructureData>> {
+        row.and_then(|row| self.by_row.get(&row))
+            .or_else(|| self.by_smiles.get(smiles))
+            .map(|entry| entry.to_structure(smiles))
+    }
+
+    /// Number of table rows.
+    pub fn len(&self) -> usize {
+        self.by_row.len() + self.by_smiles.len()
+    }
+
+    /// Whether the table holds no rows.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_descriptor_table_keys_and_validation() {
+        let mut file = NamedTempFile::new().unwrap();
+        writeln!(file, "row\tsmiles\tcanonical_smiles\tinchikey\texact_mass").unwrap();
+        writeln!(file, "\tC\tC\tVNWKTOKETHGBQD-UHFFFAOYSA-N\t16.0313").unwrap();
+        writeln!(file, "7\tO\tCCO\tLFQSCWFLJHTTHZ-UHFFFAOYSA-N\t46.0419").unwrap();
+        let table = DescriptorTable::load(file.path()).unwrap();
+        assert_eq!(table.len(), 2);
+        assert_eq!(
+            table
+                .lookup(Some(3), "C")
+                .unwrap()
+                .unwrap()
+                .inchikey
+                .as_deref(),
+            Some("VNWKTOKETHGBQD-UHFFFAOYSA-N")
+        );
+        let ethanol = table.lookup(Some(7), "OCC").unwrap().unwrap();
+        assert_eq!(ethanol.sanitized_smiles, "CCO");
+        assert!(ethanol.smiles_were_sanitized);
+        // Row-keyed entries are not reachable through their SMILES.
+        assert!(table.lookup(None, "O").is_none());
+
+        let mut bad = NamedTempFile::new().unwrap();
+        writeln!(bad, "smiles\tinchikey").unwrap();
+        writeln!(bad, "C\tVNWKTOKETHGBQD-UHFFFAOYSA").unwrap();
+        let err = DescriptorTable::load(bad.path()).unwrap_err();
+        assert!(err.to_string().contains("line 2: invalid InChIKey"));
+
+        let mut mismatched = NamedTempFile::new().unwrap();
+        writeln!(mismatched, "smiles\tinchi\tinchikey").unwrap();
+        writeln!(
+            mismatched,
+            "C\tInChI=1S/CH4/h1H4\tLFQSCWFLJHTTHZ-UHFFFAOYSA-N"
+        )
+        .unwrap();
+        let err = DescriptorTable::load(mismatched.path()).unwrap_err();
+        assert!(err.to_string().contains("line 2: InChIKey 'LFQSCWFLJHTTHZ-UHFFFAOYSA-N' does not match VNWKTOKETHGBQD-UHFFFAOYSA-N"));
+    }
+
+    #[test]
+    fn test_rows_without_smiles_validate_the_input() {
+        let mut file = NamedTempFile::new().unwrap();
+        writeln!(file, "row	inchikey").unwrap();
+        writeln!(file, "2	VNWKTOKETHGBQD-UHFFFAOYSA-N").unwrap();
+        let table = DescriptorTable::load(file.path()).unwrap();
+
+        let methane = table.lookup(Some(2), "C").unwrap().unwrap();
+        assert_eq!(methane.sanitized_smiles, "C");
+        assert!(!methane.smiles_were_sanitized);
+        assert!(table.lookup(Some(2), "N[C@@H](C)C(=O)O").unwrap().is_ok());
+
+        let err = table.lookup(Some(2), "C1CC").unwrap().unwrap_err();
+        assert!(matches!(err, CrateError::InvalidSmiles { .. }));
+    }
+}
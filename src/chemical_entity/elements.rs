@@ -0,0 +1,231 @@
+//! Periodic table data used by the local SMILES parser and the formula/mass cross-check.
+
+/// Element symbols in atomic-number order (index 0 is hydrogen).
+const SYMBOLS: [&str; 118] = [
+    "H", "He", "Li", "Be", "B", "C", "N", "O", "F", "Ne", "Na", "Mg", "Al", "Si", "P", "S", "Cl",
+    "Ar", "K", "Ca", "Sc", "Ti", "V", "Cr", "Mn", "Fe", "Co", "Ni", "Cu", "Zn", "Ga", "Ge", "As",
+    "Se", "Br", "Kr", "Rb", "Sr", "Y", "Zr", "Nb", "Mo", "Tc", "Ru", "Rh", "Pd", "Ag", "Cd", "In",
+    "Sn", "Sb", "Te", "I", "Xe", "Cs", "Ba", "La", "Ce", "Pr", "Nd", "Pm", "Sm", "Eu", "Gd", "Tb",
+    "Dy", "Ho", "Er", "Tm", "Yb", "Lu", "Hf", "Ta", "W", "Re", "Os", "Ir", "Pt", "Au", "Hg", "Tl",
+    "Pb", "Bi", "Po", "At", "Rn", "Fr", "Ra", "Ac", "Th", "Pa", "U", "Np", "Pu", "Am", "Cm", "Bk",
+    "Cf", "Es", "Fm", "Md", "No", "Lr", "Rf", "Db", "Sg", "Bh", "Hs", "Mt", "Ds", "Rg", "Cn", "Nh",
+    "Fl", "Mc", "Lv", "Ts", "Og",
+];
+
+/// Mass of an electron in dalton (charged species gain or lose it).
+pub const ELECTRON_MASS: f64 = 0.000_548_579_909;
+
+/// Mass of the most abundant isotope of each element with a stable (or long-lived) isotope.
+const MONOISOTOPIC_MASSES: [(&str, f64); 83] = [
+    ("H", 1.007_825_032_23),
+    ("He", 4.002_603_254_13),
+    ("Li", 7.016_003_436_6),
+    ("Be", 9.012_183_065),
+    ("B", 11.009_305_36),
+    ("C", 12.0),
+    ("N", 14.003_074_004_43),
+    ("O", 15.994_914_619_57),
+    ("F", 18.998_403_162_73),
+    ("Ne", 19.992_440_176_2),
+    ("Na", 22.989_769_282),
+    ("Mg", 23.985_041_697),
+    ("Al", 26.981_538_53),
+    ("Si", 27.976_926_534_65),
+    ("P", 30.973_761_998_42),
+    ("S", 31.972_071_174_4),
+    ("Cl", 34.968_852_682),
+    ("Ar", 39.962_383_123_7),
+    ("K", 38.963_706_486_4),
+    ("Ca", 39.962_590_863),
+    ("Sc", 44.955_908_28),
+    ("Ti", 47.947_941_98),
+    ("V", 50.943_957_04),
+    ("Cr", 51.940_506_23),
+    ("Mn", 54.938_043_91),
+    ("Fe", 55.934_936_33),
+    ("Co", 58.933_194_29),
+    ("Ni", 57.935_342_41),
+    ("Cu", 62.929_597_72),
+    ("Zn", 63.929_142_01),
+    ("Ga", 68.925_573_5),
+    ("Ge", 73.921_177_761),
+    ("As", 74.921_594_57),
+    ("Se", 79.916_521_8),
+    ("Br", 78.918_337_6),
+    ("Kr", 83.911_497_728_2),
+    ("Rb", 84.911_789_737_9),
+    ("Sr", 87.905_612_5),
+    ("Y", 88.905_840_3),
+    ("Zr", 89.904_697_7),
+    ("Nb", 92.906_373),
+    ("Mo", 97.905_404_82),
+    ("Ru", 101.904_344_1),
+    ("Rh", 102.905_498),
+    ("Pd", 105.903_480_4),
+    ("Ag", 106.905_091_6),
+    ("Cd", 113.903_365_09),
+    ("In", 114.903_878_776),
+    ("Sn", 119.902_201_63),
+    ("Sb", 120.903_812),
+    ("Te", 129.906_222_748),
+    ("I", 126.904_471_9),
+    ("Xe", 131.904_155_085_6),
+    ("Cs", 132.905_451_961),
+    ("Ba", 137.905_247),
+    ("La", 138.906_356_3),
+    ("Ce", 139.905_443_1),
+    ("Pr", 140.907_657_6),
+    ("Nd", 141.907_729),
+    ("Sm", 151.919_739_7),
+    ("Eu", 152.921_238),
+    ("Gd", 157.924_112_3),
+    ("Tb", 158.925_354_7),
+    ("Dy", 163.929_181_9),
+    ("Ho", 164.930_328_8),
+    ("Er", 165.930_299_5),
+    ("Tm", 168.934_217_9),
+    ("Yb", 173.938_866_4),
+    ("Lu", 174.940_775_2),
+    ("Hf", 179.946_557),
+    ("Ta", 180.947_995_8),
+    ("W", 183.950_930_92),
+    ("Re", 186.955_750_1),
+    ("Os", 191.961_477),
+    ("Ir", 192.962_921_6),
+    ("Pt", 194.964_791_7),
+    ("Au", 196.966_568_79),
+    ("Hg", 201.970_643_4),
+    ("Tl", 204.974_427_8),
+    ("Pb", 207.976_652_5),
+    ("Bi", 208.980_399_1),
+    ("Th", 232.038_055_8),
+    ("U", 238.050_788_4),
+];
+
+/// Masses of isotopes that appear as labels (`[13C]`, `[2H]`, ...) in natural-product SMILES.
+const ISOTOPE_MASSES: [(&str, u16, f64); 36] = [
+    ("H", 1, 1.007_825_032_23),
+    ("H", 2, 2.014_101_778_12),
+    ("H", 3, 3.016_049_277_9),
+    ("B", 10, 10.012_936_95),
+    ("B", 11, 11.009_305_36),
+    ("C", 11, 11.011_433_6),
+    ("C", 12, 12.0),
+    ("C", 13, 13.003_354_835_07),
+    ("C", 14, 14.003_241_988_4),
+    ("N", 14, 14.003_074_004_43),
+    ("N", 15, 15.000_108_898_88),
+    ("O", 16, 15.994_914_619_57),
+    ("O", 17, 16.999_131_756_5),
+    ("O", 18, 17.999_159_612_86),
+    ("F", 18, 18.000_937_3),
+    ("F", 19, 18.998_403_162_73),
+    ("Si", 28, 27.976_926_534_65),
+    ("Si", 29, 28.976_494_664_9),
+    ("Si", 30, 29.973_770_136),
+    ("P", 31, 30.973_761_998_42),
+    ("P", 32, 31.973_907_6),
+    ("S", 32, 31.972_071_174_4),
+    ("S", 33, 32.971_458_909_8),
+    ("S", 34, 33.967_867_004),
+    ("S", 35, 34.969_032_31),
+    ("Cl", 35, 34.968_852_682),
+    ("Cl", 37, 36.965_902_602),
+    ("Se", 77, 76.919_914),
+    ("Se", 78, 77.917_309_1),
+    ("Se", 80, 79.916_521_8),
+    ("Br", 79, 78.918_337_6),
+    ("Br", 81, 80.916_289_7),
+    ("I", 123, 122.905_589),
+    ("I", 125, 124.904_629_4),
+    ("I", 127, 126.904_471_9),
+    ("I", 131, 130.906_126_3),
+];
+
+/// Valence electrons of the main-group elements whose valences are checked.
+const VALENCE_ELECTRONS: [(&str, i8); 14] = [
+    ("B", 3),
+    ("C", 4),
+    ("N", 5),
+    ("O", 6),
+    ("F", 7),
+    ("Si", 4),
+    ("P", 5),
+    ("S", 6),
+    ("Cl", 7),
+    ("As", 5),
+    ("Se", 6),
+    ("Br", 7),
+    ("Te", 6),
+    ("I", 7),
+];
+
+/// Elements that are not metals; everything else in the periodic table counts as a metal.
+const NONMETALS: [&str; 22] = [
+    "H", "He", "B", "C", "N", "O", "F", "Ne", "Si", "P", "S", "Cl", "Ar", "As", "Se", "Br", "Kr",
+    "Te", "I", "Xe", "At", "Rn",
+];
+
+/// Canonical spelling of `symbol` (e.g. `"Cl"`), if it names an element.
+pub fn element_symbol(symbol: &str) -> Option<&'static str> {
+    SYMBOLS.iter().copied().find(|known| *known == symbol)
+}
+
+/// Atomic number of an element symbol.
+pub fn atomic_number(symbol: &str) -> Option<u8> {
+    SYMBOLS
+        .iter()
+        .position(|known| *known == symbol)
+        .map(|index| index as u8 + 1)
+}
+
+/// Mass of the most abundant isotope of `symbol`.
+pub fn monoisotopic_mass(symbol: &str) -> Option<f64> {
+    MONOISOTOPIC_MASSES
+        .iter()
+        .find(|(known, _)| *known == symbol)
+        .map(|(_, mass)| *mass)
+}
+
+/// Mass of isotope `mass_number` of `symbol`, if listed.
+pub fn isotope_mass(symbol: &str, mass_number: u16) -> Option<f64> {
+    ISOTOPE_MASSES
+        .iter()
+        .find(|(known, number, _)| *known == symbol && *number == mass_number)
+        .map(|(_, _, mass)| *mass)
+}
+
+/// Whether `symbol` names a metal. Bonds to metals are dative in most SMILES (`[Pt]([NH3])...`)
+/// and do not count towards the valence of the donor atom.
+pub fn is_metal(symbol: &str) -> bool {
+    element_symbol(symbol).is_some() && !NONMETALS.contains(&symbol)
+}
+
+/// Valences an atom of `symbol` with formal `charge` may have, lowest first, or `None` for
+/// elements without a fixed rule (metals, noble gases, hydrogen).
+///
+/// A charge shifts the element to its isoelectronic neighbour (N+ behaves like C, O- like F);
+/// elements from the third period on may also expand their octet in steps of two.
+pub fn allowed_valences(symbol: &str, charge: i8) -> Option<Vec<u8>> {
+    let (_, electrons) = VALENCE_ELECTRONS
+        .iter()
+        .find(|(known, _)| *known == symbol)?;
+    let electrons = electrons - charge;
+    if !(1..=7).contains(&electrons) {
+        return None;
+    }
+    let base = if electrons <= 4 {
+        electrons
+    } else {
+        8 - electrons
+    };
+    let mut valences = vec![base as u8];
+    if atomic_number(symbol).is_some_and(|number| number > 10) {
+        let mut expanded = base + 2;
+        while expanded <= electrons {
+            valences.push(expanded as u8);
+            expanded += 2;
+        }
+    }
+    Some(valences)
+}
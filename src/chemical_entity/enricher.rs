@@ -0,0 +1,72 @@
+//! Pluggable backends that turn an input SMILES into [`ChemicalStructureData`].
+use crate::chemical_entity::np_api::NpApiEnricher;
+use crate::chemical_entity::precomputed::PrecomputedEnricher;
+use crate::chemical_entity::pubchem::PubChemEnricher;
+use crate::chemical_entity::structure::ChemicalStructureData;
+use crate::error::{CrateError, Result};
+use futures::future::BoxFuture;
+use std::path::Path;
+use std::sync::Arc;
+
+/// A source of standardized structure data (SMILES, InChI, InChIKey, formula, mass).
+pub trait StructureEnricher: Send + Sync {
+    /// Short backend name; also namespaces the enrichment cache.
+    fn name(&self) -> &'static str;
+
+    /// Whether results should go through the on-disk enrichment cache.
+    fn cacheable(&self) -> bool {
+        true
+    }
+
+    /// Standardizes `smiles` and computes its descriptors.
+    fn enrich<'a>(
+        &'a self,
+        smiles: &'a str,
+        client: &'a reqwest::Client,
+    ) -> BoxFuture<'a, Result<ChemicalStructureData>>;
+}
+
+/// Backends selectable with `--enricher`.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EnricherKind {
+    /// Natural Products API (COCONUT pre-processing and descriptors).
+    #[default]
+    #[value(name = "np-api")]
+    NpApi,
+    /// PubChem PUG-REST compound properties.
+    #[value(name = "pubchem")]
+    Pubchem,
+    /// A local TSV of structures computed ahead of time.
+    #[value(name = "precomputed")]
+    Precomputed,
+}
+
+/// Builds the enricher for `kind`. `base_url` overrides the default endpoint of remote
+/// backends; `table` is required by (and only used for) the precomputed backend.
+pub fn build_enricher(
+    kind: EnricherKind,
+    base_url: Option<&str>,
+    table: Option<&Path>,
+) -> Result<Arc<dyn StructureEnricher>> {
+    match kind {
+        EnricherKind::NpApi => Ok(Arc::new(
+            base_url.map(NpApiEnricher::new).unwrap_or_default(),
+        )),
+        EnricherKind::Pubchem => Ok(Arc::new(
+            base_url.map(PubChemEnricher::new).unwrap_or_default(),
+        )),
+        EnricherKind::Precomputed => {
+            let table = table.ok_or_else(|| {
+                CrateError::EnricherConfigError(
+                    "--enricher precomputed requires --precomputed-table".to_string(),
+                )
+            })?;
+            Ok(Arc::new(PrecomputedEnricher::load(table)?))
+        }
+    }
+}
+
+/// The default enricher (Natural Products API at its public URL).
+pub fn default_enricher() -> Arc<dyn StructureEnricher> {
+    Arc::new(NpApiEnricher::default())
+}
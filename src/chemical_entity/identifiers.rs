@@ -0,0 +1,481 @@
+//! Cross-database identifiers resolved from the InChIKey of a structure: PubChem CIDs (P662),
+//! ChEBI IDs (P683) and ChEMBL IDs (P592) are written on new chemical items, cited with the
+//! database (S248) and the lookup date (S813). COCONUT IDs only go into the status report.
+//!
+//! PubChem and ChEMBL are queried through their own REST APIs, ChEBI and COCONUT through
+//! UniChem, which maps InChIKeys to the compound IDs of both.
+use crate::cache::{self, CacheKind};
+use crate::chemical_entity::pubchem::PUBCHEM_BASE_URL;
+use crate::error::{CrateError, Result};
+use chrono::{NaiveDate, Utc};
+use futures::future::join_all;
+use log::{info, warn};
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::fmt;
+
+/// Default base URL of UniChem (ChEBI and COCONUT lookups).
+pub const UNICHEM_BASE_URL: &str = "https://www.ebi.ac.uk/unichem/api/v1";
+
+/// Default base URL of the ChEMBL web services.
+pub const CHEMBL_BASE_URL: &str = "https://www.ebi.ac.uk/chembl/api/data";
+
+/// Databases selectable with `--link-identifiers`.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IdentifierSource {
+    /// PubChem compound ID (P662).
+    #[value(name = "pubchem")]
+    Pubchem,
+    /// ChEBI ID (P683), through UniChem.
+    #[value(name = "chebi")]
+    Chebi,
+    /// ChEMBL ID (P592).
+    #[value(name = "chembl")]
+    Chembl,
+    /// COCONUT ID, through UniChem; reported only.
+    #[value(name = "coconut")]
+    Coconut,
+}
+
+impl IdentifierSource {
+    /// Database name used in warnings and the status report.
+    pub fn label(self) -> &'static str {
+        match self {
+            IdentifierSource::Pubchem => "PubChem",
+            IdentifierSource::Chebi => "ChEBI",
+            IdentifierSource::Chembl => "ChEMBL",
+            IdentifierSource::Coconut => "COCONUT",
+        }
+    }
+
+    /// Wikidata property holding the identifier, if it is written at all.
+    pub fn property(self) -> Option<&'static str> {
+        match self {
+            IdentifierSource::Pubchem => Some("P662"),
+            IdentifierSource::Chebi => Some("P683"),
+            IdentifierSource::Chembl => Some("P592"),
+            IdentifierSource::Coconut => None,
+        }
+    }
+
+    /// Item of the database, cited as `stated in` (S248).
+    pub fn stated_in_qid(self) -> Option<&'static str> {
+        match self {
+            IdentifierSource::Pubchem => Some("Q278487"),
+            IdentifierSource::Chebi => Some("Q902623"),
+            IdentifierSource::Chembl => Some("Q6120337"),
+            IdentifierSource::Coconut => None,
+        }
+    }
+
+    fn default_base_url(self) -> &'static str {
+        match self {
+            IdentifierSource::Pubchem => PUBCHEM_BASE_URL,
+            IdentifierSource::Chembl => CHEMBL_BASE_URL,
+            IdentifierSource::Chebi | IdentifierSource::Coconut => UNICHEM_BASE_URL,
+        }
+    }
+
+    /// UniChem `shortName` of the sources looked up through UniChem.
+    fn unichem_name(self) -> Option<&'static str> {
+        match self {
+            IdentifierSource::Chebi => Some("chebi"),
+            IdentifierSource::Coconut => Some("coconut"),
+            IdentifierSource::Pubchem | IdentifierSource::Chembl => None,
+        }
+    }
+}
+
+/// An identifier of the structure in another database, with the date it was looked up.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExternalIdentifier {
+    pub source: IdentifierSource,
+    pub id: String,
+    pub retrieved_on: NaiveDate,
+}
+
+impl fmt::Display for ExternalIdentifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.source.label(), self.id)
+    }
+}
+
+/// A database hit, with the InChIKey the database stores for it when the response carries one.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct IdentifierMatch {
+    id: String,
+    inchikey: Option<String>,
+    retrieved_on: NaiveDate,
+}
+
+/// Looks InChIKeys up in one database.
+#[derive(Debug, Clone)]
+pub struct IdentifierResolver {
+    source: IdentifierSource,
+    base_url: String,
+}
+
+impl IdentifierResolver {
+    /// Resolver for `source`; `base_url` overrides the public endpoint (e.g. a local stand-in).
+    pub fn new(source: IdentifierSource, base_url: Option<&str>) -> Self {
+        Self {
+            source,
+            base_url: base_url
+                .unwrap_or(source.default_base_url())
+                .trim_end_matches('/')
+                .to_string(),
+        }
+    }
+
+    pub fn source(&self) -> IdentifierSource {
+        self.source
+    }
+
+    /// Looks `inchikey` up, through the identifier cache. Errors are not cached.
+    async fn resolve(
+        &self,
+        inchikey: &str,
+        client: &reqwest::Client,
+    ) -> Result<Option<IdentifierMatch>> {
+        let key = format!("{}:{}", self.source.label(), inchikey);
+        if let Some(cached) = cache::lookup(CacheKind::Identifiers, &key) {
+            return Ok(cached);
+        }
+        info!(
+            "Looking up {} ID for InChIKey {}",
+            self.source.label(),
+            inchikey
+        );
+        let found = match self.source {
+            IdentifierSource::Pubchem => self.lookup_pubchem(inchikey, client).await?,
+            IdentifierSource::Chembl => self.lookup_chembl(inchikey, client).await?,
+            IdentifierSource::Chebi | IdentifierSource::Coconut => {
+                self.lookup_unichem(inchikey, client).await?
+            }
+        };
+        let found = found.map(|(id, reported)| IdentifierMatch {
+            id,
+            inchikey: reported,
+            retrieved_on: Utc::now().date_naive(),
+        });
+        cache::store(CacheKind::Identifiers, &key, found.as_ref());
+        Ok(found)
+    }
+
+    /// Sends `request`; a 404 means the key is unknown to the database.
+    async fn fetch<T: DeserializeOwned>(
+        &self,
+        request: reqwest::RequestBuilder,
+        inchikey: &str,
+    ) -> Result<Option<T>> {
+        let response = request.send().await.map_err(CrateError::ApiRequestError)?;
+        match response.status() {
+            StatusCode::NOT_FOUND => Ok(None),
+            status if !status.is_success() => Err(CrateError::IdentifierLookupError {
+                database: self.source.label().to_string(),
+                inchikey: inchikey.to_string(),
+                status,
+            }),
+            _ => response
+                .json::<T>()
+                .await
+                .map(Some)
+                .map_err(CrateError::ApiJsonDecodeError),
+        }
+    }
+
+    async fn lookup_pubchem(
+        &self,
+        inchikey: &str,
+        client: &reqwest::Client,
+    ) -> Result<Option<(String, Option<String>)>> {
+        let url = format!(
+            "{}/compound/inchikey/{}/property/InChIKey/JSON",
+            self.base_url, inchikey
+        );
+        let response: Option<PubChemResponse> = self.fetch(client.get(&url), inchikey).await?;
+        Ok(response
+            .and_then(|response| response.property_table.properties.into_iter().next())
+            .map(|compound| (compound.cid.to_string(), compound.inchikey)))
+    }
+
+    async fn lookup_chembl(
+        &self,
+        inchikey: &str,
+        client: &reqwest::Client,
+    ) -> Result<Option<(String, Option<String>)>> {
+        let request = client
+            .get(format!("{}/molecule.json", self.base_url))
+            .query(&[("molecule_structures__standard_inchi_key", inchikey)]);
+        let response: Option<ChemblResponse> = self.fetch(request, inchikey).await?;
+        Ok(response
+            .and_then(|response| response.molecules.into_iter().next())
+            .map(|molecule| {
+                let reported = molecule
+                    .molecule_structures
+                    .and_then(|structures| structures.standard_inchi_key);
+                (molecule.molecule_chembl_id, reported)
+            }))
+    }
+
+    async fn lookup_unichem(
+        &self,
+        inchikey: &str,
+        client: &reqwest::Client,
+    ) -> Result<Option<(String, Option<String>)>> {
+        let Some(wanted) = self.source.unichem_name() else {
+            return Ok(None);
+        };
+        let request = client
+            .post(format!("{}/compounds", self.base_url))
+            .json(&json!({ "type": "inchikey", "compound": inchikey }));
+        let response: Option<UniChemResponse> = self.fetch(request, inchikey).await?;
+        Ok(response
+            .into_iter()
+            .flat_map(|response| response.compounds)
+            .find_map(|compound| {
+                let id = compound
+                    .sources
+                    .into_iter()
+                    .find(|source| source.short_name.eq_ignore_ascii_case(wanted))?
+                    .compound_id;
+                let id = id.strip_prefix("CHEBI:").map(str::to_string).unwrap_or(id);
+                Some((id, compound.standard_inchikey))
+            }))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct PubChemResponse {
+    #[serde(rename = "PropertyTable")]
+    property_table: PubChemTable,
+}
+
+#[derive(Debug, Deserialize)]
+struct PubChemTable {
+    #[serde(rename = "Properties")]
+    properties: Vec<PubChemCompound>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PubChemCompound {
+    #[serde(rename = "CID")]
+    cid: u64,
+    #[serde(rename = "InChIKey")]
+    inchikey: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChemblResponse {
+    #[serde(default)]
+    molecules: Vec<ChemblMolecule>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChemblMolecule {
+    molecule_chembl_id: String,
+    molecule_structures: Option<ChemblStructures>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChemblStructures {
+    standard_inchi_key: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct UniChemResponse {
+    #[serde(default)]
+    compounds: Vec<UniChemCompound>,
+}
+
+#[derive(Debug, Deserialize)]
+struct UniChemCompound {
+    #[serde(rename = "standardInchiKey")]
+    standard_inchikey: Option<String>,
+    #[serde(default)]
+    sources: Vec<UniChemSource>,
+}
+
+#[derive(Debug, Deserialize)]
+struct UniChemSource {
+    #[serde(rename = "shortName")]
+    short_name: String,
+    #[serde(rename = "compoundId")]
+    compound_id: String,
+}
+
+/// Resolves `inchikey` in every database concurrently. Returns the identifiers found plus one
+/// warning per failed lookup or per hit the database files under another InChIKey (such hits
+/// are dropped).
+pub async fn resolve_identifiers(
+    inchikey: &str,
+    resolvers: &[IdentifierResolver],
+    client: &reqwest::Client,
+) -> (Vec<ExternalIdentifier>, Vec<String>) {
+    let lookups = join_all(
+        resolvers
+            .iter()
+            .map(|resolver| resolver.resolve(inchikey, client)),
+    )
+    .await;
+    let mut identifiers = Vec::new();
+    let mut warnings = Vec::new();
+    for (resolver, lookup) in resolvers.iter().zip(lookups) {
+        let label = resolver.source().label();
+        match lookup {
+            Ok(Some(found)) => match found.inchikey.filter(|reported| reported != inchikey) {
+                Some(reported) => {
+                    warn!(
+                        "{} {} has InChIKey {}, not {}",
+                        label, found.id, reported, inchikey
+                    );
+                    warnings.push(format!(
+                        "{} ID {} is registered with InChIKey {}, not {}; it is not linked.",
+                        label, found.id, reported, inchikey
+                    ));
+                }
+                None => identifiers.push(ExternalIdentifier {
+                    source: resolver.source(),
+                    id: found.id,
+                    retrieved_on: found.retrieved_on,
+                }),
+            },
+            Ok(None) => {}
+            Err(err) => {
+                warn!("{} lookup for InChIKey {} failed: {}", label, inchikey, err);
+                warnings.push(format!("{} lookup failed: {}", label, err));
+            }
+        }
+    }
+    (identifiers, warnings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{body_partial_json, method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[tokio::test]
+    async fn test_resolve_identifiers_from_each_database() {
+        let inchikey = "QNAYBMKLOCPYGJ-REOHCLBHSA-N";
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(format!(
+                "/rest/pug/compound/inchikey/{}/property/InChIKey/JSON",
+                inchikey
+            )))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "PropertyTable": { "Properties": [{ "CID": 5950, "InChIKey": inchikey }] }
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/chembl/molecule.json"))
+            .and(query_param(
+                "molecule_structures__standard_inchi_key",
+                inchikey,
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "molecules": [{
+                    "molecule_chembl_id": "CHEMBL279597",
+                    "molecule_structures": { "standard_inchi_key": inchikey }
+                }]
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/unichem/compounds"))
+            .and(body_partial_json(
+                json!({ "type": "inchikey", "compound": inchikey }),
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "compounds": [{
+                    "standardInchiKey": inchikey,
+                    "sources": [
+                        { "shortName": "chebi", "compoundId": "CHEBI:16977" },
+                        { "shortName": "coconut", "compoundId": "CNP0265585" }
+                    ]
+                }]
+            })))
+            .mount(&server)
+            .await;
+
+        let pubchem_url = format!("{}/rest/pug", server.uri());
+        let chembl_url = format!("{}/chembl", server.uri());
+        let unichem_url = format!("{}/unichem/", server.uri());
+        let resolvers = [
+            IdentifierResolver::new(IdentifierSource::Pubchem, Some(&pubchem_url)),
+            IdentifierResolver::new(IdentifierSource::Chebi, Some(&unichem_url)),
+            IdentifierResolver::new(IdentifierSource::Chembl, Some(&chembl_url)),
+            IdentifierResolver::new(IdentifierSource::Coconut, Some(&unichem_url)),
+        ];
+        let (identifiers, warnings) =
+            resolve_identifiers(inchikey, &resolvers, &reqwest::Client::new()).await;
+        assert!(warnings.is_empty(), "{:?}", warnings);
+        let described: Vec<String> = identifiers.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            described,
+            [
+                "PubChem:5950",
+                "ChEBI:16977",
+                "ChEMBL:CHEMBL279597",
+                "COCONUT:CNP0265585"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_resolve_identifiers_drops_disagreeing_and_missing_hits() {
+        let inchikey = "RYYVLZVUVIJVGH-UHFFFAOYSA-N";
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(format!(
+                "/compound/inchikey/{}/property/InChIKey/JSON",
+                inchikey
+            )))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "PropertyTable": { "Properties": [{
+                    "CID": 2519,
+                    "InChIKey": "RYYVLZVUVIJVGH-UHFFFAOYSA-O"
+                }]}
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/molecule.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "molecules": [] })))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/compounds"))
+            .respond_with(ResponseTemplate::new(503))
+            .mount(&server)
+            .await;
+
+        let resolvers = [
+            IdentifierResolver::new(IdentifierSource::Pubchem, Some(&server.uri())),
+            IdentifierResolver::new(IdentifierSource::Chembl, Some(&server.uri())),
+            IdentifierResolver::new(IdentifierSource::Chebi, Some(&server.uri())),
+        ];
+        let (identifiers, warnings) =
+            resolve_identifiers(inchikey, &resolvers, &reqwest::Client::new()).await;
+        assert!(identifiers.is_empty());
+        assert_eq!(warnings.len(), 2);
+        assert!(
+            warnings[0].contains(
+                "PubChem ID 2519 is registered with InChIKey RYYVLZVUVIJVGH-UHFFFAOYSA-O"
+            ),
+            "{}",
+            warnings[0]
+        );
+        assert!(
+            warnings[1].starts_with("ChEBI lookup failed"),
+            "{}",
+            warnings[1]
+        );
+    }
+}
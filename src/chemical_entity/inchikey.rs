@@ -0,0 +1,223 @@
+//! Standard InChIKey hashing, used to check that the InChIKey reported for a structure (the join
+//! key for Wikidata lookups and deduplication) really belongs to the InChI written as P234.
+//!
+//! The InChI after its `InChI=1S/` prefix is split into a main part (formula, connectivity,
+//! hydrogens, charge) and the remaining layers (stereo, isotopes, ...); the protonation layer is
+//! left out of both and encoded as the last character. Each part is hashed with SHA-256 and the
+//! leading bits are spelled out as base-26 letter triplets.
+use crate::chemical_entity::structure::ChemicalStructureData;
+use crate::error::{CrateError, Result};
+use sha2::{Digest, Sha256};
+
+/// Layers (after the formula) that belong to the first, connectivity block.
+const MAIN_LAYERS: [char; 3] = ['c', 'h', 'q'];
+
+/// The second block hashes the remaining layers twice over when they are this short.
+const MINOR_DUPLICATION_LIMIT: usize = 255;
+
+/// Bits `start..start + len` of `digest`, read least significant bit first.
+fn digest_bits(digest: &[u8], start: usize, len: usize) -> usize {
+    (0..len).fold(0, |value, offset| {
+        let bit = start + offset;
+        value | (usize::from((digest[bit / 8] >> (bit % 8)) & 1) << offset)
+    })
+}
+
+/// Letter triplet for a 14-bit value. The table runs through `AAA`..`ZZZ` without the triplets
+/// starting with `E` and without `TAA`..`TTV`.
+fn triplet(value: usize) -> [u8; 3] {
+    let mut remaining = value;
+    for first in b'A'..=b'Z' {
+        let (skipped, available) = match first {
+            b'E' => continue,
+            b'T' => (516, 160),
+            _ => (0, 676),
+        };
+        if remaining < available {
+            let rest = remaining + skipped;
+            return [first, b'A' + (rest / 26) as u8, b'A' + (rest % 26) as u8];
+        }
+        remaining -= available;
+    }
+    unreachable!("14-bit value {} outside the triplet table", value)
+}
+
+/// Letter pair for a 9-bit value.
+fn doublet(value: usize) -> [u8; 2] {
+    [b'A' + (value / 26) as u8, b'A' + (value % 26) as u8]
+}
+
+/// `triplets` letter triplets followed by a doublet from the SHA-256 of `text`.
+fn hash_block(text: &str, triplets: usize) -> String {
+    let digest = Sha256::digest(text.as_bytes());
+    let mut letters = Vec::with_capacity(triplets * 3 + 2);
+    for index in 0..triplets {
+        letters.extend(triplet(digest_bits(&digest, index * 14, 14)));
+    }
+    letters.extend(doublet(digest_bits(&digest, triplets * 14, 9)));
+    String::from_utf8(letters).expect("ASCII letters")
+}
+
+/// Protonation character: `N` for none, `O`/`P`/... for added and `M`/`L`/... for removed
+/// protons, `A` beyond twelve.
+fn protonation_flag(layer: Option<&str>) -> Option<char> {
+    let Some(layer) = layer else {
+        return Some('N');
+    };
+    let protons: i32 = layer.strip_prefix('+').unwrap_or(layer).parse().ok()?;
+    Some(if protons.abs() > 12 {
+        'A'
+    } else {
+        (b'N' as i32 + protons) as u8 as char
+    })
+}
+
+/// Computes the InChIKey of a standard (`InChI=1S/`) or non-standard (`InChI=1/`) InChI.
+/// Fails with the reason when the string is not a version 1 InChI, has nothing after its
+/// prefix, or has a protonation layer that is not a single proton count.
+pub fn inchikey_from_inchi(inchi: &str) -> Result<String> {
+    let inchi = inchi.trim();
+    let invalid = |message: &str| CrateError::InvalidFormat {
+        column: "inchi".to_string(),
+        value: inchi.to_string(),
+        message: message.to_string(),
+    };
+    let (standard, body) = if let Some(body) = inchi.strip_prefix("InChI=1S/") {
+        (true, body)
+    } else if let Some(body) = inchi.strip_prefix("InChI=1/") {
+        (false, body)
+    } else {
+        return Err(invalid("InChI must start with InChI=1S/ or InChI=1/."));
+    };
+    if body.is_empty() {
+        return Err(invalid("InChI has no layers after its prefix."));
+    }
+
+    let mut main = Vec::new();
+    let mut minor = String::new();
+    let mut protonation = None;
+    for (index, layer) in body.split('/').enumerate() {
+        let prefix = layer.chars().next();
+        let in_main = index > 0 && minor.is_empty();
+        if index == 0 || in_main && prefix.is_some_and(|prefix| MAIN_LAYERS.contains(&prefix)) {
+            main.push(layer);
+        } else if in_main && prefix == Some('p') && protonation.is_none() {
+            protonation = Some(&layer[1..]);
+        } else {
+            minor.push('/');
+            minor.push_str(layer);
+        }
+    }
+    if !minor.is_empty() && minor.len() < MINOR_DUPLICATION_LIMIT {
+        minor = minor.repeat(2);
+    }
+    let flag = protonation_flag(protonation).ok_or_else(|| {
+        invalid(&format!(
+            "Protonation layer '/p{}' is not a single proton count.",
+            protonation.unwrap_or_default()
+        ))
+    })?;
+
+    Ok(format!(
+        "{}-{}{}A-{}",
+        hash_block(&main.join("/"), 4),
+        hash_block(&minor, 2),
+        if standard { 'S' } else { 'N' },
+        flag
+    ))
+}
+
+/// Rejects structures whose InChIKey was not computed from their InChI. Structures missing
+/// either value pass unchanged.
+pub fn verify_inchikey(structure: &ChemicalStructureData) -> Result<()> {
+    let (Some(inchi), Some(inchikey)) = (&structure.inchi, &structure.inchikey) else {
+        return Ok(());
+    };
+    let computed = inchikey_from_inchi(inchi)?;
+    if computed != *inchikey {
+        return Err(CrateError::InchiKeyMismatch {
+            inchikey: inchikey.clone(),
+            computed,
+            inchi: inchi.clone(),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inchikey_from_inchi() {
+        let cases = [
+            ("InChI=1S/CH4/h1H4", "VNWKTOKETHGBQD-UHFFFAOYSA-N"),
+            (
+                "InChI=1S/C8H10N4O2/c1-10-4-9-6-5(10)7(13)12(3)8(14)11(6)2/h4H,1-3H3",
+                "RYYVLZVUVIJVGH-UHFFFAOYSA-N",
+            ),
+            (
+                "InChI=1S/C3H7NO2/c1-2(4)3(5)6/h2H,4H2,1H3,(H,5,6)/t2-/m0/s1",
+                "QNAYBMKLOCPYGJ-REOHCLBHSA-N",
+            ),
+            (
+                "InChI=1S/C10H14N2/c1-12-7-3-5-10(12)9-4-2-6-11-8-9/h2,4,6,8,10H,3,5,7H2,1H3/t10-/m0/s1",
+                "SNICXCGAKADSCV-JTQLQIEISA-N",
+            ),
+            ("InChI=1S/H3N/h1H3/p+1", "QGZKDVFQNNGYKY-UHFFFAOYSA-O"),
+        ];
+        for (inchi, inchikey) in cases {
+            assert_eq!(
+                inchikey_from_inchi(inchi).ok().as_deref(),
+                Some(inchikey),
+                "{}",
+                inchi
+            );
+        }
+    }
+
+    #[test]
+    fn test_inchikey_from_inchi_explains_failures() {
+        let message = |inchi: &str| match inchikey_from_inchi(inchi) {
+            Err(CrateError::InvalidFormat { message, .. }) => message,
+            other => panic!("expected an invalid InChI for {}, got {:?}", inchi, other),
+        };
+        assert_eq!(
+            message("CH4"),
+            "InChI must start with InChI=1S/ or InChI=1/."
+        );
+        assert_eq!(
+            message("InChI=1S/"),
+            "InChI has no layers after its prefix."
+        );
+        assert_eq!(
+            message("InChI=1S/C2H7N.CH2O2/c1-2-3;2-1-3/h2-3H2,1H3;1H,(H,2,3)/p+1;-1"),
+            "Protonation layer '/p+1;-1' is not a single proton count."
+        );
+    }
+
+    #[test]
+    fn test_verify_inchikey_rejects_mismatch() {
+        let mut methane = ChemicalStructureData {
+            sanitized_smiles: "C".to_string(),
+            smiles_were_sanitized: false,
+            canonical_smiles: Some("C".to_string()),
+            isomeric_smiles: None,
+            inchi: Some("InChI=1S/CH4/h1H4".to_string()),
+            inchikey: Some("VNWKTOKETHGBQD-UHFFFAOYSA-N".to_string()),
+            molecular_formula: None,
+            exact_mass: None,
+            other_descriptors: None,
+        };
+        assert!(verify_inchikey(&methane).is_ok());
+
+        methane.inchikey = Some("RYYVLZVUVIJVGH-UHFFFAOYSA-N".to_string());
+        let err = verify_inchikey(&methane).unwrap_err();
+        assert!(matches!(err, CrateError::InchiKeyMismatch { .. }));
+        assert!(err.to_string().contains("VNWKTOKETHGBQD-UHFFFAOYSA-N"));
+
+        methane.inchi = Some("InChI=1S/CH5N/h2H2,1H3/p+1;-1".to_string());
+        let err = verify_inchikey(&methane).unwrap_err();
+        assert!(err.to_string().contains("Protonation layer"), "{}", err);
+    }
+}
@@ -0,0 +1,304 @@
+//! Natural Products API backend (`--enricher np-api`, the default): COCONUT pre-processing
+//! for standardization plus the `/chem/descriptors` endpoint for formula and mass.
+use crate::chemical_entity::enricher::StructureEnricher;
+use crate::chemical_entity::structure::{ChemicalStructureData, validate_smiles_pair};
+use crate::error::{CrateError, Result};
+use futures::future::BoxFuture;
+use log::{info, warn};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Default base URL of the Natural Products API.
+pub const NP_API_BASE_URL: &str = "https://api.naturalproducts.net/latest";
+
+/// Enriches structures through the Natural Products API.
+#[derive(Debug, Clone)]
+pub struct NpApiEnricher {
+    base_url: String,
+}
+
+impl NpApiEnricher {
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self {
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+        }
+    }
+}
+
+impl Default for NpApiEnricher {
+    fn default() -> Self {
+        Self::new(NP_API_BASE_URL)
+    }
+}
+
+impl StructureEnricher for NpApiEnricher {
+    fn name(&self) -> &'static str {
+        "np-api"
+    }
+
+    fn enrich<'a>(
+        &'a self,
+        smiles: &'a str,
+        client: &'a reqwest::Client,
+    ) -> BoxFuture<'a, Result<ChemicalStructureData>> {
+        Box::pin(fetch_structure(&self.base_url, smiles, client))
+    }
+}
+
+async fn fetch_structure(
+    base_url: &str,
+    smiles: &str,
+    client: &reqwest::Client,
+) -> Result<ChemicalStructureData> {
+    let response = fetch_preprocessing(base_url, smiles, client).await?;
+
+    let standardized_smiles = response
+        .standardized
+        .representations
+        .canonical_smiles
+        .clone()
+        .ok_or_else(|| CrateError::SmilesSanitizationFailed {
+            input_smiles: smiles.to_string(),
+            reason: "Sanitization service returned no SMILES".to_string(),
+        })?;
+    let sanitized_smiles = standardized_smiles.clone();
+    let smiles_were_sanitized = sanitized_smiles != smiles;
+
+    if sanitized_smiles.is_empty() {
+        return Err(CrateError::SmilesSanitizationFailed {
+            input_smiles: smiles.to_string(),
+            reason: "Sanitized SMILES is empty".to_string(),
+        });
+    }
+
+    if sanitized_smiles != smiles {
+        info!(
+            "Sanitized SMILES differs from original: {} -> {}",
+            smiles, sanitized_smiles
+        );
+    }
+
+    let parental_canonical = response
+        .parent
+        .as_ref()
+        .and_then(|entry| entry.representations.canonical_smiles.clone());
+    let isomeric_smiles = if response.standardized.has_stereo_defined {
+        Some(standardized_smiles.clone())
+    } else {
+        None
+    };
+    let canonical_smiles = parental_canonical
+        .clone()
+        .unwrap_or_else(|| standardized_smiles.clone());
+    let canonical_smiles = Some(canonical_smiles);
+
+    let inchi = response.standardized.representations.standard_inchi.clone();
+    let inchikey = response
+        .standardized
+        .representations
+        .standard_inchikey
+        .clone();
+
+    if inchikey.as_deref().map(str::is_empty).unwrap_or(true) {
+        return Err(CrateError::MissingDescriptor {
+            descriptor: "inchikey".to_string(),
+            smiles: sanitized_smiles.clone(),
+        });
+    }
+
+    let descriptor_data = fetch_descriptors(base_url, &sanitized_smiles, client).await?;
+
+    let molecular_formula = descriptor_data
+        .as_ref()
+        .and_then(|desc| desc.molecular_formula.clone())
+        .or_else(|| {
+            response
+                .standardized
+                .descriptors
+                .as_ref()
+                .and_then(|map| map.get("molecular_formula"))
+                .and_then(|value| value.as_str())
+                .map(|s| s.to_string())
+        });
+    let exact_mass = descriptor_data
+        .as_ref()
+        .and_then(|desc| desc.exact_molecular_weight)
+        .or_else(|| {
+            response
+                .standardized
+                .descriptors
+                .as_ref()
+                .and_then(|map| map.get("exact_molecular_weight"))
+                .and_then(|value| value.as_f64())
+        });
+    let other_descriptors = descriptor_data.map(|desc| desc.other);
+
+    let (canonical_smiles, isomeric_smiles) =
+        validate_smiles_pair(canonical_smiles, isomeric_smiles)?;
+
+    Ok(ChemicalStructureData {
+        sanitized_smiles,
+        smiles_were_sanitized,
+        canonical_smiles,
+        isomeric_smiles,
+        inchi,
+        inchikey,
+        molecular_formula,
+        exact_mass,
+        other_descriptors,
+    })
+}
+
+#[derive(Debug, Deserialize)]
+struct PreprocessingResponse {
+    standardized: PreprocessingEntry,
+    parent: Option<PreprocessingEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PreprocessingEntry {
+    representations: PreprocessingRepresentations,
+    #[serde(default)]
+    descriptors: Option<HashMap<String, Value>>,
+    #[serde(default)]
+    has_stereo_defined: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct PreprocessingRepresentations {
+    #[serde(rename = "canonical_smiles")]
+    canonical_smiles: Option<String>,
+    #[serde(rename = "standard_inchi")]
+    standard_inchi: Option<String>,
+    #[serde(rename = "standard_inchikey")]
+    standard_inchikey: Option<String>,
+}
+
+async fn fetch_preprocessing(
+    base_url: &str,
+    smiles: &str,
+    client: &reqwest::Client,
+) -> Result<PreprocessingResponse> {
+    let url = format!("{}/chem/coconut/pre-processing", base_url);
+    info!("Running coconut pre-processing for SMILES: {}", smiles);
+
+    let response = client
+        .get(&url)
+        .query(&[("smiles", smiles)])
+        .send()
+        .await
+        .map_err(CrateError::ApiRequestError)?;
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = response
+            .text()
+            .await
+            .unwrap_or_else(|_| "<failed to read body>".to_string());
+        warn!(
+            "Pre-processing API call failed for {}: Status {} - {}",
+            smiles, status, body
+        );
+        return Err(CrateError::SmilesSanitizationFailed {
+            input_smiles: smiles.to_string(),
+            reason: format!("API returned status {}", status),
+        });
+    }
+
+    response
+        .json::<PreprocessingResponse>()
+        .await
+        .map_err(CrateError::ApiJsonDecodeError)
+}
+
+#[derive(Debug, Deserialize)]
+struct DescriptorsResponse {
+    molecular_formula: Option<String>,
+    exact_molecular_weight: Option<f64>,
+    #[serde(flatten)]
+    other: HashMap<String, Value>,
+}
+
+async fn fetch_descriptors(
+    base_url: &str,
+    smiles: &str,
+    client: &reqwest::Client,
+) -> Result<Option<DescriptorsResponse>> {
+    let url = format!("{}/chem/descriptors", base_url);
+
+    let response = client
+        .get(&url)
+        .query(&[("smiles", smiles)])
+        .send()
+        .await
+        .map_err(CrateError::ApiRequestError)?;
+
+    if !response.status().is_success() {
+        warn!(
+            "API call to /chem/descriptors failed for SMILES {}: Status {}",
+            smiles,
+            response.status()
+        );
+        return Ok(None);
+    }
+
+    match response.json::<DescriptorsResponse>().await {
+        Ok(data) => Ok(Some(data)),
+        Err(err) => {
+            warn!(
+                "Failed to decode JSON response from /chem/descriptors for SMILES {}: {}",
+                smiles, err
+            );
+            Err(CrateError::ApiJsonDecodeError(err))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use wiremock::matchers::{method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[tokio::test]
+    async fn test_np_api_enricher_uses_configured_base_url() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/latest/chem/coconut/pre-processing"))
+            .and(query_param("smiles", "C"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "original": { "representations": {} },
+                "standardized": {
+                    "representations": {
+                        "canonical_smiles": "C",
+                        "standard_inchi": "InChI=1S/CH4/h1H4",
+                        "standard_inchikey": "VNWKTOKETHGBQD-UHFFFAOYSA-N"
+                    },
+                    "has_stereo_defined": false
+                }
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/latest/chem/descriptors"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "molecular_formula": "CH4",
+                "exact_molecular_weight": 16.0313
+            })))
+            .mount(&server)
+            .await;
+
+        let enricher = NpApiEnricher::new(format!("{}/latest/", server.uri()));
+        let structure = enricher.enrich("C", &reqwest::Client::new()).await.unwrap();
+        assert_eq!(structure.canonical_smiles.as_deref(), Some("C"));
+        assert_eq!(structure.isomeric_smiles, None);
+        assert_eq!(
+            structure.inchikey.as_deref(),
+            Some("VNWKTOKETHGBQD-UHFFFAOYSA-N")
+        );
+        assert_eq!(structure.molecular_formula.as_deref(), Some("CH4"));
+        assert!(!structure.smiles_were_sanitized);
+    }
+}
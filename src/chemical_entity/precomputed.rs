@@ -0,0 +1,100 @@
+//! Precomputed backend (`--enricher precomputed`): structures standardized ahead of time (for
+//! example with RDKit) and stored in a TSV, so imports need no chemistry service at all.
+//!
+//! The table uses the [`DescriptorTable`] format; the backend only sees SMILES, so rows must be
+//! keyed by input SMILES (use `--descriptors` for row-keyed tables).
+use crate::chemical_entity::descriptors::DescriptorTable;
+use crate::chemical_entity::enricher::StructureEnricher;
+use crate::chemical_entity::structure::ChemicalStructureData;
+use crate::error::{CrateError, Result};
+use futures::future::BoxFuture;
+use std::path::Path;
+
+/// Looks structures up in a table loaded from disk.
+#[derive(Debug, Default)]
+pub struct PrecomputedEnricher {
+    table: DescriptorTable,
+}
+
+impl PrecomputedEnricher {
+    /// Loads a tab-separated structure table.
+    pub fn load(path: &Path) -> Result<Self> {
+        Ok(Self {
+            table: DescriptorTable::load(path)?,
+        })
+    }
+
+    /// Number of structures in the table.
+    pub fn len(&self) -> usize {
+        self.table.len()
+    }
+
+    /// Whether the table holds no structures.
+    pub fn is_empty(&self) -> bool {
+        self.table.is_empty()
+    }
+}
+
+impl StructureEnricher for PrecomputedEnricher {
+    fn name(&self) -> &'static str {
+        "precomputed"
+    }
+
+    // The table is already local; caching it would only let stale rows outlive edits.
+    fn cacheable(&self) -> bool {
+        false
+    }
+
+    fn enrich<'a>(
+        &'a self,
+        smiles: &'a str,
+        _client: &'a reqwest::Client,
+    ) -> BoxFuture<'a, Result<ChemicalStructureData>> {
+        let structure = self
+            .table
+            .lookup(None, smiles)
+            .unwrap_or_else(|| Err(CrateError::PrecomputedStructureMissing(smiles.to_string())));
+        Box::pin(async move { structure })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    #[tokio::test]
+    async fn test_precomputed_enricher_reads_table() {
+        let mut file = NamedTempFile::new().unwrap();
+        writeln!(
+            file,
+            "smiles\tcanonical_smiles\tisomeric_smiles\tinchikey\tmolecular_formula\texact_mass"
+        )
+        .unwrap();
+        writeln!(file, "C\tC\t\tVNWKTOKETHGBQD-UHFFFAOYSA-N\tCH4\t16.0313").unwrap();
+        writeln!(
+            file,
+            "N[C@@H](C)C(O)=O\tCC(C(=O)O)N\tC[C@@H](C(=O)O)N\tQNAYBMKLOCPYGJ-REOHCLBHSA-N\tC3H7NO2\t"
+        )
+        .unwrap();
+
+        let enricher = PrecomputedEnricher::load(file.path()).unwrap();
+        assert_eq!(enricher.len(), 2);
+        let client = reqwest::Client::new();
+
+        let methane = enricher.enrich("C", &client).await.unwrap();
+        assert!(!methane.smiles_were_sanitized);
+        assert_eq!(methane.exact_mass, Some(16.0313));
+
+        let alanine = enricher.enrich("N[C@@H](C)C(O)=O", &client).await.unwrap();
+        assert_eq!(alanine.sanitized_smiles, "C[C@@H](C(=O)O)N");
+        assert!(alanine.smiles_were_sanitized);
+        assert_eq!(alanine.exact_mass, None);
+
+        assert!(matches!(
+            enricher.enrich("O", &client).await,
+            Err(CrateError::PrecomputedStructureMissing(_))
+        ));
+    }
+}
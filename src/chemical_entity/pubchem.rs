@@ -0,0 +1,224 @@
+//! PubChem PUG-REST backend (`--enricher pubchem`): standardizes a structure by looking up
+//! the PubChem compound it maps to and reading its computed properties.
+use crate::chemical_entity::enricher::StructureEnricher;
+use crate::chemical_entity::structure::{ChemicalStructureData, validate_smiles_pair};
+use crate::error::{CrateError, Result};
+use futures::future::BoxFuture;
+use log::{info, warn};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+
+/// Default base URL of PubChem PUG-REST.
+pub const PUBCHEM_BASE_URL: &str = "https://pubchem.ncbi.nlm.nih.gov/rest/pug";
+
+const PROPERTIES: &str =
+    "CanonicalSMILES,IsomericSMILES,InChI,InChIKey,MolecularFormula,MonoisotopicMass";
+
+/// Enriches structures through PubChem PUG-REST.
+#[derive(Debug, Clone)]
+pub struct PubChemEnricher {
+    base_url: String,
+}
+
+impl PubChemEnricher {
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self {
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+        }
+    }
+}
+
+impl Default for PubChemEnricher {
+    fn default() -> Self {
+        Self::new(PUBCHEM_BASE_URL)
+    }
+}
+
+impl StructureEnricher for PubChemEnricher {
+    fn name(&self) -> &'static str {
+        "pubchem"
+    }
+
+    fn enrich<'a>(
+        &'a self,
+        smiles: &'a str,
+        client: &'a reqwest::Client,
+    ) -> BoxFuture<'a, Result<ChemicalStructureData>> {
+        Box::pin(fetch_structure(&self.base_url, smiles, client))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct PropertyResponse {
+    #[serde(rename = "PropertyTable")]
+    property_table: PropertyTable,
+}
+
+#[derive(Debug, Deserialize)]
+struct PropertyTable {
+    #[serde(rename = "Properties")]
+    properties: Vec<Map<String, Value>>,
+}
+
+/// Reads the first present string property; PubChem renamed `CanonicalSMILES` to
+/// `ConnectivitySMILES` and `IsomericSMILES` to `SMILES`, so both spellings are accepted.
+fn string_property(properties: &Map<String, Value>, names: &[&str]) -> Option<String> {
+    names
+        .iter()
+        .filter_map(|name| properties.get(*name))
+        .find_map(|value| value.as_str())
+        .filter(|value| !value.is_empty())
+        .map(str::to_string)
+}
+
+/// Masses come back as strings from current PubChem and as numbers from older releases.
+fn mass_property(properties: &Map<String, Value>) -> Option<f64> {
+    match properties.get("MonoisotopicMass")? {
+        Value::Number(number) => number.as_f64(),
+        Value::String(text) => text.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+async fn fetch_structure(
+    base_url: &str,
+    smiles: &str,
+    client: &reqwest::Client,
+) -> Result<ChemicalStructureData> {
+    let url = format!("{}/compound/smiles/property/{}/JSON", base_url, PROPERTIES);
+    info!("Looking up PubChem properties for SMILES: {}", smiles);
+
+    let response = client
+        .post(&url)
+        .form(&[("smiles", smiles)])
+        .send()
+        .await
+        .map_err(CrateError::ApiRequestError)?;
+
+    if !response.status().is_success() {
+        let status = response.status();
+        warn!(
+            "PubChem property lookup failed for {}: Status {}",
+            smiles, status
+        );
+        return Err(CrateError::ApiStatusError {
+            status,
+            smiles: smiles.to_string(),
+        });
+    }
+
+    let body = response
+        .json::<PropertyResponse>()
+        .await
+        .map_err(CrateError::ApiJsonDecodeError)?;
+    let properties = body
+        .property_table
+        .properties
+        .into_iter()
+        .next()
+        .ok_or_else(|| CrateError::SmilesSanitizationFailed {
+            input_smiles: smiles.to_string(),
+            reason: "PubChem returned no compound".to_string(),
+        })?;
+
+    let canonical_smiles = string_property(&properties, &["ConnectivitySMILES", "CanonicalSMILES"]);
+    let full_smiles = string_property(&properties, &["SMILES", "IsomericSMILES"]);
+    let sanitized_smiles = full_smiles
+        .clone()
+        .or_else(|| canonical_smiles.clone())
+        .ok_or_else(|| CrateError::SmilesSanitizationFailed {
+            input_smiles: smiles.to_string(),
+            reason: "PubChem returned no SMILES".to_string(),
+        })?;
+    let smiles_were_sanitized = sanitized_smiles != smiles;
+    // PubChem repeats the connectivity SMILES when there is no stereo or isotope information.
+    let isomeric_smiles = full_smiles.filter(|full| Some(full) != canonical_smiles.as_ref());
+    let canonical_smiles = canonical_smiles.or_else(|| Some(sanitized_smiles.clone()));
+
+    let inchi = string_property(&properties, &["InChI"]);
+    let inchikey = string_property(&properties, &["InChIKey"]);
+    if inchikey.is_none() {
+        return Err(CrateError::MissingDescriptor {
+            descriptor: "inchikey".to_string(),
+            smiles: sanitized_smiles,
+        });
+    }
+    let molecular_formula = string_property(&properties, &["MolecularFormula"]);
+    let exact_mass = mass_property(&properties);
+    let other_descriptors = properties
+        .get("CID")
+        .cloned()
+        .map(|cid| HashMap::from([("pubchem_cid".to_string(), cid)]));
+
+    let (canonical_smiles, isomeric_smiles) =
+        validate_smiles_pair(canonical_smiles, isomeric_smiles)?;
+
+    Ok(ChemicalStructureData {
+        sanitized_smiles,
+        smiles_were_sanitized,
+        canonical_smiles,
+        isomeric_smiles,
+        inchi,
+        inchikey,
+        molecular_formula,
+        exact_mass,
+        other_descriptors,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use wiremock::matchers::{body_string_contains, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[tokio::test]
+    async fn test_pubchem_enricher_reads_property_table() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path(format!(
+                "/rest/pug/compound/smiles/property/{}/JSON",
+                PROPERTIES
+            )))
+            .and(body_string_contains(
+                "smiles=C%5BC%40H%5D%28N%29C%28%3DO%29O",
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "PropertyTable": { "Properties": [{
+                    "CID": 5950,
+                    "MolecularFormula": "C3H7NO2",
+                    "ConnectivitySMILES": "CC(C(=O)O)N",
+                    "SMILES": "C[C@@H](C(=O)O)N",
+                    "InChI": "InChI=1S/C3H7NO2/c1-2(4)3(5)6/h2H,4H2,1H3,(H,5,6)/t2-/m0/s1",
+                    "InChIKey": "QNAYBMKLOCPYGJ-REOHCLBHSA-N",
+                    "MonoisotopicMass": "89.047678466"
+                }]}
+            })))
+            .mount(&server)
+            .await;
+
+        let enricher = PubChemEnricher::new(format!("{}/rest/pug", server.uri()));
+        let structure = enricher
+            .enrich("C[C@H](N)C(=O)O", &reqwest::Client::new())
+            .await
+            .unwrap();
+        assert_eq!(structure.sanitized_smiles, "C[C@@H](C(=O)O)N");
+        assert!(structure.smiles_were_sanitized);
+        assert_eq!(structure.canonical_smiles.as_deref(), Some("CC(C(=O)O)N"));
+        assert_eq!(
+            structure.isomeric_smiles.as_deref(),
+            Some("C[C@@H](C(=O)O)N")
+        );
+        assert_eq!(
+            structure.inchikey.as_deref(),
+            Some("QNAYBMKLOCPYGJ-REOHCLBHSA-N")
+        );
+        assert_eq!(structure.exact_mass, Some(89.047678466));
+        assert_eq!(
+            structure.other_descriptors.unwrap().get("pubchem_cid"),
+            Some(&json!(5950))
+        );
+    }
+}
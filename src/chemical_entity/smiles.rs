@@ -0,0 +1,769 @@
+//! Local SMILES parser (OpenSMILES syntax) used to reject malformed structures before any
+//! enrichment service is called.
+//!
+//! The parser builds the atom/bond graph (atoms, bonds, branches, ring closures, stereo marks,
+//! charges and isotopes) and [`validate_smiles`] additionally checks atom valences. Errors carry
+//! the 1-based character position of the offending symbol.
+use crate::chemical_entity::elements::{allowed_valences, element_symbol, is_metal};
+use crate::error::{CrateError, Result};
+use std::collections::BTreeMap;
+
+/// Bond multiplicity as written in the SMILES.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BondOrder {
+    Single,
+    Double,
+    Triple,
+    Quadruple,
+    Aromatic,
+}
+
+impl BondOrder {
+    /// Contribution to the valence of each bonded atom. Aromatic bonds count as single bonds;
+    /// the extra π bond is left to the valence check of aromatic atoms.
+    pub fn valence(self) -> u8 {
+        match self {
+            BondOrder::Single | BondOrder::Aromatic => 1,
+            BondOrder::Double => 2,
+            BondOrder::Triple => 3,
+            BondOrder::Quadruple => 4,
+        }
+    }
+}
+
+/// Direction of a `/` or `\` single bond (double-bond geometry).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BondDirection {
+    Up,
+    Down,
+}
+
+impl BondDirection {
+    /// The same bond read from its other end.
+    pub fn reversed(self) -> Self {
+        match self {
+            BondDirection::Up => BondDirection::Down,
+            BondDirection::Down => BondDirection::Up,
+        }
+    }
+}
+
+/// One atom of a parsed SMILES.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Atom {
+    /// Element symbol in canonical spelling (`"Cl"`), or `"*"` for a wildcard.
+    pub element: &'static str,
+    pub aromatic: bool,
+    /// Whether the atom was written in brackets (then `hydrogens` is exact).
+    pub bracket: bool,
+    pub isotope: Option<u16>,
+    /// Chirality mark such as `@`, `@@` or `@TH1`.
+    pub chirality: Option<String>,
+    /// Explicit hydrogen count of a bracket atom.
+    pub hydrogens: Option<u8>,
+    pub charge: i8,
+    pub class: Option<u32>,
+    /// 1-based character position of the atom symbol.
+    pub position: usize,
+}
+
+/// A bond between two atoms (indices into [`Molecule::atoms`]).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bond {
+    pub atoms: (usize, usize),
+    pub order: BondOrder,
+    /// Direction of a `/` or `\` bond, read from `atoms.0` to `atoms.1`.
+    pub direction: Option<BondDirection>,
+    /// 1-based position of the bond symbol, or of the closing ring digit for ring bonds.
+    pub position: usize,
+    /// 1-based positions of the opening and closing digits of a ring bond (`atoms.0` opened it).
+    pub ring_closure: Option<(usize, usize)>,
+}
+
+/// Atom/bond graph of a SMILES string.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Molecule {
+    pub atoms: Vec<Atom>,
+    pub bonds: Vec<Bond>,
+}
+
+impl Molecule {
+    /// Sum of the bond valences of `atom`.
+    pub fn bond_valence(&self, atom: usize) -> u8 {
+        self.bonds
+            .iter()
+            .filter(|bond| bond.atoms.0 == atom || bond.atoms.1 == atom)
+            .map(|bond| bond.order.valence())
+            .sum()
+    }
+
+    /// Hydrogens on `atom`: the explicit count of a bracket atom, otherwise the implicit
+    /// hydrogens that fill the lowest allowed valence (organic-subset rules).
+    pub fn hydrogen_count(&self, atom: usize) -> u8 {
+        let entry = &self.atoms[atom];
+        if entry.bracket {
+            return entry.hydrogens.unwrap_or(0);
+        }
+        let Some(allowed) = allowed_valences(entry.element, 0) else {
+            return 0;
+        };
+        let valence = self.bond_valence(atom);
+        if entry.aromatic {
+            // One valence goes to the aromatic π system.
+            return allowed[0].saturating_sub(valence + 1);
+        }
+        allowed
+            .iter()
+            .find(|candidate| **candidate >= valence)
+            .map_or(0, |candidate| candidate - valence)
+    }
+
+    /// Bond valence of `atom` without its bonds to metal atoms.
+    fn covalent_valence(&self, atom: usize) -> u8 {
+        self.bonds
+            .iter()
+            .filter_map(|bond| match bond.atoms {
+                (a, other) | (other, a) if a == atom => Some((other, bond.order)),
+                _ => None,
+            })
+            .filter(|(other, _)| !is_metal(self.atoms[*other].element))
+            .map(|(_, order)| order.valence())
+            .sum()
+    }
+
+    fn has_bond(&self, a: usize, b: usize) -> bool {
+        self.bonds
+            .iter()
+            .any(|bond| bond.atoms == (a, b) || bond.atoms == (b, a))
+    }
+
+    // Nitro groups are often written `N(=O)=O`; services normalize them to `[N+](=O)[O-]`.
+    fn is_uncharged_nitro(&self, atom: usize) -> bool {
+        let doubly_bonded_oxygens = self
+            .bonds
+            .iter()
+            .filter(|bond| bond.order == BondOrder::Double)
+            .filter_map(|bond| match bond.atoms {
+                (a, other) | (other, a) if a == atom => Some(other),
+                _ => None,
+            })
+            .filter(|other| self.atoms[*other].element == "O")
+            .count();
+        self.atoms[atom].element == "N"
+            && self.atoms[atom].charge == 0
+            && doubly_bonded_oxygens == 2
+    }
+}
+
+/// Bond symbol waiting for the atom (or ring digit) it leads to.
+#[derive(Debug, Clone, Copy)]
+struct BondSpec {
+    order: BondOrder,
+    direction: Option<BondDirection>,
+    position: usize,
+}
+
+/// Ring bond opened by a digit and not closed yet.
+#[derive(Debug, Clone, Copy)]
+struct OpenRing {
+    atom: usize,
+    bond: Option<BondSpec>,
+    position: usize,
+}
+
+struct Parser<'a> {
+    smiles: &'a str,
+    chars: Vec<char>,
+    index: usize,
+    molecule: Molecule,
+}
+
+impl<'a> Parser<'a> {
+    fn new(smiles: &'a str) -> Self {
+        Self {
+            smiles,
+            chars: smiles.chars().collect(),
+            index: 0,
+            molecule: Molecule::default(),
+        }
+    }
+
+    fn error(&self, position: usize, message: impl Into<String>) -> CrateError {
+        CrateError::InvalidSmiles {
+            smiles: self.smiles.to_string(),
+            position,
+            message: message.into(),
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.index).copied()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.index + offset).copied()
+    }
+
+    /// 1-based position of the next character.
+    fn position(&self) -> usize {
+        self.index + 1
+    }
+
+    fn parse(mut self) -> Result<Molecule> {
+        if self.chars.is_empty() {
+            return Err(self.error(1, "SMILES is empty"));
+        }
+        let mut previous: Option<usize> = None;
+        let mut pending: Option<BondSpec> = None;
+        // (atom the branch hangs from, position of '(', atom count when it opened)
+        let mut branches: Vec<(Option<usize>, usize, usize)> = Vec::new();
+        let mut rings: BTreeMap<u32, OpenRing> = BTreeMap::new();
+
+        while let Some(c) = self.peek() {
+            let position = self.position();
+            match c {
+                '-' | '=' | '#' | '$' | ':' | '/' | '\\' => {
+                    if previous.is_none() {
+                        return Err(
+                            self.error(position, format!("bond '{}' must follow an atom", c))
+                        );
+                    }
+                    if pending.is_some() {
+                        return Err(self.error(
+                            position,
+                            format!("bond '{}' directly follows another bond", c),
+                        ));
+                    }
+                    let (order, direction) = match c {
+                        '=' => (BondOrder::Double, None),
+                        '#' => (BondOrder::Triple, None),
+                        '$' => (BondOrder::Quadruple, None),
+                        ':' => (BondOrder::Aromatic, None),
+                        '/' => (BondOrder::Single, Some(BondDirection::Up)),
+                        '\\' => (BondOrder::Single, Some(BondDirection::Down)),
+                        _ => (BondOrder::Single, None),
+                    };
+                    pending = Some(BondSpec {
+                        order,
+                        direction,
+                        position,
+                    });
+                    self.index += 1;
+                }
+                '(' => {
+                    if previous.is_none() {
+                        return Err(self.error(position, "branch '(' must follow an atom"));
+                    }
+                    if let Some(bond) = pending {
+                        return Err(self.error(
+                            bond.position,
+                            "bond must be written inside the branch, after '('",
+                        ));
+                    }
+                    branches.push((previous, position, self.molecule.atoms.len()));
+                    self.index += 1;
+                }
+                ')' => {
+                    let Some((root, _, atom_count)) = branches.pop() else {
+                        return Err(self.error(position, "')' has no matching '('"));
+                    };
+                    if let Some(bond) = pending {
+                        return Err(self.error(bond.position, "bond is not followed by an atom"));
+                    }
+                    if self.molecule.atoms.len() == atom_count {
+                        return Err(self.error(position, "branch is empty"));
+                    }
+                    previous = root;
+                    self.index += 1;
+                }
+                '0'..='9' | '%' => {
+                    let Some(atom) = previous else {
+                        return Err(self.error(position, "ring-closure digit must follow an atom"));
+                    };
+                    let number = self.ring_number()?;
+                    let bond = pending.take();
+                    match rings.remove(&number) {
+                        Some(open) => self.close_ring(number, open, atom, bond, position)?,
+                        None => {
+                            rings.insert(
+                                number,
+                                OpenRing {
+                                    atom,
+                                    bond,
+                                    position,
+                                },
+                            );
+                        }
+                    }
+                }
+                '.' => {
+                    if previous.is_none() {
+                        return Err(self.error(position, "'.' must separate two fragments"));
+                    }
+                    if let Some(bond) = pending {
+                        return Err(self.error(bond.position, "bond is not followed by an atom"));
+                    }
+                    previous = None;
+                    self.index += 1;
+                }
+                _ => {
+                    let atom = self.atom()?;
+                    if let Some(previous) = previous {
+                        self.add_bond(previous, atom, pending.take(), position);
+                    } else if let Some(bond) = pending {
+                        return Err(self.error(bond.position, "bond must follow an atom"));
+                    }
+                    previous = Some(atom);
+                }
+            }
+        }
+
+        if let Some(bond) = pending {
+            return Err(self.error(bond.position, "bond is not followed by an atom"));
+        }
+        if let Some((_, position, _)) = branches.first() {
+            return Err(self.error(*position, "'(' is never closed"));
+        }
+        if let Some((number, open)) = rings.iter().min_by_key(|(_, open)| open.position) {
+            return Err(self.error(
+                open.position,
+                format!("ring bond {} is never closed", number),
+            ));
+        }
+        Ok(self.molecule)
+    }
+
+    fn ring_number(&mut self) -> Result<u32> {
+        let position = self.position();
+        if self.peek() == Some('%') {
+            let digits: String = [self.peek_at(1), self.peek_at(2)]
+                .into_iter()
+                .flatten()
+                .take_while(|c| c.is_ascii_digit())
+                .collect();
+            if digits.len() != 2 {
+                return Err(self.error(position, "'%' must be followed by two digits"));
+            }
+            self.index += 3;
+            return Ok(digits.parse().expect("two ASCII digits"));
+        }
+        let digit = self.peek().and_then(|c| c.to_digit(10)).expect("digit");
+        self.index += 1;
+        Ok(digit)
+    }
+
+    fn close_ring(
+        &mut self,
+        number: u32,
+        open: OpenRing,
+        atom: usize,
+        bond: Option<BondSpec>,
+        position: usize,
+    ) -> Result<()> {
+        if open.atom == atom {
+            return Err(self.error(
+                position,
+                format!("ring bond {} connects an atom to itself", number),
+            ));
+        }
+        if self.molecule.has_bond(open.atom, atom) {
+            return Err(self.error(
+                position,
+                format!("ring bond {} duplicates an existing bond", number),
+            ));
+        }
+        let bond = match (open.bond, bond) {
+            (Some(first), Some(second)) if first.order != second.order => {
+                return Err(self.error(
+                    position,
+                    format!(
+                        "ring bond {} has different bond orders at positions {} and {}",
+                        number, first.position, second.position
+                    ),
+                ));
+            }
+            // Directions are relative to the atom they are written next to, so only the
+            // closing symbol's direction is kept, turned around to read from the opening atom.
+            (_, Some(mut second)) => {
+                second.direction = second.direction.map(BondDirection::reversed);
+                Some(second)
+            }
+            (first, None) => first,
+        };
+        self.add_bond(open.atom, atom, bond, position);
+        if let Some(bond) = self.molecule.bonds.last_mut() {
+            bond.ring_closure = Some((open.position, position));
+        }
+        Ok(())
+    }
+
+    fn add_bond(&mut self, from: usize, to: usize, bond: Option<BondSpec>, position: usize) {
+        let aromatic = self.molecule.atoms[from].aromatic && self.molecule.atoms[to].aromatic;
+        let (order, direction, position) = match bond {
+            Some(bond) => (bond.order, bond.direction, bond.position),
+            None if aromatic => (BondOrder::Aromatic, None, position),
+            None => (BondOrder::Single, None, position),
+        };
+        self.molecule.bonds.push(Bond {
+            atoms: (from, to),
+            order,
+            direction,
+            position,
+            ring_closure: None,
+        });
+    }
+
+    fn atom(&mut self) -> Result<usize> {
+        let position = self.position();
+        let c = self.peek().expect("atom character");
+        let atom = if c == '[' {
+            self.bracket_atom()?
+        } else {
+            let (element, aromatic, length) = match (c, self.peek_at(1)) {
+                ('B', Some('r')) => ("Br", false, 2),
+                ('C', Some('l')) => ("Cl", false, 2),
+                ('B', _) => ("B", false, 1),
+                ('C', _) => ("C", false, 1),
+                ('N', _) => ("N", false, 1),
+                ('O', _) => ("O", false, 1),
+                ('P', _) => ("P", false, 1),
+                ('S', _) => ("S", false, 1),
+                ('F', _) => ("F", false, 1),
+                ('I', _) => ("I", false, 1),
+                ('*', _) => ("*", false, 1),
+                ('b', _) => ("B", true, 1),
+                ('c', _) => ("C", true, 1),
+                ('n', _) => ("N", true, 1),
+                ('o', _) => ("O", true, 1),
+                ('p', _) => ("P", true, 1),
+                ('s', _) => ("S", true, 1),
+                (c, _) if c.is_ascii_alphabetic() => {
+                    return Err(self.error(
+                        position,
+                        format!(
+                            "'{}' is not an organic-subset atom; write other elements in brackets",
+                            c
+                        ),
+                    ));
+                }
+                (c, _) => {
+                    return Err(self.error(position, format!("unexpected character '{}'", c)));
+                }
+            };
+            self.index += length;
+            Atom {
+                element,
+                aromatic,
+                bracket: false,
+                isotope: None,
+                chirality: None,
+                hydrogens: None,
+                charge: 0,
+                class: None,
+                position,
+            }
+        };
+        self.molecule.atoms.push(atom);
+        Ok(self.molecule.atoms.len() - 1)
+    }
+
+    fn digits(&mut self) -> Option<String> {
+        let digits: String = self.chars[self.index..]
+            .iter()
+            .take_while(|c| c.is_ascii_digit())
+            .collect();
+        self.index += digits.len();
+        (!digits.is_empty()).then_some(digits)
+    }
+
+    fn bracket_atom(&mut self) -> Result<Atom> {
+        let open = self.position();
+        self.index += 1;
+        let isotope_position = self.position();
+        let isotope = match self.digits() {
+            Some(digits) => Some(
+                digits
+                    .parse::<u16>()
+                    .map_err(|_| self.error(isotope_position, "isotope number is too large"))?,
+            ),
+            None => None,
+        };
+
+        let position = self.position();
+        let (element, aromatic) = self.bracket_symbol(position)?;
+
+        let mut chirality = None;
+        if self.peek() == Some('@') {
+            let start = self.index;
+            self.index += 1;
+            if self.peek() == Some('@') {
+                self.index += 1;
+            } else if let (Some(a), Some(b)) = (self.peek(), self.peek_at(1)) {
+                let class: String = [a, b].iter().collect();
+                if ["TH", "AL", "SP", "TB", "OH"].contains(&class.as_str()) {
+                    self.index += 2;
+                    if self.digits().is_none() {
+                        return Err(self.error(
+                            self.position(),
+                            format!("chirality class @{} needs a number", class),
+                        ));
+                    }
+                }
+            }
+            chirality = Some(self.chars[start..self.index].iter().collect());
+        }
+
+        let mut hydrogens = None;
+        if self.peek() == Some('H') {
+            self.index += 1;
+            let count = self.digits().map_or(Ok(1), |digits| digits.parse::<u8>());
+            hydrogens =
+                Some(count.map_err(|_| self.error(position, "hydrogen count is too large"))?);
+        }
+
+        let mut charge: i8 = 0;
+        if let Some(sign @ ('+' | '-')) = self.peek() {
+            let unit: i8 = if sign == '+' { 1 } else { -1 };
+            self.index += 1;
+            let magnitude = match self.digits() {
+                Some(digits) => digits
+                    .parse::<i8>()
+                    .map_err(|_| self.error(position, "charge is too large"))?,
+                None => {
+                    let mut repeated = 1;
+                    while self.peek() == Some(sign) {
+                        repeated += 1;
+                        self.index += 1;
+                    }
+                    repeated
+                }
+            };
+            charge = unit * magnitude;
+        }
+
+        let mut class = None;
+        if self.peek() == Some(':') {
+            self.index += 1;
+            let class_position = self.position();
+            let digits = self
+                .digits()
+                .ok_or_else(|| self.error(class_position, "atom class ':' needs a number"))?;
+            class = Some(
+                digits
+                    .parse()
+                    .map_err(|_| self.error(class_position, "atom class is too large"))?,
+            );
+        }
+
+        match self.peek() {
+            Some(']') => self.index += 1,
+            Some(c) => {
+                return Err(self.error(
+                    self.position(),
+                    format!("unexpected '{}' in bracket atom", c),
+                ));
+            }
+            None => return Err(self.error(open, "'[' is never closed")),
+        }
+
+        Ok(Atom {
+            element,
+            aromatic,
+            bracket: true,
+            isotope,
+            chirality,
+            hydrogens,
+            charge,
+            class,
+            position,
+        })
+    }
+
+    fn bracket_symbol(&mut self, position: usize) -> Result<(&'static str, bool)> {
+        let first = match self.peek() {
+            Some(']') | None => {
+                return Err(self.error(position, "bracket atom has no element symbol"));
+            }
+            Some(c) => c,
+        };
+        if first == '*' {
+            self.index += 1;
+            return Ok(("*", false));
+        }
+        if first.is_ascii_lowercase() {
+            // Aromatic symbols: b, c, n, o, p, s, se, as.
+            for (symbol, element) in [("se", "Se"), ("as", "As")] {
+                if self.chars[self.index..].starts_with(&symbol.chars().collect::<Vec<_>>()) {
+                    self.index += 2;
+                    return Ok((element, true));
+                }
+            }
+            let element = match first {
+                'b' => "B",
+                'c' => "C",
+                'n' => "N",
+                'o' => "O",
+                'p' => "P",
+                's' => "S",
+                _ => {
+                    return Err(self.error(
+                        position,
+                        format!("'{}' is not an aromatic element symbol", first),
+                    ));
+                }
+            };
+            self.index += 1;
+            return Ok((element, true));
+        }
+        if first.is_ascii_uppercase() {
+            if let Some(second) = self.peek_at(1).filter(char::is_ascii_lowercase) {
+                let symbol: String = [first, second].iter().collect();
+                if let Some(element) = element_symbol(&symbol) {
+                    self.index += 2;
+                    return Ok((element, false));
+                }
+            }
+            if let Some(element) = element_symbol(&first.to_string()) {
+                self.index += 1;
+                return Ok((element, false));
+            }
+            let unknown: String = self.chars[self.index..]
+                .iter()
+                .take(2)
+                .take_while(|c| c.is_ascii_alphabetic())
+                .collect();
+            return Err(self.error(position, format!("unknown element '{}'", unknown)));
+        }
+        Err(self.error(
+            position,
+            format!(
+                "unexpected '{}' where an element symbol was expected",
+                first
+            ),
+        ))
+    }
+}
+
+/// Parses `smiles` into its atom/bond graph, checking syntax only.
+pub fn parse_smiles(smiles: &str) -> Result<Molecule> {
+    Parser::new(smiles).parse()
+}
+
+/// Parses `smiles` and checks that no atom exceeds its allowed valence.
+pub fn validate_smiles(smiles: &str) -> Result<Molecule> {
+    let molecule = parse_smiles(smiles)?;
+    match valence_errors(smiles, &molecule).into_iter().next() {
+        Some(error) => Err(error),
+        None => Ok(molecule),
+    }
+}
+
+/// One positioned error per atom of `molecule` (parsed from `smiles`) that exceeds its allowed
+/// valence. Bonds to metals are left out of the count, and uncharged nitro groups are accepted.
+pub fn valence_errors(smiles: &str, molecule: &Molecule) -> Vec<CrateError> {
+    let mut errors = Vec::new();
+    for (index, atom) in molecule.atoms.iter().enumerate() {
+        let Some(allowed) = allowed_valences(atom.element, atom.charge) else {
+            continue;
+        };
+        let valence = molecule.covalent_valence(index) + atom.hydrogens.unwrap_or(0);
+        let maximum = *allowed.last().expect("at least one valence");
+        if valence > maximum && !molecule.is_uncharged_nitro(index) {
+            let charge = match atom.charge {
+                0 => String::new(),
+                charge => format!(" with charge {:+}", charge),
+            };
+            errors.push(CrateError::InvalidSmiles {
+                smiles: smiles.to_string(),
+                position: atom.position,
+                message: format!(
+                    "{}{} has valence {} but allows at most {}",
+                    atom.element, charge, valence, maximum
+                ),
+            });
+        }
+    }
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error_of(smiles: &str) -> (usize, String) {
+        match validate_smiles(smiles) {
+            Err(CrateError::InvalidSmiles {
+                position, message, ..
+            }) => (position, message),
+            other => panic!(
+                "expected an invalid SMILES error for {}, got {:?}",
+                smiles, other
+            ),
+        }
+    }
+
+    #[test]
+    fn test_parses_real_structures() {
+        let caffeine = validate_smiles("CN1C=NC2=C1C(=O)N(C(=O)N2C)C").unwrap();
+        assert_eq!(caffeine.atoms.len(), 14);
+        assert_eq!(caffeine.bonds.len(), 15);
+        for smiles in [
+            "c1ccccc1",
+            "C[C@@H](C(=O)O)N",
+            "Cl/C=C/Cl",
+            "[13CH4]",
+            "[NH4+].[Cl-]",
+            "c1cc[nH]c1",
+            "CN(=O)=O",
+            "C[N+](C)(C)C",
+            "OS(=O)(=O)O",
+            "C%10CC%10",
+            "[O-][n+]1ccccc1",
+            "[Fe+2]",
+            "[C@TH1H](F)(Cl)Br",
+        ] {
+            assert!(validate_smiles(smiles).is_ok(), "{} should parse", smiles);
+        }
+        let alanine = parse_smiles("C[C@@H](C(=O)O)N").unwrap();
+        assert_eq!(alanine.atoms[1].chirality.as_deref(), Some("@@"));
+        assert_eq!(alanine.atoms[1].hydrogens, Some(1));
+        let ammonium = parse_smiles("[15NH4+]").unwrap();
+        assert_eq!(ammonium.atoms[0].isotope, Some(15));
+        assert_eq!(ammonium.atoms[0].charge, 1);
+    }
+
+    #[test]
+    fn test_reports_positions() {
+        assert_eq!(error_of("C1CC").0, 2);
+        assert_eq!(error_of("CC(C").0, 3);
+        assert_eq!(error_of("CC)C").0, 3);
+        assert_eq!(error_of("CC()C").0, 4);
+        assert_eq!(error_of("CC=").0, 3);
+        assert_eq!(error_of("C[Xx]C").0, 3);
+        assert_eq!(error_of("C[CH4").0, 2);
+        assert_eq!(error_of("CC?").0, 3);
+        assert_eq!(error_of("C=1CCC#1").0, 8);
+        assert_eq!(error_of("NaCl").0, 2);
+        let (position, message) = error_of("CC(C)(C)(C)C");
+        assert_eq!(position, 2);
+        assert!(message.contains("valence 5"), "{}", message);
+        assert_eq!(error_of("O=N(=O)(=O)C").0, 3);
+        assert_eq!(error_of("[CH5]").0, 2);
+    }
+
+    #[test]
+    fn test_bonds_to_metals_do_not_count() {
+        assert!(validate_smiles("[Pt](Cl)(Cl)([NH3])[NH3]").is_ok());
+        assert!(validate_smiles("O=C=[Fe](=C=O)(=C=O)(=C=O)=C=O").is_ok());
+        let molecule = parse_smiles("C(C)(C)(C)(C)C.[NH4]N").unwrap();
+        let errors = valence_errors("C(C)(C)(C)(C)C.[NH4]N", &molecule);
+        let positions: Vec<usize> = errors
+            .iter()
+            .map(|error| match error {
+                CrateError::InvalidSmiles { position, .. } => *position,
+                other => panic!("unexpected error {:?}", other),
+            })
+            .collect();
+        assert_eq!(positions, vec![1, 17]);
+    }
+}
@@ -0,0 +1,617 @@
+//! Stereo descriptors read from the InChI stereo layers (`/b`, `/t`, `/m`), used to compare
+//! structures that share an InChIKey connectivity block, and the stereo completeness of a SMILES
+//! ([`StereoClass`]), used to pick the class of new chemical items.
+//!
+//! Atom numbers in the InChI layers are canonical for the connectivity, so two InChIs with the
+//! same first InChIKey block can be compared entry by entry.
+use crate::chemical_entity::smiles::{BondOrder, Molecule, parse_smiles};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+
+/// Second InChIKey block of structures without stereo, isotope or fixed-H layers.
+const NO_STEREO_BLOCK: &str = "UHFFFAOY";
+
+/// How a structure's stereo descriptors relate to those of another one with the same skeleton.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StereoRelation {
+    /// Identical stereo descriptors.
+    Same,
+    /// Defines everything the other one defines, and more.
+    MoreSpecific,
+    /// Defines a subset of what the other one defines.
+    LessSpecific,
+    /// Conflicting descriptors: another stereoisomer.
+    Different,
+}
+
+/// Defined double-bond and stereocentre parities of one structure.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StereoLayers {
+    /// Parity (`+`/`-`) per double bond (`b:<atoms>`) and stereocentre (`t:<atom>`), each
+    /// prefixed with its component number; centres are already inverted when `/m1` says so.
+    defined: BTreeMap<String, char>,
+    /// Stereocentre parities of relative (`/s2`) or racemic (`/s3`) stereo, keyed like
+    /// `defined`: they fix the centres against each other but not the absolute configuration,
+    /// so the set and its mirror image describe the same thing.
+    relative: BTreeMap<String, char>,
+    /// Number of stereo elements marked `?` (present but undefined).
+    undefined: usize,
+}
+
+impl StereoLayers {
+    /// Reads the main stereo layers of an InChI (isotopic and fixed-H layers are ignored).
+    /// Returns `None` for strings that are not InChIs.
+    pub fn from_inchi(inchi: &str) -> Option<Self> {
+        let body = inchi.trim().strip_prefix("InChI=")?;
+        let mut layers = Self::default();
+        let mut bonds = None;
+        let mut centres = None;
+        let mut inverted: Vec<bool> = Vec::new();
+        let mut absolute = true;
+        for layer in body.split('/').skip(2) {
+            match layer.split_at_checked(1) {
+                Some(("b", value)) => bonds = Some(value),
+                Some(("t", value)) => centres = Some(value),
+                Some(("m", value)) => inverted = value.split('.').map(|m| m == "1").collect(),
+                Some(("s", value)) => absolute = value == "1",
+                Some(("i" | "f" | "r", _)) => break,
+                _ => {}
+            }
+        }
+        for (component, value) in bonds.into_iter().flat_map(|v| v.split(';').enumerate()) {
+            layers.add_entries("b", component, value, false, false);
+        }
+        for (component, value) in centres.into_iter().flat_map(|v| v.split(';').enumerate()) {
+            // `/m` only picks the enantiomer of absolute stereo.
+            let invert = absolute
+                && inverted
+                    .get(component)
+                    .or(inverted.first())
+                    .copied()
+                    .unwrap_or(false);
+            layers.add_entries("t", component, value, invert, !absolute);
+        }
+        Some(layers)
+    }
+
+    /// Stereo descriptors implied by an InChIKey alone: only keys whose second block shows no
+    /// stereo or isotope layers can be read (as "no stereo defined").
+    pub fn from_inchikey(inchikey: &str) -> Option<Self> {
+        let (_, rest) = inchikey.split_once('-')?;
+        rest.starts_with(NO_STEREO_BLOCK).then(Self::default)
+    }
+
+    fn add_entries(
+        &mut self,
+        kind: &str,
+        component: usize,
+        value: &str,
+        invert: bool,
+        relative: bool,
+    ) {
+        for entry in value.split(',').filter(|entry| !entry.is_empty()) {
+            let Some(parity) = entry.chars().last() else {
+                continue;
+            };
+            let atoms = &entry[..entry.len() - 1];
+            let parity = match (parity, invert) {
+                ('?', _) => {
+                    self.undefined += 1;
+                    continue;
+                }
+                ('+', true) => '-',
+                ('-', true) => '+',
+                ('+' | '-', false) => parity,
+                _ => continue,
+            };
+            let entries = if relative {
+                &mut self.relative
+            } else {
+                &mut self.defined
+            };
+            entries.insert(format!("{}{}:{}", kind, component, atoms), parity);
+        }
+    }
+
+    /// Number of stereo elements with a defined (absolute) parity.
+    pub fn defined_count(&self) -> usize {
+        self.defined.len()
+    }
+
+    /// Number of stereocentres defined only relative to each other (`/s2` or `/s3`).
+    pub fn relative_count(&self) -> usize {
+        self.relative.len()
+    }
+
+    /// Number of stereo elements present in the InChI but left undefined (`?`).
+    pub fn undefined_count(&self) -> usize {
+        self.undefined
+    }
+
+    /// Whether everything `self` defines is defined the same way by `other`. Relative centres
+    /// are matched against the relative or absolute centres of `other`, as a whole or as a
+    /// whole mirrored.
+    fn is_subset_of(&self, other: &Self) -> bool {
+        let absolute = self
+            .defined
+            .iter()
+            .all(|(element, parity)| other.defined.get(element) == Some(parity));
+        let matches = |mirrored: bool| {
+            self.relative.iter().all(|(element, parity)| {
+                let theirs = other
+                    .relative
+                    .get(element)
+                    .or_else(|| other.defined.get(element));
+                theirs.is_some_and(|theirs| (theirs == parity) != mirrored)
+            })
+        };
+        absolute && (matches(false) || matches(true))
+    }
+
+    /// How `self` relates to `other`, assuming both share the same connectivity.
+    pub fn relation_to(&self, other: &Self) -> StereoRelation {
+        match (self.is_subset_of(other), other.is_subset_of(self)) {
+            (true, true) => StereoRelation::Same,
+            (true, false) => StereoRelation::LessSpecific,
+            (false, true) => StereoRelation::MoreSpecific,
+            (false, false) => StereoRelation::Different,
+        }
+    }
+}
+
+/// Double bonds in smaller rings cannot be trans, so their geometry is fixed by the ring.
+const MIN_STEREO_RING_SIZE: usize = 8;
+
+/// How completely the stereo elements (stereocentres and stereogenic double bonds) of a
+/// structure are specified.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StereoClass {
+    /// Every stereo element is specified, or the structure has none.
+    Defined,
+    /// Some stereo elements are specified, others are not.
+    PartiallyDefined,
+    /// None of the stereo elements is specified.
+    Undefined,
+}
+
+impl StereoClass {
+    /// Classifies a parsed SMILES.
+    ///
+    /// Stereocentres are tetrahedral C/Si (and cationic N/P) atoms with four distinct
+    /// substituents, or ring atoms whose cis/trans arrangement matters although their two ring
+    /// paths look alike (see [`ring_stereocentres`]); stereogenic double bonds are C=C/C=N bonds
+    /// outside small rings whose ends each carry distinct substituents. Substituents are
+    /// compared by their symmetry classes; every atom written with a chirality mark counts as a
+    /// specified stereo element.
+    pub fn from_molecule(molecule: &Molecule) -> Self {
+        let neighbours = neighbours(molecule);
+        let classes = symmetry_classes(molecule, &neighbours);
+        let ring_centres = ring_stereocentres(molecule, &neighbours, &classes);
+        let mut defined = 0;
+        let mut undefined = 0;
+        for (atom, in_ring_pair) in ring_centres.into_iter().enumerate() {
+            if molecule.atoms[atom].chirality.is_some() {
+                defined += 1;
+            } else if in_ring_pair || is_stereocentre(molecule, &neighbours, &classes, atom) {
+                undefined += 1;
+            }
+        }
+        for (index, bond) in molecule.bonds.iter().enumerate() {
+            if !is_stereogenic_double_bond(molecule, &neighbours, &classes, index) {
+                continue;
+            }
+            let (a, b) = bond.atoms;
+            let marked = |end: usize| {
+                neighbours[end]
+                    .iter()
+                    .any(|&(_, other)| other != index && molecule.bonds[other].direction.is_some())
+            };
+            if marked(a) && marked(b) {
+                defined += 1;
+            } else {
+                undefined += 1;
+            }
+        }
+        match (defined, undefined) {
+            (_, 0) => StereoClass::Defined,
+            (0, _) => StereoClass::Undefined,
+            _ => StereoClass::PartiallyDefined,
+        }
+    }
+
+    /// Classifies `smiles`, or `None` if it cannot be parsed.
+    pub fn from_smiles(smiles: &str) -> Option<Self> {
+        parse_smiles(smiles)
+            .ok()
+            .map(|molecule| Self::from_molecule(&molecule))
+    }
+}
+
+/// `(neighbour atom, bond index)` pairs of every atom.
+pub(super) fn neighbours(molecule: &Molecule) -> Vec<Vec<(usize, usize)>> {
+    let mut neighbours = vec![Vec::new(); molecule.atoms.len()];
+    for (index, bond) in molecule.bonds.iter().enumerate() {
+        neighbours[bond.atoms.0].push((bond.atoms.1, index));
+        neighbours[bond.atoms.1].push((bond.atoms.0, index));
+    }
+    neighbours
+}
+
+fn order_code(order: BondOrder) -> u8 {
+    match order {
+        BondOrder::Single => 1,
+        BondOrder::Double => 2,
+        BondOrder::Triple => 3,
+        BondOrder::Quadruple => 4,
+        BondOrder::Aromatic => 5,
+    }
+}
+
+/// Dense ranks (`0..`) of `keys`, equal keys sharing a rank.
+fn dense_ranks<T: Ord>(keys: &[T]) -> Vec<usize> {
+    let mut sorted: Vec<&T> = keys.iter().collect();
+    sorted.sort();
+    sorted.dedup();
+    keys.iter()
+        .map(|key| sorted.binary_search(&key).expect("key is ranked"))
+        .collect()
+}
+
+/// Symmetry classes from atom invariants refined by the classes of the neighbours until the
+/// partition stops splitting (Morgan-style).
+fn symmetry_classes(molecule: &Molecule, neighbours: &[Vec<(usize, usize)>]) -> Vec<usize> {
+    let invariants: Vec<_> = molecule
+        .atoms
+        .iter()
+        .enumerate()
+        .map(|(index, atom)| {
+            (
+                atom.element,
+                atom.isotope,
+                atom.charge,
+                atom.aromatic,
+                molecule.hydrogen_count(index),
+                neighbours[index].len(),
+            )
+        })
+        .collect();
+    refine_classes(dense_ranks(&invariants), neighbours, |bond| {
+        order_code(molecule.bonds[bond].order)
+    })
+}
+
+/// Symmetry classes that ignore bond orders and aromaticity, so that Kekulé and aromatic
+/// notations of the same structure get the same classes.
+pub(super) fn constitution_classes(
+    molecule: &Molecule,
+    neighbours: &[Vec<(usize, usize)>],
+) -> Vec<usize> {
+    let invariants: Vec<_> = molecule
+        .atoms
+        .iter()
+        .enumerate()
+        .map(|(index, atom)| {
+            (
+                atom.element,
+                atom.isotope,
+                atom.charge,
+                molecule.hydrogen_count(index),
+                neighbours[index].len(),
+            )
+        })
+        .collect();
+    refine_classes(dense_ranks(&invariants), neighbours, |_| 0)
+}
+
+fn refine_classes(
+    mut classes: Vec<usize>,
+    neighbours: &[Vec<(usize, usize)>],
+    bond_code: impl Fn(usize) -> u8,
+) -> Vec<usize> {
+    let class_count = |classes: &[usize]| classes.iter().max().map_or(0, |max| max + 1);
+    loop {
+        let keys: Vec<_> = (0..classes.len())
+            .map(|atom| {
+                let mut around: Vec<(u8, usize)> = neighbours[atom]
+                    .iter()
+                    .map(|&(other, bond)| (bond_code(bond), classes[other]))
+                    .collect();
+                around.sort_unstable();
+                (classes[atom], around)
+            })
+            .collect();
+        let refined = dense_ranks(&keys);
+        if class_count(&refined) == class_count(&classes) {
+            return refined;
+        }
+        classes = refined;
+    }
+}
+
+/// Whether the classes are pairwise distinct, implicit hydrogens (`None`) included.
+pub(super) fn all_distinct(substituents: &[Option<usize>]) -> bool {
+    substituents
+        .iter()
+        .enumerate()
+        .all(|(index, class)| !substituents[..index].contains(class))
+}
+
+pub(super) fn is_stereocentre(
+    molecule: &Molecule,
+    neighbours: &[Vec<(usize, usize)>],
+    classes: &[usize],
+    atom: usize,
+) -> bool {
+    tetrahedral_substituents(molecule, neighbours, classes, atom)
+        .is_some_and(|substituents| all_distinct(&substituents))
+}
+
+/// Classes of the four substituents of a tetrahedral atom, in neighbour order followed by the
+/// hydrogen (`None`), or `None` if the atom cannot be a stereocentre.
+fn tetrahedral_substituents(
+    molecule: &Molecule,
+    neighbours: &[Vec<(usize, usize)>],
+    classes: &[usize],
+    atom: usize,
+) -> Option<Vec<Option<usize>>> {
+    let entry = &molecule.atoms[atom];
+    let tetrahedral = matches!(
+        (entry.element, entry.charge),
+        ("C" | "Si", 0) | ("N" | "P", 1)
+    );
+    if !tetrahedral
+        || neighbours[atom]
+            .iter()
+            .any(|&(_, bond)| molecule.bonds[bond].order != BondOrder::Single)
+    {
+        return None;
+    }
+    let hydrogens = usize::from(molecule.hydrogen_count(atom));
+    if hydrogens > 1 || neighbours[atom].len() + hydrogens != 4 {
+        return None;
+    }
+    let mut substituents: Vec<Option<usize>> = neighbours[atom]
+        .iter()
+        .map(|&(other, _)| Some(classes[other]))
+        .collect();
+    substituents.extend((0..hydrogens).map(|_| None));
+    Some(substituents)
+}
+
+/// Ring atoms that are stereo elements through cis/trans isomerism of their ring, although
+/// their two ring paths have the same symmetry class: the two CH(OH) carbons of
+/// cyclohexane-1,4-diol, every carbon of inositol, the bridgeheads of decalin.
+///
+/// Such an atom has exactly one pair of alike substituents, both reached through ring bonds,
+/// and two other distinct ones. A lone atom of this kind (methylcyclohexane) has no stereo; it
+/// only becomes one together with another such atom in the same ring system.
+fn ring_stereocentres(
+    molecule: &Molecule,
+    neighbours: &[Vec<(usize, usize)>],
+    classes: &[usize],
+) -> Vec<bool> {
+    let ring_bonds: Vec<bool> = molecule
+        .bonds
+        .iter()
+        .enumerate()
+        .map(|(index, bond)| {
+            smallest_ring_size(neighbours, index, bond.atoms.0, bond.atoms.1).is_some()
+        })
+        .collect();
+    let is_candidate = |atom: usize| {
+        let Some(substituents) = tetrahedral_substituents(molecule, neighbours, classes, atom)
+        else {
+            return false;
+        };
+        let mut alike = Vec::new();
+        for (i, class) in substituents.iter().enumerate() {
+            for (j, other) in substituents.iter().enumerate().skip(i + 1) {
+                if class == other {
+                    alike.push((i, j));
+                }
+            }
+        }
+        let through_ring = |position: usize| {
+            neighbours[atom]
+                .get(position)
+                .is_some_and(|&(_, bond)| ring_bonds[bond])
+        };
+        matches!(alike.as_slice(), [(i, j)] if through_ring(*i) && through_ring(*j))
+    };
+
+    // Ring systems: atoms joined by ring bonds.
+    let mut system: Vec<usize> = (0..molecule.atoms.len()).collect();
+    fn root(system: &mut [usize], mut atom: usize) -> usize {
+        while system[atom] != atom {
+            system[atom] = system[system[atom]];
+            atom = system[atom];
+        }
+        atom
+    }
+    for (bond, is_ring) in molecule.bonds.iter().zip(&ring_bonds) {
+        if *is_ring {
+            let (a, b) = (
+                root(&mut system, bond.atoms.0),
+                root(&mut system, bond.atoms.1),
+            );
+            system[a] = b;
+        }
+    }
+
+    let candidates: Vec<bool> = (0..molecule.atoms.len()).map(is_candidate).collect();
+    let mut per_system: BTreeMap<usize, usize> = BTreeMap::new();
+    for atom in (0..molecule.atoms.len()).filter(|&atom| candidates[atom]) {
+        *per_system.entry(root(&mut system, atom)).or_default() += 1;
+    }
+    (0..molecule.atoms.len())
+        .map(|atom| candidates[atom] && per_system[&root(&mut system, atom)] >= 2)
+        .collect()
+}
+
+pub(super) fn is_stereogenic_double_bond(
+    molecule: &Molecule,
+    neighbours: &[Vec<(usize, usize)>],
+    classes: &[usize],
+    index: usize,
+) -> bool {
+    let bond = &molecule.bonds[index];
+    if bond.order != BondOrder::Double {
+        return false;
+    }
+    let (a, b) = bond.atoms;
+    let end_is_stereogenic = |end: usize| {
+        let atom = &molecule.atoms[end];
+        if atom.aromatic || !matches!(atom.element, "C" | "N") {
+            return false;
+        }
+        let others: Vec<&(usize, usize)> = neighbours[end]
+            .iter()
+            .filter(|&&(_, other)| other != index)
+            .collect();
+        if others.is_empty()
+            || others
+                .iter()
+                .any(|&&(_, other)| molecule.bonds[other].order != BondOrder::Single)
+        {
+            return false;
+        }
+        let mut substituents: Vec<Option<usize>> = others
+            .iter()
+            .map(|&&(other, _)| Some(classes[other]))
+            .collect();
+        substituents.extend((0..molecule.hydrogen_count(end)).map(|_| None));
+        substituents.len() <= 2 && all_distinct(&substituents)
+    };
+    end_is_stereogenic(a)
+        && end_is_stereogenic(b)
+        && smallest_ring_size(neighbours, index, a, b)
+            .is_none_or(|size| size >= MIN_STEREO_RING_SIZE)
+}
+
+/// Size of the smallest ring through bond `index` (between `from` and `to`), if any.
+fn smallest_ring_size(
+    neighbours: &[Vec<(usize, usize)>],
+    index: usize,
+    from: usize,
+    to: usize,
+) -> Option<usize> {
+    let mut distance = vec![None; neighbours.len()];
+    distance[from] = Some(0);
+    let mut queue = VecDeque::from([from]);
+    while let Some(atom) = queue.pop_front() {
+        let next = distance[atom].map_or(0, |d| d + 1);
+        for &(other, bond) in &neighbours[atom] {
+            if bond == index || distance[other].is_some() {
+                continue;
+            }
+            if other == to {
+                return Some(next + 1);
+            }
+            distance[other] = Some(next);
+            queue.push_back(other);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALANINE: &str = "InChI=1S/C3H7NO2/c1-2(4)3(5)6/h2H,4H2,1H3,(H,5,6)";
+
+    #[test]
+    fn test_stereo_relations() {
+        let racemic = StereoLayers::from_inchi(ALANINE).unwrap();
+        let l_alanine = StereoLayers::from_inchi(&format!("{}/t2-/m0/s1", ALANINE)).unwrap();
+        let d_alanine = StereoLayers::from_inchi(&format!("{}/t2-/m1/s1", ALANINE)).unwrap();
+        assert_eq!(racemic.defined_count(), 0);
+        assert_eq!(l_alanine.defined_count(), 1);
+        assert_eq!(
+            l_alanine.relation_to(&racemic),
+            StereoRelation::MoreSpecific
+        );
+        assert_eq!(
+            racemic.relation_to(&l_alanine),
+            StereoRelation::LessSpecific
+        );
+        assert_eq!(l_alanine.relation_to(&d_alanine), StereoRelation::Different);
+        assert_eq!(l_alanine.relation_to(&l_alanine), StereoRelation::Same);
+
+        let partial = StereoLayers::from_inchi(
+            "InChI=1S/C6H12O6/c7-1-2-3(8)4(9)5(10)6(11)12-2/h2-11H,1H2/t2-,3-,4+,5-,6?/m1/s1",
+        )
+        .unwrap();
+        assert_eq!(partial.defined_count(), 4);
+        assert_eq!(partial.undefined_count(), 1);
+
+        assert_eq!(
+            StereoLayers::from_inchikey("QNAYBMKLOCPYGJ-UHFFFAOYSA-N"),
+            Some(StereoLayers::default())
+        );
+        assert_eq!(
+            StereoLayers::from_inchikey("QNAYBMKLOCPYGJ-REOHCLBHSA-N"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_relative_and_racemic_stereo() {
+        const THREONINE: &str = "InChI=1S/C4H9NO3/c1-2(6)3(5)4(7)8/h2-3,6H,5H2,1H3,(H,7,8)";
+        let layers = |stereo: &str| StereoLayers::from_inchi(&format!("{}{}", THREONINE, stereo));
+        let racemic = layers("/t2-,3+/m1/s3").unwrap();
+        let relative = layers("/t2-,3+/m1/s2").unwrap();
+        let l_threonine = layers("/t2-,3+/m1/s1").unwrap();
+        let d_threonine = layers("/t2-,3+/m0/s1").unwrap();
+        let allo = layers("/t2-,3-/m1/s1").unwrap();
+        assert_eq!(racemic.defined_count(), 0);
+        assert_eq!(racemic.relative_count(), 2);
+        assert_eq!(
+            l_threonine.relation_to(&racemic),
+            StereoRelation::MoreSpecific
+        );
+        assert_eq!(
+            racemic.relation_to(&l_threonine),
+            StereoRelation::LessSpecific
+        );
+        assert_eq!(
+            racemic.relation_to(&d_threonine),
+            StereoRelation::LessSpecific
+        );
+        assert_eq!(racemic.relation_to(&relative), StereoRelation::Same);
+        assert_eq!(racemic.relation_to(&allo), StereoRelation::Different);
+    }
+
+    #[test]
+    fn test_stereo_class_from_smiles() {
+        let cases = [
+            ("C[C@@H](C(=O)O)N", StereoClass::Defined),
+            ("CC(N)C(=O)O", StereoClass::Undefined),
+            ("CC(O)CC", StereoClass::Undefined),
+            ("CCC(O)CC", StereoClass::Defined),
+            ("C", StereoClass::Defined),
+            ("CN1C=NC2=C1C(=O)N(C(=O)N2C)C", StereoClass::Defined),
+            (
+                "OC[C@H]1OC(O)[C@H](O)[C@@H](O)[C@@H]1O",
+                StereoClass::PartiallyDefined,
+            ),
+            ("CC=CC", StereoClass::Undefined),
+            ("C/C=C/C", StereoClass::Defined),
+            ("CC(C)=CC", StereoClass::Defined),
+            ("C1CC=CCC1", StereoClass::Defined),
+            ("C/C=C/C(O)CC", StereoClass::PartiallyDefined),
+            ("OC1CCC(O)CC1", StereoClass::Undefined),
+            ("O[C@H]1CC[C@@H](O)CC1", StereoClass::Defined),
+            ("O[C@H]1CCC(O)CC1", StereoClass::PartiallyDefined),
+            ("OC1C(O)C(O)C(O)C(O)C1O", StereoClass::Undefined),
+            ("C1CCC2CCCCC2C1", StereoClass::Undefined),
+            ("CC1CCCCC1", StereoClass::Defined),
+            ("CC1(C)CCC(O)CC1", StereoClass::Defined),
+        ];
+        for (smiles, class) in cases {
+            assert_eq!(StereoClass::from_smiles(smiles), Some(class), "{}", smiles);
+        }
+        assert_eq!(StereoClass::from_smiles("C1CC"), None);
+    }
+}
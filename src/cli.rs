@@ -1,15 +1,146 @@
 //! Command-line interface definitions for lotus-o3.
-use clap::Parser;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use log::info;
+use lotus_o3::chemical_entity::descriptors::DescriptorTable;
+use lotus_o3::chemical_entity::enricher::{EnricherKind, StructureEnricher, build_enricher};
+use lotus_o3::chemical_entity::identifiers::{IdentifierResolver, IdentifierSource};
+use lotus_o3::csv_handler::{ColumnConfig, ValidationMode};
+use lotus_o3::error::{CrateError, Result};
+use lotus_o3::pipeline::PipelineBuilder;
+use lotus_o3::planner::{NearDuplicatePolicy, PlanOptions};
+use lotus_o3::taxon::backbone::TaxonBackbone;
+use lotus_o3::wikidata::api::WIKIDATA_API_URL;
+use lotus_o3::wikidata::checker::{CheckOptions, WIKIDATA_SPARQL_URL};
 use std::path::PathBuf;
+use std::sync::Arc;
 
 /// CLI arguments accepted by lotus-o3.
 #[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
+#[command(
+    author,
+    version,
+    about,
+    long_about = None,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 pub struct Cli {
+    /// Pipeline step or maintenance subcommand; without one, every step runs on `--input-file`.
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     /// Path to the input CSV file.
-    #[arg(short, long, value_name = "FILE")]
-    pub input_file: PathBuf,
+    #[arg(short, long, value_name = "FILE", required = true)]
+    pub input_file: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub columns: ColumnArgs,
+
+    #[command(flatten)]
+    pub validation: ValidationArgs,
+
+    #[command(flatten)]
+    pub enrichment: EnricherArgs,
+
+    #[command(flatten)]
+    pub taxa: TaxonArgs,
+
+    #[command(flatten)]
+    pub creation: CreationArgs,
+
+    /// Output mode: generate QuickStatements or push edits directly through the Wikibase API.
+    #[arg(short, long, value_enum, default_value = "qs")]
+    pub mode: OutputMode,
+
+    /// Path to the output QuickStatements file (required if mode is "qs").
+    #[arg(short, long, value_name = "FILE", required_if_eq("mode", "qs"))]
+    pub output_file: Option<PathBuf>,
+
+    /// Wikibase action API endpoint used by direct mode.
+    #[arg(
+        long = "api-url",
+        value_name = "URL",
+        default_value = WIKIDATA_API_URL,
+        help = "Action API endpoint for direct mode; point it at a local Wikibase for testing."
+    )]
+    pub api_url: String,
+
+    /// SPARQL endpoint used to check rows against Wikidata.
+    #[arg(
+        long = "sparql-url",
+        value_name = "URL",
+        default_value = WIKIDATA_SPARQL_URL,
+        help = "SPARQL endpoint for the Wikidata checks; point it at a mirror or a local query service."
+    )]
+    pub sparql_url: String,
 
+    /// Bot-password username (`User@botname`) for direct mode.
+    #[arg(
+        long = "bot-username",
+        value_name = "USER",
+        env = "LOTUS_O3_BOT_USERNAME"
+    )]
+    pub bot_username: Option<String>,
+
+    /// Bot password generated on Special:BotPasswords for direct mode.
+    #[arg(
+        long = "bot-password",
+        value_name = "PASSWORD",
+        env = "LOTUS_O3_BOT_PASSWORD",
+        hide_env_values = true
+    )]
+    pub bot_password: Option<String>,
+
+    /// Number of records enriched and checked concurrently.
+    #[arg(
+        short = 'j',
+        long = "jobs",
+        value_name = "N",
+        default_value_t = 1,
+        value_parser = clap::value_parser!(u16).range(1..),
+        help = "Process up to N records concurrently; output keeps the input row order."
+    )]
+    pub jobs: u16,
+
+    /// Directory for the persistent lookup cache (enrichment, SPARQL and Crossref results).
+    #[arg(
+        long = "cache-dir",
+        value_name = "DIR",
+        env = "LOTUS_O3_CACHE_DIR",
+        help = "Persist lookups to this directory so reruns of the same CSV reuse them."
+    )]
+    pub cache_dir: Option<PathBuf>,
+
+    /// Checkpoint journal written while processing (defaults to `<output>_journal.jsonl`).
+    #[arg(
+        long = "journal",
+        value_name = "FILE",
+        conflicts_with = "resume",
+        help = "Append each finished row to this JSONL journal so an interrupted run can be resumed."
+    )]
+    pub journal: Option<PathBuf>,
+
+    /// Start a new journal even if the journal file already holds rows.
+    #[arg(
+        long = "overwrite-journal",
+        conflicts_with = "resume",
+        help = "Replace an existing checkpoint journal instead of refusing to start."
+    )]
+    pub overwrite_journal: bool,
+
+    /// Journal from an interrupted run; rows already recorded there are not processed again.
+    #[arg(
+        long = "resume",
+        value_name = "JOURNAL",
+        help = "Skip rows already recorded in this journal and append the remaining ones to it."
+    )]
+    pub resume: Option<PathBuf>,
+    // TODO: Add options for verbosity/logging level
+}
+
+/// Header names of the CSV columns mapped onto the required fields.
+#[derive(Args, Debug, Clone)]
+pub struct ColumnArgs {
     /// CSV column for the chemical entity name.
     #[arg(
         long = "column-chemical-name",
@@ -46,15 +177,435 @@ pub struct Cli {
     )]
     pub column_doi: String,
 
-    /// Output mode: generate QuickStatements or attempt direct push (not implemented).
-    #[arg(short, long, value_enum, default_value = "qs")]
-    pub mode: OutputMode,
+    /// Optional CSV column holding an already-resolved chemical QID.
+    #[arg(
+        long = "column-chemical-qid",
+        value_name = "COLUMN",
+        default_value = "chemical_qid",
+        help = "Header name for pre-resolved chemical QIDs (optional column)."
+    )]
+    pub column_chemical_qid: String,
 
-    /// Path to the output QuickStatements file (required if mode is "qs").
-    #[arg(short, long, value_name = "FILE", required_if_eq("mode", "qs"))]
-    pub output_file: Option<PathBuf>,
-    // TODO: Add options for verbosity/logging level
-    // TODO: Add options for direct push credentials (if implemented)
+    /// Optional CSV column holding an already-resolved taxon QID.
+    #[arg(
+        long = "column-taxon-qid",
+        value_name = "COLUMN",
+        default_value = "taxon_qid",
+        help = "Header name for pre-resolved taxon QIDs (optional column)."
+    )]
+    pub column_taxon_qid: String,
+
+    /// Optional CSV column holding an already-resolved reference QID.
+    #[arg(
+        long = "column-reference-qid",
+        value_name = "COLUMN",
+        default_value = "reference_qid",
+        help = "Header name for pre-resolved reference QIDs (optional column)."
+    )]
+    pub column_reference_qid: String,
+
+    /// Optional CSV column holding a kingdom or higher taxon name.
+    #[arg(
+        long = "column-higher-taxon",
+        value_name = "COLUMN",
+        default_value = "higher_taxon",
+        help = "Header name for a kingdom/higher taxon used to pick between homonymous taxa (optional column)."
+    )]
+    pub column_higher_taxon: String,
+}
+
+/// How invalid CSV rows are handled.
+#[derive(Args, Debug, Clone)]
+pub struct ValidationArgs {
+    /// Validation mode for CSV rows.
+    #[arg(
+        long = "validation",
+        value_enum,
+        value_name = "MODE",
+        default_value = "fail-fast",
+        help = "fail-fast: stop at the first invalid row; strict: list every invalid row, then stop; lenient: skip invalid rows."
+    )]
+    pub validation: ValidationMode,
+
+    /// CSV receiving one line per rejected row problem (lenient mode).
+    #[arg(
+        long = "rejected-rows",
+        value_name = "FILE",
+        help = "Where lenient mode writes rejected rows (default: <output or input stem>_rejected.csv)."
+    )]
+    pub rejected_rows: Option<PathBuf>,
+}
+
+/// Which backend standardizes structures and computes their descriptors.
+#[derive(Args, Debug, Clone)]
+pub struct EnricherArgs {
+    /// Structure enrichment backend.
+    #[arg(
+        long = "enricher",
+        value_enum,
+        default_value = "np-api",
+        help = "Backend that standardizes SMILES and computes InChI, InChIKey, formula and mass."
+    )]
+    pub enricher: EnricherKind,
+
+    /// Base URL of the enrichment service.
+    #[arg(
+        long = "enricher-url",
+        value_name = "URL",
+        help = "Override the base URL of the np-api or pubchem backend (e.g. a mirror or local instance)."
+    )]
+    pub enricher_url: Option<String>,
+
+    /// Structure table read by the precomputed backend.
+    #[arg(
+        long = "precomputed-table",
+        value_name = "FILE",
+        help = "TSV of precomputed structures keyed by input SMILES (required by --enricher precomputed)."
+    )]
+    pub precomputed_table: Option<PathBuf>,
+
+    /// Precomputed structures that bypass the enricher for the rows they list.
+    #[arg(
+        long = "descriptors",
+        value_name = "FILE",
+        help = "TSV of precomputed SMILES, InChI, InChIKey, formula and mass keyed by input SMILES or row; listed rows skip the enricher."
+    )]
+    pub descriptors: Option<PathBuf>,
+
+    /// Databases whose identifiers are added to new chemical items.
+    #[arg(
+        long = "link-identifiers",
+        value_enum,
+        value_name = "DATABASES",
+        value_delimiter = ',',
+        help = "Look InChIKeys up in these databases (comma-separated: pubchem, chebi, chembl, coconut) and add their IDs to new chemical items."
+    )]
+    pub link_identifiers: Vec<IdentifierSource>,
+
+    /// Base URL overrides of the identifier lookups, as `DATABASE=URL`.
+    #[arg(
+        long = "identifier-url",
+        value_name = "DATABASE=URL",
+        value_parser = parse_identifier_url,
+        help = "Override the base URL of one --link-identifiers database (e.g. chembl=http://localhost:8000/chembl/api/data); repeatable."
+    )]
+    pub identifier_urls: Vec<(IdentifierSource, String)>,
+}
+
+impl EnricherArgs {
+    /// Builds the selected backend, loading the precomputed table if needed.
+    pub fn build(&self) -> Result<Arc<dyn StructureEnricher>> {
+        let enricher = build_enricher(
+            self.enricher,
+            self.enricher_url.as_deref(),
+            self.precomputed_table.as_deref(),
+        )?;
+        info!("Enriching structures with the {} backend", enricher.name());
+        Ok(enricher)
+    }
+
+    /// Loads the `--descriptors` table, if any.
+    pub fn descriptor_table(&self) -> Result<Option<Arc<DescriptorTable>>> {
+        let Some(path) = &self.descriptors else {
+            return Ok(None);
+        };
+        let table = DescriptorTable::load(path)?;
+        info!(
+            "Loaded {} precomputed structures from {:?}",
+            table.len(),
+            path
+        );
+        Ok(Some(Arc::new(table)))
+    }
+
+    /// One resolver per `--link-identifiers` database, with its base URL override.
+    pub fn identifier_resolvers(&self) -> Vec<IdentifierResolver> {
+        let mut sources: Vec<IdentifierSource> = Vec::new();
+        for source in &self.link_identifiers {
+            if !sources.contains(source) {
+                sources.push(*source);
+            }
+        }
+        sources
+            .into_iter()
+            .map(|source| {
+                let base_url = self
+                    .identifier_urls
+                    .iter()
+                    .rev()
+                    .find(|(database, _)| *database == source)
+                    .map(|(_, url)| url.as_str());
+                IdentifierResolver::new(source, base_url)
+            })
+            .collect()
+    }
+
+    /// Sets the enricher, descriptor table and identifier resolvers on a pipeline builder.
+    pub fn configure(&self, builder: PipelineBuilder) -> Result<PipelineBuilder> {
+        let builder = builder
+            .enricher(self.build()?)
+            .identifier_resolvers(self.identifier_resolvers());
+        Ok(match self.descriptor_table()? {
+            Some(table) => builder.descriptors(table),
+            None => builder,
+        })
+    }
+}
+
+/// Parses a `--identifier-url` value such as `chebi=http://localhost:8000/unichem`.
+fn parse_identifier_url(value: &str) -> std::result::Result<(IdentifierSource, String), String> {
+    let (database, url) = value
+        .split_once('=')
+        .ok_or_else(|| format!("expected DATABASE=URL, got '{}'", value))?;
+    let source = IdentifierSource::from_str(database.trim(), true)?;
+    Ok((source, url.trim().to_string()))
+}
+
+/// How taxon names are resolved against Wikidata.
+#[derive(Args, Debug, Clone)]
+pub struct TaxonArgs {
+    /// Keep synonym taxon QIDs instead of following them to the accepted taxon.
+    #[arg(
+        long = "keep-synonyms",
+        help = "Use the QID of a synonym taxon item as is instead of its accepted taxon."
+    )]
+    pub keep_synonyms: bool,
+
+    /// Local checklist (TSV or extracted Darwin Core Archive) consulted before Wikidata.
+    #[arg(
+        long = "taxon-backbone",
+        value_name = "FILE",
+        help = "Resolve taxa against a local checklist (GBIF/Catalogue of Life TSV or extracted DwC-A) before querying Wikidata."
+    )]
+    pub taxon_backbone: Option<PathBuf>,
+
+    /// Maximum edit distance at which a unique close taxon name is accepted.
+    #[arg(
+        long = "accept-fuzzy-taxa",
+        value_name = "MAXDIST",
+        help = "When a taxon name is not found, use the only name of its genus within MAXDIST edits (typos, case, diacritics)."
+    )]
+    pub accept_fuzzy_taxa: Option<usize>,
+}
+
+impl TryFrom<&TaxonArgs> for CheckOptions {
+    type Error = CrateError;
+
+    /// Loads the backbone file, if any.
+    fn try_from(taxa: &TaxonArgs) -> Result<Self> {
+        let taxon_backbone = match &taxa.taxon_backbone {
+            Some(path) => {
+                let backbone = TaxonBackbone::load(path)?;
+                info!(
+                    "Loaded {} taxon backbone entries from {:?}",
+                    backbone.len(),
+                    path
+                );
+                Some(Arc::new(backbone))
+            }
+            None => None,
+        };
+        Ok(CheckOptions {
+            keep_synonyms: taxa.keep_synonyms,
+            taxon_backbone,
+            accept_fuzzy_taxa: taxa.accept_fuzzy_taxa,
+        })
+    }
+}
+
+/// Opt-in item creations.
+#[derive(Args, Debug, Clone)]
+pub struct CreationArgs {
+    /// Create missing species under their genus (QuickStatements output only).
+    #[arg(
+        long = "create-taxa",
+        help = "Emit CREATE blocks for species missing from Wikidata whose genus already exists."
+    )]
+    pub create_taxa: bool,
+
+    /// What to do with chemicals whose skeleton is already on Wikidata with other stereo.
+    #[arg(
+        long = "near-duplicates",
+        value_enum,
+        value_name = "POLICY",
+        default_value = "review",
+        help = "review: hold chemicals sharing the InChIKey connectivity block with an equal, more or less specific existing item; create: create them anyway."
+    )]
+    pub near_duplicates: NearDuplicatePolicy,
+}
+
+impl From<&CreationArgs> for PlanOptions {
+    fn from(creation: &CreationArgs) -> Self {
+        PlanOptions {
+            create_taxa: creation.create_taxa,
+            near_duplicates: creation.near_duplicates,
+        }
+    }
+}
+
+impl From<&ColumnArgs> for ColumnConfig {
+    fn from(columns: &ColumnArgs) -> Self {
+        ColumnConfig {
+            chemical_name: columns.column_chemical_name.clone(),
+            structure: columns.column_structure.clone(),
+            taxon: columns.column_taxon.clone(),
+            doi: columns.column_doi.clone(),
+            chemical_qid: columns.column_chemical_qid.clone(),
+            taxon_qid: columns.column_taxon_qid.clone(),
+            reference_qid: columns.column_reference_qid.clone(),
+            higher_taxon: columns.column_higher_taxon.clone(),
+        }
+    }
+}
+
+/// Pipeline steps that can be run one at a time, plus maintenance commands.
+///
+/// Running without a subcommand chains `validate → enrich → check → plan → emit` in memory.
+/// The step subcommands write their result to a JSONL file that the next step reads, so
+/// intermediate results can be inspected or fixed by hand between steps.
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Validate the input CSV without contacting any service.
+    Validate(ValidateArgs),
+    /// Enrich CSV rows with structure descriptors and write one EnrichedData JSON line per row.
+    Enrich(EnrichArgs),
+    /// Check enriched rows against Wikidata and write one checked record per line.
+    Check(CheckArgs),
+    /// Plan item creations from checked records and write the plan plus a status report.
+    Plan(PlanArgs),
+    /// Write a QuickStatements file from a (possibly hand-edited) plan.
+    Emit(EmitArgs),
+    /// Inspect or clear the persistent lookup cache.
+    Cache {
+        #[command(subcommand)]
+        action: CacheAction,
+    },
+}
+
+/// Arguments for `lotus-o3 validate`.
+#[derive(Args, Debug)]
+pub struct ValidateArgs {
+    /// Path to the input CSV file.
+    #[arg(short, long, value_name = "FILE")]
+    pub input_file: PathBuf,
+
+    #[command(flatten)]
+    pub columns: ColumnArgs,
+
+    #[command(flatten)]
+    pub validation: ValidationArgs,
+}
+
+/// Arguments for `lotus-o3 enrich`.
+#[derive(Args, Debug)]
+pub struct EnrichArgs {
+    /// Path to the input CSV file.
+    #[arg(short, long, value_name = "FILE")]
+    pub input_file: PathBuf,
+
+    /// Enriched records (JSONL) to write.
+    #[arg(short, long, value_name = "FILE")]
+    pub output_file: PathBuf,
+
+    #[command(flatten)]
+    pub columns: ColumnArgs,
+
+    #[command(flatten)]
+    pub validation: ValidationArgs,
+
+    #[command(flatten)]
+    pub enrichment: EnricherArgs,
+
+    /// Number of records enriched concurrently.
+    #[arg(
+        short = 'j',
+        long = "jobs",
+        value_name = "N",
+        default_value_t = 1,
+        value_parser = clap::value_parser!(u16).range(1..)
+    )]
+    pub jobs: u16,
+
+    /// Directory for the persistent lookup cache.
+    #[arg(long = "cache-dir", value_name = "DIR", env = "LOTUS_O3_CACHE_DIR")]
+    pub cache_dir: Option<PathBuf>,
+}
+
+/// Arguments for `lotus-o3 check`.
+#[derive(Args, Debug)]
+pub struct CheckArgs {
+    /// Enriched records (JSONL) written by `lotus-o3 enrich`.
+    #[arg(short, long, value_name = "FILE")]
+    pub input_file: PathBuf,
+
+    /// Checked records (JSONL) to write.
+    #[arg(short, long, value_name = "FILE")]
+    pub output_file: PathBuf,
+
+    #[command(flatten)]
+    pub taxa: TaxonArgs,
+
+    /// SPARQL endpoint used to check rows against Wikidata.
+    #[arg(long = "sparql-url", value_name = "URL", default_value = WIKIDATA_SPARQL_URL)]
+    pub sparql_url: String,
+
+    /// Number of DOIs missing from Wikidata looked up on Crossref concurrently.
+    #[arg(
+        short = 'j',
+        long = "jobs",
+        value_name = "N",
+        default_value_t = 1,
+        value_parser = clap::value_parser!(u16).range(1..)
+    )]
+    pub jobs: u16,
+
+    /// Directory for the persistent lookup cache.
+    #[arg(long = "cache-dir", value_name = "DIR", env = "LOTUS_O3_CACHE_DIR")]
+    pub cache_dir: Option<PathBuf>,
+}
+
+/// Arguments for `lotus-o3 plan`.
+#[derive(Args, Debug)]
+pub struct PlanArgs {
+    /// Checked records (JSONL) written by `lotus-o3 check`, or a checkpoint journal.
+    #[arg(short, long, value_name = "FILE")]
+    pub input_file: PathBuf,
+
+    /// Planned records (JSONL) to write; the status report is written next to it.
+    #[arg(short, long, value_name = "FILE")]
+    pub output_file: PathBuf,
+
+    #[command(flatten)]
+    pub creation: CreationArgs,
+}
+
+/// Arguments for `lotus-o3 emit`.
+#[derive(Args, Debug)]
+pub struct EmitArgs {
+    /// Planned records (JSONL) written by `lotus-o3 plan`.
+    #[arg(short, long, value_name = "FILE")]
+    pub input_file: PathBuf,
+
+    /// Path to the output QuickStatements file.
+    #[arg(short, long, value_name = "FILE")]
+    pub output_file: PathBuf,
+}
+
+/// Actions available under `lotus-o3 cache`.
+#[derive(Subcommand, Debug, PartialEq, Eq)]
+pub enum CacheAction {
+    /// Delete every cache file in the cache directory.
+    Clear(CacheDirArgs),
+    /// Show entry counts per lookup kind.
+    Stats(CacheDirArgs),
+}
+
+/// Cache directory shared by the `cache` actions.
+#[derive(Args, Debug, PartialEq, Eq)]
+pub struct CacheDirArgs {
+    /// Cache directory (same value as `--cache-dir` for regular runs).
+    #[arg(long = "cache-dir", value_name = "DIR", env = "LOTUS_O3_CACHE_DIR")]
+    pub cache_dir: PathBuf,
 }
 
 /// Supported output modes.
@@ -63,7 +614,7 @@ pub enum OutputMode {
     /// Generate a QuickStatements V1 file.
     #[value(name = "qs")]
     QuickStatements,
-    /// Push data directly to Wikidata (requires authentication, not implemented).
+    /// Push data directly to Wikidata through the action API (requires a bot password).
     #[value(name = "direct")]
     DirectPush,
 }
@@ -77,7 +628,7 @@ mod tests {
     fn test_cli_qs_mode() {
         let args = vec!["lotus-o3", "-i", "input.csv", "-m", "qs", "-o", "output.qs"];
         let cli = Cli::parse_from(args);
-        assert_eq!(cli.input_file, PathBuf::from("input.csv"));
+        assert_eq!(cli.input_file, Some(PathBuf::from("input.csv")));
         assert_eq!(cli.mode, OutputMode::QuickStatements);
         assert_eq!(cli.output_file, Some(PathBuf::from("output.qs")));
     }
@@ -86,7 +637,7 @@ mod tests {
     fn test_cli_qs_mode_default() {
         let args = vec!["lotus-o3", "-i", "input.csv", "-o", "output.qs"];
         let cli = Cli::parse_from(args);
-        assert_eq!(cli.input_file, PathBuf::from("input.csv"));
+        assert_eq!(cli.input_file, Some(PathBuf::from("input.csv")));
         assert_eq!(cli.mode, OutputMode::QuickStatements);
         assert_eq!(cli.output_file, Some(PathBuf::from("output.qs")));
     }
@@ -95,11 +646,300 @@ mod tests {
     fn test_cli_direct_mode() {
         let args = vec!["lotus-o3", "-i", "input.csv", "-m", "direct"];
         let cli = Cli::parse_from(args);
-        assert_eq!(cli.input_file, PathBuf::from("input.csv"));
+        assert_eq!(cli.input_file, Some(PathBuf::from("input.csv")));
         assert_eq!(cli.mode, OutputMode::DirectPush);
         assert!(cli.output_file.is_none());
     }
 
+    #[test]
+    fn test_cli_direct_mode_credentials() {
+        let args = vec![
+            "lotus-o3",
+            "-i",
+            "input.csv",
+            "-m",
+            "direct",
+            "--api-url",
+            "http://localhost:8181/w/api.php",
+            "--bot-username",
+            "User@lotus",
+            "--bot-password",
+            "secret",
+        ];
+        let cli = Cli::parse_from(args);
+        assert_eq!(cli.api_url, "http://localhost:8181/w/api.php");
+        assert_eq!(cli.bot_username.as_deref(), Some("User@lotus"));
+        assert_eq!(cli.bot_password.as_deref(), Some("secret"));
+    }
+
+    #[test]
+    fn test_cli_cache_subcommand() {
+        let args = vec![
+            "lotus-o3",
+            "cache",
+            "stats",
+            "--cache-dir",
+            "/tmp/lotus-cache",
+        ];
+        let cli = Cli::parse_from(args);
+        assert!(cli.input_file.is_none());
+        match cli.command {
+            Some(Command::Cache {
+                action: CacheAction::Stats(dir),
+            }) => assert_eq!(dir.cache_dir, PathBuf::from("/tmp/lotus-cache")),
+            other => panic!("unexpected command: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_cli_cache_dir_for_runs() {
+        let args = vec![
+            "lotus-o3",
+            "-i",
+            "input.csv",
+            "-o",
+            "output.qs",
+            "--cache-dir",
+            ".lotus-cache",
+        ];
+        let cli = Cli::parse_from(args);
+        assert!(cli.command.is_none());
+        assert_eq!(cli.cache_dir, Some(PathBuf::from(".lotus-cache")));
+    }
+
+    #[test]
+    fn test_cli_jobs() {
+        let cli = Cli::parse_from(vec!["lotus-o3", "-i", "in.csv", "-o", "out.qs"]);
+        assert_eq!(cli.jobs, 1);
+        let cli = Cli::parse_from(vec!["lotus-o3", "-i", "in.csv", "-o", "out.qs", "-j", "8"]);
+        assert_eq!(cli.jobs, 8);
+        let result =
+            Cli::try_parse_from(vec!["lotus-o3", "-i", "in.csv", "-o", "out.qs", "-j", "0"]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_cli_journal_and_resume() {
+        let cli = Cli::parse_from(vec![
+            "lotus-o3",
+            "-i",
+            "in.csv",
+            "-o",
+            "out.qs",
+            "--resume",
+            "out_journal.jsonl",
+        ]);
+        assert_eq!(cli.resume, Some(PathBuf::from("out_journal.jsonl")));
+        assert!(cli.journal.is_none());
+        let result = Cli::try_parse_from(vec![
+            "lotus-o3",
+            "-i",
+            "in.csv",
+            "-o",
+            "out.qs",
+            "--journal",
+            "a.jsonl",
+            "--resume",
+            "b.jsonl",
+        ]);
+        assert!(result.is_err());
+        let result = Cli::try_parse_from(vec![
+            "lotus-o3",
+            "-i",
+            "in.csv",
+            "-o",
+            "out.qs",
+            "--overwrite-journal",
+            "--resume",
+            "b.jsonl",
+        ]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_cli_pipeline_subcommands() {
+        let cli = Cli::parse_from(vec![
+            "lotus-o3",
+            "enrich",
+            "-i",
+            "input.csv",
+            "-o",
+            "enriched.jsonl",
+            "--column-taxon",
+            "organism",
+            "--column-taxon-qid",
+            "organism_qid",
+            "-j",
+            "4",
+        ]);
+        match cli.command {
+            Some(Command::Enrich(args)) => {
+                assert_eq!(args.output_file, PathBuf::from("enriched.jsonl"));
+                assert_eq!(args.columns.column_taxon, "organism");
+                assert_eq!(ColumnConfig::from(&args.columns).taxon_qid, "organism_qid");
+                assert_eq!(args.jobs, 4);
+            }
+            other => panic!("unexpected command: {:?}", other),
+        }
+
+        let cli = Cli::parse_from(vec!["lotus-o3", "emit", "-i", "plan.jsonl", "-o", "out.qs"]);
+        assert!(matches!(cli.command, Some(Command::Emit(_))));
+        assert!(Cli::try_parse_from(vec!["lotus-o3", "plan", "-i", "checked.jsonl"]).is_err());
+    }
+
+    #[test]
+    fn test_cli_validation_mode() {
+        let cli = Cli::parse_from(vec!["lotus-o3", "-i", "in.csv", "-o", "out.qs"]);
+        assert_eq!(cli.validation.validation, ValidationMode::FailFast);
+        let cli = Cli::parse_from(vec![
+            "lotus-o3",
+            "-i",
+            "in.csv",
+            "-o",
+            "out.qs",
+            "--validation",
+            "lenient",
+            "--rejected-rows",
+            "bad.csv",
+        ]);
+        assert_eq!(cli.validation.validation, ValidationMode::Lenient);
+        assert_eq!(cli.validation.rejected_rows, Some(PathBuf::from("bad.csv")));
+    }
+
+    #[test]
+    fn test_cli_keep_synonyms() {
+        let cli = Cli::parse_from(vec!["lotus-o3", "-i", "in.csv", "-o", "out.qs"]);
+        assert!(!CheckOptions::try_from(&cli.taxa).unwrap().keep_synonyms);
+        let cli = Cli::parse_from(vec![
+            "lotus-o3",
+            "check",
+            "-i",
+            "enriched.jsonl",
+            "-o",
+            "checked.jsonl",
+            "--keep-synonyms",
+            "--taxon-backbone",
+            "Taxon.tsv",
+            "--accept-fuzzy-taxa",
+            "2",
+        ]);
+        match cli.command {
+            Some(Command::Check(args)) => {
+                assert!(args.taxa.keep_synonyms);
+                assert_eq!(args.taxa.taxon_backbone, Some(PathBuf::from("Taxon.tsv")));
+                assert_eq!(args.taxa.accept_fuzzy_taxa, Some(2));
+            }
+            other => panic!("unexpected command: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_cli_create_taxa() {
+        let cli = Cli::parse_from(vec!["lotus-o3", "-i", "in.csv", "-o", "out.qs"]);
+        assert!(!PlanOptions::from(&cli.creation).create_taxa);
+        assert_eq!(cli.creation.near_duplicates, NearDuplicatePolicy::Review);
+        let cli = Cli::parse_from(vec![
+            "lotus-o3",
+            "plan",
+            "-i",
+            "checked.jsonl",
+            "-o",
+            "planned.jsonl",
+            "--create-taxa",
+            "--near-duplicates",
+            "create",
+        ]);
+        match cli.command {
+            Some(Command::Plan(args)) => {
+                let options = PlanOptions::from(&args.creation);
+                assert!(options.create_taxa);
+                assert_eq!(options.near_duplicates, NearDuplicatePolicy::Create);
+            }
+            other => panic!("unexpected command: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_cli_enricher() {
+        let cli = Cli::parse_from(vec!["lotus-o3", "-i", "in.csv", "-o", "out.qs"]);
+        assert_eq!(cli.enrichment.enricher, EnricherKind::NpApi);
+        assert!(cli.enrichment.enricher_url.is_none());
+        assert!(cli.enrichment.identifier_resolvers().is_empty());
+        let cli = Cli::parse_from(vec![
+            "lotus-o3",
+            "enrich",
+            "-i",
+            "in.csv",
+            "-o",
+            "enriched.jsonl",
+            "--enricher",
+            "pubchem",
+            "--enricher-url",
+            "http://localhost:8080/rest/pug",
+            "--link-identifiers",
+            "pubchem,chembl,pubchem",
+            "--identifier-url",
+            "chembl=http://localhost:9000/chembl",
+        ]);
+        match cli.command {
+            Some(Command::Enrich(args)) => {
+                assert_eq!(args.enrichment.enricher, EnricherKind::Pubchem);
+                assert_eq!(args.enrichment.build().unwrap().name(), "pubchem");
+                let sources: Vec<IdentifierSource> = args
+                    .enrichment
+                    .identifier_resolvers()
+                    .iter()
+                    .map(IdentifierResolver::source)
+                    .collect();
+                assert_eq!(
+                    sources,
+                    [IdentifierSource::Pubchem, IdentifierSource::Chembl]
+                );
+            }
+            other => panic!("unexpected command: {:?}", other),
+        }
+        assert_eq!(
+            parse_identifier_url("chebi=http://localhost:8000/unichem"),
+            Ok((
+                IdentifierSource::Chebi,
+                "http://localhost:8000/unichem".to_string()
+            ))
+        );
+        assert!(parse_identifier_url("zinc=http://localhost").is_err());
+        let cli = Cli::parse_from(vec![
+            "lotus-o3",
+            "-i",
+            "in.csv",
+            "-o",
+            "out.qs",
+            "--enricher",
+            "precomputed",
+        ]);
+        assert!(matches!(
+            cli.enrichment.build(),
+            Err(CrateError::EnricherConfigError(_))
+        ));
+        let cli = Cli::parse_from(vec![
+            "lotus-o3",
+            "-i",
+            "in.csv",
+            "-o",
+            "out.qs",
+            "--descriptors",
+            "rdkit.tsv",
+        ]);
+        assert_eq!(
+            cli.enrichment.descriptors.as_deref(),
+            Some(std::path::Path::new("rdkit.tsv"))
+        );
+    }
+
+    #[test]
+    fn test_cli_missing_input_without_subcommand() {
+        let args = vec!["lotus-o3", "-o", "output.qs"];
+        assert!(Cli::try_parse_from(args).is_err());
+    }
+
     #[test]
     fn test_cli_qs_mode_missing_output() {
         let args = vec!["lotus-o3", "-i", "input.csv", "-m", "qs"];
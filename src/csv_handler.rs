@@ -1,47 +1,85 @@
 //! Helpers for loading and validating occurrence CSV files.
+use crate::chemical_entity::smiles::validate_smiles;
 use crate::error::{CrateError, Result};
-use crate::taxon::normalizer::normalize_taxon_name;
+use crate::taxon::parser::parse_taxon_name;
 use once_cell::sync::Lazy;
 use regex::Regex;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::path::Path;
 
 /// A normalized occurrence record read from the CSV.
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct InputRecord {
     pub chemical_entity_name: String,
     pub chemical_entity_smiles: String,
     pub taxon_name: String,
     pub reference_doi: String,
+    #[serde(default)]
+    pub preset_qids: PresetQids,
+    /// Warnings from parsing the verbatim taxon name (see [`parse_taxon_name`]).
+    #[serde(default)]
+    pub taxon_name_warnings: Vec<String>,
+    /// Kingdom or any higher taxon name (optional column) used to pick between homonyms.
+    #[serde(default)]
+    pub higher_taxon: Option<String>,
 }
 
-/// Defines which CSV columns map to the required fields.
+/// QIDs a curator already resolved, taken from the optional `*_qid` columns. They replace the
+/// corresponding SPARQL lookup once confirmed to exist on Wikidata.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PresetQids {
+    pub chemical: Option<String>,
+    pub taxon: Option<String>,
+    pub reference: Option<String>,
+}
+
+impl PresetQids {
+    pub fn is_empty(&self) -> bool {
+        self.chemical.is_none() && self.taxon.is_none() && self.reference.is_none()
+    }
+}
+
+/// Defines which CSV columns map to the required fields and the optional QID columns.
 #[derive(Debug, Clone)]
 pub struct ColumnConfig {
     pub chemical_name: String,
     pub structure: String,
     pub taxon: String,
     pub doi: String,
+    pub chemical_qid: String,
+    pub taxon_qid: String,
+    pub reference_qid: String,
+    pub higher_taxon: String,
 }
 
-impl ColumnConfig {
+impl Default for ColumnConfig {
     /// Returns the default column mapping expected by lotus-o3.
-    pub fn default() -> Self {
+    fn default() -> Self {
         Self {
             chemical_name: "chemical_entity_name".to_string(),
             structure: "chemical_entity_smiles".to_string(),
             taxon: "taxon_name".to_string(),
             doi: "reference_doi".to_string(),
+            chemical_qid: "chemical_qid".to_string(),
+            taxon_qid: "taxon_qid".to_string(),
+            reference_qid: "reference_qid".to_string(),
+            higher_taxon: "higher_taxon".to_string(),
         }
     }
+}
 
+impl ColumnConfig {
     fn name_for(&self, role: ColumnRole) -> &str {
         match role {
             ColumnRole::ChemicalName => &self.chemical_name,
             ColumnRole::Structure => &self.structure,
             ColumnRole::Taxon => &self.taxon,
             ColumnRole::Doi => &self.doi,
+            ColumnRole::ChemicalQid => &self.chemical_qid,
+            ColumnRole::TaxonQid => &self.taxon_qid,
+            ColumnRole::ReferenceQid => &self.reference_qid,
+            ColumnRole::HigherTaxon => &self.higher_taxon,
         }
     }
 }
@@ -52,8 +90,32 @@ enum ColumnRole {
     Structure,
     Taxon,
     Doi,
+    ChemicalQid,
+    TaxonQid,
+    ReferenceQid,
+    HigherTaxon,
 }
 
+/// Optional columns: a missing header is fine unless the user renamed it explicitly.
+const OPTIONAL_COLUMNS: [(ColumnRole, &str, &str); 4] = [
+    (
+        ColumnRole::ChemicalQid,
+        "chemical_qid",
+        "--column-chemical-qid",
+    ),
+    (ColumnRole::TaxonQid, "taxon_qid", "--column-taxon-qid"),
+    (
+        ColumnRole::ReferenceQid,
+        "reference_qid",
+        "--column-reference-qid",
+    ),
+    (
+        ColumnRole::HigherTaxon,
+        "higher_taxon",
+        "--column-higher-taxon",
+    ),
+];
+
 /// Metadata about each required CSV column for error reporting.
 struct ColumnRequirement {
     role: ColumnRole,
@@ -93,6 +155,20 @@ static DOI_REGEX: Lazy<Regex> = Lazy::new(|| {
     Regex::new(r#"^(10\.[0-9]{4,}(?:\.[0-9]+)*/[^"'&\s]+)$"#).expect("valid DOI regex")
 });
 
+static QID_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^Q[1-9][0-9]*$").expect("valid QID regex"));
+
+/// Accepts `Q123`, `wd:Q123` and entity/wiki URLs; returns the bare QID when well-formed.
+pub(crate) fn normalize_qid(raw: &str) -> Option<String> {
+    let trimmed = raw.trim();
+    let bare = trimmed
+        .rsplit(['/', ':'])
+        .next()
+        .unwrap_or(trimmed)
+        .to_uppercase();
+    QID_REGEX.is_match(&bare).then_some(bare)
+}
+
 fn normalize_doi(raw: &str) -> String {
     let trimmed = raw.trim();
     let lowered = trimmed.to_lowercase();
@@ -112,8 +188,94 @@ fn normalize_doi(raw: &str) -> String {
     trimmed.to_string()
 }
 
+/// How `load_csv` reacts to invalid rows.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ValidationMode {
+    /// Abort on the first invalid row.
+    #[default]
+    #[value(name = "fail-fast")]
+    FailFast,
+    /// Check every row, then fail with all problems listed.
+    #[value(name = "strict")]
+    Strict,
+    /// Skip invalid rows and report them as diagnostics.
+    #[value(name = "lenient")]
+    Lenient,
+}
+
+/// One problem found in a CSV row.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RowDiagnostic {
+    pub row: usize,
+    pub column: String,
+    pub value: String,
+    pub reason: String,
+}
+
+impl std::fmt::Display for RowDiagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Row {}, column '{}', value '{}': {}",
+            self.row, self.column, self.value, self.reason
+        )
+    }
+}
+
+impl From<RowDiagnostic> for CrateError {
+    /// Maps a diagnostic back to the error fail-fast mode has always returned.
+    fn from(diagnostic: RowDiagnostic) -> Self {
+        if diagnostic.value.is_empty() {
+            CrateError::MissingValue {
+                column: diagnostic.column,
+                row: diagnostic.row,
+            }
+        } else {
+            CrateError::InvalidFormat {
+                column: diagnostic.column,
+                value: diagnostic.value,
+                message: diagnostic.reason,
+            }
+        }
+    }
+}
+
+/// Valid records (with their CSV row numbers) plus diagnostics for the rows that were skipped.
+#[derive(Debug, Default)]
+pub struct ValidatedCsv {
+    pub records: Vec<(usize, InputRecord)>,
+    pub diagnostics: Vec<RowDiagnostic>,
+}
+
+impl ValidatedCsv {
+    /// Number of distinct rows that were rejected.
+    pub fn rejected_rows(&self) -> usize {
+        let mut rows: Vec<usize> = self.diagnostics.iter().map(|d| d.row).collect();
+        rows.dedup();
+        rows.len()
+    }
+}
+
 /// Loads and validates the input CSV file, applying the provided column mapping.
 pub fn load_and_validate_csv(file_path: &Path, columns: &ColumnConfig) -> Result<Vec<InputRecord>> {
+    let validated = load_csv(file_path, columns, ValidationMode::FailFast)?;
+    Ok(validated
+        .records
+        .into_iter()
+        .map(|(_, record)| record)
+        .collect())
+}
+
+/// Loads the input CSV and validates every row according to `mode`.
+///
+/// Missing headers always fail immediately. Row problems fail on the first one
+/// (`FailFast`), after all rows were checked (`Strict`), or are returned as diagnostics
+/// while the remaining rows are kept (`Lenient`).
+pub fn load_csv(
+    file_path: &Path,
+    columns: &ColumnConfig,
+    mode: ValidationMode,
+) -> Result<ValidatedCsv> {
     let mut reader = csv::Reader::from_path(file_path)?;
     let headers = reader.headers()?.clone();
 
@@ -127,61 +289,150 @@ pub fn load_and_validate_csv(file_path: &Path, columns: &ColumnConfig) -> Result
     let structure_idx = lookup_column_index(&header_map, columns, ColumnRole::Structure)?;
     let taxon_idx = lookup_column_index(&header_map, columns, ColumnRole::Taxon)?;
     let doi_idx = lookup_column_index(&header_map, columns, ColumnRole::Doi)?;
+    let mut optional_indices = Vec::new();
+    for (role, default_header, cli_flag) in OPTIONAL_COLUMNS {
+        let name = columns.name_for(role);
+        match header_map.get(name) {
+            Some(idx) => optional_indices.push((role, *idx)),
+            None if name == default_header => {}
+            None => {
+                return Err(CrateError::MissingHeader(format!(
+                    "Column '{}' given with {} was not found in the CSV header.",
+                    name, cli_flag
+                )));
+            }
+        }
+    }
 
-    let mut valid_records = Vec::new();
+    let mut validated = ValidatedCsv::default();
     for (i, result) in reader.records().enumerate() {
-        let record = result?;
         let row_num = i + 2; // header + 1-based index
+        let record = match result {
+            Ok(record) => record,
+            Err(e) if mode == ValidationMode::FailFast => return Err(e.into()),
+            Err(e) => {
+                validated.diagnostics.push(RowDiagnostic {
+                    row: row_num,
+                    column: String::new(),
+                    value: String::new(),
+                    reason: format!("Unreadable CSV record: {}", e),
+                });
+                continue;
+            }
+        };
 
         let mut normalized = InputRecord {
             chemical_entity_name: record.get(chemical_idx).unwrap_or("").trim().to_string(),
             chemical_entity_smiles: record.get(structure_idx).unwrap_or("").trim().to_string(),
             taxon_name: record.get(taxon_idx).unwrap_or("").trim().to_string(),
             reference_doi: record.get(doi_idx).unwrap_or("").to_string(),
+            preset_qids: PresetQids::default(),
+            taxon_name_warnings: Vec::new(),
+            higher_taxon: None,
         };
 
-        if normalized.chemical_entity_name.is_empty() {
-            return Err(CrateError::MissingValue {
-                column: columns.name_for(ColumnRole::ChemicalName).to_string(),
-                row: row_num,
-            });
-        }
-        if normalized.chemical_entity_smiles.is_empty() {
-            return Err(CrateError::MissingValue {
-                column: columns.name_for(ColumnRole::Structure).to_string(),
-                row: row_num,
-            });
-        }
-        if normalized.taxon_name.is_empty() {
-            return Err(CrateError::MissingValue {
-                column: columns.name_for(ColumnRole::Taxon).to_string(),
-                row: row_num,
-            });
+        let mut problems = Vec::new();
+        for (role, value) in [
+            (ColumnRole::ChemicalName, &normalized.chemical_entity_name),
+            (ColumnRole::Structure, &normalized.chemical_entity_smiles),
+            (ColumnRole::Taxon, &normalized.taxon_name),
+            (ColumnRole::Doi, &normalized.reference_doi),
+        ] {
+            if value.trim().is_empty() {
+                problems.push(RowDiagnostic {
+                    row: row_num,
+                    column: columns.name_for(role).to_string(),
+                    value: String::new(),
+                    reason: "Missing required value".to_string(),
+                });
+            }
         }
-        if normalized.reference_doi.is_empty() {
-            return Err(CrateError::MissingValue {
-                column: columns.name_for(ColumnRole::Doi).to_string(),
+
+        if !normalized.chemical_entity_smiles.is_empty()
+            && let Err(CrateError::InvalidSmiles {
+                position, message, ..
+            }) = validate_smiles(&normalized.chemical_entity_smiles)
+        {
+            problems.push(RowDiagnostic {
                 row: row_num,
+                column: columns.name_for(ColumnRole::Structure).to_string(),
+                value: normalized.chemical_entity_smiles.clone(),
+                reason: format!("Invalid SMILES at position {}: {}", position, message),
             });
         }
 
-        normalized.taxon_name = normalize_taxon_name(&normalized.taxon_name);
+        let parsed_taxon = parse_taxon_name(&normalized.taxon_name);
+        normalized.taxon_name = parsed_taxon.canonical;
+        normalized.taxon_name_warnings = parsed_taxon.warnings;
         normalized.reference_doi = normalize_doi(&normalized.reference_doi);
-        if !DOI_REGEX.is_match(&normalized.reference_doi) {
-            return Err(CrateError::InvalidFormat {
+        if !normalized.reference_doi.is_empty() && !DOI_REGEX.is_match(&normalized.reference_doi) {
+            problems.push(RowDiagnostic {
+                row: row_num,
                 column: columns.name_for(ColumnRole::Doi).to_string(),
                 value: normalized.reference_doi.clone(),
-                message: "DOI must match Wikidata's DOI format".to_string(),
+                reason: "DOI must match Wikidata's DOI format".to_string(),
             });
         }
-        valid_records.push(normalized);
+
+        for (role, idx) in &optional_indices {
+            let raw = record.get(*idx).unwrap_or("").trim();
+            if raw.is_empty() {
+                continue;
+            }
+            if *role == ColumnRole::HigherTaxon {
+                normalized.higher_taxon = Some(raw.to_string());
+                continue;
+            }
+            let Some(qid) = normalize_qid(raw) else {
+                problems.push(RowDiagnostic {
+                    row: row_num,
+                    column: columns.name_for(*role).to_string(),
+                    value: raw.to_string(),
+                    reason: "QID must look like Q12345".to_string(),
+                });
+                continue;
+            };
+            let slot = match role {
+                ColumnRole::ChemicalQid => &mut normalized.preset_qids.chemical,
+                ColumnRole::TaxonQid => &mut normalized.preset_qids.taxon,
+                _ => &mut normalized.preset_qids.reference,
+            };
+            *slot = Some(qid);
+        }
+
+        if problems.is_empty() {
+            validated.records.push((row_num, normalized));
+        } else if mode == ValidationMode::FailFast {
+            return Err(problems.remove(0).into());
+        } else {
+            validated.diagnostics.extend(problems);
+        }
+    }
+
+    if mode == ValidationMode::Strict && !validated.diagnostics.is_empty() {
+        return Err(CrateError::InvalidRows(validated.diagnostics));
     }
+    Ok(validated)
+}
 
-    Ok(valid_records)
+/// Writes diagnostics as a CSV with `row,column,value,reason` columns.
+pub fn write_rejected_rows(diagnostics: &[RowDiagnostic], path: &Path) -> Result<()> {
+    let mut writer = csv::Writer::from_path(path)?;
+    writer.write_record(["row", "column", "value", "reason"])?;
+    for diagnostic in diagnostics {
+        writer.write_record([
+            diagnostic.row.to_string().as_str(),
+            diagnostic.column.as_str(),
+            diagnostic.value.as_str(),
+            diagnostic.reason.as_str(),
+        ])?;
+    }
+    writer.flush()?;
+    Ok(())
 }
 
-fn lookup_column_index<'a>(
-    header_map: &HashMap<&'a str, usize>,
+fn lookup_column_index(
+    header_map: &HashMap<&str, usize>,
     columns: &ColumnConfig,
     role: ColumnRole,
 ) -> Result<usize> {
@@ -230,14 +481,16 @@ mod tests {
 
     #[test]
     fn test_load_valid_csv() {
-        let content = "chemical_entity_name,chemical_entity_smiles,taxon_name,reference_doi\nCompoundA,C1=CC=CC=C1,TaxonX species extra , 10.1000/test1 \nCompoundB,C,TaxonY,10.1000/test2";
+        let content = "chemical_entity_name,chemical_entity_smiles,taxon_name,reference_doi\nCompoundA,C1=CC=CC=C1,Taxonx species L. , 10.1000/test1 \nCompoundB,C,Taxony sp.,10.1000/test2";
         let file = create_test_csv(content);
         let records = load_and_validate_csv(file.path(), &ColumnConfig::default()).unwrap();
         assert_eq!(records.len(), 2);
         assert_eq!(records[0].chemical_entity_name, "CompoundA");
-        assert_eq!(records[0].taxon_name, "TaxonX species");
+        assert_eq!(records[0].taxon_name, "Taxonx species");
+        assert!(records[0].taxon_name_warnings.is_empty());
         assert_eq!(records[0].reference_doi, "10.1000/test1");
-        assert_eq!(records[1].taxon_name, "TaxonY");
+        assert_eq!(records[1].taxon_name, "Taxony");
+        assert_eq!(records[1].taxon_name_warnings.len(), 1);
     }
 
     #[test]
@@ -277,12 +530,12 @@ mod tests {
 
     #[test]
     fn test_trim_fields() {
-        let content = "chemical_entity_name,chemical_entity_smiles,taxon_name,reference_doi\n CompoundA , C1=CC=CC=C1 , TaxonX extra info , 10.5772/28961 \r";
+        let content = "chemical_entity_name,chemical_entity_smiles,taxon_name,reference_doi\n CompoundA , C1=CC=CC=C1 , Taxonx extra (L.) DC. , 10.5772/28961 \r";
         let file = create_test_csv(content);
         let records = load_and_validate_csv(file.path(), &ColumnConfig::default()).unwrap();
         assert_eq!(records[0].chemical_entity_name, "CompoundA");
         assert_eq!(records[0].chemical_entity_smiles, "C1=CC=CC=C1");
-        assert_eq!(records[0].taxon_name, "TaxonX extra");
+        assert_eq!(records[0].taxon_name, "Taxonx extra");
         assert_eq!(records[0].reference_doi, "10.5772/28961");
     }
 
@@ -307,6 +560,118 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn test_lenient_mode_skips_and_reports_invalid_rows() {
+        let content = "chemical_entity_name,chemical_entity_smiles,taxon_name,reference_doi\nCompoundA,C,TaxonX,10.1000/test1\nCompoundB,,TaxonY,not-a-doi\nCompoundC,CC,TaxonZ,10.1000/test3";
+        let file = create_test_csv(content);
+        let validated = load_csv(
+            file.path(),
+            &ColumnConfig::default(),
+            ValidationMode::Lenient,
+        )
+        .unwrap();
+        let rows: Vec<usize> = validated.records.iter().map(|(row, _)| *row).collect();
+        assert_eq!(rows, vec![2, 4]);
+        assert_eq!(validated.rejected_rows(), 1);
+        assert_eq!(validated.diagnostics.len(), 2);
+        assert_eq!(validated.diagnostics[0].column, "chemical_entity_smiles");
+        assert_eq!(validated.diagnostics[1].value, "not-a-doi");
+
+        let rejected = NamedTempFile::new().unwrap();
+        write_rejected_rows(&validated.diagnostics, rejected.path()).unwrap();
+        let written = std::fs::read_to_string(rejected.path()).unwrap();
+        assert!(written.starts_with("row,column,value,reason\n3,chemical_entity_smiles,,"));
+    }
+
+    #[test]
+    fn test_invalid_smiles_is_rejected_with_position() {
+        let content = "chemical_entity_name,chemical_entity_smiles,taxon_name,reference_doi\nCompoundA,C1CC,TaxonX,10.1000/test1\nCompoundB,CC,TaxonY,10.1000/test2";
+        let file = create_test_csv(content);
+        let validated = load_csv(
+            file.path(),
+            &ColumnConfig::default(),
+            ValidationMode::Lenient,
+        )
+        .unwrap();
+        assert_eq!(validated.records.len(), 1);
+        assert_eq!(validated.diagnostics[0].value, "C1CC");
+        assert_eq!(
+            validated.diagnostics[0].reason,
+            "Invalid SMILES at position 2: ring bond 1 is never closed"
+        );
+    }
+
+    #[test]
+    fn test_valence_problems_reject_rows() {
+        let content = "chemical_entity_name,chemical_entity_smiles,taxon_name,reference_doi\nCisplatin,[Pt](Cl)(Cl)([NH3])[NH3],TaxonX,10.1000/test1\nPentavalent,CC(C)(C)(C)C,TaxonY,10.1000/test2";
+        let file = create_test_csv(content);
+        let validated = load_csv(
+            file.path(),
+            &ColumnConfig::default(),
+            ValidationMode::Lenient,
+        )
+        .unwrap();
+        assert_eq!(validated.records.len(), 1);
+        assert_eq!(
+            validated.records[0].1.chemical_entity_smiles,
+            "[Pt](Cl)(Cl)([NH3])[NH3]"
+        );
+        assert_eq!(validated.diagnostics.len(), 1);
+        assert_eq!(validated.diagnostics[0].row, 3);
+        assert_eq!(
+            validated.diagnostics[0].reason,
+            "Invalid SMILES at position 2: C has valence 5 but allows at most 4"
+        );
+    }
+
+    #[test]
+    fn test_strict_mode_lists_every_invalid_row() {
+        let content = "chemical_entity_name,chemical_entity_smiles,taxon_name,reference_doi\nCompoundA,C,TaxonX,bad-1\nCompoundB,C,TaxonY,10.1000/ok\nCompoundC,C,TaxonZ,bad-2";
+        let file = create_test_csv(content);
+        let result = load_csv(
+            file.path(),
+            &ColumnConfig::default(),
+            ValidationMode::Strict,
+        );
+        match result {
+            Err(CrateError::InvalidRows(diagnostics)) => {
+                let rows: Vec<usize> = diagnostics.iter().map(|d| d.row).collect();
+                assert_eq!(rows, vec![2, 4]);
+            }
+            other => panic!("expected InvalidRows, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_optional_qid_columns() {
+        let content = "chemical_entity_name,chemical_entity_smiles,taxon_name,reference_doi,taxon_qid,ref_q,higher_taxon\nCompoundA,C,TaxonX,10.1000/test1,http://www.wikidata.org/entity/Q2355919,wd:Q105275116,\nCompoundB,C,TaxonY,10.1000/test2,,, Plantae \nCompoundC,C,TaxonZ,10.1000/test3,Taxon Z,,";
+        let file = create_test_csv(content);
+        let config = ColumnConfig {
+            reference_qid: "ref_q".to_string(),
+            ..ColumnConfig::default()
+        };
+        let validated = load_csv(file.path(), &config, ValidationMode::Lenient).unwrap();
+        assert_eq!(validated.records.len(), 2);
+        let presets = &validated.records[0].1.preset_qids;
+        assert_eq!(presets.taxon.as_deref(), Some("Q2355919"));
+        assert_eq!(presets.reference.as_deref(), Some("Q105275116"));
+        assert!(presets.chemical.is_none());
+        assert!(validated.records[1].1.preset_qids.is_empty());
+        assert_eq!(validated.records[0].1.higher_taxon, None);
+        assert_eq!(
+            validated.records[1].1.higher_taxon.as_deref(),
+            Some("Plantae")
+        );
+        assert_eq!(validated.diagnostics[0].column, "taxon_qid");
+
+        let renamed = ColumnConfig {
+            chemical_qid: "compound_qid".to_string(),
+            ..ColumnConfig::default()
+        };
+        let result = load_csv(file.path(), &renamed, ValidationMode::FailFast);
+        assert!(matches!(result, Err(CrateError::MissingHeader(h)) if h.contains("compound_qid")));
+    }
+
     #[test]
     fn test_custom_column_mapping() {
         let content =
@@ -317,6 +682,7 @@ mod tests {
             structure: "structure".to_string(),
             taxon: "taxa".to_string(),
             doi: "doi".to_string(),
+            ..ColumnConfig::default()
         };
         let records = load_and_validate_csv(file.path(), &config).unwrap();
         assert_eq!(records.len(), 1);
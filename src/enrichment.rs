@@ -1,13 +1,20 @@
 //! Chemoinformatics enrichment utilities.
+use crate::chemical_entity::composition::cross_check_composition;
+use crate::chemical_entity::enricher::StructureEnricher;
+use crate::chemical_entity::identifiers::{
+    ExternalIdentifier, IdentifierResolver, resolve_identifiers,
+};
+use crate::chemical_entity::smiles::{parse_smiles, valence_errors, validate_smiles};
+use crate::chemical_entity::stereo::StereoClass;
 use crate::chemical_entity::structure::{ChemicalStructureData, enrich_structure};
-use crate::csv_handler::InputRecord;
+use crate::csv_handler::{InputRecord, PresetQids};
 use crate::error::Result;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::HashMap;
 
 /// Holds the input data plus descriptors fetched from external services.
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
 pub struct EnrichedData {
     pub chemical_entity_name: String,
     pub input_smiles: String,
@@ -22,11 +29,45 @@ pub struct EnrichedData {
     pub molecular_formula: Option<String>,
     pub exact_mass: Option<f64>,
     pub other_descriptors: Option<HashMap<String, Value>>,
+    #[serde(default, skip_serializing_if = "PresetQids::is_empty")]
+    pub preset_qids: PresetQids,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub taxon_name_warnings: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub higher_taxon: Option<String>,
+    /// Problems found while cross-checking the structure data (e.g. a disputed formula).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub structure_warnings: Vec<String>,
+    /// Stereo completeness of the sanitized SMILES; `None` if it could not be parsed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stereo: Option<StereoClass>,
+    /// PubChem, ChEBI, ChEMBL and COCONUT IDs found for the InChIKey.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub identifiers: Vec<ExternalIdentifier>,
 }
 
-/// Calls the underlying chemical-entity enrichment helpers for a single CSV row.
-pub async fn enrich_record(record: InputRecord, client: &reqwest::Client) -> Result<EnrichedData> {
-    let structure = enrich_structure(&record.chemical_entity_smiles, client).await?;
+/// Calls the underlying chemical-entity enrichment helpers for a single CSV row. Malformed SMILES
+/// and over-valent atoms fail here with their character position instead of being sent to the
+/// enricher.
+pub async fn enrich_record(
+    record: InputRecord,
+    enricher: &dyn StructureEnricher,
+    client: &reqwest::Client,
+) -> Result<EnrichedData> {
+    validate_smiles(&record.chemical_entity_smiles)?;
+    let structure = enrich_structure(&record.chemical_entity_smiles, enricher, client).await?;
+    Ok(enriched_from_structure(record, structure))
+}
+
+/// Combines a CSV row with structure data obtained elsewhere (e.g. a descriptor table), after
+/// cross-checking its formula and mass against the SMILES and classifying its stereochemistry.
+pub fn enriched_from_structure(
+    record: InputRecord,
+    mut structure: ChemicalStructureData,
+) -> EnrichedData {
+    let mut structure_warnings = input_smiles_warnings(&record.chemical_entity_smiles);
+    structure_warnings.extend(cross_check_composition(&mut structure));
+    let stereo = StereoClass::from_smiles(&structure.sanitized_smiles);
     let ChemicalStructureData {
         sanitized_smiles,
         smiles_were_sanitized,
@@ -39,7 +80,7 @@ pub async fn enrich_record(record: InputRecord, client: &reqwest::Client) -> Res
         other_descriptors,
     } = structure;
 
-    Ok(EnrichedData {
+    EnrichedData {
         chemical_entity_name: record.chemical_entity_name,
         input_smiles: record.chemical_entity_smiles,
         sanitized_smiles,
@@ -53,12 +94,49 @@ pub async fn enrich_record(record: InputRecord, client: &reqwest::Client) -> Res
         molecular_formula,
         exact_mass,
         other_descriptors,
-    })
+        preset_qids: record.preset_qids,
+        taxon_name_warnings: record.taxon_name_warnings,
+        higher_taxon: record.higher_taxon,
+        structure_warnings,
+        stereo,
+        identifiers: Vec::new(),
+    }
+}
+
+/// Positioned parser findings on the input SMILES: over-valent atoms, or the syntax error when the
+/// structure came from a table that did not need the input to parse.
+fn input_smiles_warnings(smiles: &str) -> Vec<String> {
+    match parse_smiles(smiles) {
+        Ok(molecule) => valence_errors(smiles, &molecule)
+            .iter()
+            .map(ToString::to_string)
+            .collect(),
+        Err(error) => vec![error.to_string()],
+    }
+}
+
+/// Looks the InChIKey of `data` up with every resolver and records the identifiers found;
+/// failed or disagreeing lookups become structure warnings.
+pub async fn link_identifiers(
+    data: &mut EnrichedData,
+    resolvers: &[IdentifierResolver],
+    client: &reqwest::Client,
+) {
+    let Some(inchikey) = data.inchikey.as_deref() else {
+        return;
+    };
+    if resolvers.is_empty() {
+        return;
+    }
+    let (identifiers, warnings) = resolve_identifiers(inchikey, resolvers, client).await;
+    data.identifiers = identifiers;
+    data.structure_warnings.extend(warnings);
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::chemical_entity::np_api::NpApiEnricher;
     use crate::error::CrateError;
     use tokio;
 
@@ -70,9 +148,14 @@ mod tests {
             chemical_entity_smiles: "CN1C=NC2=C1C(=O)N(C(=O)N2C)C".to_string(),
             taxon_name: "Coffea arabica".to_string(),
             reference_doi: "10.1000/test".to_string(),
+            preset_qids: Default::default(),
+            taxon_name_warnings: Vec::new(),
+            higher_taxon: None,
         };
         let client = reqwest::Client::new();
-        let enriched_data = enrich_record(record, &client).await.unwrap();
+        let enriched_data = enrich_record(record, &NpApiEnricher::default(), &client)
+            .await
+            .unwrap();
 
         assert!(enriched_data.inchikey.is_some());
         assert_eq!(
@@ -99,12 +182,84 @@ mod tests {
             chemical_entity_smiles: "Cl/C=C/1\\C=C2[C@]3([C@H]1OC(=O)C(C)CCCCCCC(CC([C@]1([C@@H]4[C@H]([C@@]52OC(O4)(O[C@@H]1[C@@H]5[C@H]1[C@]([C@H]3O)(CO)O1)c1ccccc1)C)O)(O)COC(=O)c1ccccc1)C)O".to_string(),
             taxon_name: "Trigonostemon cherrieri".to_string(),
             reference_doi: "10.1016/J.PHYTOCHEM.2012.07.023".to_string(),
+            preset_qids: Default::default(),
+            taxon_name_warnings: Vec::new(),
+            higher_taxon: None,
         };
         let client = reqwest::Client::new();
-        let result = enrich_record(record, &client).await;
+        let result = enrich_record(record, &NpApiEnricher::default(), &client).await;
         assert!(result.is_err(), "Expected failure for invalid SMILES");
         if let Err(e) = result {
             assert!(matches!(e, CrateError::SmilesSanitizationFailed { .. }));
         }
     }
+
+    fn record_with_smiles(smiles: &str) -> InputRecord {
+        InputRecord {
+            chemical_entity_name: "Compound".to_string(),
+            chemical_entity_smiles: smiles.to_string(),
+            taxon_name: "Coffea arabica".to_string(),
+            reference_doi: "10.1000/test".to_string(),
+            preset_qids: Default::default(),
+            taxon_name_warnings: Vec::new(),
+            higher_taxon: None,
+        }
+    }
+
+    fn structure_for(smiles: &str) -> ChemicalStructureData {
+        ChemicalStructureData {
+            sanitized_smiles: smiles.to_string(),
+            smiles_were_sanitized: false,
+            canonical_smiles: Some(smiles.to_string()),
+            isomeric_smiles: None,
+            inchi: None,
+            inchikey: None,
+            molecular_formula: None,
+            exact_mass: None,
+            other_descriptors: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_malformed_smiles_never_reach_the_enricher() {
+        // Nothing listens here, so a request would fail with an API error instead.
+        let enricher = NpApiEnricher::new("http://127.0.0.1:9");
+        for smiles in ["C1CC", "CC(C)(C)(C)C"] {
+            let result = enrich_record(
+                record_with_smiles(smiles),
+                &enricher,
+                &reqwest::Client::new(),
+            )
+            .await;
+            match result {
+                Err(CrateError::InvalidSmiles { position, .. }) => {
+                    assert_eq!(position, 2, "{}", smiles)
+                }
+                other => panic!("expected an invalid SMILES error, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_valence_problems_become_structure_warnings() {
+        let data = enriched_from_structure(
+            record_with_smiles("CC(C)(C)(C)C"),
+            structure_for("CC(C)(C)C"),
+        );
+        assert_eq!(
+            data.structure_warnings,
+            vec![
+                "Invalid SMILES 'CC(C)(C)(C)C' at position 2: C has valence 5 but allows at most 4"
+                    .to_string()
+            ]
+        );
+
+        let cisplatin = "[Pt](Cl)(Cl)([NH3])[NH3]";
+        let data = enriched_from_structure(record_with_smiles(cisplatin), structure_for(cisplatin));
+        assert!(
+            data.structure_warnings.is_empty(),
+            "{:?}",
+            data.structure_warnings
+        );
+    }
 }
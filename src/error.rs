@@ -1,4 +1,5 @@
 //! Common error types used throughout the crate.
+use crate::csv_handler::RowDiagnostic;
 use thiserror::Error;
 
 /// Unified error enum for lotus-o3.
@@ -23,6 +24,9 @@ pub enum CrateError {
         message: String,
     },
 
+    #[error("{} invalid CSV row(s):\n{}", .0.len(), format_diagnostics(.0))]
+    InvalidRows(Vec<RowDiagnostic>),
+
     #[error("API request error: {0}")]
     ApiRequestError(reqwest::Error),
 
@@ -41,12 +45,33 @@ pub enum CrateError {
     #[error("Missing expected descriptor '{descriptor}' in API response for SMILES: {smiles}")]
     MissingDescriptor { descriptor: String, smiles: String },
 
+    #[error("Invalid SMILES '{smiles}' at position {position}: {message}")]
+    InvalidSmiles {
+        smiles: String,
+        position: usize,
+        message: String,
+    },
+
+    #[error("InChIKey {inchikey} does not match {computed} computed from {inchi}")]
+    InchiKeyMismatch {
+        inchikey: String,
+        computed: String,
+        inchi: String,
+    },
+
     #[error("Failed to sanitize SMILES: {input_smiles}")]
     SmilesSanitizationFailed {
         input_smiles: String,
         reason: String,
     },
 
+    #[error("{database} lookup for InChIKey {inchikey} returned status {status}")]
+    IdentifierLookupError {
+        database: String,
+        inchikey: String,
+        status: reqwest::StatusCode,
+    },
+
     #[error("Wikidata SPARQL query failed: {0}")]
     SparqlQueryError(reqwest::Error),
 
@@ -61,12 +86,39 @@ pub enum CrateError {
     #[error("Unexpected SPARQL response format: {0}")]
     SparqlResponseFormatError(String),
 
+    #[error("Batched SPARQL lookup failed: {0}")]
+    SparqlBatchError(String),
+
     #[error("Wikidata check failed for record: {record_smiles}")]
     WikidataCheckError {
         record_smiles: String,
         source: Box<CrateError>,
     },
 
+    #[error("Invalid record on line {line} of {path}: {message}")]
+    ArtifactParseError {
+        path: String,
+        line: usize,
+        message: String,
+    },
+
+    #[error(
+        "Checkpoint journal {path} already holds rows; pass --resume {path} to continue that run or --overwrite-journal to start over"
+    )]
+    JournalExists { path: String },
+
+    #[error("Invalid taxon backbone {path}: {message}")]
+    TaxonBackboneError { path: String, message: String },
+
+    #[error("Invalid structure enricher configuration: {0}")]
+    EnricherConfigError(String),
+
+    #[error("Invalid descriptor table {path}: {message}")]
+    DescriptorTableError { path: String, message: String },
+
+    #[error("No precomputed structure for SMILES: {0}")]
+    PrecomputedStructureMissing(String),
+
     #[error("QuickStatements generation error: {0}")]
     QuickStatementError(String),
 
@@ -81,3 +133,11 @@ pub enum CrateError {
 }
 
 pub type Result<T> = std::result::Result<T, CrateError>;
+
+fn format_diagnostics(diagnostics: &[RowDiagnostic]) -> String {
+    diagnostics
+        .iter()
+        .map(|diagnostic| format!("  - {}", diagnostic))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
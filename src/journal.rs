@@ -0,0 +1,241 @@
+//! Append-only checkpoint journal that makes long runs resumable.
+//!
+//! Every row that passes enrichment and the Wikidata checks is written to the journal as one
+//! JSON line as soon as it (and every row before it) finishes. `--resume <journal>` reloads
+//! those rows and only processes the remaining ones; newly finished rows are appended to the
+//! same file. A journal that already holds rows is never truncated unless asked to.
+use crate::csv_handler::InputRecord;
+use crate::enrichment::EnrichedData;
+use crate::error::{CrateError, Result};
+use crate::wikidata::checker::WikidataInfo;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+/// One finished row: its CSV row number plus everything planning needs.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub row: usize,
+    pub enriched: EnrichedData,
+    pub wikidata: WikidataInfo,
+}
+
+impl JournalEntry {
+    /// True when the entry was recorded for this exact input row (guards against resuming
+    /// with an edited or different CSV).
+    pub fn matches(&self, record: &InputRecord) -> bool {
+        self.enriched.chemical_entity_name == record.chemical_entity_name
+            && self.enriched.input_smiles == record.chemical_entity_smiles
+            && self.enriched.taxon_name == record.taxon_name
+            && self.enriched.reference_doi == record.reference_doi
+            && self.enriched.preset_qids == record.preset_qids
+            && self.enriched.taxon_name_warnings == record.taxon_name_warnings
+            && self.enriched.higher_taxon == record.higher_taxon
+    }
+}
+
+/// Open journal file; each entry is flushed immediately so a crash loses at most one line.
+pub struct Journal {
+    path: PathBuf,
+    file: File,
+}
+
+impl Journal {
+    /// Starts a new journal. Fails if `path` already holds rows, which are typically those of
+    /// an interrupted run that `--resume` still needs.
+    pub fn create(path: &Path) -> Result<Self> {
+        if fs::metadata(path).is_ok_and(|meta| meta.len() > 0) {
+            return Err(CrateError::JournalExists {
+                path: path.display().to_string(),
+            });
+        }
+        Self::overwrite(path)
+    }
+
+    /// Starts a new journal, truncating any previous file at `path`.
+    pub fn overwrite(path: &Path) -> Result<Self> {
+        let file = File::create(path)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+        })
+    }
+
+    /// Opens an existing journal for appending (used by `--resume`).
+    pub fn append_to(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Appends one finished row.
+    pub fn record(&mut self, entry: &JournalEntry) -> Result<()> {
+        let line = serde_json::to_string(entry).map_err(std::io::Error::from)?;
+        writeln!(self.file, "{}", line)?;
+        self.file.flush()?;
+        Ok(())
+    }
+}
+
+/// Loads a journal keyed by CSV row number. Unreadable lines (typically a line cut short by a
+/// crash) are skipped with a warning; later entries for the same row win.
+pub fn load_journal(path: &Path) -> Result<HashMap<usize, JournalEntry>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut entries = HashMap::new();
+    for (line_number, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str::<JournalEntry>(&line) {
+            Ok(entry) => {
+                entries.insert(entry.row, entry);
+            }
+            Err(e) => warn!(
+                "Skipping unreadable journal line {} in {:?}: {}",
+                line_number + 1,
+                path,
+                e
+            ),
+        }
+    }
+    Ok(entries)
+}
+
+/// Splits input rows into those already finished in `entries` and those still to process. Rows
+/// whose input changed since they were journaled are processed again.
+pub fn split_resumed(
+    rows: Vec<(usize, InputRecord)>,
+    mut entries: HashMap<usize, JournalEntry>,
+) -> (Vec<JournalEntry>, Vec<(usize, InputRecord)>) {
+    let mut resumed = Vec::new();
+    let mut pending = Vec::new();
+    for (row_num, record) in rows {
+        match entries.remove(&row_num) {
+            Some(entry) if entry.matches(&record) => resumed.push(entry),
+            Some(_) => {
+                warn!(
+                    "Row {} no longer matches its journal entry; processing it again.",
+                    row_num
+                );
+                pending.push((row_num, record));
+            }
+            None => pending.push((row_num, record)),
+        }
+    }
+    (resumed, pending)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn entry(row: usize, smiles: &str) -> JournalEntry {
+        JournalEntry {
+            row,
+            enriched: EnrichedData {
+                chemical_entity_name: "Compound".to_string(),
+                input_smiles: smiles.to_string(),
+                sanitized_smiles: smiles.to_string(),
+                taxon_name: "Coffea arabica".to_string(),
+                reference_doi: "10.1000/test".to_string(),
+                inchikey: Some("VNWKTOKETHGBQD-UHFFFAOYSA-N".to_string()),
+                ..Default::default()
+            },
+            wikidata: WikidataInfo {
+                chemical_qid: Some("Q37129".to_string()),
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn test_journal_round_trip_and_resume_append() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("run_journal.jsonl");
+
+        let mut journal = Journal::create(&path).unwrap();
+        journal.record(&entry(2, "C")).unwrap();
+        drop(journal);
+
+        let mut journal = Journal::append_to(&path).unwrap();
+        journal.record(&entry(3, "CC")).unwrap();
+        drop(journal);
+
+        let entries = load_journal(&path).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[&2].wikidata.chemical_qid.as_deref(), Some("Q37129"));
+        assert_eq!(entries[&3].enriched.input_smiles, "CC");
+    }
+
+    #[test]
+    fn test_rerun_keeps_the_previous_journal() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out_journal.jsonl");
+        let mut journal = Journal::create(&path).unwrap();
+        journal.record(&entry(2, "C")).unwrap();
+        drop(journal);
+
+        // A plain rerun must not truncate the journal of the crashed run.
+        assert!(matches!(
+            Journal::create(&path),
+            Err(CrateError::JournalExists { .. })
+        ));
+        assert!(load_journal(&path).unwrap().contains_key(&2));
+
+        let mut journal = Journal::append_to(&path).unwrap();
+        journal.record(&entry(3, "CC")).unwrap();
+        drop(journal);
+        assert_eq!(load_journal(&path).unwrap().len(), 2);
+
+        Journal::overwrite(&path).unwrap();
+        assert!(load_journal(&path).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_truncated_last_line_is_skipped() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("crashed.jsonl");
+        let complete = serde_json::to_string(&entry(2, "C")).unwrap();
+        fs::write(&path, format!("{}\n{{\"row\":3,\"enr", complete)).unwrap();
+
+        let entries = load_journal(&path).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert!(entries.contains_key(&2));
+    }
+
+    #[test]
+    fn test_entry_matches_only_the_same_input_row() {
+        let record = InputRecord {
+            chemical_entity_name: "Compound".to_string(),
+            chemical_entity_smiles: "C".to_string(),
+            taxon_name: "Coffea arabica".to_string(),
+            reference_doi: "10.1000/test".to_string(),
+            preset_qids: Default::default(),
+            taxon_name_warnings: Vec::new(),
+            higher_taxon: None,
+        };
+        assert!(entry(2, "C").matches(&record));
+        assert!(!entry(2, "CC").matches(&record));
+
+        let entries = HashMap::from([(2, entry(2, "C")), (3, entry(3, "CCO"))]);
+        let changed = InputRecord {
+            chemical_entity_smiles: "CC".to_string(),
+            ..record.clone()
+        };
+        let (resumed, pending) = split_resumed(vec![(2, record), (3, changed)], entries);
+        assert_eq!(resumed.len(), 1);
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].0, 3);
+    }
+}
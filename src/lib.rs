@@ -0,0 +1,14 @@
+//! lotus-o3 library: CSV loading, structure enrichment, Wikidata checks, creation planning and
+//! QuickStatements/API output, wired together by [`pipeline::Pipeline`].
+pub mod artifacts;
+pub mod cache;
+pub mod chemical_entity;
+pub mod csv_handler;
+pub mod enrichment;
+pub mod error;
+pub mod journal;
+pub mod pipeline;
+pub mod planner;
+pub mod reference;
+pub mod taxon;
+pub mod wikidata;
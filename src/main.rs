@@ -1,30 +1,32 @@
 //! lotus-o3 CLI entry point orchestrating CSV parsing and Wikidata preparation.
-pub mod chemical_entity;
-pub mod cli;
-pub mod csv_handler;
-pub mod enrichment;
-pub mod error;
-pub mod reference;
-pub mod taxon;
-pub mod wikidata;
+mod cli;
 
 use clap::Parser;
-use cli::{Cli, OutputMode};
-use csv::WriterBuilder;
-use csv_handler::{ColumnConfig, load_and_validate_csv};
-use enrichment::{EnrichedData, enrich_record};
-use error::{CrateError, Result};
+use cli::{
+    CacheAction, CheckArgs, Cli, Command, EmitArgs, EnrichArgs, OutputMode, PlanArgs, ValidateArgs,
+    ValidationArgs,
+};
 use indicatif::{ProgressBar, ProgressStyle};
 use log::{error, info, warn};
-use reqwest::Client;
-use std::collections::HashSet;
+use lotus_o3::artifacts::{EnrichedRow, PlannedRecord, read_jsonl, write_jsonl};
+use lotus_o3::cache;
+use lotus_o3::csv_handler::{
+    ColumnConfig, InputRecord, RowDiagnostic, load_csv, write_rejected_rows,
+};
+use lotus_o3::enrichment::EnrichedData;
+use lotus_o3::error::{CrateError, Result};
+use lotus_o3::journal::{self, Journal, JournalEntry};
+use lotus_o3::pipeline::{Pipeline, RowOutcome};
+use lotus_o3::planner::{CreationPlan, PlanOptions, RecordReport, write_status_report};
+use lotus_o3::wikidata::api::WikibaseSession;
+use lotus_o3::wikidata::checker::{self, CheckOptions, WikidataInfo};
+use lotus_o3::wikidata::writer::push_to_wikidata;
+use std::collections::{HashMap, HashSet};
 use std::fs::{self, File};
 use std::io::{BufWriter, Write};
 use std::path::{Path, PathBuf};
 use std::time::Instant;
 use urlencoding::encode;
-use wikidata::checker::{WikidataInfo, check_wikidata};
-use wikidata::writer::generate_quickstatements;
 
 /// Parses CLI args, loads the CSV, and drives enrichment plus QS generation.
 #[tokio::main]
@@ -40,111 +42,173 @@ async fn main() -> Result<()> {
 
     // Parse CLI arguments
     let cli = Cli::parse();
+    match &cli.command {
+        Some(Command::Validate(args)) => return run_validate(args),
+        Some(Command::Enrich(args)) => return run_enrich(args).await,
+        Some(Command::Check(args)) => return run_check(args).await,
+        Some(Command::Plan(args)) => return run_plan(args),
+        Some(Command::Emit(args)) => return run_emit(args),
+        Some(Command::Cache { action }) => return run_cache_command(action),
+        None => {}
+    }
+    let input_file = cli
+        .input_file
+        .clone()
+        .expect("Input file path is required without a subcommand");
     info!("Starting Wikidata Importer...");
-    info!("Input file: {:?}", cli.input_file);
+    info!("Input file: {:?}", input_file);
     info!("Output mode: {:?}", cli.mode);
     if let Some(output_file) = &cli.output_file {
         info!("Output file: {:?}", output_file);
     }
+    let direct_credentials = match cli.mode {
+        OutputMode::DirectPush => match (&cli.bot_username, &cli.bot_password) {
+            (Some(username), Some(password)) => Some((username.clone(), password.clone())),
+            _ => {
+                let message = "Direct mode requires --bot-username and --bot-password \
+(or LOTUS_O3_BOT_USERNAME / LOTUS_O3_BOT_PASSWORD)"
+                    .to_string();
+                error!("{}", message);
+                return Err(CrateError::WikidataWriteError(message));
+            }
+        },
+        OutputMode::QuickStatements => None,
+    };
+
+    cache::configure(cli.cache_dir.as_deref())?;
+    checker::configure_sparql_url(&cli.sparql_url);
 
     let start_time = Instant::now();
 
     // 1. Load and Validate CSV
     info!("Loading and validating CSV...");
-    let column_config = ColumnConfig {
-        chemical_name: cli.column_chemical_name.clone(),
-        structure: cli.column_structure.clone(),
-        taxon: cli.column_taxon.clone(),
-        doi: cli.column_doi.clone(),
-    };
-
-    let input_records = match load_and_validate_csv(&cli.input_file, &column_config) {
-        Ok(records) => {
-            info!(
-                "Successfully loaded and validated {} records.",
-                records.len()
-            );
-            records
-        }
-        Err(e) => {
-            error!("Failed to load or validate CSV: {}", e);
-            return Err(e);
-        }
-    };
+    let column_config = ColumnConfig::from(&cli.columns);
+
+    let LoadedInput {
+        records: input_records,
+        diagnostics,
+        rejected_rows_path,
+    } = load_input(
+        &input_file,
+        &column_config,
+        &cli.validation,
+        cli.output_file.as_deref(),
+    )?;
+    info!(
+        "Successfully loaded and validated {} records.",
+        input_records.len()
+    );
 
     if input_records.is_empty() {
         info!("Input CSV is empty or contains no valid records. Exiting.");
+        print_rejected_rows(&diagnostics, rejected_rows_path.as_deref());
         return Ok(());
     }
 
     // 2. Process Records (Enrichment & Wikidata Check)
     info!("Processing records (enrichment and Wikidata checks)...");
-    // Explicitly map the reqwest::Error from client building
-    let client = Client::builder()
-        .user_agent(wikidata::checker::USER_AGENT) // Use the defined user agent
-        .build()
-        .map_err(CrateError::ApiRequestError)?;
-
     let mut processed_data = Vec::new();
+    let mut processed_rows = Vec::new();
     let mut errors_count = 0;
     let mut error_details: Vec<String> = Vec::new();
 
-    // Initialize the progress bar
-    let num_records = input_records.len() as u64;
-    let pb = ProgressBar::new(num_records);
-    pb.set_style(ProgressStyle::default_bar()
-        .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({percent}%) {msg}")
-        .expect("Failed to set progress bar style") // Added expect for error handling
-        .progress_chars("##-"));
+    // Rows finished by an interrupted run are reused as-is; new rows are appended to its journal.
+    let resumed_entries = match &cli.resume {
+        Some(path) => {
+            let entries = journal::load_journal(path)?;
+            info!(
+                "Loaded {} finished row(s) from journal {:?}",
+                entries.len(),
+                path
+            );
+            entries
+        }
+        None => HashMap::new(),
+    };
+    let new_journal = |path: &Path| {
+        if cli.overwrite_journal {
+            Journal::overwrite(path)
+        } else {
+            Journal::create(path)
+        }
+    };
+    let mut journal = match (&cli.resume, &cli.journal, &cli.output_file) {
+        (Some(path), _, _) => Some(Journal::append_to(path)?),
+        (None, Some(path), _) => Some(new_journal(path)?),
+        (None, None, Some(output_path)) => Some(new_journal(&build_journal_path(output_path))?),
+        (None, None, None) => None,
+    };
+    if let Some(journal) = &journal {
+        info!("Checkpoint journal: {:?}", journal.path());
+    }
 
-    for (index, record) in input_records.into_iter().enumerate() {
-        let row_num = index + 2; // CSV row number (1-based + header)
-        let smiles = record.chemical_entity_smiles.clone(); // Clone for error reporting
-        let chemical_entity_name = record.chemical_entity_name.clone(); // Clone for error reporting
-
-        // Update progress bar message (optional)
-        pb.set_message(format!("Processing: {} ({})", chemical_entity_name, smiles));
-
-        match enrich_record(record, &client).await {
-            Ok(enriched) => {
-                let inchikey = enriched
-                    .inchikey
-                    .clone()
-                    .unwrap_or_else(|| "N/A".to_string());
-                match check_wikidata(&enriched, &client).await {
-                    Ok(wikidata_info) => {
-                        processed_data.push((enriched, wikidata_info));
-                    }
-                    Err(e) => {
-                        let error_message = format!(
-                            "Row {}: Wikidata check failed for InChIKey {}: {}",
-                            row_num, inchikey, e
-                        );
-                        pb.println(format!(
-                            "Error (Wikidata check) for row {}: {} - {}",
-                            row_num, inchikey, e
-                        )); // For progress bar
-                        error!("{}", error_message); // Your existing log
-                        error_details.push(error_message);
-                        errors_count += 1;
-                    }
-                }
-            }
-            Err(e) => {
-                let error_message = format!(
-                    "Row {}: Enrichment failed for SMILES {}: {}",
-                    row_num, smiles, e
+    let mut plan_options = PlanOptions::from(&cli.creation);
+    if plan_options.create_taxa && cli.mode == OutputMode::DirectPush {
+        warn!("--create-taxa only applies to QuickStatements output; ignoring it in direct mode.");
+        plan_options.create_taxa = false;
+    }
+
+    let pb = new_progress_bar(input_records.len());
+    let pipeline = cli
+        .enrichment
+        .configure(Pipeline::builder())?
+        .jobs(usize::from(cli.jobs))
+        .progress(pb.clone())
+        .check_options(CheckOptions::try_from(&cli.taxa)?)
+        .plan_options(plan_options)
+        .build()?;
+
+    let (resumed, pending_records) = journal::split_resumed(input_records, resumed_entries);
+    let resumed_count = resumed.len();
+    pb.inc(resumed_count as u64);
+    if cli.resume.is_some() {
+        info!(
+            "Resuming: {} row(s) taken from the journal, {} left to process.",
+            resumed_count,
+            pending_records.len()
+        );
+    }
+    let mut outcomes: Vec<(usize, RowOutcome)> = resumed
+        .into_iter()
+        .map(|entry| (entry.row, Ok((entry.enriched, entry.wikidata))))
+        .collect();
+
+    // Each row is journaled as soon as it (and every row before it) finishes, so an
+    // interruption only loses the rows still in flight.
+    let processed = pipeline
+        .process(pending_records, |row_num, outcome| {
+            let (Some(open_journal), Ok((enriched, wikidata))) = (journal.as_mut(), outcome) else {
+                return;
+            };
+            let entry = JournalEntry {
+                row: row_num,
+                enriched: enriched.clone(),
+                wikidata: wikidata.clone(),
+            };
+            if let Err(e) = open_journal.record(&entry) {
+                warn!(
+                    "Failed to write checkpoint journal {:?}: {}; continuing without it.",
+                    open_journal.path(),
+                    e
                 );
-                pb.println(format!(
-                    "Error (Enrichment) for row {}: {} - {}",
-                    row_num, smiles, e
-                )); // For progress bar
-                error!("{}", error_message); // Your existing log
-                error_details.push(error_message); // <<< ADD THIS LINE
+                journal = None;
+            }
+        })
+        .await;
+    outcomes.extend(processed);
+    outcomes.sort_by_key(|(row_num, _)| *row_num);
+
+    for (row_num, outcome) in outcomes {
+        match outcome {
+            Ok(processed) => {
+                processed_data.push(processed);
+                processed_rows.push(row_num);
+            }
+            Err(failure) => {
+                error_details.push(failure.detail(row_num));
                 errors_count += 1;
             }
         }
-        pb.inc(1); // Increment the progress bar
     }
 
     // Finish the progress bar
@@ -156,21 +220,47 @@ async fn main() -> Result<()> {
         errors_count
     );
 
-    let chemical_creation_plan = plan_chemical_creations(&processed_data);
-    let (reference_creation_plan, planned_reference_dois) =
-        plan_reference_creations(&processed_data);
-    let pending_chemical_creations = chemical_creation_plan.iter().any(|flag| *flag);
-    let pending_reference_creations = reference_creation_plan.iter().any(|flag| *flag);
-    let emit_occurrences = !(pending_chemical_creations || pending_reference_creations);
-    let record_reports = build_record_reports(
-        &processed_data,
-        &chemical_creation_plan,
-        &reference_creation_plan,
-        &planned_reference_dois,
-        emit_occurrences,
-    );
+    // Direct mode writes everything now, so the plans below describe the post-push state.
+    let mut direct_push_summary = None;
+    if let Some((username, password)) = &direct_credentials {
+        let plan = pipeline.plan(&processed_data);
+        info!("Logging in to {} as {}...", cli.api_url, username);
+        let session = WikibaseSession::login(&cli.api_url, username, password).await?;
+        let summary = push_to_wikidata(
+            &mut processed_data,
+            &plan.chemical,
+            &plan.reference,
+            &session,
+        )
+        .await?;
+        direct_push_summary = Some(summary);
+
+        // Re-journal the rows with their new QIDs so a resumed run does not create them twice.
+        if let Some(open_journal) = journal.as_mut() {
+            for (row, (enriched, wikidata)) in processed_rows.iter().zip(&processed_data) {
+                let entry = JournalEntry {
+                    row: *row,
+                    enriched: enriched.clone(),
+                    wikidata: wikidata.clone(),
+                };
+                if let Err(e) = open_journal.record(&entry) {
+                    warn!(
+                        "Failed to update checkpoint journal {:?} after the push: {}",
+                        open_journal.path(),
+                        e
+                    );
+                    break;
+                }
+            }
+        }
+    }
+
+    let plan = pipeline.plan(&processed_data);
+    let emit_occurrences = plan.emit_occurrences;
+    let record_reports = plan.record_reports(&processed_data);
     let chemical_creations = record_reports.iter().filter(|r| r.create_chemical).count();
     let reference_creations = record_reports.iter().filter(|r| r.create_reference).count();
+    let taxon_creations = record_reports.iter().filter(|r| r.create_taxon).count();
     let occurrence_creations = record_reports
         .iter()
         .filter(|r| r.create_occurrence)
@@ -179,13 +269,21 @@ async fn main() -> Result<()> {
         .iter()
         .filter(|r| r.occurrence_waiting_on_reference)
         .count();
+    let taxon_deferred_occurrences = record_reports
+        .iter()
+        .filter(|r| r.occurrence_waiting_on_taxon)
+        .count();
     let chemical_deferred_occurrences = record_reports
         .iter()
         .filter(|r| r.occurrence_waiting_on_chemical)
         .count();
     let unresolved_taxa = record_reports
         .iter()
-        .filter(|r| r.taxon_qid.is_none())
+        .filter(|r| r.taxon_qid.is_none() && !r.occurrence_waiting_on_taxon)
+        .count();
+    let ambiguous_taxa = record_reports
+        .iter()
+        .filter(|r| !r.taxon_candidates.is_empty())
         .count();
     let problematic_records = record_reports
         .iter()
@@ -209,13 +307,7 @@ async fn main() -> Result<()> {
             match File::create(&output_path) {
                 Ok(file) => {
                     let mut writer = BufWriter::new(file);
-                    if let Err(e) = generate_quickstatements(
-                        &processed_data,
-                        &chemical_creation_plan,
-                        &reference_creation_plan,
-                        emit_occurrences,
-                        &mut writer,
-                    ) {
+                    if let Err(e) = pipeline.emit(&processed_data, &plan, &mut writer) {
                         error!("Failed to generate QuickStatements: {}", e);
                         return Err(e);
                     }
@@ -236,15 +328,14 @@ async fn main() -> Result<()> {
             }
         }
         OutputMode::DirectPush => {
-            warn!("Direct push mode is not yet implemented.");
-            // Placeholder for future implementation
-            // if let Err(e) = wikidata::writer::push_to_wikidata(&processed_data, &client).await {
-            //     error!("Failed to push data directly to Wikidata: {}", e);
-            //     return Err(e);
-            // }
+            info!("Direct push finished; no QuickStatements file is written in this mode.");
         }
     }
 
+    if let Err(e) = cache::persist() {
+        warn!("Failed to compact the lookup cache: {}", e);
+    }
+
     let duration = start_time.elapsed();
     info!("Total execution time: {:.2?}", duration);
 
@@ -254,15 +345,31 @@ async fn main() -> Result<()> {
         "Total CSV records read (from initial validation): {}",
         processed_data.len() + errors_count
     );
+    if !diagnostics.is_empty() {
+        println!(
+            "Rows rejected by CSV validation: {}",
+            diagnostics
+                .iter()
+                .map(|d| d.row)
+                .collect::<HashSet<_>>()
+                .len()
+        );
+    }
     println!(
         "Successfully processed (passed enrichment and Wikidata checks): {}",
         processed_data.len()
     );
+    if resumed_count > 0 {
+        println!("Rows reused from the checkpoint journal: {}", resumed_count);
+    }
     println!("Chemical items queued for creation: {}", chemical_creations);
     println!(
         "Reference items queued for creation: {}",
         reference_creations
     );
+    if taxon_creations > 0 {
+        println!("Taxon items queued for creation: {}", taxon_creations);
+    }
     println!("Occurrence statements queued: {}", occurrence_creations);
     if deferred_occurrences > 0 {
         println!(
@@ -283,8 +390,12 @@ rerun this tool after the reference batch finishes to emit those occurrences."
             "  Run lotus-o3 again after submitting this batch so those occurrences can cite finalized QIDs."
         );
     } else if !emit_occurrences {
+        println!("Occurrence statements were skipped because item creations are still pending.");
+    }
+    if taxon_deferred_occurrences > 0 {
         println!(
-            "Occurrence statements were skipped because chemical/reference creations are still pending."
+            "Occurrence statements waiting on new taxa: {}",
+            taxon_deferred_occurrences
         );
     }
     if chemical_deferred_occurrences > 0 {
@@ -298,10 +409,21 @@ rerun this tool after the reference batch finishes to emit those occurrences."
     }
     if unresolved_taxa > 0 {
         println!(
-            "Records without a Wikidata taxon (not auto-created): {}",
+            "Records without a Wikidata taxon (not created): {}",
             unresolved_taxa
         );
-        println!("  Taxonomic name resolution/creation is not yet supported.");
+        println!(
+            "  --create-taxa creates missing species under an existing genus; the status report says why other rows were not created."
+        );
+    }
+    if ambiguous_taxa > 0 {
+        println!(
+            "Records whose taxon name matches several Wikidata items: {}",
+            ambiguous_taxa
+        );
+        println!(
+            "  Add a higher_taxon (e.g. kingdom) or taxon_qid column value to pick the right item."
+        );
     }
     if problematic_records > 0 {
         println!(
@@ -309,6 +431,30 @@ rerun this tool after the reference batch finishes to emit those occurrences."
             problematic_records
         );
     }
+    if let Some(summary) = &direct_push_summary {
+        println!(
+            "Chemical items created via the API: {}",
+            summary.created_chemicals
+        );
+        println!(
+            "Reference items created via the API: {}",
+            summary.created_references
+        );
+        println!(
+            "Occurrence statements added via the API: {}",
+            summary.added_occurrences
+        );
+        println!(
+            "Missing chemical statements added via the API: {}",
+            summary.added_statements
+        );
+        if !summary.failures.is_empty() {
+            println!("Direct edits that failed: {}", summary.failures.len());
+            for failure in &summary.failures {
+                println!("- {}", failure);
+            }
+        }
+    }
     println!("Errors encountered during processing: {}", errors_count);
     if !error_details.is_empty() {
         println!("\n--- Detailed Errors ---");
@@ -316,12 +462,19 @@ rerun this tool after the reference batch finishes to emit those occurrences."
             println!("- {}", detail);
         }
     }
+    print_rejected_rows(&diagnostics, rejected_rows_path.as_deref());
     if let Some(report_path) = status_report_path.clone() {
         println!(
             "Per-record status report saved to: {}",
             report_path.display()
         );
     }
+    if let Some(open_journal) = &journal {
+        println!(
+            "Checkpoint journal saved to: {} (pass it to --resume to skip finished rows)",
+            open_journal.path().display()
+        );
+    }
     println!("\n--- Next actions ---");
     if let Some(qs_path) = &quickstatements_file {
         if let Some(artifacts) = &qs_artifacts {
@@ -337,6 +490,11 @@ rerun this tool after the reference batch finishes to emit those occurrences."
         } else {
             println!("- Submit {} via QuickStatements.", qs_path.display());
         }
+    } else if direct_push_summary.is_some() {
+        println!(
+            "- Edits were written directly through {}; nothing to upload.",
+            cli.api_url
+        );
     } else {
         println!("- No QuickStatements batch generated in this run; nothing to upload.");
     }
@@ -366,6 +524,12 @@ rerun this tool after the reference batch finishes to emit those occurrences."
             problematic_records
         );
     }
+    if let Some(path) = &rejected_rows_path {
+        println!(
+            "- Fix the rows listed in {} and rerun lotus-o3 on them.",
+            path.display()
+        );
+    }
     if unresolved_taxa > 0 {
         println!(
             "- Resolve the {} missing taxon QID(s) manually on Wikidata before rerunning.",
@@ -377,233 +541,320 @@ rerun this tool after the reference batch finishes to emit those occurrences."
     Ok(())
 }
 
-fn handle_quickstatement_artifacts(
-    output_path: &Path,
-    records: &[RecordReport],
-) -> Result<QuickstatementArtifacts> {
-    let report_path = build_report_path(output_path);
-    write_status_report(records, &report_path)?;
-    println!("Per-record status saved to {}", report_path.display());
+fn new_progress_bar(len: usize) -> ProgressBar {
+    let pb = ProgressBar::new(len as u64);
+    pb.set_style(ProgressStyle::default_bar()
+        .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({percent}%) {msg}")
+        .expect("Failed to set progress bar style") // Added expect for error handling
+        .progress_chars("##-"));
+    pb
+}
 
-    let qs_content = fs::read_to_string(output_path)?;
-    let mut qs_url_file = None;
-    if qs_content.trim().is_empty() {
-        println!(
-            "\nQuickStatements file {} is empty; nothing to submit.",
-            output_path.display()
-        );
-    } else {
-        println!(
-            "\nQuickStatements commands saved to {}.",
-            output_path.display()
-        );
-        println!(
-            "Submit them via https://quickstatements.toolforge.org/ by pasting the file contents or opening this ready-to-run link (OAuth required):"
+/// Loads the input CSV in the requested validation mode. Rejected rows (lenient mode) are
+/// written next to the output file, or next to the input when there is no output file.
+fn load_input(
+    input_file: &Path,
+    columns: &ColumnConfig,
+    validation: &ValidationArgs,
+    output_file: Option<&Path>,
+) -> Result<LoadedInput> {
+    let validated = load_csv(input_file, columns, validation.validation).inspect_err(|e| {
+        error!("Failed to load or validate CSV: {}", e);
+    })?;
+    let mut rejected_rows_path = None;
+    if !validated.diagnostics.is_empty() {
+        warn!(
+            "Skipping {} invalid CSV row(s) ({} problem(s)).",
+            validated.rejected_rows(),
+            validated.diagnostics.len()
         );
-        let qs_url = quickstatements_link(&qs_content);
-        println!("{}", qs_url);
-        let url_path = build_qs_link_path(output_path);
-        fs::write(&url_path, format!("{}\n", qs_url))?;
-        println!("QuickStatements URL saved to {}", url_path.display());
-        qs_url_file = Some(url_path);
+        let path = validation
+            .rejected_rows
+            .clone()
+            .unwrap_or_else(|| build_rejected_rows_path(output_file.unwrap_or(input_file)));
+        write_rejected_rows(&validated.diagnostics, &path)?;
+        rejected_rows_path = Some(path);
     }
-
-    Ok(QuickstatementArtifacts {
-        status_report: report_path,
-        qs_url_file,
+    Ok(LoadedInput {
+        records: validated.records,
+        diagnostics: validated.diagnostics,
+        rejected_rows_path,
     })
 }
 
-fn plan_chemical_creations(records: &[(EnrichedData, WikidataInfo)]) -> Vec<bool> {
-    let mut seen: HashSet<String> = HashSet::new();
-    records
-        .iter()
-        .map(|(data, info)| {
-            if info.chemical_qid.is_some() {
-                false
-            } else if let Some(inchikey) = data.inchikey.as_deref() {
-                if seen.insert(inchikey.to_string()) {
-                    true
-                } else {
-                    false
-                }
-            } else {
-                true
-            }
-        })
-        .collect()
+fn print_rejected_rows(diagnostics: &[RowDiagnostic], rejected_rows_path: Option<&Path>) {
+    if diagnostics.is_empty() {
+        return;
+    }
+    println!("\n--- Rejected CSV rows ---");
+    for diagnostic in diagnostics {
+        println!("- {}", diagnostic);
+    }
+    if let Some(path) = rejected_rows_path {
+        println!("Rejected rows written to: {}", path.display());
+    }
 }
 
-fn plan_reference_creations(
-    records: &[(EnrichedData, WikidataInfo)],
-) -> (Vec<bool>, HashSet<String>) {
-    let mut seen: HashSet<String> = HashSet::new();
-    let mut plan = Vec::with_capacity(records.len());
-    for (_, info) in records.iter() {
-        let mut should_create = false;
-        if info.reference_qid.is_none() {
-            if let Some(metadata) = &info.reference_metadata {
-                let key = metadata.doi.trim().to_lowercase();
-                if seen.insert(key) {
-                    should_create = true;
-                }
-            }
+/// Runs `lotus-o3 validate`.
+fn run_validate(args: &ValidateArgs) -> Result<()> {
+    let LoadedInput {
+        records,
+        diagnostics,
+        rejected_rows_path,
+    } = load_input(
+        &args.input_file,
+        &ColumnConfig::from(&args.columns),
+        &args.validation,
+        None,
+    )?;
+    println!(
+        "{} record(s) in {} passed validation.",
+        records.len(),
+        args.input_file.display()
+    );
+    print_rejected_rows(&diagnostics, rejected_rows_path.as_deref());
+    Ok(())
+}
+
+/// Runs `lotus-o3 enrich`: CSV in, one enriched row per JSON line out.
+async fn run_enrich(args: &EnrichArgs) -> Result<()> {
+    cache::configure(args.cache_dir.as_deref())?;
+    let LoadedInput {
+        records: rows,
+        diagnostics,
+        rejected_rows_path,
+    } = load_input(
+        &args.input_file,
+        &ColumnConfig::from(&args.columns),
+        &args.validation,
+        Some(&args.output_file),
+    )?;
+    let pb = new_progress_bar(rows.len());
+    let pipeline = args
+        .enrichment
+        .configure(Pipeline::builder())?
+        .jobs(usize::from(args.jobs))
+        .progress(pb.clone())
+        .build()?;
+
+    let mut enriched_rows = Vec::new();
+    let mut failures = Vec::new();
+    for (row, outcome) in pipeline.enrich(rows).await {
+        match outcome {
+            Ok(data) => enriched_rows.push(EnrichedRow { row, data }),
+            Err(failure) => failures.push(failure.detail(row)),
         }
-        plan.push(should_create);
     }
-    (plan, seen)
+    pb.finish_with_message("Enrichment complete.");
+    write_jsonl(&args.output_file, &enriched_rows)?;
+    if let Err(e) = cache::persist() {
+        warn!("Failed to compact the lookup cache: {}", e);
+    }
+    print_step_summary(
+        "Enriched",
+        enriched_rows.len(),
+        &failures,
+        &args.output_file,
+    );
+    print_rejected_rows(&diagnostics, rejected_rows_path.as_deref());
+    Ok(())
 }
 
-fn build_record_reports(
-    records: &[(EnrichedData, WikidataInfo)],
-    chemical_creation_plan: &[bool],
-    reference_creation_plan: &[bool],
-    planned_reference_dois: &HashSet<String>,
-    emit_occurrences: bool,
-) -> Vec<RecordReport> {
-    records
-        .iter()
-        .zip(
-            chemical_creation_plan
-                .iter()
-                .zip(reference_creation_plan.iter()),
-        )
-        .map(|((data, info), (should_create_chemical, should_create_reference))| {
-            let create_chemical = *should_create_chemical;
-            let create_reference = *should_create_reference;
-            let taxon_available = info.taxon_qid.is_some();
-            let reference_qid_available = info.reference_qid.is_some();
-            let reference_key = data.reference_doi.trim().to_lowercase();
-            let reference_planned =
-                reference_qid_available || planned_reference_dois.contains(&reference_key);
-            let chemical_ready_now = info.chemical_qid.is_some();
-            let dependencies_ready_now =
-                chemical_ready_now && reference_qid_available && taxon_available;
-
-            let create_occurrence =
-                emit_occurrences && dependencies_ready_now && !info.occurrence_exists;
-            let occurrence_waiting_on_reference = !reference_qid_available && reference_planned;
-            let occurrence_waiting_on_chemical = !chemical_ready_now;
-            let occurrence_waiting_on_batch =
-                !emit_occurrences && dependencies_ready_now && !info.occurrence_exists;
-
-            let mut issues = Vec::new();
-            if info.taxon_qid.is_none() {
-                issues.push(
-                    "Taxon entity not found in Wikidata; taxonomic name resolution is not implemented."
-                        .to_string(),
-                );
-            }
-            if info.reference_qid.is_none() && info.reference_metadata.is_none() {
-                issues.push(
-                    "DOI missing in Wikidata and Crossref lookup failed; reference must be curated manually."
-                        .to_string(),
-                );
-            }
-            if occurrence_waiting_on_reference {
-                issues.push(
-                    "Occurrence deferred until the new reference item has a QID; rerun the importer after this batch finishes in QuickStatements."
-                        .to_string(),
-                );
-            }
-            if occurrence_waiting_on_chemical {
-                issues.push(
-                    "Occurrence deferred until the chemical item exists in Wikidata; rerun after uploading this creation batch."
-                        .to_string(),
-                );
-            }
-            if occurrence_waiting_on_batch {
-                issues.push(
-                    "Occurrence deferred because this run is limited to entity creations; rerun lotus-o3 once those QIDs are live."
-                        .to_string(),
-                );
-            } else if !info.occurrence_exists && !create_occurrence && taxon_available {
-                if !reference_planned {
-                    issues.push("Missing reference metadata prevents occurrence creation.".to_string());
-                }
-            }
+/// Runs `lotus-o3 check`: enriched rows in, checked records (journal format) out.
+async fn run_check(args: &CheckArgs) -> Result<()> {
+    cache::configure(args.cache_dir.as_deref())?;
+    checker::configure_sparql_url(&args.sparql_url);
+    let enriched_rows: Vec<EnrichedRow> = read_jsonl(&args.input_file)?;
+    let pb = new_progress_bar(enriched_rows.len());
+    let pipeline = Pipeline::builder()
+        .jobs(usize::from(args.jobs))
+        .progress(pb.clone())
+        .check_options(CheckOptions::try_from(&args.taxa)?)
+        .build()?;
+    let rows = enriched_rows
+        .into_iter()
+        .map(|enriched_row| (enriched_row.row, enriched_row.data))
+        .collect();
+
+    let mut checked = Vec::new();
+    let mut failures = Vec::new();
+    for (row, outcome) in pipeline.check(rows).await {
+        match outcome {
+            Ok((enriched, wikidata)) => checked.push(JournalEntry {
+                row,
+                enriched,
+                wikidata,
+            }),
+            Err(failure) => failures.push(failure.detail(row)),
+        }
+    }
+    pb.finish_with_message("Wikidata checks complete.");
+    write_jsonl(&args.output_file, &checked)?;
+    if let Err(e) = cache::persist() {
+        warn!("Failed to compact the lookup cache: {}", e);
+    }
+    print_step_summary("Checked", checked.len(), &failures, &args.output_file);
+    Ok(())
+}
 
-            RecordReport {
-                chemical_entity_name: data.chemical_entity_name.clone(),
-                original_smiles: data.input_smiles.clone(),
-                sanitized_smiles: data.sanitized_smiles.clone(),
-                canonical_smiles: data.canonical_smiles.clone(),
-                isomeric_smiles: data.isomeric_smiles.clone(),
-                smiles_were_sanitized: data.smiles_were_sanitized,
-                taxon_name: data.taxon_name.clone(),
-                reference_doi: data.reference_doi.clone(),
-                chemical_qid: info.chemical_qid.clone(),
-                taxon_qid: info.taxon_qid.clone(),
-                reference_qid: info.reference_qid.clone(),
-                create_chemical,
-                create_reference,
-                create_occurrence,
-                occurrence_waiting_on_reference,
-                occurrence_waiting_on_chemical,
-                occurrence_waiting_on_batch,
-                issues,
-            }
+/// Runs `lotus-o3 plan`: checked records in, planned records plus status report out.
+fn run_plan(args: &PlanArgs) -> Result<()> {
+    let mut checked: Vec<JournalEntry> = read_jsonl(&args.input_file)?;
+    checked.sort_by_key(|entry| entry.row);
+    let rows: Vec<usize> = checked.iter().map(|entry| entry.row).collect();
+    let records: Vec<(EnrichedData, WikidataInfo)> = checked
+        .into_iter()
+        .map(|entry| (entry.enriched, entry.wikidata))
+        .collect();
+
+    let plan = CreationPlan::new(&records, &PlanOptions::from(&args.creation));
+    let record_reports = plan.record_reports(&records);
+
+    let planned: Vec<PlannedRecord> = rows
+        .into_iter()
+        .zip(records)
+        .enumerate()
+        .map(|(index, (row, (enriched, wikidata)))| PlannedRecord {
+            row,
+            enriched,
+            wikidata,
+            create_chemical: plan.chemical[index],
+            create_reference: plan.reference[index],
+            create_taxon: plan.taxon[index],
         })
-        .collect()
+        .collect();
+    write_jsonl(&args.output_file, &planned)?;
+    let report_path = build_report_path(&args.output_file);
+    write_status_report(&record_reports, &report_path)?;
+
+    println!(
+        "Planned {} record(s): {} chemical, {} reference and {} taxon creation(s), {} occurrence statement(s).",
+        planned.len(),
+        record_reports.iter().filter(|r| r.create_chemical).count(),
+        record_reports.iter().filter(|r| r.create_reference).count(),
+        record_reports.iter().filter(|r| r.create_taxon).count(),
+        record_reports
+            .iter()
+            .filter(|r| r.create_occurrence)
+            .count()
+    );
+    println!("Plan written to {}", args.output_file.display());
+    println!("Per-record status saved to {}", report_path.display());
+    Ok(())
 }
 
-fn write_status_report(rows: &[RecordReport], path: &Path) -> Result<()> {
-    let mut writer = WriterBuilder::new().delimiter(b'\t').from_path(path)?;
-    writer.write_record([
-        "chemical_entity_name",
-        "input_smiles",
-        "sanitized_smiles",
-        "canonical_smiles",
-        "isomeric_smiles",
-        "smiles_were_sanitized",
-        "taxon_name",
-        "reference_doi",
-        "chemical_qid",
-        "taxon_qid",
-        "reference_qid",
-        "create_chemical",
-        "create_reference",
-        "create_occurrence",
-        "occurrence_waiting_on_reference",
-        "occurrence_waiting_on_chemical",
-        "occurrence_waiting_on_batch",
-        "issues",
-    ])?;
-
-    for row in rows {
-        let issues_text = if row.issues.is_empty() {
-            "".to_string()
-        } else {
-            row.issues.join("; ")
-        };
-        let canonical = row.canonical_smiles.as_deref().unwrap_or("");
-        let isomeric = row.isomeric_smiles.as_deref().unwrap_or("");
-        writer.write_record([
-            row.chemical_entity_name.as_str(),
-            row.original_smiles.as_str(),
-            row.sanitized_smiles.as_str(),
-            canonical,
-            isomeric,
-            bool_to_label(row.smiles_were_sanitized),
-            row.taxon_name.as_str(),
-            row.reference_doi.as_str(),
-            row.chemical_qid.as_deref().unwrap_or(""),
-            row.taxon_qid.as_deref().unwrap_or(""),
-            row.reference_qid.as_deref().unwrap_or(""),
-            bool_to_label(row.create_chemical),
-            bool_to_label(row.create_reference),
-            bool_to_label(row.create_occurrence),
-            bool_to_label(row.occurrence_waiting_on_reference),
-            bool_to_label(row.occurrence_waiting_on_chemical),
-            bool_to_label(row.occurrence_waiting_on_batch),
-            issues_text.as_str(),
-        ])?;
+/// Runs `lotus-o3 emit`: planned records in, QuickStatements file out.
+fn run_emit(args: &EmitArgs) -> Result<()> {
+    let mut planned: Vec<PlannedRecord> = read_jsonl(&args.input_file)?;
+    planned.sort_by_key(|record| record.row);
+    let plan = CreationPlan::from_flags(
+        planned.iter().map(|r| r.create_chemical).collect(),
+        planned.iter().map(|r| r.create_reference).collect(),
+        planned.iter().map(|r| r.create_taxon).collect(),
+        HashSet::new(),
+        HashSet::new(),
+    );
+    let records: Vec<(EnrichedData, WikidataInfo)> = planned
+        .into_iter()
+        .map(|record| (record.enriched, record.wikidata))
+        .collect();
+
+    let pipeline = Pipeline::builder().build()?;
+    let mut writer = BufWriter::new(File::create(&args.output_file)?);
+    pipeline.emit(&records, &plan, &mut writer)?;
+    writer.flush()?;
+    write_quickstatements_link(&args.output_file)?;
+    if !plan.emit_occurrences {
+        println!("Occurrence statements were skipped because item creations are still pending.");
     }
+    Ok(())
+}
 
-    writer.flush()?;
+fn print_step_summary(step: &str, written: usize, failures: &[String], output_path: &Path) {
+    println!(
+        "{} {} record(s); written to {}",
+        step,
+        written,
+        output_path.display()
+    );
+    if !failures.is_empty() {
+        println!("Records that failed and were left out: {}", failures.len());
+        for failure in failures {
+            println!("- {}", failure);
+        }
+    }
+}
+
+/// Runs `lotus-o3 cache clear|stats`.
+fn run_cache_command(action: &CacheAction) -> Result<()> {
+    match action {
+        CacheAction::Clear(args) => {
+            let removed = cache::clear(&args.cache_dir)?;
+            println!(
+                "Removed {} cache file(s) from {}",
+                removed,
+                args.cache_dir.display()
+            );
+        }
+        CacheAction::Stats(args) => {
+            println!("Lookup cache at {}", args.cache_dir.display());
+            println!("kind\tentries\tnegative\texpired\tbytes");
+            for stats in cache::stats(&args.cache_dir)? {
+                println!(
+                    "{}\t{}\t{}\t{}\t{}",
+                    stats.kind.name(),
+                    stats.entries,
+                    stats.negative,
+                    stats.expired,
+                    stats.bytes
+                );
+            }
+        }
+    }
     Ok(())
 }
 
+fn handle_quickstatement_artifacts(
+    output_path: &Path,
+    records: &[RecordReport],
+) -> Result<QuickstatementArtifacts> {
+    let report_path = build_report_path(output_path);
+    write_status_report(records, &report_path)?;
+    println!("Per-record status saved to {}", report_path.display());
+
+    let qs_url_file = write_quickstatements_link(output_path)?;
+
+    Ok(QuickstatementArtifacts {
+        status_report: report_path,
+        qs_url_file,
+    })
+}
+
+/// Prints the QuickStatements link for a non-empty batch and saves it next to the QS file.
+fn write_quickstatements_link(output_path: &Path) -> Result<Option<PathBuf>> {
+    let qs_content = fs::read_to_string(output_path)?;
+    if qs_content.trim().is_empty() {
+        println!(
+            "\nQuickStatements file {} is empty; nothing to submit.",
+            output_path.display()
+        );
+        return Ok(None);
+    }
+    println!(
+        "\nQuickStatements commands saved to {}.",
+        output_path.display()
+    );
+    println!(
+        "Submit them via https://quickstatements.toolforge.org/ by pasting the file contents or opening this ready-to-run link (OAuth required):"
+    );
+    let qs_url = quickstatements_link(&qs_content);
+    println!("{}", qs_url);
+    let url_path = build_qs_link_path(output_path);
+    fs::write(&url_path, format!("{}\n", qs_url))?;
+    println!("QuickStatements URL saved to {}", url_path.display());
+    Ok(Some(url_path))
+}
+
 fn build_report_path(output_path: &Path) -> PathBuf {
     let stem = output_path
         .file_stem()
@@ -613,6 +864,24 @@ fn build_report_path(output_path: &Path) -> PathBuf {
     output_path.with_file_name(file_name)
 }
 
+fn build_rejected_rows_path(base_path: &Path) -> PathBuf {
+    let stem = base_path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("lotus-o3");
+    let file_name = format!("{}_rejected.csv", stem);
+    base_path.with_file_name(file_name)
+}
+
+fn build_journal_path(output_path: &Path) -> PathBuf {
+    let stem = output_path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("lotus-o3");
+    let file_name = format!("{}_journal.jsonl", stem);
+    output_path.with_file_name(file_name)
+}
+
 fn build_qs_link_path(output_path: &Path) -> PathBuf {
     let stem = output_path
         .file_stem()
@@ -631,29 +900,11 @@ fn quickstatements_link(contents: &str) -> String {
     )
 }
 
-fn bool_to_label(flag: bool) -> &'static str {
-    if flag { "yes" } else { "no" }
-}
-
-struct RecordReport {
-    chemical_entity_name: String,
-    original_smiles: String,
-    sanitized_smiles: String,
-    canonical_smiles: Option<String>,
-    isomeric_smiles: Option<String>,
-    smiles_were_sanitized: bool,
-    taxon_name: String,
-    reference_doi: String,
-    chemical_qid: Option<String>,
-    taxon_qid: Option<String>,
-    reference_qid: Option<String>,
-    create_chemical: bool,
-    create_reference: bool,
-    create_occurrence: bool,
-    occurrence_waiting_on_reference: bool,
-    occurrence_waiting_on_chemical: bool,
-    occurrence_waiting_on_batch: bool,
-    issues: Vec<String>,
+/// Valid input rows plus what was rejected while loading them.
+struct LoadedInput {
+    records: Vec<(usize, InputRecord)>,
+    diagnostics: Vec<RowDiagnostic>,
+    rejected_rows_path: Option<PathBuf>,
 }
 
 struct QuickstatementArtifacts {
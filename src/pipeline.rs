@@ -0,0 +1,640 @@
+//! Configurable enrich → check → plan → emit pipeline used by the CLI and by library callers.
+//!
+//! ```no_run
+//! # async fn run(records: Vec<lotus_o3::csv_handler::InputRecord>) -> lotus_o3::error::Result<()> {
+//! use lotus_o3::pipeline::Pipeline;
+//!
+//! let pipeline = Pipeline::builder().jobs(8).build()?;
+//! let rows = records.into_iter().enumerate().map(|(i, r)| (i + 2, r)).collect();
+//! let processed: Vec<_> = pipeline
+//!     .process(rows, |_, _| {})
+//!     .await
+//!     .into_iter()
+//!     .filter_map(|(_, outcome)| outcome.ok())
+//!     .collect();
+//! let plan = pipeline.plan(&processed);
+//! pipeline.emit(&processed, &plan, &mut std::io::stdout())?;
+//! # Ok(())
+//! # }
+//! ```
+use crate::chemical_entity::descriptors::DescriptorTable;
+use crate::chemical_entity::enricher::{StructureEnricher, default_enricher};
+use crate::chemical_entity::identifiers::IdentifierResolver;
+use crate::csv_handler::InputRecord;
+use crate::enrichment::{EnrichedData, enrich_record, enriched_from_structure, link_identifiers};
+use crate::error::{CrateError, Result};
+use crate::planner::{CreationPlan, PlanOptions};
+use crate::wikidata::checker::{CheckOptions, USER_AGENT, WikidataInfo, check_wikidata_batch};
+use crate::wikidata::writer::generate_quickstatements;
+use futures::channel::mpsc;
+use futures::future::{self, BoxFuture};
+use futures::stream::{self, StreamExt};
+use indicatif::ProgressBar;
+use log::error;
+use reqwest::Client;
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::sync::Arc;
+
+/// Maximum number of enriched rows `process` checks against Wikidata in one batch.
+pub const DEFAULT_CHECK_BATCH_SIZE: usize = 500;
+
+/// Enrichment hook; same contract as [`enrich_record`].
+pub type EnrichHook =
+    Arc<dyn Fn(InputRecord, Client) -> BoxFuture<'static, Result<EnrichedData>> + Send + Sync>;
+
+/// Per-record Wikidata check hook; same contract as
+/// [`check_wikidata`](crate::wikidata::checker::check_wikidata).
+pub type CheckHook =
+    Arc<dyn Fn(EnrichedData, Client) -> BoxFuture<'static, Result<WikidataInfo>> + Send + Sync>;
+
+/// Output hook; same contract as [`generate_quickstatements`].
+pub type EmitHook = Arc<
+    dyn Fn(
+            &[(EnrichedData, WikidataInfo)],
+            &[bool],
+            &[bool],
+            &[bool],
+            bool,
+            &mut dyn Write,
+        ) -> Result<()>
+        + Send
+        + Sync,
+>;
+
+/// Outcome of enriching and checking a single row.
+pub type RowOutcome = std::result::Result<(EnrichedData, WikidataInfo), RecordFailure>;
+
+/// Why a single CSV row dropped out of processing.
+#[derive(Debug)]
+pub enum RecordFailure {
+    Enrichment { smiles: String, error: CrateError },
+    WikidataCheck { inchikey: String, error: CrateError },
+}
+
+impl RecordFailure {
+    /// Message used in the detailed error list.
+    pub fn detail(&self, row_num: usize) -> String {
+        match self {
+            RecordFailure::Enrichment { smiles, error } => format!(
+                "Row {}: Enrichment failed for SMILES {}: {}",
+                row_num, smiles, error
+            ),
+            RecordFailure::WikidataCheck { inchikey, error } => format!(
+                "Row {}: Wikidata check failed for InChIKey {}: {}",
+                row_num, inchikey, error
+            ),
+        }
+    }
+
+    /// Shorter message printed above the progress bar.
+    pub fn progress_line(&self, row_num: usize) -> String {
+        match self {
+            RecordFailure::Enrichment { smiles, error } => format!(
+                "Error (Enrichment) for row {}: {} - {}",
+                row_num, smiles, error
+            ),
+            RecordFailure::WikidataCheck { inchikey, error } => format!(
+                "Error (Wikidata check) for row {}: {} - {}",
+                row_num, inchikey, error
+            ),
+        }
+    }
+}
+
+/// Builder for [`Pipeline`]; every hook defaults to the built-in implementation.
+pub struct PipelineBuilder {
+    client: Option<Client>,
+    jobs: usize,
+    check_batch_size: usize,
+    progress: Option<ProgressBar>,
+    check_options: CheckOptions,
+    plan_options: PlanOptions,
+    enricher: Arc<dyn StructureEnricher>,
+    descriptors: Option<Arc<DescriptorTable>>,
+    identifier_resolvers: Vec<IdentifierResolver>,
+    enrich: Option<EnrichHook>,
+    check: Option<CheckHook>,
+    emit: EmitHook,
+}
+
+impl Default for PipelineBuilder {
+    fn default() -> Self {
+        Self {
+            client: None,
+            jobs: 1,
+            check_batch_size: DEFAULT_CHECK_BATCH_SIZE,
+            progress: None,
+            check_options: CheckOptions::default(),
+            plan_options: PlanOptions::default(),
+            enricher: default_enricher(),
+            descriptors: None,
+            identifier_resolvers: Vec::new(),
+            enrich: None,
+            check: None,
+            emit: Arc::new(generate_quickstatements),
+        }
+    }
+}
+
+impl PipelineBuilder {
+    /// HTTP client shared by all hooks (defaults to one with the lotus-o3 user agent).
+    pub fn client(mut self, client: Client) -> Self {
+        self.client = Some(client);
+        self
+    }
+
+    /// Number of rows enriched (or checked by a custom hook) concurrently; at least 1.
+    pub fn jobs(mut self, jobs: usize) -> Self {
+        self.jobs = jobs.max(1);
+        self
+    }
+
+    /// Maximum number of rows `process` checks in one Wikidata batch; at least 1.
+    pub fn check_batch_size(mut self, rows: usize) -> Self {
+        self.check_batch_size = rows.max(1);
+        self
+    }
+
+    /// Progress bar advanced once per enriched row.
+    pub fn progress(mut self, progress: ProgressBar) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
+    /// Options for the built-in Wikidata check (ignored by a custom `check_with` hook).
+    pub fn check_options(mut self, options: CheckOptions) -> Self {
+        self.check_options = options;
+        self
+    }
+
+    /// Opt-in creations for `plan` (e.g. missing taxa).
+    pub fn plan_options(mut self, options: PlanOptions) -> Self {
+        self.plan_options = options;
+        self
+    }
+
+    /// Structure enrichment backend for the built-in enrichment step (ignored by a custom
+    /// `enrich_with` hook).
+    pub fn enricher(mut self, enricher: Arc<dyn StructureEnricher>) -> Self {
+        self.enricher = enricher;
+        self
+    }
+
+    /// Precomputed structures used instead of the enrichment step for the rows (or SMILES) they
+    /// list.
+    pub fn descriptors(mut self, table: Arc<DescriptorTable>) -> Self {
+        self.descriptors = Some(table);
+        self
+    }
+
+    /// Databases (PubChem, ChEBI, ChEMBL, COCONUT) whose identifiers are looked up for every
+    /// enriched row, including rows from a custom `enrich_with` hook or the descriptor table.
+    pub fn identifier_resolvers(mut self, resolvers: Vec<IdentifierResolver>) -> Self {
+        self.identifier_resolvers = resolvers;
+        self
+    }
+
+    /// Replaces the enrichment step.
+    pub fn enrich_with<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(InputRecord, Client) -> BoxFuture<'static, Result<EnrichedData>>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.enrich = Some(Arc::new(hook));
+        self
+    }
+
+    /// Replaces the Wikidata check with a per-record hook. Without one, the pipeline resolves
+    /// every row with batched VALUES queries, which gives the same results as calling
+    /// `check_wikidata` per record with far fewer requests.
+    pub fn check_with<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(EnrichedData, Client) -> BoxFuture<'static, Result<WikidataInfo>>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.check = Some(Arc::new(hook));
+        self
+    }
+
+    /// Replaces the QuickStatements writer.
+    pub fn emit_with<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(
+                &[(EnrichedData, WikidataInfo)],
+                &[bool],
+                &[bool],
+                &[bool],
+                bool,
+                &mut dyn Write,
+            ) -> Result<()>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.emit = Arc::new(hook);
+        self
+    }
+
+    pub fn build(self) -> Result<Pipeline> {
+        let client = match self.client {
+            Some(client) => client,
+            None => Client::builder()
+                .user_agent(USER_AGENT)
+                .build()
+                .map_err(CrateError::ApiRequestError)?,
+        };
+        let enricher = self.enricher;
+        let enrich = self.enrich.unwrap_or_else(|| {
+            Arc::new(move |record, client| {
+                let enricher = Arc::clone(&enricher);
+                Box::pin(async move { enrich_record(record, enricher.as_ref(), &client).await })
+            })
+        });
+        Ok(Pipeline {
+            client,
+            jobs: self.jobs,
+            check_batch_size: self.check_batch_size,
+            progress: self.progress.unwrap_or_else(ProgressBar::hidden),
+            check_options: self.check_options,
+            plan_options: self.plan_options,
+            descriptors: self.descriptors,
+            identifier_resolvers: Arc::new(self.identifier_resolvers),
+            enrich,
+            check: self.check,
+            emit: self.emit,
+        })
+    }
+}
+
+/// Enrich → check → plan → emit, with each step replaceable through [`PipelineBuilder`].
+///
+/// Rows are identified by their CSV row number; every step returns outcomes sorted by it.
+pub struct Pipeline {
+    client: Client,
+    jobs: usize,
+    check_batch_size: usize,
+    progress: ProgressBar,
+    check_options: CheckOptions,
+    plan_options: PlanOptions,
+    descriptors: Option<Arc<DescriptorTable>>,
+    identifier_resolvers: Arc<Vec<IdentifierResolver>>,
+    enrich: EnrichHook,
+    check: Option<CheckHook>,
+    emit: EmitHook,
+}
+
+impl Pipeline {
+    pub fn builder() -> PipelineBuilder {
+        PipelineBuilder::default()
+    }
+
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    /// Enriches and checks `rows`, passing each finished row to `on_row` in input order as soon
+    /// as it and every row before it are done (the CLI journals it there). Enrichment keeps up
+    /// to `jobs` rows in flight while earlier rows are checked; each Wikidata batch takes the
+    /// rows enriched since the previous batch started, up to the check batch size.
+    pub async fn process<F>(
+        &self,
+        rows: Vec<(usize, InputRecord)>,
+        mut on_row: F,
+    ) -> Vec<(usize, RowOutcome)>
+    where
+        F: FnMut(usize, &RowOutcome),
+    {
+        let total = rows.len();
+        let (sender, receiver) = mpsc::unbounded();
+        let enrichment = async move {
+            let mut enriched = stream::iter(rows.into_iter().enumerate())
+                .map(|(position, (row_num, record))| async move {
+                    (position, row_num, self.enrich_row(row_num, record).await)
+                })
+                .buffer_unordered(self.jobs);
+            while let Some((position, row_num, outcome)) = enriched.next().await {
+                self.report_enrichment(row_num, &outcome);
+                if sender.unbounded_send((position, row_num, outcome)).is_err() {
+                    break;
+                }
+            }
+        };
+        let checking = async {
+            let mut outcomes = Vec::with_capacity(total);
+            // Rows finished out of order wait here until every earlier row is done.
+            let mut finished: BTreeMap<usize, (usize, RowOutcome)> = BTreeMap::new();
+            let mut batches = receiver.ready_chunks(self.check_batch_size);
+            while let Some(batch) = batches.next().await {
+                let mut positions = Vec::new();
+                let mut enriched_rows = Vec::new();
+                for (position, row_num, outcome) in batch {
+                    match outcome {
+                        Ok(enriched) => {
+                            positions.push(position);
+                            enriched_rows.push((row_num, enriched));
+                        }
+                        Err(failure) => {
+                            finished.insert(position, (row_num, Err(failure)));
+                        }
+                    }
+                }
+                if !enriched_rows.is_empty() {
+                    finished.extend(positions.into_iter().zip(self.check(enriched_rows).await));
+                }
+                while let Some((row_num, outcome)) = finished.remove(&outcomes.len()) {
+                    on_row(row_num, &outcome);
+                    outcomes.push((row_num, outcome));
+                }
+            }
+            outcomes
+        };
+        let ((), outcomes) = future::join(enrichment, checking).await;
+        outcomes
+    }
+
+    /// Enriches rows concurrently (bounded by `jobs`). Rows found in the descriptor table skip
+    /// the enrichment hook; identifiers are then linked for every enriched row.
+    pub async fn enrich(
+        &self,
+        rows: Vec<(usize, InputRecord)>,
+    ) -> Vec<(usize, std::result::Result<EnrichedData, RecordFailure>)> {
+        let mut outcomes = Vec::with_capacity(rows.len());
+        let mut pending =
+            stream::iter(rows)
+                .map(|(row_num, record)| async move {
+                    (row_num, self.enrich_row(row_num, record).await)
+                })
+                .buffer_unordered(self.jobs);
+        while let Some((row_num, outcome)) = pending.next().await {
+            self.report_enrichment(row_num, &outcome);
+            outcomes.push((row_num, outcome));
+        }
+        outcomes.sort_by_key(|(row_num, _)| *row_num);
+        outcomes
+    }
+
+    // Enriches one row from the descriptor table or the enrichment hook, then links identifiers.
+    async fn enrich_row(
+        &self,
+        row_num: usize,
+        record: InputRecord,
+    ) -> std::result::Result<EnrichedData, RecordFailure> {
+        self.progress.set_message(format!(
+            "Processing: {} ({})",
+            record.chemical_entity_name, record.chemical_entity_smiles
+        ));
+        let precomputed = self
+            .descriptors
+            .as_ref()
+            .and_then(|table| table.lookup(Some(row_num), &record.chemical_entity_smiles));
+        let smiles = record.chemical_entity_smiles.clone(); // Clone for error reporting
+        let mut enriched = match precomputed {
+            Some(Ok(structure)) => enriched_from_structure(record, structure),
+            Some(Err(error)) => return Err(RecordFailure::Enrichment { smiles, error }),
+            None => (self.enrich)(record, self.client.clone())
+                .await
+                .map_err(|error| RecordFailure::Enrichment { smiles, error })?,
+        };
+        link_identifiers(&mut enriched, &self.identifier_resolvers, &self.client).await;
+        Ok(enriched)
+    }
+
+    fn report_enrichment(
+        &self,
+        row_num: usize,
+        outcome: &std::result::Result<EnrichedData, RecordFailure>,
+    ) {
+        if let Err(failure) = outcome {
+            self.progress.println(failure.progress_line(row_num));
+            error!("{}", failure.detail(row_num));
+        }
+        self.progress.inc(1);
+    }
+
+    /// Checks enriched rows against Wikidata.
+    pub async fn check(&self, rows: Vec<(usize, EnrichedData)>) -> Vec<(usize, RowOutcome)> {
+        let pb = &self.progress;
+        pb.set_message(format!("Checking {} records against Wikidata", rows.len()));
+        let (row_nums, enriched_records): (Vec<usize>, Vec<EnrichedData>) =
+            rows.into_iter().unzip();
+        let checks = match &self.check {
+            None => {
+                check_wikidata_batch(
+                    &enriched_records,
+                    &self.check_options,
+                    self.jobs,
+                    &self.client,
+                )
+                .await
+            }
+            Some(hook) => {
+                stream::iter(enriched_records.iter().cloned())
+                    .map(|record| hook(record, self.client.clone()))
+                    .buffered(self.jobs)
+                    .collect()
+                    .await
+            }
+        };
+
+        let mut outcomes = Vec::with_capacity(row_nums.len());
+        for ((row_num, enriched), check) in row_nums.into_iter().zip(enriched_records).zip(checks) {
+            match check {
+                Ok(wikidata_info) => outcomes.push((row_num, Ok((enriched, wikidata_info)))),
+                Err(error) => {
+                    let failure = RecordFailure::WikidataCheck {
+                        inchikey: enriched
+                            .inchikey
+                            .clone()
+                            .unwrap_or_else(|| "N/A".to_string()),
+                        error,
+                    };
+                    pb.println(failure.progress_line(row_num));
+                    error!("{}", failure.detail(row_num));
+                    outcomes.push((row_num, Err(failure)));
+                }
+            }
+        }
+        outcomes
+    }
+
+    /// Plans item creations for the processed records.
+    pub fn plan(&self, records: &[(EnrichedData, WikidataInfo)]) -> CreationPlan {
+        CreationPlan::new(records, &self.plan_options)
+    }
+
+    /// Writes the planned edits through the emit hook (QuickStatements by default).
+    pub fn emit(
+        &self,
+        records: &[(EnrichedData, WikidataInfo)],
+        plan: &CreationPlan,
+        writer: &mut dyn Write,
+    ) -> Result<()> {
+        (self.emit)(
+            records,
+            &plan.chemical,
+            &plan.reference,
+            &plan.taxon,
+            plan.emit_occurrences,
+            writer,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::FutureExt;
+
+    fn input(smiles: &str) -> InputRecord {
+        InputRecord {
+            chemical_entity_name: format!("Compound {}", smiles),
+            chemical_entity_smiles: smiles.to_string(),
+            taxon_name: "Coffea arabica".to_string(),
+            reference_doi: "10.1000/test".to_string(),
+            preset_qids: Default::default(),
+            taxon_name_warnings: Vec::new(),
+            higher_taxon: None,
+        }
+    }
+
+    fn offline_pipeline(batch_size: usize) -> Pipeline {
+        Pipeline::builder()
+            .jobs(4)
+            .check_batch_size(batch_size)
+            .enrich_with(|record, _client| {
+                async move {
+                    if record.chemical_entity_smiles == "invalid" {
+                        return Err(CrateError::SmilesSanitizationFailed {
+                            input_smiles: record.chemical_entity_smiles,
+                            reason: "test".to_string(),
+                        });
+                    }
+                    Ok(EnrichedData {
+                        chemical_entity_name: record.chemical_entity_name,
+                        input_smiles: record.chemical_entity_smiles.clone(),
+                        sanitized_smiles: record.chemical_entity_smiles,
+                        taxon_name: record.taxon_name,
+                        reference_doi: record.reference_doi,
+                        inchikey: Some("VNWKTOKETHGBQD-UHFFFAOYSA-N".to_string()),
+                        preset_qids: record.preset_qids,
+                        taxon_name_warnings: record.taxon_name_warnings,
+                        higher_taxon: record.higher_taxon,
+                        ..Default::default()
+                    })
+                }
+                .boxed()
+            })
+            .check_with(|_record, _client| {
+                async move {
+                    Ok(WikidataInfo {
+                        chemical_qid: Some("Q37129".to_string()),
+                        taxon_qid: Some("Q1".to_string()),
+                        reference_qid: Some("Q2".to_string()),
+                        ..Default::default()
+                    })
+                }
+                .boxed()
+            })
+            .build()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_process_with_custom_hooks_keeps_row_order() {
+        let pipeline = offline_pipeline(2);
+        let rows = vec![(2, input("C")), (3, input("invalid")), (4, input("CC"))];
+        let mut finished = Vec::new();
+        let outcomes = pipeline
+            .process(rows, |row_num, _| finished.push(row_num))
+            .await;
+
+        assert_eq!(finished, vec![2, 3, 4]);
+        let row_nums: Vec<usize> = outcomes.iter().map(|(row, _)| *row).collect();
+        assert_eq!(row_nums, vec![2, 3, 4]);
+        assert!(matches!(
+            outcomes[1].1,
+            Err(RecordFailure::Enrichment { .. })
+        ));
+
+        let processed: Vec<_> = outcomes
+            .into_iter()
+            .filter_map(|(_, outcome)| outcome.ok())
+            .collect();
+        let plan = pipeline.plan(&processed);
+        assert!(plan.emit_occurrences);
+        let mut output = Vec::new();
+        pipeline.emit(&processed, &plan, &mut output).unwrap();
+        let qs = String::from_utf8(output).unwrap();
+        assert!(qs.contains("Q37129\tP703\tQ1"));
+    }
+
+    #[tokio::test]
+    async fn test_process_hands_out_rows_before_later_ones_finish() {
+        // Row 3 only finishes once row 2 has been handed to the callback.
+        let row_two_done = Arc::new(tokio::sync::Notify::new());
+        let waiter = Arc::clone(&row_two_done);
+        let pipeline = Pipeline::builder()
+            .jobs(2)
+            .enrich_with(move |record, _client| {
+                let waiter = Arc::clone(&waiter);
+                async move {
+                    if record.chemical_entity_smiles == "CC" {
+                        waiter.notified().await;
+                    }
+                    Ok(EnrichedData {
+                        input_smiles: record.chemical_entity_smiles,
+                        ..Default::default()
+                    })
+                }
+                .boxed()
+            })
+            .check_with(|_record, _client| async { Ok(WikidataInfo::default()) }.boxed())
+            .build()
+            .unwrap();
+
+        let rows = vec![(2, input("C")), (3, input("CC"))];
+        let outcomes = tokio::time::timeout(
+            std::time::Duration::from_secs(5),
+            pipeline.process(rows, |row_num, _| {
+                if row_num == 2 {
+                    row_two_done.notify_one();
+                }
+            }),
+        )
+        .await
+        .expect("row 2 is handed out while row 3 is still running");
+        assert_eq!(outcomes.len(), 2);
+        assert!(outcomes.iter().all(|(_, outcome)| outcome.is_ok()));
+    }
+
+    #[tokio::test]
+    async fn test_descriptor_table_skips_enrichment() {
+        use std::io::Write;
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        writeln!(file, "row\tcanonical_smiles\tinchikey").unwrap();
+        writeln!(file, "3\tO\tXLYOFNOQVPJJNP-UHFFFAOYSA-N").unwrap();
+        let table = DescriptorTable::load(file.path()).unwrap();
+        let mut pipeline = offline_pipeline(10);
+        pipeline.descriptors = Some(Arc::new(table));
+
+        let outcomes = pipeline
+            .enrich(vec![(2, input("C")), (3, input("invalid"))])
+            .await;
+        let water = outcomes
+            .iter()
+            .find(|(row, _)| *row == 3)
+            .and_then(|(_, outcome)| outcome.as_ref().ok())
+            .expect("row 3 comes from the descriptor table");
+        assert_eq!(water.sanitized_smiles, "O");
+        assert_eq!(
+            water.inchikey.as_deref(),
+            Some("XLYOFNOQVPJJNP-UHFFFAOYSA-N")
+        );
+        assert!(outcomes.iter().all(|(_, outcome)| outcome.is_ok()));
+    }
+}
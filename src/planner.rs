@@ -0,0 +1,620 @@
+//! Creation planning shared by the full run and the `plan` subcommand.
+//!
+//! Planning is pure: it only looks at enriched records and their Wikidata check results and
+//! decides which items to create, which occurrences can be written now and what to flag.
+use crate::enrichment::EnrichedData;
+use crate::error::Result;
+use crate::taxon::parser::parse_taxon_name;
+use crate::wikidata::checker::{RelatedChemical, WikidataInfo};
+use csv::WriterBuilder;
+use std::collections::HashSet;
+use std::path::Path;
+
+/// Opt-in creations; chemicals and references with enough metadata are always planned.
+#[derive(Debug, Clone, Default)]
+pub struct PlanOptions {
+    /// Create missing species under their (existing) genus.
+    pub create_taxa: bool,
+    /// What to do with chemicals whose skeleton is already on Wikidata.
+    pub near_duplicates: NearDuplicatePolicy,
+}
+
+/// Handling of new chemicals that share their InChIKey connectivity block with an existing item
+/// of the same or less/more specific stereo (see [`RelatedChemical`]).
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NearDuplicatePolicy {
+    /// Hold the creation and flag the row for review.
+    #[default]
+    #[value(name = "review")]
+    Review,
+    /// Create the item anyway; the related items are still reported.
+    #[value(name = "create")]
+    Create,
+}
+
+/// Creation decisions for a batch of records, index-aligned with the records.
+#[derive(Debug, Clone, Default)]
+pub struct CreationPlan {
+    pub chemical: Vec<bool>,
+    /// Chemicals not created because they nearly duplicate an existing item.
+    pub chemical_held: Vec<bool>,
+    pub reference: Vec<bool>,
+    pub taxon: Vec<bool>,
+    /// Lowercase DOIs of the references that will be created.
+    pub planned_reference_dois: HashSet<String>,
+    /// Names of the taxa that will be created.
+    pub planned_taxon_names: HashSet<String>,
+    /// Occurrences are only written once no creation is pending, because QuickStatements
+    /// cannot cite items created earlier in the same batch.
+    pub emit_occurrences: bool,
+}
+
+impl CreationPlan {
+    /// Plans creations for `records`.
+    pub fn new(records: &[(EnrichedData, WikidataInfo)], options: &PlanOptions) -> Self {
+        let chemical = plan_chemical_creations(records, options.near_duplicates);
+        let (reference, planned_reference_dois) = plan_reference_creations(records);
+        let (taxon, planned_taxon_names) = if options.create_taxa {
+            plan_taxon_creations(records)
+        } else {
+            (vec![false; records.len()], HashSet::new())
+        };
+        let mut plan = Self::from_flags(
+            chemical,
+            reference,
+            taxon,
+            planned_reference_dois,
+            planned_taxon_names,
+        );
+        plan.chemical_held = records
+            .iter()
+            .map(|(_, info)| {
+                info.chemical_qid.is_none()
+                    && chemical_creation_hold(info, options.near_duplicates).is_some()
+            })
+            .collect();
+        plan
+    }
+
+    /// Rebuilds a plan from explicit (possibly hand-edited) creation flags.
+    pub fn from_flags(
+        chemical: Vec<bool>,
+        reference: Vec<bool>,
+        taxon: Vec<bool>,
+        planned_reference_dois: HashSet<String>,
+        planned_taxon_names: HashSet<String>,
+    ) -> Self {
+        let emit_occurrences = ![&chemical, &reference, &taxon]
+            .iter()
+            .any(|flags| flags.iter().any(|flag| *flag));
+        Self {
+            chemical_held: vec![false; chemical.len()],
+            chemical,
+            reference,
+            taxon,
+            planned_reference_dois,
+            planned_taxon_names,
+            emit_occurrences,
+        }
+    }
+
+    /// Per-record status rows for this plan.
+    pub fn record_reports(&self, records: &[(EnrichedData, WikidataInfo)]) -> Vec<RecordReport> {
+        build_record_reports(records, self)
+    }
+}
+
+/// Marks the first record of every chemical without a QID (deduplicated by InChIKey), leaving
+/// out near-duplicates of existing items unless `near_duplicates` allows them.
+pub fn plan_chemical_creations(
+    records: &[(EnrichedData, WikidataInfo)],
+    near_duplicates: NearDuplicatePolicy,
+) -> Vec<bool> {
+    let mut seen: HashSet<String> = HashSet::new();
+    records
+        .iter()
+        .map(|(data, info)| {
+            if info.chemical_qid.is_some()
+                || chemical_creation_hold(info, near_duplicates).is_some()
+            {
+                false
+            } else if let Some(inchikey) = data.inchikey.as_deref() {
+                seen.insert(inchikey.to_string())
+            } else {
+                true
+            }
+        })
+        .collect()
+}
+
+/// Why the creation of a missing chemical is held for review, or `None` when it can go ahead.
+pub fn chemical_creation_hold(
+    info: &WikidataInfo,
+    near_duplicates: NearDuplicatePolicy,
+) -> Option<String> {
+    if near_duplicates == NearDuplicatePolicy::Create {
+        return None;
+    }
+    let related: Vec<String> = info
+        .related_chemicals
+        .iter()
+        .filter(|related| related.relation.is_near_duplicate())
+        .map(describe_related_chemical)
+        .collect();
+    (!related.is_empty()).then(|| {
+        format!(
+            "the same skeleton is already on Wikidata as {}",
+            related.join(", ")
+        )
+    })
+}
+
+fn describe_related_chemical(related: &RelatedChemical) -> String {
+    format!("{} ({})", related.qid, related.relation.label())
+}
+
+/// Marks the first record of every DOI that has Crossref metadata but no Wikidata item; also
+/// returns the lowercase DOIs that will be created.
+pub fn plan_reference_creations(
+    records: &[(EnrichedData, WikidataInfo)],
+) -> (Vec<bool>, HashSet<String>) {
+    let mut seen: HashSet<String> = HashSet::new();
+    let mut plan = Vec::with_capacity(records.len());
+    for (_, info) in records.iter() {
+        let mut should_create = false;
+        if info.reference_qid.is_none()
+            && let Some(metadata) = &info.reference_metadata
+        {
+            let key = metadata.doi.trim().to_lowercase();
+            if seen.insert(key) {
+                should_create = true;
+            }
+        }
+        plan.push(should_create);
+    }
+    (plan, seen)
+}
+
+/// Why the missing taxon of a record cannot be created, or `None` when it can. Only clean
+/// species names whose genus resolved to a single accepted Wikidata taxon are created.
+pub fn taxon_creation_refusal(data: &EnrichedData, info: &WikidataInfo) -> Option<String> {
+    if !data.taxon_name_warnings.is_empty() {
+        return Some("the taxon name needed cleanup, so it must be checked by hand".to_string());
+    }
+    if !info.taxon_suggestions.is_empty() {
+        return Some(format!(
+            "close names exist on Wikidata ({}), check for a typo",
+            info.taxon_suggestions.join(", ")
+        ));
+    }
+    let parsed = parse_taxon_name(&data.taxon_name);
+    if !parsed.is_plain_species() {
+        return Some(
+            "only species names (no hybrids or infraspecific ranks) are created".to_string(),
+        );
+    }
+    if info.parent_taxon_qid.is_none() {
+        return Some(format!(
+            "genus '{}' does not resolve to a single accepted Wikidata taxon",
+            parsed.genus
+        ));
+    }
+    None
+}
+
+/// Marks the first record of every missing species that can be created (deduplicated by
+/// name); also returns the names that will be created.
+pub fn plan_taxon_creations(
+    records: &[(EnrichedData, WikidataInfo)],
+) -> (Vec<bool>, HashSet<String>) {
+    let mut seen: HashSet<String> = HashSet::new();
+    let plan = records
+        .iter()
+        .map(|(data, info)| {
+            info.taxon_qid.is_none()
+                && info.taxon_candidates.is_empty()
+                && taxon_creation_refusal(data, info).is_none()
+                && seen.insert(data.taxon_name.clone())
+        })
+        .collect();
+    (plan, seen)
+}
+
+/// Builds the per-record status rows (creation flags, deferred occurrences and issues).
+pub fn build_record_reports(
+    records: &[(EnrichedData, WikidataInfo)],
+    plan: &CreationPlan,
+) -> Vec<RecordReport> {
+    let emit_occurrences = plan.emit_occurrences;
+    records
+        .iter()
+        .enumerate()
+        .map(|(index, (data, info))| {
+            let create_chemical = plan.chemical[index];
+            let chemical_held = plan.chemical_held.get(index).copied().unwrap_or(false);
+            let create_reference = plan.reference[index];
+            let create_taxon = plan.taxon[index];
+            let taxon_available = info.taxon_qid.is_some();
+            let reference_qid_available = info.reference_qid.is_some();
+            let reference_key = data.reference_doi.trim().to_lowercase();
+            let reference_planned =
+                reference_qid_available || plan.planned_reference_dois.contains(&reference_key);
+            let taxon_planned = plan.planned_taxon_names.contains(&data.taxon_name);
+            let chemical_ready_now = info.chemical_qid.is_some();
+            let dependencies_ready_now =
+                chemical_ready_now && reference_qid_available && taxon_available;
+
+            let create_occurrence =
+                emit_occurrences && dependencies_ready_now && !info.occurrence_exists;
+            let occurrence_waiting_on_reference = !reference_qid_available && reference_planned;
+            let occurrence_waiting_on_taxon = !taxon_available && taxon_planned;
+            let occurrence_waiting_on_chemical = !chemical_ready_now;
+            let occurrence_waiting_on_batch =
+                !emit_occurrences && dependencies_ready_now && !info.occurrence_exists;
+
+            let mut issues = data.structure_warnings.clone();
+            issues.extend(data.taxon_name_warnings.iter().cloned());
+            issues.extend(info.warnings.iter().cloned());
+            if !taxon_available && info.taxon_candidates.is_empty() && !taxon_planned {
+                issues.push(match taxon_creation_refusal(data, info) {
+                    Some(reason) => format!(
+                        "Taxon entity not found in Wikidata and cannot be created automatically: {}.",
+                        reason
+                    ),
+                    None => "Taxon entity not found in Wikidata; rerun with --create-taxa to create it under its genus."
+                        .to_string(),
+                });
+            }
+            if chemical_held
+                && let Some(reason) = chemical_creation_hold(info, NearDuplicatePolicy::Review)
+            {
+                issues.push(format!(
+                    "Chemical creation held for review: {}; rerun with --near-duplicates create to create it anyway.",
+                    reason
+                ));
+            }
+            if info.reference_qid.is_none() && info.reference_metadata.is_none() {
+                issues.push(
+                    "DOI missing in Wikidata and Crossref lookup failed; reference must be curated manually."
+                        .to_string(),
+                );
+            }
+            if occurrence_waiting_on_reference {
+                issues.push(
+                    "Occurrence deferred until the new reference item has a QID; rerun the importer after this batch finishes in QuickStatements."
+                        .to_string(),
+                );
+            }
+            if occurrence_waiting_on_taxon {
+                issues.push(
+                    "Occurrence deferred until the new taxon item has a QID; rerun the importer after this batch finishes in QuickStatements."
+                        .to_string(),
+                );
+            }
+            if occurrence_waiting_on_chemical && !chemical_held {
+                issues.push(
+                    "Occurrence deferred until the chemical item exists in Wikidata; rerun after uploading this creation batch."
+                        .to_string(),
+                );
+            }
+            if occurrence_waiting_on_batch {
+                issues.push(
+                    "Occurrence deferred because this run is limited to entity creations; rerun lotus-o3 once those QIDs are live."
+                        .to_string(),
+                );
+            } else if !info.occurrence_exists && !create_occurrence && taxon_available
+                && !reference_planned {
+                    issues.push("Missing reference metadata prevents occurrence creation.".to_string());
+                }
+
+            RecordReport {
+                chemical_entity_name: data.chemical_entity_name.clone(),
+                original_smiles: data.input_smiles.clone(),
+                sanitized_smiles: data.sanitized_smiles.clone(),
+                canonical_smiles: data.canonical_smiles.clone(),
+                isomeric_smiles: data.isomeric_smiles.clone(),
+                smiles_were_sanitized: data.smiles_were_sanitized,
+                taxon_name: data.taxon_name.clone(),
+                resolved_taxon_name: info
+                    .resolved_taxon_name
+                    .clone()
+                    .or_else(|| taxon_available.then(|| data.taxon_name.clone())),
+                reference_doi: data.reference_doi.clone(),
+                chemical_qid: info.chemical_qid.clone(),
+                taxon_qid: info.taxon_qid.clone(),
+                taxon_candidates: info.taxon_candidates.clone(),
+                related_chemicals: info
+                    .related_chemicals
+                    .iter()
+                    .map(describe_related_chemical)
+                    .collect(),
+                identifiers: data.identifiers.iter().map(ToString::to_string).collect(),
+                statement_conflicts: info
+                    .chemical_statements
+                    .as_ref()
+                    .map(|statements| statements.compare(data).conflicts)
+                    .unwrap_or_default()
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect(),
+                reference_qid: info.reference_qid.clone(),
+                create_chemical,
+                create_reference,
+                create_taxon,
+                create_occurrence,
+                occurrence_waiting_on_reference,
+                occurrence_waiting_on_taxon,
+                occurrence_waiting_on_chemical,
+                occurrence_waiting_on_batch,
+                issues,
+            }
+        })
+        .collect()
+}
+
+/// Writes the per-record status report as TSV.
+pub fn write_status_report(rows: &[RecordReport], path: &Path) -> Result<()> {
+    let mut writer = WriterBuilder::new().delimiter(b'\t').from_path(path)?;
+    writer.write_record([
+        "chemical_entity_name",
+        "input_smiles",
+        "sanitized_smiles",
+        "canonical_smiles",
+        "isomeric_smiles",
+        "smiles_were_sanitized",
+        "taxon_name",
+        "resolved_taxon_name",
+        "reference_doi",
+        "chemical_qid",
+        "taxon_qid",
+        "taxon_candidates",
+        "related_chemicals",
+        "identifiers",
+        "statement_conflicts",
+        "reference_qid",
+        "create_chemical",
+        "create_reference",
+        "create_taxon",
+        "create_occurrence",
+        "occurrence_waiting_on_reference",
+        "occurrence_waiting_on_taxon",
+        "occurrence_waiting_on_chemical",
+        "occurrence_waiting_on_batch",
+        "issues",
+    ])?;
+
+    for row in rows {
+        let issues_text = if row.issues.is_empty() {
+            "".to_string()
+        } else {
+            row.issues.join("; ")
+        };
+        let canonical = row.canonical_smiles.as_deref().unwrap_or("");
+        let isomeric = row.isomeric_smiles.as_deref().unwrap_or("");
+        let taxon_candidates = row.taxon_candidates.join(";");
+        let identifiers = row.identifiers.join(";");
+        // InChI layers contain ';', so conflicts get a separator of their own.
+        let statement_conflicts = row.statement_conflicts.join(" | ");
+        let related_chemicals = if row.chemical_qid.is_none() && row.related_chemicals.is_empty() {
+            "new skeleton".to_string()
+        } else {
+            row.related_chemicals.join(";")
+        };
+        writer.write_record([
+            row.chemical_entity_name.as_str(),
+            row.original_smiles.as_str(),
+            row.sanitized_smiles.as_str(),
+            canonical,
+            isomeric,
+            bool_to_label(row.smiles_were_sanitized),
+            row.taxon_name.as_str(),
+            row.resolved_taxon_name.as_deref().unwrap_or(""),
+            row.reference_doi.as_str(),
+            row.chemical_qid.as_deref().unwrap_or(""),
+            row.taxon_qid.as_deref().unwrap_or(""),
+            taxon_candidates.as_str(),
+            related_chemicals.as_str(),
+            identifiers.as_str(),
+            statement_conflicts.as_str(),
+            row.reference_qid.as_deref().unwrap_or(""),
+            bool_to_label(row.create_chemical),
+            bool_to_label(row.create_reference),
+            bool_to_label(row.create_taxon),
+            bool_to_label(row.create_occurrence),
+            bool_to_label(row.occurrence_waiting_on_reference),
+            bool_to_label(row.occurrence_waiting_on_taxon),
+            bool_to_label(row.occurrence_waiting_on_chemical),
+            bool_to_label(row.occurrence_waiting_on_batch),
+            issues_text.as_str(),
+        ])?;
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+fn bool_to_label(flag: bool) -> &'static str {
+    if flag { "yes" } else { "no" }
+}
+
+/// One row of the per-record status report.
+#[derive(Debug, Clone)]
+pub struct RecordReport {
+    pub chemical_entity_name: String,
+    pub original_smiles: String,
+    pub sanitized_smiles: String,
+    pub canonical_smiles: Option<String>,
+    pub isomeric_smiles: Option<String>,
+    pub smiles_were_sanitized: bool,
+    /// Taxon name as given in the input (canonical form).
+    pub taxon_name: String,
+    /// Name of the taxon behind `taxon_qid` (the accepted name when the input was a synonym).
+    pub resolved_taxon_name: Option<String>,
+    pub reference_doi: String,
+    pub chemical_qid: Option<String>,
+    pub taxon_qid: Option<String>,
+    /// QIDs of homonymous taxa when the taxon needs review.
+    pub taxon_candidates: Vec<String>,
+    /// Items sharing the connectivity block of a missing chemical, as `QID (relation)`.
+    pub related_chemicals: Vec<String>,
+    /// Cross-database identifiers of the structure, as `Database:ID`.
+    pub identifiers: Vec<String>,
+    /// Values on the existing chemical item that disagree with ours, each with both values and
+    /// what differs; they are never overwritten.
+    pub statement_conflicts: Vec<String>,
+    pub reference_qid: Option<String>,
+    pub create_chemical: bool,
+    pub create_reference: bool,
+    pub create_taxon: bool,
+    pub create_occurrence: bool,
+    pub occurrence_waiting_on_reference: bool,
+    pub occurrence_waiting_on_taxon: bool,
+    pub occurrence_waiting_on_chemical: bool,
+    pub occurrence_waiting_on_batch: bool,
+    pub issues: Vec<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wikidata::checker::ChemicalRelation;
+
+    fn record(
+        inchikey: &str,
+        doi: &str,
+        chemical_qid: Option<&str>,
+    ) -> (EnrichedData, WikidataInfo) {
+        let data = EnrichedData {
+            chemical_entity_name: "Compound".to_string(),
+            input_smiles: "C".to_string(),
+            sanitized_smiles: "C".to_string(),
+            taxon_name: "Coffea arabica".to_string(),
+            reference_doi: doi.to_string(),
+            inchikey: Some(inchikey.to_string()),
+            ..Default::default()
+        };
+        let info = WikidataInfo {
+            chemical_qid: chemical_qid.map(str::to_string),
+            taxon_qid: Some("Q1".to_string()),
+            reference_qid: Some("Q2".to_string()),
+            ..Default::default()
+        };
+        (data, info)
+    }
+
+    #[test]
+    fn test_chemical_creations_are_deduplicated_by_inchikey() {
+        let records = vec![
+            record("AAAAAAAAAAAAAA-UHFFFAOYSA-N", "10.1000/a", None),
+            record("AAAAAAAAAAAAAA-UHFFFAOYSA-N", "10.1000/b", None),
+            record("BBBBBBBBBBBBBB-UHFFFAOYSA-N", "10.1000/a", Some("Q5")),
+        ];
+        let plan = CreationPlan::new(&records, &PlanOptions::default());
+        assert_eq!(plan.chemical, vec![true, false, false]);
+        assert!(!plan.emit_occurrences);
+
+        let reports = plan.record_reports(&records);
+        assert!(reports[0].create_chemical);
+        assert!(reports[0].occurrence_waiting_on_chemical);
+        assert!(reports[2].occurrence_waiting_on_batch);
+        assert!(!reports[2].create_occurrence);
+    }
+
+    #[test]
+    fn test_near_duplicate_chemicals_are_held_for_review() {
+        let mut related = record("QNAYBMKLOCPYGJ-UHFFFAOYSA-N", "10.1000/a", None);
+        related.1.related_chemicals = vec![RelatedChemical {
+            qid: "Q218642".to_string(),
+            inchikey: "QNAYBMKLOCPYGJ-REOHCLBHSA-N".to_string(),
+            relation: ChemicalRelation::LessSpecific,
+        }];
+        let mut stereoisomer = record("QNAYBMKLOCPYGJ-UWTATZPHSA-N", "10.1000/a", None);
+        stereoisomer.1.related_chemicals = vec![RelatedChemical {
+            relation: ChemicalRelation::Stereoisomer,
+            ..related.1.related_chemicals[0].clone()
+        }];
+        let records = vec![related, stereoisomer];
+
+        let plan = CreationPlan::new(&records, &PlanOptions::default());
+        assert_eq!(plan.chemical, vec![false, true]);
+        assert_eq!(plan.chemical_held, vec![true, false]);
+        let reports = plan.record_reports(&records);
+        assert_eq!(
+            reports[0].related_chemicals,
+            vec!["Q218642 (less specific)"]
+        );
+        assert!(reports[0].issues[0].contains("held for review"));
+        assert!(reports[0].issues[0].contains("Q218642 (less specific)"));
+
+        let options = PlanOptions {
+            near_duplicates: NearDuplicatePolicy::Create,
+            ..Default::default()
+        };
+        let plan = CreationPlan::new(&records, &options);
+        assert_eq!(plan.chemical, vec![true, true]);
+        assert!(
+            plan.record_reports(&records)[0]
+                .issues
+                .iter()
+                .all(|issue| !issue.contains("held for review"))
+        );
+    }
+
+    #[test]
+    fn test_occurrences_are_emitted_when_nothing_is_created() {
+        let records = vec![record(
+            "AAAAAAAAAAAAAA-UHFFFAOYSA-N",
+            "10.1000/a",
+            Some("Q5"),
+        )];
+        let plan = CreationPlan::new(&records, &PlanOptions::default());
+        assert!(plan.emit_occurrences);
+        let reports = plan.record_reports(&records);
+        assert!(reports[0].create_occurrence);
+        assert!(reports[0].issues.is_empty());
+        assert_eq!(
+            reports[0].resolved_taxon_name.as_deref(),
+            Some("Coffea arabica")
+        );
+
+        let mut synonym_row = records[0].clone();
+        synonym_row.1.resolved_taxon_name = Some("Coffea arabica subsp. arabica".to_string());
+        let reports = plan.record_reports(&[synonym_row]);
+        assert_eq!(
+            reports[0].resolved_taxon_name.as_deref(),
+            Some("Coffea arabica subsp. arabica")
+        );
+    }
+
+    #[test]
+    fn test_missing_species_are_created_under_their_genus() {
+        let mut missing = record("AAAAAAAAAAAAAA-UHFFFAOYSA-N", "10.1000/a", Some("Q5"));
+        missing.0.taxon_name = "Coffea nova".to_string();
+        missing.1.taxon_qid = None;
+        missing.1.parent_taxon_qid = Some("Q157811".to_string());
+        let mut no_genus = missing.clone();
+        no_genus.0.taxon_name = "Nullgenus nova".to_string();
+        no_genus.1.parent_taxon_qid = None;
+        let records = vec![missing.clone(), missing, no_genus];
+
+        let plan = CreationPlan::new(&records, &PlanOptions::default());
+        assert_eq!(plan.taxon, vec![false, false, false]);
+        let reports = plan.record_reports(&records);
+        assert!(reports[0].issues[0].contains("--create-taxa"));
+
+        let plan = CreationPlan::new(
+            &records,
+            &PlanOptions {
+                create_taxa: true,
+                ..Default::default()
+            },
+        );
+        assert_eq!(plan.taxon, vec![true, false, false]);
+        assert!(!plan.emit_occurrences);
+        let reports = plan.record_reports(&records);
+        assert!(reports[0].create_taxon);
+        assert!(reports[1].occurrence_waiting_on_taxon);
+        assert!(!reports[2].occurrence_waiting_on_taxon);
+        assert!(reports[2].issues[0].contains("genus 'Nullgenus'"));
+    }
+}
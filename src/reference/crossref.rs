@@ -1,20 +1,16 @@
 //! Crossref reference enrichment helpers.
+use crate::cache::{self, CacheKind};
 use crate::error::{CrateError, Result};
 use chrono::{Datelike, NaiveDate, Utc};
 use log::{info, warn};
-use once_cell::sync::Lazy;
 use reqwest::StatusCode;
-use serde::Deserialize;
-use std::collections::HashMap;
-use std::sync::Mutex;
+use serde::{Deserialize, Serialize};
 
 const CROSSREF_API_URL: &str = "https://api.crossref.org/works/doi";
 pub const CROSSREF_QID: &str = "Q5188229";
-static CROSSREF_CACHE: Lazy<Mutex<HashMap<String, Option<ReferenceMetadata>>>> =
-    Lazy::new(|| Mutex::new(HashMap::new()));
 
 /// Structured metadata about a reference retrieved from Crossref.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ReferenceMetadata {
     pub doi: String,
     pub title: String,
@@ -32,14 +28,14 @@ pub struct ReferenceMetadata {
 }
 
 /// Author name and ordinal data.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ReferenceAuthor {
     pub full_name: String,
     pub ordinal: usize,
 }
 
 /// Publication date information with precision metadata.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ReferenceDate {
     pub year: i32,
     pub month: Option<u32>,
@@ -73,13 +69,24 @@ impl ReferenceDate {
 
     /// Formats the date for QuickStatements.
     pub fn to_quickstatements_time(&self) -> String {
+        format!("{}/{}", self.to_wikibase_time(), self.precision())
+    }
+
+    /// Formats the date as a Wikibase API timestamp (precision is carried separately).
+    pub fn to_wikibase_time(&self) -> String {
         let month = self.month.unwrap_or(1);
         let day = self.day.unwrap_or(1);
-        format!(
-            "+{year:04}-{month:02}-{day:02}T00:00:00Z/{precision}",
-            year = self.year,
-            precision = self.precision()
-        )
+        format!("+{year:04}-{month:02}-{day:02}T00:00:00Z", year = self.year)
+    }
+}
+
+impl From<NaiveDate> for ReferenceDate {
+    fn from(date: NaiveDate) -> Self {
+        Self {
+            year: date.year(),
+            month: Some(date.month()),
+            day: Some(date.day()),
+        }
     }
 }
 
@@ -128,11 +135,7 @@ pub async fn fetch_reference_metadata(
     }
 
     let key = trimmed.to_lowercase();
-    if let Some(cached) = CROSSREF_CACHE
-        .lock()
-        .ok()
-        .and_then(|cache| cache.get(&key).cloned())
-    {
+    if let Some(cached) = cache::lookup(CacheKind::Crossref, &key) {
         return Ok(cached);
     }
 
@@ -205,7 +208,7 @@ pub async fn fetch_reference_metadata(
     let publication_date = message
         .issued
         .as_ref()
-        .and_then(|issued| issued.date_parts.get(0))
+        .and_then(|issued| issued.date_parts.first())
         .and_then(|parts| ReferenceDate::from_parts(parts));
 
     let authors: Vec<ReferenceAuthor> = message
@@ -215,15 +218,15 @@ pub async fn fetch_reference_metadata(
         .filter_map(|author| {
             let full_name = author.name.or_else(|| {
                 let mut pieces = Vec::new();
-                if let Some(given) = author.given {
-                    if !given.trim().is_empty() {
-                        pieces.push(given);
-                    }
+                if let Some(given) = author.given
+                    && !given.trim().is_empty()
+                {
+                    pieces.push(given);
                 }
-                if let Some(family) = author.family {
-                    if !family.trim().is_empty() {
-                        pieces.push(family);
-                    }
+                if let Some(family) = author.family
+                    && !family.trim().is_empty()
+                {
+                    pieces.push(family);
                 }
                 if pieces.is_empty() {
                     None
@@ -324,16 +327,9 @@ fn map_work_type_to_qid(work_type: Option<&str>) -> &'static str {
 
 /// Formats a retrieval date for QuickStatements references.
 pub fn format_retrieved_date(date: NaiveDate) -> String {
-    format!(
-        "+{year:04}-{month:02}-{day:02}T00:00:00Z/11",
-        year = date.year(),
-        month = date.month(),
-        day = date.day()
-    )
+    ReferenceDate::from(date).to_quickstatements_time()
 }
 
 fn cache_crossref_result(doi_key: &str, value: Option<ReferenceMetadata>) {
-    if let Ok(mut cache) = CROSSREF_CACHE.lock() {
-        cache.insert(doi_key.to_string(), value);
-    }
+    cache::store(CacheKind::Crossref, doi_key, value.as_ref());
 }
@@ -1,3 +1,6 @@
 //! Namespace for taxon-specific helpers.
 
+pub mod backbone;
+pub mod fuzzy;
 pub mod normalizer;
+pub mod parser;
@@ -0,0 +1,367 @@
+//! Local taxonomic backbone (`--taxon-backbone`): a checklist such as a GBIF backbone or a
+//! Catalogue of Life export, used to resolve names before (or instead of) Wikidata SPARQL.
+//!
+//! The file is a TSV (or CSV) with a header row; an extracted Darwin Core Archive directory is
+//! accepted too, in which case its core taxon file is read. Headers are matched by their
+//! Darwin Core term, ignoring case, underscores and namespace prefixes (`dwc:`, `col:`).
+use crate::csv_handler::normalize_qid;
+use crate::error::{CrateError, Result};
+use crate::taxon::fuzzy::damerau_levenshtein;
+use crate::taxon::normalizer::normalize_taxon_name;
+use csv::ReaderBuilder;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Names that differ from a backbone name by more edits than this are not matched.
+pub const MAX_FUZZY_DISTANCE: usize = 2;
+
+/// Core file names looked for in an extracted Darwin Core Archive.
+const CORE_FILES: [&str; 5] = [
+    "taxon.tsv",
+    "taxon.txt",
+    "taxa.txt",
+    "nameusage.tsv",
+    "taxon.csv",
+];
+
+const CANONICAL_NAME_HEADERS: [&str; 1] = ["canonicalname"];
+const SCIENTIFIC_NAME_HEADERS: [&str; 3] = ["scientificname", "taxonname", "name"];
+const ID_HEADERS: [&str; 2] = ["taxonid", "id"];
+const ACCEPTED_ID_HEADERS: [&str; 2] = ["acceptednameusageid", "acceptedid"];
+const ACCEPTED_NAME_HEADERS: [&str; 3] = [
+    "acceptednameusage",
+    "acceptedname",
+    "acceptedscientificname",
+];
+const QID_HEADERS: [&str; 4] = ["wikidataqid", "wikidata", "qid", "wikidataid"];
+
+/// One checklist row.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackboneEntry {
+    /// Canonical name (as produced by `normalize_taxon_name`).
+    pub name: String,
+    /// Canonical accepted name when this row is a synonym.
+    pub accepted_name: Option<String>,
+    pub wikidata_qid: Option<String>,
+}
+
+/// What the backbone knows about a looked-up name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackboneMatch {
+    /// Backbone name that matched; differs from the input for fuzzy matches.
+    pub name: String,
+    /// Edit distance between the input and `name` (0 for exact matches).
+    pub distance: usize,
+    pub wikidata_qid: Option<String>,
+    /// Accepted name when `name` is a synonym.
+    pub accepted_name: Option<String>,
+    /// QID listed for the accepted name.
+    pub accepted_qid: Option<String>,
+}
+
+/// Checklist indexed by canonical name and by genus.
+#[derive(Debug, Default)]
+pub struct TaxonBackbone {
+    entries: Vec<BackboneEntry>,
+    by_name: HashMap<String, Vec<usize>>,
+    by_genus: HashMap<String, Vec<String>>,
+}
+
+struct Columns {
+    canonical_name: Option<usize>,
+    scientific_name: Option<usize>,
+    id: Option<usize>,
+    accepted_id: Option<usize>,
+    accepted_name: Option<usize>,
+    wikidata_qid: Option<usize>,
+}
+
+// `dwc:acceptedNameUsageID`, `accepted_name_usage_id` and `acceptedNameUsageID` are one term.
+fn header_key(header: &str) -> String {
+    header
+        .rsplit(':')
+        .next()
+        .unwrap_or(header)
+        .trim()
+        .to_lowercase()
+        .replace(['_', ' '], "")
+}
+
+fn find_column(headers: &[String], names: &[&str]) -> Option<usize> {
+    names
+        .iter()
+        .find_map(|name| headers.iter().position(|header| header == name))
+}
+
+fn genus_of(name: &str) -> &str {
+    name.split_whitespace()
+        .find(|token| *token != "×")
+        .unwrap_or(name)
+}
+
+fn backbone_error(path: &Path, message: impl Into<String>) -> CrateError {
+    CrateError::TaxonBackboneError {
+        path: path.display().to_string(),
+        message: message.into(),
+    }
+}
+
+// The file to read: `path` itself, or the core taxon file of an extracted archive.
+fn core_file(path: &Path) -> Result<PathBuf> {
+    if !path.is_dir() {
+        return Ok(path.to_path_buf());
+    }
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let file_name = entry.file_name().to_string_lossy().to_lowercase();
+        if CORE_FILES.contains(&file_name.as_str()) {
+            return Ok(entry.path());
+        }
+    }
+    Err(backbone_error(
+        path,
+        format!("no core taxon file ({}) found", CORE_FILES.join(", ")),
+    ))
+}
+
+impl TaxonBackbone {
+    /// Loads a checklist file or an extracted Darwin Core Archive directory.
+    pub fn load(path: &Path) -> Result<Self> {
+        let file = core_file(path)?;
+        let is_csv = file
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("csv"));
+        let mut reader = ReaderBuilder::new()
+            .delimiter(if is_csv { b',' } else { b'\t' })
+            // Checklist TSVs contain unescaped quotes in authorships.
+            .quoting(is_csv)
+            .flexible(true)
+            .from_path(&file)?;
+        let headers: Vec<String> = reader.headers()?.iter().map(header_key).collect();
+        let columns = Columns {
+            canonical_name: find_column(&headers, &CANONICAL_NAME_HEADERS),
+            scientific_name: find_column(&headers, &SCIENTIFIC_NAME_HEADERS),
+            id: find_column(&headers, &ID_HEADERS),
+            accepted_id: find_column(&headers, &ACCEPTED_ID_HEADERS),
+            accepted_name: find_column(&headers, &ACCEPTED_NAME_HEADERS),
+            wikidata_qid: find_column(&headers, &QID_HEADERS),
+        };
+        if columns.canonical_name.is_none() && columns.scientific_name.is_none() {
+            return Err(backbone_error(
+                &file,
+                "needs a canonicalName or scientificName column",
+            ));
+        }
+        if columns.wikidata_qid.is_none()
+            && columns.accepted_name.is_none()
+            && (columns.id.is_none() || columns.accepted_id.is_none())
+        {
+            return Err(backbone_error(
+                &file,
+                "needs a Wikidata QID column or accepted-name columns",
+            ));
+        }
+
+        let mut rows: Vec<(BackboneEntry, Option<String>)> = Vec::new();
+        let mut names_by_id: HashMap<String, String> = HashMap::new();
+        for record in reader.records() {
+            let record = record?;
+            let cell = |column: Option<usize>| {
+                column
+                    .and_then(|index| record.get(index))
+                    .map(str::trim)
+                    .filter(|value| !value.is_empty())
+            };
+            let Some(name) = cell(columns.canonical_name)
+                .or(cell(columns.scientific_name))
+                .map(normalize_taxon_name)
+                .filter(|name| !name.is_empty())
+            else {
+                continue;
+            };
+            if let Some(id) = cell(columns.id) {
+                names_by_id.insert(id.to_string(), name.clone());
+            }
+            let entry = BackboneEntry {
+                accepted_name: cell(columns.accepted_name).map(normalize_taxon_name),
+                wikidata_qid: cell(columns.wikidata_qid).and_then(normalize_qid),
+                name,
+            };
+            rows.push((entry, cell(columns.accepted_id).map(String::from)));
+        }
+
+        let mut backbone = Self::default();
+        for (mut entry, accepted_id) in rows {
+            if entry.accepted_name.is_none() {
+                entry.accepted_name = accepted_id.and_then(|id| names_by_id.get(&id).cloned());
+            }
+            if entry.accepted_name.as_ref() == Some(&entry.name) {
+                entry.accepted_name = None;
+            }
+            backbone.insert(entry);
+        }
+        Ok(backbone)
+    }
+
+    fn insert(&mut self, entry: BackboneEntry) {
+        let indices = self.by_name.entry(entry.name.clone()).or_default();
+        if indices.is_empty() {
+            self.by_genus
+                .entry(genus_of(&entry.name).to_string())
+                .or_default()
+                .push(entry.name.clone());
+        }
+        indices.push(self.entries.len());
+        self.entries.push(entry);
+    }
+
+    /// Number of checklist rows.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Looks up a canonical name, falling back to the closest name of the same genus within
+    /// [`MAX_FUZZY_DISTANCE`] edits. Returns `None` when nothing (or more than one taxon)
+    /// matches.
+    pub fn lookup(&self, name: &str) -> Option<BackboneMatch> {
+        let (matched, distance) = if self.by_name.contains_key(name) {
+            (name, 0)
+        } else {
+            self.closest(name)?
+        };
+        let entries: Vec<&BackboneEntry> = self.by_name[matched]
+            .iter()
+            .map(|index| &self.entries[*index])
+            .collect();
+        let accepted_name = agreed(entries.iter().map(|e| e.accepted_name.as_deref()))?;
+        let wikidata_qid = agreed(entries.iter().map(|e| e.wikidata_qid.as_deref()))?;
+        let accepted_qid = match accepted_name {
+            Some(accepted) => self.by_name.get(accepted).and_then(|indices| {
+                agreed(
+                    indices
+                        .iter()
+                        .map(|index| &self.entries[*index])
+                        .filter(|e| e.accepted_name.is_none())
+                        .map(|e| e.wikidata_qid.as_deref()),
+                )
+                .flatten()
+            }),
+            None => None,
+        };
+        Some(BackboneMatch {
+            name: matched.to_string(),
+            distance,
+            wikidata_qid: wikidata_qid.map(String::from),
+            accepted_name: accepted_name.map(String::from),
+            accepted_qid: accepted_qid.map(String::from),
+        })
+    }
+
+    // Closest name of the same genus; ties are left unresolved.
+    fn closest(&self, name: &str) -> Option<(&str, usize)> {
+        let mut best: Option<(&str, usize)> = None;
+        let mut tied = false;
+        for candidate in self.by_genus.get(genus_of(name))? {
+            let distance = damerau_levenshtein(name, candidate);
+            match best {
+                Some((_, best_distance)) if distance > best_distance => {}
+                Some((_, best_distance)) if distance == best_distance => tied = true,
+                _ => {
+                    best = Some((candidate, distance));
+                    tied = false;
+                }
+            }
+        }
+        best.filter(|(_, distance)| !tied && *distance <= MAX_FUZZY_DISTANCE)
+    }
+}
+
+// The single value shared by all rows (missing values are ignored); `None` when rows disagree.
+fn agreed<'a>(values: impl Iterator<Item = Option<&'a str>>) -> Option<Option<&'a str>> {
+    let mut agreed: Option<&str> = None;
+    for value in values.flatten() {
+        match agreed {
+            Some(existing) if existing != value => return None,
+            _ => agreed = Some(value),
+        }
+    }
+    Some(agreed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHECKLIST: &str =
+        "taxonID\tscientificName\tcanonicalName\tacceptedNameUsageID\ttaxonomicStatus\twikidata
+1\tCoffea arabica L.\tCoffea arabica\t\taccepted\tQ101
+2\tCoffea arabica var. typica Cramer\tCoffea arabica var. typica\t1\tsynonym\t
+3\tCoffea liberica Hiern\tCoffea liberica\t\taccepted\t
+4\tCoffea \"racemosa\" Lour.\tCoffea racemosa\t\taccepted\tQ104
+5\tMorus alba L.\tMorus alba\t\taccepted\tQ105
+6\tMorus alba Smith\tMorus alba\t\taccepted\tQ106
+";
+
+    fn load(contents: &str, file_name: &str) -> Result<TaxonBackbone> {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join(file_name), contents).unwrap();
+        TaxonBackbone::load(dir.path())
+    }
+
+    #[test]
+    fn test_exact_synonym_and_fuzzy_lookups() {
+        let backbone = load(CHECKLIST, "Taxon.tsv").unwrap();
+        assert_eq!(backbone.len(), 6);
+
+        let exact = backbone.lookup("Coffea arabica").unwrap();
+        assert_eq!(exact.distance, 0);
+        assert_eq!(exact.wikidata_qid.as_deref(), Some("Q101"));
+        assert_eq!(exact.accepted_name, None);
+
+        let synonym = backbone.lookup("Coffea arabica var. typica").unwrap();
+        assert_eq!(synonym.accepted_name.as_deref(), Some("Coffea arabica"));
+        assert_eq!(synonym.accepted_qid.as_deref(), Some("Q101"));
+
+        let typo = backbone.lookup("Coffea arabia").unwrap();
+        assert_eq!(typo.name, "Coffea arabica");
+        assert_eq!(typo.distance, 1);
+        assert_eq!(backbone.lookup("Coffea robusta"), None);
+        assert_eq!(backbone.lookup("Cofea arabica"), None);
+
+        assert_eq!(
+            backbone
+                .lookup("Coffea racemosa")
+                .unwrap()
+                .wikidata_qid
+                .as_deref(),
+            Some("Q104")
+        );
+        // Homonyms listing different QIDs are left to the Wikidata lookup.
+        assert_eq!(backbone.lookup("Morus alba"), None);
+    }
+
+    #[test]
+    fn test_plain_tsv_with_accepted_name_column() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("checklist.tsv");
+        fs::write(
+            &path,
+            "canonical_name\taccepted_name\twikidata_qid\nAus bus\tAus cus\t\nAus cus\t\twd:Q7\n",
+        )
+        .unwrap();
+        let backbone = TaxonBackbone::load(&path).unwrap();
+        let hit = backbone.lookup("Aus bus").unwrap();
+        assert_eq!(hit.accepted_name.as_deref(), Some("Aus cus"));
+        assert_eq!(hit.accepted_qid.as_deref(), Some("Q7"));
+
+        assert!(matches!(
+            load("name\tstatus\nAus bus\taccepted\n", "taxon.txt"),
+            Err(CrateError::TaxonBackboneError { .. })
+        ));
+    }
+}
@@ -0,0 +1,65 @@
+//! Edit distance used to match misspelled taxon names.
+
+/// Lowercases a name and strips common Latin diacritics, so `Pinus Müller` and `pinus muller`
+/// compare equal.
+pub fn fold_name(name: &str) -> String {
+    name.chars()
+        .flat_map(char::to_lowercase)
+        .map(|c| match c {
+            'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' => 'a',
+            'ç' => 'c',
+            'è' | 'é' | 'ê' | 'ë' => 'e',
+            'ì' | 'í' | 'î' | 'ï' => 'i',
+            'ñ' => 'n',
+            'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ø' => 'o',
+            'ù' | 'ú' | 'û' | 'ü' => 'u',
+            'ý' | 'ÿ' => 'y',
+            '×' => 'x',
+            other => other,
+        })
+        .collect()
+}
+
+/// Damerau-Levenshtein distance (optimal string alignment) over Unicode scalar values:
+/// insertions, deletions, substitutions and transpositions of adjacent characters all cost 1.
+pub fn damerau_levenshtein(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let width = b.len() + 1;
+    let mut rows = vec![0usize; (a.len() + 1) * width];
+    for (i, cell) in rows.iter_mut().step_by(width).enumerate() {
+        *cell = i;
+    }
+    for (j, cell) in rows.iter_mut().take(width).enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut best = (rows[(i - 1) * width + j] + 1)
+                .min(rows[i * width + j - 1] + 1)
+                .min(rows[(i - 1) * width + j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                best = best.min(rows[(i - 2) * width + j - 2] + 1);
+            }
+            rows[i * width + j] = best;
+        }
+    }
+    rows[a.len() * width + b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_typos_and_transpositions() {
+        assert_eq!(damerau_levenshtein("Coffea arabica", "Coffea arabica"), 0);
+        assert_eq!(damerau_levenshtein("Coffea arabia", "Coffea arabica"), 1);
+        assert_eq!(damerau_levenshtein("Coffea arabcia", "Coffea arabica"), 1);
+        assert_eq!(damerau_levenshtein("Coffea robusta", "Coffea arabica"), 5);
+        assert_eq!(damerau_levenshtein("", "abc"), 3);
+        assert_eq!(damerau_levenshtein("Pinus müller", "Pinus muller"), 1);
+        assert_eq!(fold_name("Pinus Müller"), "pinus muller");
+    }
+}
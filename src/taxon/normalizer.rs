@@ -1,10 +1,9 @@
-/// Normalizes verbose taxon labels (e.g., truncating authorship info).
+use crate::taxon::parser::parse_taxon_name;
+
+/// Reduces a verbatim taxon label to the canonical name used for the P225 lookup (authorship,
+/// years and identification qualifiers are dropped; rank markers and hybrid signs are kept).
 pub fn normalize_taxon_name(taxon_name: &str) -> String {
-    taxon_name
-        .split_whitespace()
-        .take(2)
-        .collect::<Vec<_>>()
-        .join(" ")
+    parse_taxon_name(taxon_name).canonical
 }
 
 #[cfg(test)]
@@ -12,15 +11,19 @@ mod tests {
     use super::*;
 
     #[test]
-    fn trims_and_truncates() {
+    fn trims_and_drops_authorship() {
         assert_eq!(
             normalize_taxon_name("Vernonanthura patens (Kunth) H.Rob."),
             "Vernonanthura patens"
         );
         assert_eq!(normalize_taxon_name("Single"), "Single");
         assert_eq!(
-            normalize_taxon_name("  Leading  and trailing  "),
-            "Leading and"
+            normalize_taxon_name("  Leading  trailing L.  "),
+            "Leading trailing"
+        );
+        assert_eq!(
+            normalize_taxon_name("Citrus × aurantium var. bergamia"),
+            "Citrus × aurantium var. bergamia"
         );
     }
 }
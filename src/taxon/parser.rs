@@ -0,0 +1,426 @@
+//! Scientific-name parser for the taxon column.
+//!
+//! Splits a verbatim name into genus, epithets, rank marker, authorship and year, in the spirit
+//! of the GBIF / Global Names parsers. The canonical name (without authorship or qualifiers) is
+//! what gets matched against Wikidata's P225; anything the parser had to drop or guess is
+//! reported as a warning.
+use serde::{Deserialize, Serialize};
+
+/// A taxon name split into its parts.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ParsedTaxonName {
+    /// Input as given, with whitespace collapsed.
+    pub verbatim: String,
+    /// Name used for the P225 lookup, e.g. `Citrus × aurantium var. bergamia`.
+    pub canonical: String,
+    pub genus: String,
+    pub specific_epithet: Option<String>,
+    pub infraspecific_epithet: Option<String>,
+    /// Normalized infraspecific rank marker (`subsp.`, `var.`, `subvar.`, `f.`, `subf.`).
+    pub rank_marker: Option<String>,
+    /// True for named hybrids (`Citrus × aurantium`, `× Citrofortunella`).
+    pub hybrid: bool,
+    /// Identification qualifier dropped from the canonical name (`cf.`, `aff.`).
+    pub qualifier: Option<String>,
+    pub authorship: Option<String>,
+    pub year: Option<u16>,
+    pub warnings: Vec<String>,
+}
+
+impl ParsedTaxonName {
+    /// True for a binomial that is not a hybrid, the only kind of name created automatically.
+    pub fn is_plain_species(&self) -> bool {
+        self.rank_qid() == Some("Q7432") && !self.hybrid
+    }
+
+    /// Wikidata item for the rank implied by the name (the P105 value), when it is unambiguous.
+    /// Trinomials without a rank marker return `None`.
+    pub fn rank_qid(&self) -> Option<&'static str> {
+        if self.genus.is_empty() {
+            return None;
+        }
+        match (&self.specific_epithet, &self.infraspecific_epithet) {
+            (None, _) => Some("Q34740"),
+            (Some(_), None) => Some("Q7432"),
+            (Some(_), Some(_)) => match self.rank_marker.as_deref()? {
+                "subsp." | "nothosubsp." => Some("Q68947"),
+                "var." => Some("Q767728"),
+                "subvar." => Some("Q630771"),
+                "f." => Some("Q279749"),
+                "subf." => Some("Q12774043"),
+                _ => None,
+            },
+        }
+    }
+}
+
+const HYBRID_SIGN: &str = "×";
+
+// Infraspecific rank markers and their normalized spelling.
+const RANK_MARKERS: [(&str, &str); 12] = [
+    ("subsp.", "subsp."),
+    ("subsp", "subsp."),
+    ("ssp.", "subsp."),
+    ("ssp", "subsp."),
+    ("var.", "var."),
+    ("var", "var."),
+    ("subvar.", "subvar."),
+    ("f.", "f."),
+    ("fo.", "f."),
+    ("forma", "f."),
+    ("subf.", "subf."),
+    ("nothosubsp.", "nothosubsp."),
+];
+
+const QUALIFIERS: [(&str, &str); 5] = [
+    ("cf.", "cf."),
+    ("cf", "cf."),
+    ("cfr.", "cf."),
+    ("aff.", "aff."),
+    ("aff", "aff."),
+];
+
+const UNSPECIFIED_SPECIES: [&str; 4] = ["sp.", "sp", "spp.", "spp"];
+
+// Lowercase words that start an authorship rather than an epithet (`van der Werff`, `de Wild.`).
+const AUTHOR_PARTICLES: [&str; 12] = [
+    "d'", "da", "de", "del", "della", "den", "der", "du", "la", "le", "van", "von",
+];
+
+fn rank_marker(token: &str) -> Option<&'static str> {
+    RANK_MARKERS
+        .iter()
+        .find(|(spelling, _)| token.eq_ignore_ascii_case(spelling))
+        .map(|(_, normalized)| *normalized)
+}
+
+fn qualifier(token: &str) -> Option<&'static str> {
+    QUALIFIERS
+        .iter()
+        .find(|(spelling, _)| token.eq_ignore_ascii_case(spelling))
+        .map(|(_, normalized)| *normalized)
+}
+
+fn is_hybrid_marker(token: &str) -> bool {
+    token == HYBRID_SIGN || token == "x" || token == "X"
+}
+
+fn is_epithet(token: &str) -> bool {
+    !AUTHOR_PARTICLES.contains(&token)
+        && rank_marker(token).is_none()
+        && token.chars().next().is_some_and(|c| c.is_lowercase())
+        && token.chars().all(|c| c.is_lowercase() || c == '-')
+}
+
+fn is_genus(token: &str) -> bool {
+    let mut chars = token.chars();
+    chars.next().is_some_and(|c| c.is_uppercase()) && chars.all(|c| c.is_lowercase() || c == '-')
+}
+
+// Splits glued parentheses (`patens(Kunth)`), attached hybrid signs (`×aurantium`) and
+// authorships glued onto an epithet (`thalianaL.`) into separate tokens.
+fn tokenize(name: &str, warnings: &mut Vec<String>) -> Vec<String> {
+    let spaced = name
+        .replace('(', " (")
+        .replace(')', ") ")
+        .replace(HYBRID_SIGN, &format!(" {} ", HYBRID_SIGN));
+    let mut tokens = Vec::new();
+    for token in spaced.split_whitespace() {
+        match split_glued_authorship(token) {
+            Some((epithet, author)) => {
+                warnings.push(format!(
+                    "Authorship '{}' was glued to '{}'; read them as separate words.",
+                    author, epithet
+                ));
+                tokens.push(epithet.to_string());
+                tokens.push(author.to_string());
+            }
+            None => tokens.push(token.to_string()),
+        }
+    }
+    tokens
+}
+
+// A lowercase word running straight into a capitalized one: `thalianaL.`, `patensKunth`.
+fn split_glued_authorship(token: &str) -> Option<(&str, &str)> {
+    let (index, _) = token.char_indices().find(|(_, c)| c.is_uppercase())?;
+    let (epithet, author) = token.split_at(index);
+    (!epithet.is_empty() && epithet.chars().all(|c| c.is_lowercase() || c == '-'))
+        .then_some((epithet, author))
+}
+
+fn parse_year(authorship: &str) -> Option<u16> {
+    authorship
+        .split(|c: char| !c.is_ascii_digit())
+        .filter(|digits| digits.len() == 4)
+        .filter_map(|digits| digits.parse::<u16>().ok())
+        .rfind(|year| (1753..=2100).contains(year))
+}
+
+/// Parses a verbatim taxon name. Never fails: unparseable input is kept as its own canonical
+/// name with a warning.
+pub fn parse_taxon_name(verbatim: &str) -> ParsedTaxonName {
+    let verbatim = verbatim.split_whitespace().collect::<Vec<_>>().join(" ");
+    let mut parsed = ParsedTaxonName {
+        verbatim: verbatim.clone(),
+        ..Default::default()
+    };
+    let mut tokens = tokenize(&verbatim, &mut parsed.warnings)
+        .into_iter()
+        .peekable();
+
+    if let Some(q) = tokens.peek().and_then(|t| qualifier(t)) {
+        tokens.next();
+        parsed.qualifier = Some(q.to_string());
+    }
+    let hybrid_genus = tokens.peek().is_some_and(|t| is_hybrid_marker(t));
+    if hybrid_genus {
+        tokens.next();
+        parsed.hybrid = true;
+    }
+    let Some(first) = tokens.next() else {
+        parsed.warnings.push("Empty taxon name.".to_string());
+        return parsed;
+    };
+    let genus = if is_genus(&first) {
+        first
+    } else if first.chars().all(|c| c.is_alphabetic() || c == '-') {
+        let mut chars = first.chars();
+        let fixed: String = chars
+            .next()
+            .into_iter()
+            .flat_map(char::to_uppercase)
+            .chain(chars.flat_map(char::to_lowercase))
+            .collect();
+        parsed.warnings.push(format!(
+            "Genus '{}' is not capitalized; matched as '{}'.",
+            first, fixed
+        ));
+        fixed
+    } else {
+        parsed.warnings.push(format!(
+            "'{}' does not look like a scientific name; it was matched verbatim.",
+            verbatim
+        ));
+        parsed.canonical = verbatim;
+        return parsed;
+    };
+    parsed.genus = genus;
+
+    // Zoological subgenus: `Aus (Bus) cus`. A parenthesised capitalized word followed by an
+    // epithet is a subgenus, otherwise it is the start of a basionym authorship.
+    let mut rest: Vec<String> = tokens.collect();
+    if rest.len() >= 2
+        && rest[0].starts_with('(')
+        && rest[0].ends_with(')')
+        && is_genus(rest[0].trim_matches(|c| c == '(' || c == ')'))
+        && is_epithet(&rest[1])
+    {
+        rest.remove(0);
+    }
+
+    let mut authorship: Vec<String> = Vec::new();
+    let mut pending_marker: Option<&'static str> = None;
+    let mut rest = rest.into_iter().peekable();
+    while let Some(token) = rest.next() {
+        let bare = token.trim_end_matches(',');
+        if !authorship.is_empty() && pending_marker.is_none() {
+            // Authorship of a species can precede an infraspecific part:
+            // `Aus bus L. var. cus Pers.` (but `L. f.` is "Linnaeus filius", not a forma).
+            if let Some(marker) = rank_marker(bare)
+                && parsed.infraspecific_epithet.is_none()
+                && parsed.specific_epithet.is_some()
+                && rest
+                    .peek()
+                    .is_some_and(|next| is_epithet(next.trim_end_matches(',')))
+            {
+                authorship.clear();
+                pending_marker = Some(marker);
+            } else {
+                authorship.push(token);
+            }
+            continue;
+        }
+        if let Some(q) = qualifier(bare) {
+            parsed.qualifier = Some(q.to_string());
+            continue;
+        }
+        if is_hybrid_marker(bare) && parsed.specific_epithet.is_none() {
+            parsed.hybrid = true;
+            continue;
+        }
+        if parsed.specific_epithet.is_none()
+            && UNSPECIFIED_SPECIES
+                .iter()
+                .any(|sp| bare.eq_ignore_ascii_case(sp))
+        {
+            parsed.warnings.push(format!(
+                "Unspecified species ('{}'); resolved at genus level.",
+                bare
+            ));
+            continue;
+        }
+        if let Some(marker) = rank_marker(bare)
+            && parsed.specific_epithet.is_some()
+            && parsed.infraspecific_epithet.is_none()
+        {
+            pending_marker = Some(marker);
+            continue;
+        }
+        if is_epithet(bare) && !is_hybrid_marker(bare) {
+            if parsed.specific_epithet.is_none() {
+                parsed.specific_epithet = Some(bare.to_string());
+                continue;
+            }
+            if parsed.infraspecific_epithet.is_none() {
+                parsed.infraspecific_epithet = Some(bare.to_string());
+                match pending_marker.take() {
+                    Some(marker) => parsed.rank_marker = Some(marker.to_string()),
+                    None => parsed.warnings.push(format!(
+                        "Infraspecific epithet '{}' has no rank marker.",
+                        bare
+                    )),
+                }
+                continue;
+            }
+        }
+        authorship.push(token);
+    }
+
+    if let Some(marker) = pending_marker {
+        parsed.warnings.push(format!(
+            "Rank marker '{}' is not followed by an epithet and was ignored.",
+            marker
+        ));
+    }
+    if let Some(q) = &parsed.qualifier {
+        parsed.warnings.push(format!(
+            "Identification qualifier '{}' was dropped; the name was matched without it.",
+            q
+        ));
+    }
+    if !authorship.is_empty() {
+        let joined = authorship.join(" ").replace("( ", "(").replace(" )", ")");
+        parsed.year = parse_year(&joined);
+        parsed.authorship = Some(joined);
+    }
+
+    let mut canonical = Vec::new();
+    if hybrid_genus {
+        canonical.push(HYBRID_SIGN.to_string());
+    }
+    canonical.push(parsed.genus.clone());
+    if let Some(epithet) = &parsed.specific_epithet {
+        if parsed.hybrid && !hybrid_genus {
+            canonical.push(HYBRID_SIGN.to_string());
+        }
+        canonical.push(epithet.clone());
+    }
+    if let Some(epithet) = &parsed.infraspecific_epithet {
+        if let Some(marker) = &parsed.rank_marker {
+            canonical.push(marker.clone());
+        }
+        canonical.push(epithet.clone());
+    }
+    parsed.canonical = canonical.join(" ");
+    parsed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_binomial_with_authorship_and_year() {
+        let parsed = parse_taxon_name("Vernonanthura patens (Kunth) H.Rob.");
+        assert_eq!(parsed.canonical, "Vernonanthura patens");
+        assert_eq!(parsed.authorship.as_deref(), Some("(Kunth) H.Rob."));
+        assert!(parsed.warnings.is_empty());
+
+        let parsed = parse_taxon_name("Homo sapiens Linnaeus, 1758");
+        assert_eq!(parsed.canonical, "Homo sapiens");
+        assert_eq!(parsed.year, Some(1758));
+
+        let parsed = parse_taxon_name("Aloe vera (L.) Burm. f.");
+        assert_eq!(parsed.canonical, "Aloe vera");
+        assert_eq!(parsed.authorship.as_deref(), Some("(L.) Burm. f."));
+
+        let parsed = parse_taxon_name("Vernonanthura patens(Kunth)H.Rob.");
+        assert_eq!(parsed.canonical, "Vernonanthura patens");
+
+        let parsed = parse_taxon_name("Arabidopsis thalianaL.");
+        assert_eq!(parsed.canonical, "Arabidopsis thaliana");
+        assert_eq!(parsed.specific_epithet.as_deref(), Some("thaliana"));
+        assert_eq!(parsed.authorship.as_deref(), Some("L."));
+        assert_eq!(parsed.warnings.len(), 1);
+        assert!(parsed.is_plain_species());
+
+        let parsed = parse_taxon_name("Ocotea foetens (Aiton) Baill. ex de Wild.");
+        assert_eq!(parsed.canonical, "Ocotea foetens");
+        let parsed = parse_taxon_name("Aniba canelilla van der Werff");
+        assert_eq!(parsed.canonical, "Aniba canelilla");
+    }
+
+    #[test]
+    fn test_infraspecific_and_hybrid_names() {
+        let parsed = parse_taxon_name("Citrus × aurantium var. bergamia (Risso) Wight & Arn.");
+        assert_eq!(parsed.canonical, "Citrus × aurantium var. bergamia");
+        assert!(parsed.hybrid);
+        assert_eq!(parsed.rank_marker.as_deref(), Some("var."));
+        assert_eq!(parsed.rank_qid(), Some("Q767728"));
+        assert_eq!(parsed.authorship.as_deref(), Some("(Risso) Wight & Arn."));
+
+        assert_eq!(
+            parse_taxon_name("Mentha x piperita L.").canonical,
+            "Mentha × piperita"
+        );
+        assert_eq!(
+            parse_taxon_name("× Citrofortunella microcarpa").canonical,
+            "× Citrofortunella microcarpa"
+        );
+        assert_eq!(
+            parse_taxon_name("Brassica oleracea L. ssp. capitata").canonical,
+            "Brassica oleracea subsp. capitata"
+        );
+
+        let parsed = parse_taxon_name("Apis mellifera ligustica");
+        assert_eq!(parsed.canonical, "Apis mellifera ligustica");
+        assert_eq!(parsed.warnings.len(), 1);
+        assert_eq!(parsed.rank_qid(), None);
+    }
+
+    #[test]
+    fn test_genus_only_qualifiers_and_warnings() {
+        let parsed = parse_taxon_name("Streptomyces sp.");
+        assert_eq!(parsed.canonical, "Streptomyces");
+        assert_eq!(parsed.warnings.len(), 1);
+        assert_eq!(parsed.rank_qid(), Some("Q34740"));
+
+        assert_eq!(
+            parse_taxon_name("Penicillium Link").canonical,
+            "Penicillium"
+        );
+
+        let parsed = parse_taxon_name("Aspergillus cf. niger");
+        assert_eq!(parsed.canonical, "Aspergillus niger");
+        assert_eq!(parsed.qualifier.as_deref(), Some("cf."));
+        assert!(parsed.warnings[0].contains("cf."));
+
+        let parsed = parse_taxon_name("coffea arabica");
+        assert_eq!(parsed.canonical, "Coffea arabica");
+        assert_eq!(parsed.warnings.len(), 1);
+
+        let parsed = parse_taxon_name("  Aus  (Bus)  cus  ");
+        assert_eq!(parsed.verbatim, "Aus (Bus) cus");
+        assert_eq!(parsed.canonical, "Aus cus");
+
+        assert_eq!(
+            parse_taxon_name("cf. Aspergillus niger").canonical,
+            "Aspergillus niger"
+        );
+
+        let parsed = parse_taxon_name("Fungus-12 isolate");
+        assert_eq!(parsed.canonical, "Fungus-12 isolate");
+        assert_eq!(parsed.warnings.len(), 1);
+    }
+}
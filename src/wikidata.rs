@@ -1,3 +1,4 @@
 //! Helpers for checking existing Wikidata data and generating edits.
+pub mod api;
 pub mod checker;
 pub mod writer;
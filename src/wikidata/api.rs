@@ -0,0 +1,389 @@
+//! Minimal Wikibase action API client used for direct edits (bot-password login + `wbeditentity`).
+use crate::error::{CrateError, Result};
+use crate::wikidata::checker::USER_AGENT;
+use log::{info, warn};
+use serde_json::{Value, json};
+use std::time::Duration;
+
+/// Default action API endpoint for Wikidata.
+pub const WIKIDATA_API_URL: &str = "https://www.wikidata.org/w/api.php";
+const ENTITY_URI_PREFIX: &str = "http://www.wikidata.org/entity/";
+const GREGORIAN_CALENDAR_QID: &str = "Q1985727";
+const MAXLAG_SECONDS: &str = "5";
+const MAX_EDIT_ATTEMPTS: usize = 3;
+const DEFAULT_RETRY_AFTER_SECONDS: u64 = 5;
+
+/// Logged-in session holding the cookie jar and CSRF token needed for edits.
+pub struct WikibaseSession {
+    client: reqwest::Client,
+    api_url: String,
+    csrf_token: String,
+}
+
+impl WikibaseSession {
+    /// Logs in with a bot password (`User@botname` + generated password) and fetches a CSRF token.
+    pub async fn login(api_url: &str, username: &str, password: &str) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .user_agent(USER_AGENT)
+            .cookie_store(true)
+            .build()
+            .map_err(CrateError::ApiRequestError)?;
+
+        let login_token = fetch_token(&client, api_url, "login").await?;
+        let payload: Value = client
+            .post(api_url)
+            .form(&[
+                ("action", "login"),
+                ("lgname", username),
+                ("lgpassword", password),
+                ("lgtoken", login_token.as_str()),
+                ("format", "json"),
+            ])
+            .send()
+            .await
+            .map_err(CrateError::ApiRequestError)?
+            .json()
+            .await
+            .map_err(CrateError::ApiJsonDecodeError)?;
+
+        let result = payload["login"]["result"].as_str().unwrap_or("");
+        if result != "Success" {
+            let reason = payload["login"]["reason"]
+                .as_str()
+                .or_else(|| payload["error"]["info"].as_str())
+                .unwrap_or(result);
+            return Err(CrateError::WikidataWriteError(format!(
+                "Login failed for {}: {}",
+                username, reason
+            )));
+        }
+        info!("Logged in to {} as {}", api_url, username);
+
+        let csrf_token = fetch_token(&client, api_url, "csrf").await?;
+        if csrf_token == "+\\" {
+            return Err(CrateError::WikidataWriteError(
+                "Received an anonymous CSRF token; the login session was not kept".to_string(),
+            ));
+        }
+
+        Ok(Self {
+            client,
+            api_url: api_url.to_string(),
+            csrf_token,
+        })
+    }
+
+    /// Creates a new item from Wikibase entity JSON and returns its QID.
+    pub async fn create_item(&self, data: &Value, summary: &str) -> Result<String> {
+        let serialized = data.to_string();
+        let payload = self
+            .post_edit(&[
+                ("action", "wbeditentity"),
+                ("new", "item"),
+                ("data", serialized.as_str()),
+                ("summary", summary),
+            ])
+            .await?;
+        payload["entity"]["id"]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| {
+                CrateError::WikidataWriteError(
+                    "wbeditentity response did not contain an entity id".to_string(),
+                )
+            })
+    }
+
+    /// Adds a single statement (with its qualifiers and references) to an existing item.
+    pub async fn add_claim(&self, entity_id: &str, claim: &Value, summary: &str) -> Result<()> {
+        let serialized = json!({ "claims": [claim] }).to_string();
+        self.post_edit(&[
+            ("action", "wbeditentity"),
+            ("id", entity_id),
+            ("data", serialized.as_str()),
+            ("summary", summary),
+        ])
+        .await?;
+        Ok(())
+    }
+
+    async fn post_edit(&self, params: &[(&str, &str)]) -> Result<Value> {
+        let mut form: Vec<(&str, &str)> = params.to_vec();
+        form.extend([
+            ("token", self.csrf_token.as_str()),
+            ("bot", "1"),
+            ("maxlag", MAXLAG_SECONDS),
+            ("format", "json"),
+        ]);
+
+        let mut attempt = 1;
+        loop {
+            let response = self
+                .client
+                .post(&self.api_url)
+                .form(&form)
+                .send()
+                .await
+                .map_err(CrateError::ApiRequestError)?;
+            let retry_after = response
+                .headers()
+                .get(reqwest::header::RETRY_AFTER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.parse::<u64>().ok())
+                .unwrap_or(DEFAULT_RETRY_AFTER_SECONDS);
+            let payload: Value = response
+                .json()
+                .await
+                .map_err(CrateError::ApiJsonDecodeError)?;
+
+            let Some(error) = payload.get("error") else {
+                return Ok(payload);
+            };
+            let code = error["code"].as_str().unwrap_or("unknown");
+            let message = error["info"].as_str().unwrap_or("");
+            if code == "maxlag" && attempt < MAX_EDIT_ATTEMPTS {
+                warn!(
+                    "Wikibase replication lag too high ({}); retrying in {}s",
+                    message, retry_after
+                );
+                tokio::time::sleep(Duration::from_secs(retry_after)).await;
+                attempt += 1;
+                continue;
+            }
+            return Err(CrateError::WikidataWriteError(format!(
+                "{}: {}",
+                code, message
+            )));
+        }
+    }
+}
+
+async fn fetch_token(client: &reqwest::Client, api_url: &str, token_type: &str) -> Result<String> {
+    let payload: Value = client
+        .get(api_url)
+        .query(&[
+            ("action", "query"),
+            ("meta", "tokens"),
+            ("type", token_type),
+            ("format", "json"),
+        ])
+        .send()
+        .await
+        .map_err(CrateError::ApiRequestError)?
+        .json()
+        .await
+        .map_err(CrateError::ApiJsonDecodeError)?;
+
+    payload["query"]["tokens"][format!("{}token", token_type)]
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| {
+            CrateError::WikidataWriteError(format!(
+                "Token response did not contain a {} token",
+                token_type
+            ))
+        })
+}
+
+/// Builds a `wikibase-item` value snak.
+pub fn item_snak(property: &str, qid: &str) -> Value {
+    let numeric_id: u64 = qid.trim_start_matches('Q').parse().unwrap_or_default();
+    value_snak(
+        property,
+        json!({
+            "value": { "entity-type": "item", "numeric-id": numeric_id, "id": qid },
+            "type": "wikibase-entityid"
+        }),
+    )
+}
+
+/// Builds a `string`/`external-id` value snak.
+pub fn string_snak(property: &str, value: &str) -> Value {
+    value_snak(property, json!({ "value": value, "type": "string" }))
+}
+
+/// Builds a `monolingualtext` value snak.
+pub fn monolingual_snak(property: &str, language: &str, text: &str) -> Value {
+    value_snak(
+        property,
+        json!({
+            "value": { "text": text, "language": language },
+            "type": "monolingualtext"
+        }),
+    )
+}
+
+/// Builds a Gregorian `time` value snak from a `+YYYY-MM-DDT00:00:00Z` timestamp.
+pub fn time_snak(property: &str, time: &str, precision: u8) -> Value {
+    value_snak(
+        property,
+        json!({
+            "value": {
+                "time": time,
+                "timezone": 0,
+                "before": 0,
+                "after": 0,
+                "precision": precision,
+                "calendarmodel": format!("{}{}", ENTITY_URI_PREFIX, GREGORIAN_CALENDAR_QID)
+            },
+            "type": "time"
+        }),
+    )
+}
+
+/// Builds a `quantity` value snak with the given unit item.
+pub fn quantity_snak(property: &str, amount: &str, unit_qid: &str) -> Value {
+    let signed = if amount.starts_with('-') || amount.starts_with('+') {
+        amount.to_string()
+    } else {
+        format!("+{}", amount)
+    };
+    value_snak(
+        property,
+        json!({
+            "value": { "amount": signed, "unit": format!("{}{}", ENTITY_URI_PREFIX, unit_qid) },
+            "type": "quantity"
+        }),
+    )
+}
+
+/// Wraps a main snak into a normal-rank statement with optional qualifiers and one reference.
+pub fn statement(mainsnak: Value, qualifiers: Vec<Value>, reference: Vec<Value>) -> Value {
+    let mut claim = json!({
+        "mainsnak": mainsnak,
+        "type": "statement",
+        "rank": "normal"
+    });
+    if !qualifiers.is_empty() {
+        claim["qualifiers"] = group_snaks(qualifiers);
+    }
+    if !reference.is_empty() {
+        claim["references"] = json!([{ "snaks": group_snaks(reference) }]);
+    }
+    claim
+}
+
+fn value_snak(property: &str, datavalue: Value) -> Value {
+    json!({
+        "snaktype": "value",
+        "property": property,
+        "datavalue": datavalue
+    })
+}
+
+fn group_snaks(snaks: Vec<Value>) -> Value {
+    let mut grouped = serde_json::Map::new();
+    for snak in snaks {
+        let property = snak["property"].as_str().unwrap_or_default().to_string();
+        grouped
+            .entry(property)
+            .or_insert_with(|| Value::Array(Vec::new()))
+            .as_array_mut()
+            .expect("grouped snaks are arrays")
+            .push(snak);
+    }
+    Value::Object(grouped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{body_string_contains, method, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    async fn mount_login(server: &MockServer) {
+        Mock::given(method("GET"))
+            .and(query_param("type", "login"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "query": { "tokens": { "logintoken": "login-token+\\" } }
+            })))
+            .mount(server)
+            .await;
+        Mock::given(method("GET"))
+            .and(query_param("type", "csrf"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "query": { "tokens": { "csrftoken": "csrf-token+\\" } }
+            })))
+            .mount(server)
+            .await;
+    }
+
+    #[test]
+    fn test_statement_groups_reference_snaks() {
+        let claim = statement(
+            item_snak("P703", "Q2"),
+            vec![string_snak("P1545", "1")],
+            vec![item_snak("P248", "Q3"), item_snak("P248", "Q4")],
+        );
+        assert_eq!(claim["mainsnak"]["datavalue"]["value"]["numeric-id"], 2);
+        assert_eq!(claim["qualifiers"]["P1545"][0]["datavalue"]["value"], "1");
+        assert_eq!(
+            claim["references"][0]["snaks"]["P248"]
+                .as_array()
+                .unwrap()
+                .len(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_quantity_snak_is_signed() {
+        let snak = quantity_snak("P2067", "16.0", "Q483261");
+        assert_eq!(snak["datavalue"]["value"]["amount"], "+16.0");
+        assert_eq!(
+            snak["datavalue"]["value"]["unit"],
+            "http://www.wikidata.org/entity/Q483261"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_login_and_create_item_against_mock() {
+        let server = MockServer::start().await;
+        mount_login(&server).await;
+        Mock::given(method("POST"))
+            .and(body_string_contains("action=login"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "login": { "result": "Success", "lgusername": "Bot" }
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(body_string_contains("action=wbeditentity"))
+            .and(body_string_contains("new=item"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "success": 1,
+                "entity": { "id": "Q42" }
+            })))
+            .mount(&server)
+            .await;
+
+        let api_url = format!("{}/w/api.php", server.uri());
+        let session = WikibaseSession::login(&api_url, "User@bot", "secret")
+            .await
+            .unwrap();
+        let qid = session
+            .create_item(&json!({ "labels": {} }), "test")
+            .await
+            .unwrap();
+        assert_eq!(qid, "Q42");
+    }
+
+    #[tokio::test]
+    async fn test_login_failure_is_reported() {
+        let server = MockServer::start().await;
+        mount_login(&server).await;
+        Mock::given(method("POST"))
+            .and(body_string_contains("action=login"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "login": { "result": "Failed", "reason": "Incorrect username or password entered." }
+            })))
+            .mount(&server)
+            .await;
+
+        let api_url = format!("{}/w/api.php", server.uri());
+        let result = WikibaseSession::login(&api_url, "User@bot", "wrong").await;
+        assert!(
+            matches!(result, Err(CrateError::WikidataWriteError(msg)) if msg.contains("Incorrect"))
+        );
+    }
+}
@@ -1,39 +1,80 @@
 //! SPARQL helpers that check Wikidata for chemicals, taxa, references, and occurrences.
+use crate::cache::{self, CacheKind};
 use crate::enrichment::EnrichedData;
 use crate::error::{CrateError, Result};
 use crate::reference::{ReferenceMetadata, fetch_reference_metadata};
+use crate::taxon::backbone::TaxonBackbone;
+use chemical::find_related_chemicals;
+pub use chemical::{ChemicalRelation, RelatedChemical};
+use futures::stream::{self, StreamExt};
 use log::{info, warn};
 use once_cell::sync::Lazy;
-use serde::Deserialize;
-use std::collections::HashMap;
-use std::sync::Mutex;
+use serde::{Deserialize, Serialize};
+use statements::fetch_chemical_statements;
+pub use statements::{ChemicalStatements, DisputedValue, StatementComparison, StatementConflict};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, RwLock};
+use taxon::{
+    apply_backbone, batch_lookup_taxa, distinct_higher_taxa, resolve_parent_taxa, resolve_taxon,
+    suggest_close_taxa, taxon_lookup_name,
+};
+
+mod chemical;
+mod statements;
+mod taxon;
 
 /// Stores results from Wikidata checks for a single row.
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct WikidataInfo {
     pub chemical_qid: Option<String>,
     pub taxon_qid: Option<String>,
     pub reference_qid: Option<String>,
     pub occurrence_exists: bool, // Added field for occurrence check
     pub reference_metadata: Option<ReferenceMetadata>,
+    /// Problems found while resolving the row (e.g. a preset QID that is a redirect).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+    /// Competing taxon QIDs when the name matched several items; the row needs review.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub taxon_candidates: Vec<String>,
+    /// Accepted name behind `taxon_qid` when the input name was a synonym.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resolved_taxon_name: Option<String>,
+    /// Genus QID a missing species would be created under (see `--create-taxa`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_taxon_qid: Option<String>,
+    /// Close names from the same genus when the taxon name was not found.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub taxon_suggestions: Vec<String>,
+    /// Items sharing the InChIKey connectivity block when the full InChIKey was not found.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub related_chemicals: Vec<RelatedChemical>,
+    /// Statements the matched chemical item already has; `None` when there is no item or the
+    /// lookup failed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chemical_statements: Option<ChemicalStatements>,
+}
+
+/// Switches that change how rows are resolved against Wikidata.
+#[derive(Debug, Clone, Default)]
+pub struct CheckOptions {
+    /// Keep the QID of a synonym taxon item instead of following it to the accepted taxon.
+    pub keep_synonyms: bool,
+    /// Local checklist consulted before the SPARQL taxon lookup.
+    pub taxon_backbone: Option<Arc<TaxonBackbone>>,
+    /// Accept a unique close name from the same genus within this many edits when a name is
+    /// not found.
+    pub accept_fuzzy_taxa: Option<usize>,
 }
 
 // Structure to deserialize SPARQL JSON results (both SELECT and ASK)
 // Made fields optional to handle variations in response structure
 #[derive(Deserialize, Debug)]
 struct SparqlResponse {
-    head: Option<SparqlHead>,
     results: Option<SparqlResults>,
     boolean: Option<bool>, // For ASK queries
 }
 
-#[derive(Deserialize, Debug)]
-struct SparqlHead {
-    // Made vars optional as it might be missing
-    #[serde(default)] // Use default (empty vec) if missing
-    vars: Vec<String>,
-}
-
 #[derive(Deserialize, Debug)]
 struct SparqlResults {
     // Made bindings optional or default
@@ -48,28 +89,55 @@ struct SparqlBinding {
     value: String,
 }
 
-const WIKIDATA_SPARQL_URL: &str = "https://query.wikidata.org/sparql";
+/// Default SPARQL endpoint.
+pub const WIKIDATA_SPARQL_URL: &str = "https://query.wikidata.org/sparql";
 pub const USER_AGENT: &str =
     "lotus-o3/0.1 (https://github.com/your_repo; your_email@example.com) reqwest/0.11"; // Replace with actual info
 
-static JOURNAL_LABEL_CACHE: Lazy<Mutex<HashMap<String, Option<String>>>> =
-    Lazy::new(|| Mutex::new(HashMap::new()));
-static JOURNAL_ISSN_CACHE: Lazy<Mutex<HashMap<String, Option<String>>>> =
-    Lazy::new(|| Mutex::new(HashMap::new()));
-static REFERENCE_QID_CACHE: Lazy<Mutex<HashMap<String, Option<String>>>> =
-    Lazy::new(|| Mutex::new(HashMap::new()));
+static SPARQL_URL: Lazy<RwLock<String>> =
+    Lazy::new(|| RwLock::new(WIKIDATA_SPARQL_URL.to_string()));
+
+/// Points every SPARQL lookup of the process at another endpoint (a mirror, or a mock server).
+pub fn configure_sparql_url(url: &str) {
+    if let Ok(mut endpoint) = SPARQL_URL.write() {
+        *endpoint = url.to_string();
+    }
+}
+
+fn sparql_url() -> String {
+    SPARQL_URL
+        .read()
+        .map(|endpoint| endpoint.clone())
+        .unwrap_or_else(|_| WIKIDATA_SPARQL_URL.to_string())
+}
 
 // Helper function to execute a SPARQL query and parse the result
 async fn execute_sparql_query(query: &str, client: &reqwest::Client) -> Result<SparqlResponse> {
     let response = client
-        .get(WIKIDATA_SPARQL_URL)
+        .get(sparql_url())
         .query(&[("query", query), ("format", "json")])
         .header(reqwest::header::USER_AGENT, USER_AGENT)
         .header(reqwest::header::ACCEPT, "application/sparql-results+json")
         .send()
         .await
         .map_err(CrateError::SparqlQueryError)?;
+    parse_sparql_response(response).await
+}
 
+// Batched queries carry long VALUES blocks, so they are sent as POST bodies instead of URLs.
+async fn execute_sparql_post(query: &str, client: &reqwest::Client) -> Result<SparqlResponse> {
+    let response = client
+        .post(sparql_url())
+        .form(&[("query", query), ("format", "json")])
+        .header(reqwest::header::USER_AGENT, USER_AGENT)
+        .header(reqwest::header::ACCEPT, "application/sparql-results+json")
+        .send()
+        .await
+        .map_err(CrateError::SparqlQueryError)?;
+    parse_sparql_response(response).await
+}
+
+async fn parse_sparql_response(response: reqwest::Response) -> Result<SparqlResponse> {
     if !response.status().is_success() {
         // Use SparqlQueryError for non-2xx status codes from the SPARQL endpoint
         return Err(CrateError::SparqlQueryError(
@@ -90,7 +158,7 @@ async fn execute_sparql_query(query: &str, client: &reqwest::Client) -> Result<S
 // Now handles potentially missing results or bindings
 fn extract_qid(response: &SparqlResponse, var_name: &str) -> Option<String> {
     response.results.as_ref().and_then(|results| {
-        results.bindings.get(0).and_then(|binding| {
+        results.bindings.first().and_then(|binding| {
             binding.get(var_name).and_then(|item_binding| {
                 if item_binding.datatype == "uri" {
                     item_binding.value.split("/").last().map(String::from)
@@ -104,53 +172,34 @@ fn extract_qid(response: &SparqlResponse, var_name: &str) -> Option<String> {
 
 // Check for chemical entity by InChIKey (P235)
 async fn check_chemical(inchikey: &str, client: &reqwest::Client) -> Result<Option<String>> {
+    if let Some(cached) = cache::lookup(CacheKind::ChemicalQid, inchikey) {
+        return Ok(cached);
+    }
     let query = format!("SELECT ?item WHERE {{ ?item wdt:P235 \"{inchikey}\". }}");
     let response = execute_sparql_query(&query, client).await?;
-    Ok(extract_qid(&response, "item"))
-}
-
-// Check for taxon by name
-async fn check_taxon(taxon_name: &str, client: &reqwest::Client) -> Result<Option<String>> {
-    let query = format!("SELECT ?item WHERE {{ ?item wdt:P225 \"{taxon_name}\". }}");
-    let response = execute_sparql_query(&query, client).await?;
-    Ok(extract_qid(&response, "item"))
+    let found = extract_qid(&response, "item");
+    cache::store(CacheKind::ChemicalQid, inchikey, found.as_ref());
+    Ok(found)
 }
 
 // Check for reference (publication) by DOI (P356)
 async fn check_reference(doi: &str, client: &reqwest::Client) -> Result<Option<String>> {
     let trimmed = doi.trim();
     let key = trimmed.to_lowercase();
-    if let Some(cached) = REFERENCE_QID_CACHE
-        .lock()
-        .ok()
-        .and_then(|cache| cache.get(&key).cloned())
-    {
+    if let Some(cached) = cache::lookup(CacheKind::ReferenceQid, &key) {
         return Ok(cached);
     }
     info!("Searching Wikidata for DOI {}", trimmed);
-    let mut candidates = Vec::new();
-    candidates.push(trimmed.to_string());
-
-    let upper = trimmed.to_uppercase();
-    if upper != trimmed {
-        candidates.push(upper);
-    }
-
-    let lower = trimmed.to_lowercase();
-    if lower != trimmed && lower != trimmed.to_uppercase() {
-        candidates.push(lower);
-    }
-
     let mut found = None;
-    for candidate in candidates {
-        let escaped = candidate.replace('\"', "\\\"");
+    for candidate in doi_candidates(trimmed) {
+        let literal = sparql_literal(&candidate);
         let query = format!(
             r#"SELECT ?item WHERE {{
                 {{
-                    ?item wdt:P356 "{escaped}".
+                    ?item wdt:P356 {literal}.
                 }} UNION {{
                     SERVICE wdsubgraph:scholarly_articles {{
-                        ?item wdt:P356 "{escaped}".
+                        ?item wdt:P356 {literal}.
                     }}
                 }}
             }}"#
@@ -162,42 +211,51 @@ async fn check_reference(doi: &str, client: &reqwest::Client) -> Result<Option<S
         }
     }
 
-    if let Ok(mut cache) = REFERENCE_QID_CACHE.lock() {
-        cache.insert(key, found.clone());
-    }
+    cache::store(CacheKind::ReferenceQid, &key, found.as_ref());
 
     Ok(found)
 }
 
+// DOIs are stored with inconsistent casing on Wikidata, so try the input, upper and lower case.
+fn doi_candidates(trimmed: &str) -> Vec<String> {
+    let mut candidates = Vec::new();
+    candidates.push(trimmed.to_string());
+
+    let upper = trimmed.to_uppercase();
+    if upper != trimmed {
+        candidates.push(upper);
+    }
+
+    let lower = trimmed.to_lowercase();
+    if lower != trimmed && lower != trimmed.to_uppercase() {
+        candidates.push(lower);
+    }
+    candidates
+}
+
 async fn lookup_journal_qid(title: &str, client: &reqwest::Client) -> Result<Option<String>> {
     let trimmed = title.trim();
     if trimmed.is_empty() {
         return Ok(None);
     }
 
-    if let Some(cached) = JOURNAL_LABEL_CACHE
-        .lock()
-        .ok()
-        .and_then(|cache| cache.get(trimmed).cloned())
-    {
+    if let Some(cached) = cache::lookup(CacheKind::JournalLabel, trimmed) {
         return Ok(cached);
     }
 
-    let escaped = trimmed.replace('"', "\"");
+    let literal = sparql_literal(trimmed);
     let query = format!(
         r#"SELECT ?item WHERE {{
             VALUES ?class {{ wd:Q5633421 wd:Q1002697 wd:Q737498 }}
             ?item wdt:P31/wdt:P279* ?class ;
                   rdfs:label ?label .
-            FILTER (lcase(str(?label)) = lcase("{escaped}"))
+            FILTER (lcase(str(?label)) = lcase({literal}))
         }} LIMIT 1"#
     );
 
     let response = execute_sparql_query(&query, client).await?;
     let qid = extract_qid(&response, "item");
-    if let Ok(mut cache) = JOURNAL_LABEL_CACHE.lock() {
-        cache.insert(trimmed.to_string(), qid.clone());
-    }
+    cache::store(CacheKind::JournalLabel, trimmed, qid.as_ref());
     Ok(qid)
 }
 
@@ -210,26 +268,20 @@ async fn lookup_journal_qid_by_issn(
         return Ok(None);
     }
 
-    if let Some(cached) = JOURNAL_ISSN_CACHE
-        .lock()
-        .ok()
-        .and_then(|cache| cache.get(trimmed).cloned())
-    {
+    if let Some(cached) = cache::lookup(CacheKind::JournalIssn, trimmed) {
         return Ok(cached);
     }
 
-    let escaped = trimmed.replace('"', "\"");
+    let literal = sparql_literal(trimmed);
     let query = format!(
         r#"SELECT ?item WHERE {{
-            ?item wdt:P236 "{escaped}" .
+            ?item wdt:P236 {literal} .
         }} LIMIT 1"#
     );
 
     let response = execute_sparql_query(&query, client).await?;
     let qid = extract_qid(&response, "item");
-    if let Ok(mut cache) = JOURNAL_ISSN_CACHE.lock() {
-        cache.insert(trimmed.to_string(), qid.clone());
-    }
+    cache::store(CacheKind::JournalIssn, trimmed, qid.as_ref());
     Ok(qid)
 }
 
@@ -266,28 +318,69 @@ async fn check_occurrence(
 /// Resolves existing Wikidata entities and detects missing references for an enriched record.
 pub async fn check_wikidata(
     record: &EnrichedData,
+    options: &CheckOptions,
     client: &reqwest::Client,
 ) -> Result<WikidataInfo> {
-    let inchikey = record
-        .inchikey
-        .as_deref()
-        .ok_or_else(|| CrateError::MissingDescriptor {
-            descriptor: "inchikey".to_string(),
-            smiles: record.sanitized_smiles.clone(),
-        })?;
-
-    let chemical_qid_fut = check_chemical(inchikey, client);
-    let taxon_qid_fut = check_taxon(&record.taxon_name, client);
-    let reference_qid_fut = check_reference(&record.reference_doi, client);
+    let mut presets = verify_presets(std::slice::from_ref(record), client)
+        .await
+        .pop()
+        .unwrap_or_else(|| Ok(RecordEntities::default()))?;
+    apply_backbone(record, options, &mut presets);
+
+    let chemical_qid_fut = async {
+        if presets.chemical.is_some() {
+            return Ok(presets.chemical.clone());
+        }
+        let inchikey = record
+            .inchikey
+            .as_deref()
+            .ok_or_else(|| CrateError::MissingDescriptor {
+                descriptor: "inchikey".to_string(),
+                smiles: record.sanitized_smiles.clone(),
+            })?;
+        check_chemical(inchikey, client).await
+    };
+    let taxon_candidates_fut = async {
+        if presets.taxon.is_some() {
+            return Ok(None);
+        }
+        let names = [taxon_lookup_name(record, &presets).to_string()];
+        let higher_taxa = distinct_higher_taxa(std::iter::once(record));
+        batch_lookup_taxa(&names, &higher_taxa, client)
+            .await
+            .resolve(&names[0])
+    };
+    let reference_qid_fut = async {
+        match &presets.reference {
+            Some(qid) => Ok(Some(qid.clone())),
+            None => check_reference(&record.reference_doi, client).await,
+        }
+    };
 
     // Execute entity checks concurrently
     let (chemical_result, taxon_result, reference_result) =
-        tokio::join!(chemical_qid_fut, taxon_qid_fut, reference_qid_fut);
+        tokio::join!(chemical_qid_fut, taxon_candidates_fut, reference_qid_fut);
 
     // Collect entity results, propagating the first error encountered
     let chemical_qid = chemical_result?;
-    let taxon_qid = taxon_result?;
+    let taxon_candidates = taxon_result?;
     let reference_qid = reference_result?;
+    let mut entities = presets;
+    entities.chemical = chemical_qid.clone();
+    if let Some(candidates) = taxon_candidates {
+        resolve_taxon(record, &candidates, options, &mut entities);
+        let mut resolved = [Ok(entities)];
+        suggest_close_taxa(std::slice::from_ref(record), &mut resolved, options, client).await;
+        resolve_parent_taxa(std::slice::from_ref(record), &mut resolved, client).await;
+        let [resolved] = resolved;
+        entities = resolved?;
+    }
+    let mut resolved = [Ok(entities)];
+    find_related_chemicals(std::slice::from_ref(record), &mut resolved, client).await;
+    fetch_chemical_statements(&mut resolved, client).await;
+    let [resolved] = resolved;
+    let entities = resolved?;
+    let taxon_qid = entities.taxon;
 
     let mut occurrence_exists = false;
     let mut reference_metadata = None;
@@ -295,41 +388,7 @@ pub async fn check_wikidata(
     if let (Some(chem_q), Some(tax_q), Some(ref_q)) = (&chemical_qid, &taxon_qid, &reference_qid) {
         occurrence_exists = check_occurrence(chem_q, tax_q, ref_q, client).await?;
     } else if reference_qid.is_none() {
-        info!(
-            "DOI {} not found on Wikidata. Falling back to Crossref metadata lookup.",
-            record.reference_doi
-        );
-        match fetch_reference_metadata(&record.reference_doi, client).await {
-            Ok(Some(mut metadata)) => {
-                if let Some(issn) = metadata.issn.clone() {
-                    match lookup_journal_qid_by_issn(&issn, client).await {
-                        Ok(Some(journal_qid)) => metadata.journal_qid = Some(journal_qid),
-                        Ok(None) => {}
-                        Err(err) => {
-                            warn!("Failed to match journal ISSN {} on Wikidata: {}", issn, err)
-                        }
-                    }
-                }
-
-                if metadata.journal_qid.is_none() {
-                    if let Some(title) = metadata.container_title.clone() {
-                        match lookup_journal_qid(&title, client).await {
-                            Ok(Some(journal_qid)) => metadata.journal_qid = Some(journal_qid),
-                            Ok(None) => {}
-                            Err(err) => {
-                                warn!("Failed to match journal '{}' on Wikidata: {}", title, err)
-                            }
-                        }
-                    }
-                }
-                reference_metadata = Some(metadata);
-            }
-            Ok(None) => reference_metadata = None,
-            Err(err) => warn!(
-                "Failed to fetch Crossref metadata for DOI {}: {}",
-                record.reference_doi, err
-            ),
-        }
+        reference_metadata = fetch_reference_fallback(&record.reference_doi, client).await;
     }
 
     Ok(WikidataInfo {
@@ -338,14 +397,645 @@ pub async fn check_wikidata(
         reference_qid,
         occurrence_exists,
         reference_metadata,
+        warnings: entities.warnings,
+        taxon_candidates: entities.taxon_candidates,
+        resolved_taxon_name: entities.resolved_taxon_name,
+        parent_taxon_qid: entities.parent_taxon,
+        taxon_suggestions: entities.taxon_suggestions,
+        related_chemicals: entities.related_chemicals,
+        chemical_statements: entities.chemical_statements,
     })
 }
 
+// Crossref lookup (plus journal matching) for DOIs that are not on Wikidata yet.
+async fn fetch_reference_fallback(
+    doi: &str,
+    client: &reqwest::Client,
+) -> Option<ReferenceMetadata> {
+    info!(
+        "DOI {} not found on Wikidata. Falling back to Crossref metadata lookup.",
+        doi
+    );
+    match fetch_reference_metadata(doi, client).await {
+        Ok(Some(mut metadata)) => {
+            if let Some(issn) = metadata.issn.clone() {
+                match lookup_journal_qid_by_issn(&issn, client).await {
+                    Ok(Some(journal_qid)) => metadata.journal_qid = Some(journal_qid),
+                    Ok(None) => {}
+                    Err(err) => {
+                        warn!("Failed to match journal ISSN {} on Wikidata: {}", issn, err)
+                    }
+                }
+            }
+
+            if metadata.journal_qid.is_none()
+                && let Some(title) = metadata.container_title.clone()
+            {
+                match lookup_journal_qid(&title, client).await {
+                    Ok(Some(journal_qid)) => metadata.journal_qid = Some(journal_qid),
+                    Ok(None) => {}
+                    Err(err) => {
+                        warn!("Failed to match journal '{}' on Wikidata: {}", title, err)
+                    }
+                }
+            }
+            Some(metadata)
+        }
+        Ok(None) => None,
+        Err(err) => {
+            warn!("Failed to fetch Crossref metadata for DOI {}: {}", doi, err);
+            None
+        }
+    }
+}
+
+// --- Batched lookups ---
+// Instead of one request per row, distinct keys from all records are resolved with chunked
+// `VALUES` queries. A failed chunk only fails the records whose keys it contained.
+
+/// Maximum number of keys sent in one `VALUES` block.
+const SPARQL_BATCH_SIZE: usize = 100;
+
+type OccurrenceTriple = (String, String, String);
+
+/// Resolved keys plus the error message for keys whose chunk failed.
+#[derive(Debug)]
+struct BatchLookup<K, V = String> {
+    found: HashMap<K, V>,
+    failed: HashMap<K, String>,
+}
+
+impl<K: std::hash::Hash + Eq, V> Default for BatchLookup<K, V> {
+    fn default() -> Self {
+        Self {
+            found: HashMap::new(),
+            failed: HashMap::new(),
+        }
+    }
+}
+
+impl<K: std::hash::Hash + Eq, V: Clone> BatchLookup<K, V> {
+    fn resolve(&self, key: &K) -> Result<Option<V>> {
+        if let Some(reason) = self.failed.get(key) {
+            return Err(CrateError::SparqlBatchError(reason.clone()));
+        }
+        Ok(self.found.get(key).cloned())
+    }
+}
+
+fn sparql_literal(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn binding_qid(binding: &HashMap<String, SparqlBinding>, var_name: &str) -> Option<String> {
+    binding
+        .get(var_name)
+        .filter(|value| value.datatype == "uri")
+        .and_then(|value| value.value.split('/').next_back())
+        .map(String::from)
+}
+
+fn binding_text<'a>(
+    binding: &'a HashMap<String, SparqlBinding>,
+    var_name: &str,
+) -> Option<&'a str> {
+    binding.get(var_name).map(|value| value.value.as_str())
+}
+
+/// Maps each `?key` literal to the first `?item` bound for it (same rule as `extract_qid`).
+fn collect_key_items(response: &SparqlResponse) -> HashMap<String, String> {
+    let mut found = HashMap::new();
+    if let Some(results) = &response.results {
+        for binding in &results.bindings {
+            if let (Some(key), Some(qid)) =
+                (binding_text(binding, "key"), binding_qid(binding, "item"))
+            {
+                found.entry(key.to_string()).or_insert(qid);
+            }
+        }
+    }
+    found
+}
+
+fn chemical_batch_query(values: &str) -> String {
+    format!("SELECT ?key ?item WHERE {{ VALUES ?key {{ {values} }} ?item wdt:P235 ?key . }}")
+}
+
+fn reference_batch_query(values: &str) -> String {
+    format!(
+        r#"SELECT ?key ?item WHERE {{
+            {{
+                VALUES ?key {{ {values} }}
+                ?item wdt:P356 ?key .
+            }} UNION {{
+                SERVICE wdsubgraph:scholarly_articles {{
+                    VALUES ?key {{ {values} }}
+                    ?item wdt:P356 ?key .
+                }}
+            }}
+        }}"#
+    )
+}
+
+fn occurrence_batch_query(triples: &[OccurrenceTriple]) -> String {
+    let values = triples
+        .iter()
+        .map(|(chemical, taxon, reference)| format!("(wd:{chemical} wd:{taxon} wd:{reference})"))
+        .collect::<Vec<_>>()
+        .join(" ");
+    format!(
+        "SELECT ?chemical ?taxon ?reference WHERE {{
+            VALUES (?chemical ?taxon ?reference) {{ {values} }}
+            ?chemical p:P703 ?statement.
+            ?statement ps:P703 ?taxon;
+                wikibase:rank wikibase:NormalRank;
+                (prov:wasDerivedFrom/pr:P248) ?reference.
+        }}"
+    )
+}
+
+// Resolves string keys to items in chunks of `SPARQL_BATCH_SIZE`.
+async fn batch_lookup(
+    keys: &[String],
+    build_query: fn(&str) -> String,
+    client: &reqwest::Client,
+) -> BatchLookup<String> {
+    let mut lookup = BatchLookup::default();
+    for chunk in keys.chunks(SPARQL_BATCH_SIZE) {
+        let values = chunk
+            .iter()
+            .map(|key| sparql_literal(key))
+            .collect::<Vec<_>>()
+            .join(" ");
+        match execute_sparql_post(&build_query(&values), client).await {
+            Ok(response) => lookup.found.extend(collect_key_items(&response)),
+            Err(err) => {
+                warn!(
+                    "Batched SPARQL lookup of {} keys failed: {}",
+                    chunk.len(),
+                    err
+                );
+                let reason = err.to_string();
+                for key in chunk {
+                    lookup.failed.insert(key.clone(), reason.clone());
+                }
+            }
+        }
+    }
+    lookup
+}
+
+// Resolves InChIKeys to chemical items, asking Wikidata only for keys missing from the cache.
+async fn batch_lookup_chemicals(
+    inchikeys: &[String],
+    client: &reqwest::Client,
+) -> BatchLookup<String> {
+    let mut lookup = BatchLookup::default();
+    let mut pending = Vec::new();
+    for inchikey in inchikeys {
+        match cache::lookup::<String>(CacheKind::ChemicalQid, inchikey) {
+            Some(Some(qid)) => {
+                lookup.found.insert(inchikey.clone(), qid);
+            }
+            Some(None) => {}
+            None => pending.push(inchikey.clone()),
+        }
+    }
+    if pending.is_empty() {
+        return lookup;
+    }
+
+    let fetched = batch_lookup(&pending, chemical_batch_query, client).await;
+    for inchikey in pending {
+        if let Some(qid) = fetched.found.get(&inchikey) {
+            cache::store(CacheKind::ChemicalQid, &inchikey, Some(qid));
+        } else if !fetched.failed.contains_key(&inchikey) {
+            cache::store::<String>(CacheKind::ChemicalQid, &inchikey, None);
+        }
+    }
+    lookup.found.extend(fetched.found);
+    lookup.failed.extend(fetched.failed);
+    lookup
+}
+
+// Resolves DOIs (keyed by lowercase DOI) using the cache and all case variants.
+async fn batch_lookup_references(dois: &[String], client: &reqwest::Client) -> BatchLookup<String> {
+    let mut lookup = BatchLookup::default();
+    let mut pending: Vec<(String, Vec<String>)> = Vec::new();
+    for doi in dois {
+        let trimmed = doi.trim();
+        let key = trimmed.to_lowercase();
+        match cache::lookup::<String>(CacheKind::ReferenceQid, &key) {
+            Some(Some(qid)) => {
+                lookup.found.insert(key, qid);
+            }
+            Some(None) => {}
+            None => pending.push((key, doi_candidates(trimmed))),
+        }
+    }
+    if pending.is_empty() {
+        return lookup;
+    }
+
+    info!("Searching Wikidata for {} DOIs", pending.len());
+    let candidates: Vec<String> = pending
+        .iter()
+        .flat_map(|(_, variants)| variants.iter().cloned())
+        .collect();
+    let candidate_lookup = batch_lookup(&candidates, reference_batch_query, client).await;
+    for (key, variants) in pending {
+        if let Some(qid) = variants
+            .iter()
+            .find_map(|variant| candidate_lookup.found.get(variant))
+        {
+            cache::store(CacheKind::ReferenceQid, &key, Some(qid));
+            lookup.found.insert(key, qid.clone());
+        } else if let Some(reason) = variants
+            .iter()
+            .find_map(|variant| candidate_lookup.failed.get(variant))
+        {
+            lookup.failed.insert(key, reason.clone());
+        } else {
+            cache::store::<String>(CacheKind::ReferenceQid, &key, None);
+        }
+    }
+    lookup
+}
+
+// Returns the subset of triples that already exist as referenced P703 statements.
+async fn batch_check_occurrences(
+    triples: &[OccurrenceTriple],
+    client: &reqwest::Client,
+) -> BatchLookup<OccurrenceTriple> {
+    let mut lookup = BatchLookup::default();
+    for chunk in triples.chunks(SPARQL_BATCH_SIZE) {
+        match execute_sparql_post(&occurrence_batch_query(chunk), client).await {
+            Ok(response) => {
+                for binding in response.results.iter().flat_map(|r| r.bindings.iter()) {
+                    if let (Some(chemical), Some(taxon), Some(reference)) = (
+                        binding_qid(binding, "chemical"),
+                        binding_qid(binding, "taxon"),
+                        binding_qid(binding, "reference"),
+                    ) {
+                        lookup
+                            .found
+                            .insert((chemical, taxon, reference.clone()), reference);
+                    }
+                }
+            }
+            Err(err) => {
+                warn!(
+                    "Batched occurrence check of {} triples failed: {}",
+                    chunk.len(),
+                    err
+                );
+                let reason = err.to_string();
+                for triple in chunk {
+                    lookup.failed.insert(triple.clone(), reason.clone());
+                }
+            }
+        }
+    }
+    lookup
+}
+
+// --- Preset QIDs ---
+// QIDs supplied in the input CSV are confirmed with one `VALUES` query over the items
+// themselves: live items carry `schema:version`, redirects carry `owl:sameAs`.
+
+/// What Wikidata says about a QID taken from the input CSV.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ItemStatus {
+    Exists,
+    RedirectsTo(String),
+    Missing,
+}
+
+/// QIDs resolved for one record; after preset verification `None` means "look it up as usual".
+#[derive(Debug, Default)]
+struct RecordEntities {
+    chemical: Option<String>,
+    taxon: Option<String>,
+    reference: Option<String>,
+    warnings: Vec<String>,
+    taxon_candidates: Vec<String>,
+    resolved_taxon_name: Option<String>,
+    parent_taxon: Option<String>,
+    taxon_suggestions: Vec<String>,
+    related_chemicals: Vec<RelatedChemical>,
+    chemical_statements: Option<ChemicalStatements>,
+    /// Name to look up instead of the input one (backbone correction or accepted name).
+    taxon_lookup: Option<String>,
+}
+
+fn item_status_query(values: &str) -> String {
+    format!(
+        "SELECT ?item ?target ?version WHERE {{
+            VALUES ?item {{ {values} }}
+            OPTIONAL {{ ?item owl:sameAs ?target . }}
+            OPTIONAL {{ ?item schema:version ?version . }}
+        }}"
+    )
+}
+
+fn collect_item_statuses(response: &SparqlResponse) -> HashMap<String, ItemStatus> {
+    let mut statuses = HashMap::new();
+    for binding in response.results.iter().flat_map(|r| r.bindings.iter()) {
+        let Some(item) = binding_qid(binding, "item") else {
+            continue;
+        };
+        let status = if let Some(target) = binding_qid(binding, "target") {
+            ItemStatus::RedirectsTo(target)
+        } else if binding.contains_key("version") {
+            ItemStatus::Exists
+        } else {
+            ItemStatus::Missing
+        };
+        statuses.insert(item, status);
+    }
+    statuses
+}
+
+async fn batch_item_statuses(
+    qids: &[String],
+    client: &reqwest::Client,
+) -> BatchLookup<String, ItemStatus> {
+    let mut lookup = BatchLookup::default();
+    for chunk in qids.chunks(SPARQL_BATCH_SIZE) {
+        let values = chunk
+            .iter()
+            .map(|qid| format!("wd:{}", qid))
+            .collect::<Vec<_>>()
+            .join(" ");
+        match execute_sparql_post(&item_status_query(&values), client).await {
+            Ok(response) => lookup.found.extend(collect_item_statuses(&response)),
+            Err(err) => {
+                warn!("Checking {} preset QIDs failed: {}", chunk.len(), err);
+                let reason = err.to_string();
+                for qid in chunk {
+                    lookup.failed.insert(qid.clone(), reason.clone());
+                }
+            }
+        }
+    }
+    lookup
+}
+
+// Keeps a confirmed preset, follows a redirect, or drops a missing item (with a warning).
+fn verify_preset(
+    kind: &str,
+    preset: Option<&String>,
+    statuses: &BatchLookup<String, ItemStatus>,
+    warnings: &mut Vec<String>,
+) -> Result<Option<String>> {
+    let Some(qid) = preset else {
+        return Ok(None);
+    };
+    match statuses.resolve(qid)?.unwrap_or(ItemStatus::Missing) {
+        ItemStatus::Exists => Ok(Some(qid.clone())),
+        ItemStatus::RedirectsTo(target) => {
+            warnings.push(format!(
+                "Preset {} QID {} redirects to {}; using {}",
+                kind, qid, target, target
+            ));
+            Ok(Some(target))
+        }
+        ItemStatus::Missing => {
+            warnings.push(format!(
+                "Preset {} QID {} does not exist on Wikidata; looked it up instead",
+                kind, qid
+            ));
+            Ok(None)
+        }
+    }
+}
+
+async fn verify_presets(
+    records: &[EnrichedData],
+    client: &reqwest::Client,
+) -> Vec<Result<RecordEntities>> {
+    let qids = distinct(records.iter().flat_map(|r| {
+        let presets = &r.preset_qids;
+        [&presets.chemical, &presets.taxon, &presets.reference]
+            .into_iter()
+            .filter_map(|qid| qid.as_deref())
+    }));
+    if !qids.is_empty() {
+        info!("Verifying {} preset QIDs", qids.len());
+    }
+    let statuses = batch_item_statuses(&qids, client).await;
+    records
+        .iter()
+        .map(|record| {
+            let presets = &record.preset_qids;
+            let mut warnings = Vec::new();
+            Ok(RecordEntities {
+                chemical: verify_preset(
+                    "chemical",
+                    presets.chemical.as_ref(),
+                    &statuses,
+                    &mut warnings,
+                )?,
+                taxon: verify_preset("taxon", presets.taxon.as_ref(), &statuses, &mut warnings)?,
+                reference: verify_preset(
+                    "reference",
+                    presets.reference.as_ref(),
+                    &statuses,
+                    &mut warnings,
+                )?,
+                warnings,
+                ..Default::default()
+            })
+        })
+        .collect()
+}
+
+fn distinct<'a>(values: impl Iterator<Item = &'a str>) -> Vec<String> {
+    let mut seen = HashSet::new();
+    values
+        .filter(|value| seen.insert(*value))
+        .map(String::from)
+        .collect()
+}
+
+/// Batched counterpart of [`check_wikidata`]: resolves all records with chunked `VALUES`
+/// queries and returns one result per record, in input order. Up to `jobs` DOIs missing from
+/// Wikidata are looked up on Crossref at once.
+pub async fn check_wikidata_batch(
+    records: &[EnrichedData],
+    options: &CheckOptions,
+    jobs: usize,
+    client: &reqwest::Client,
+) -> Vec<Result<WikidataInfo>> {
+    let mut presets = verify_presets(records, client).await;
+    for (record, entities) in records.iter().zip(&mut presets) {
+        if let Ok(entities) = entities {
+            apply_backbone(record, options, entities);
+        }
+    }
+    // Only records whose preset is missing (or unusable) need the regular lookups.
+    let pending = || {
+        records
+            .iter()
+            .zip(&presets)
+            .filter_map(|(record, presets)| presets.as_ref().ok().map(|p| (record, p)))
+    };
+    let inchikeys = distinct(
+        pending()
+            .filter(|(_, p)| p.chemical.is_none())
+            .filter_map(|(r, _)| r.inchikey.as_deref()),
+    );
+    let taxa = distinct(
+        pending()
+            .filter(|(_, p)| p.taxon.is_none())
+            .map(|(r, p)| taxon_lookup_name(r, p)),
+    );
+    let dois = distinct(
+        pending()
+            .filter(|(_, p)| p.reference.is_none())
+            .map(|(r, _)| r.reference_doi.trim()),
+    );
+    info!(
+        "Resolving {} InChIKeys, {} taxa and {} DOIs in batches",
+        inchikeys.len(),
+        taxa.len(),
+        dois.len()
+    );
+
+    let higher_taxa =
+        distinct_higher_taxa(pending().filter(|(_, p)| p.taxon.is_none()).map(|(r, _)| r));
+
+    let (chemicals, taxon_items, references) = tokio::join!(
+        batch_lookup_chemicals(&inchikeys, client),
+        batch_lookup_taxa(&taxa, &higher_taxa, client),
+        batch_lookup_references(&dois, client),
+    );
+
+    let mut resolved = Vec::with_capacity(records.len());
+    for (record, presets) in records.iter().zip(presets) {
+        let entities = presets.and_then(|mut entities| {
+            if entities.chemical.is_none() {
+                let inchikey =
+                    record
+                        .inchikey
+                        .as_ref()
+                        .ok_or_else(|| CrateError::MissingDescriptor {
+                            descriptor: "inchikey".to_string(),
+                            smiles: record.sanitized_smiles.clone(),
+                        })?;
+                entities.chemical = chemicals.resolve(inchikey)?;
+            }
+            if entities.taxon.is_none() {
+                let candidates = taxon_items
+                    .resolve(&taxon_lookup_name(record, &entities).to_string())?
+                    .unwrap_or_default();
+                resolve_taxon(record, &candidates, options, &mut entities);
+            }
+            if entities.reference.is_none() {
+                entities.reference =
+                    references.resolve(&record.reference_doi.trim().to_lowercase())?;
+            }
+            Ok(entities)
+        });
+        resolved.push(entities);
+    }
+
+    // Names not found on Wikidata: close names from the same genus, then the genus itself.
+    suggest_close_taxa(records, &mut resolved, options, client).await;
+    resolve_parent_taxa(records, &mut resolved, client).await;
+    // Chemicals not found by InChIKey: items with the same skeleton but other stereo.
+    find_related_chemicals(records, &mut resolved, client).await;
+    // Chemicals found: what their items already state.
+    fetch_chemical_statements(&mut resolved, client).await;
+
+    let triples: Vec<OccurrenceTriple> = resolved
+        .iter()
+        .filter_map(|entities| match entities {
+            Ok(RecordEntities {
+                chemical: Some(chemical),
+                taxon: Some(taxon),
+                reference: Some(reference),
+                ..
+            }) => Some((chemical.clone(), taxon.clone(), reference.clone())),
+            _ => None,
+        })
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect();
+    let occurrences = batch_check_occurrences(&triples, client).await;
+
+    let mut missing_dois: HashMap<String, &str> = HashMap::new();
+    for (record, entities) in records.iter().zip(&resolved) {
+        if let Ok(RecordEntities {
+            reference: None, ..
+        }) = entities
+        {
+            let doi = record.reference_doi.trim();
+            missing_dois.entry(doi.to_lowercase()).or_insert(doi);
+        }
+    }
+    let metadata_by_doi: HashMap<String, Option<ReferenceMetadata>> = stream::iter(missing_dois)
+        .map(|(key, doi)| async move { (key, fetch_reference_fallback(doi, client).await) })
+        .buffer_unordered(jobs.max(1))
+        .collect()
+        .await;
+
+    resolved
+        .into_iter()
+        .zip(records)
+        .map(|(entities, record)| {
+            let RecordEntities {
+                chemical: chemical_qid,
+                taxon: taxon_qid,
+                reference: reference_qid,
+                warnings,
+                taxon_candidates,
+                resolved_taxon_name,
+                parent_taxon: parent_taxon_qid,
+                taxon_suggestions,
+                related_chemicals,
+                chemical_statements,
+                ..
+            } = entities?;
+            let mut occurrence_exists = false;
+            let mut reference_metadata = None;
+            if let (Some(chem_q), Some(tax_q), Some(ref_q)) =
+                (&chemical_qid, &taxon_qid, &reference_qid)
+            {
+                let triple = (chem_q.clone(), tax_q.clone(), ref_q.clone());
+                occurrence_exists = occurrences.resolve(&triple)?.is_some();
+            } else if reference_qid.is_none() {
+                reference_metadata = metadata_by_doi
+                    .get(&record.reference_doi.trim().to_lowercase())
+                    .cloned()
+                    .flatten();
+            }
+            Ok(WikidataInfo {
+                chemical_qid,
+                taxon_qid,
+                reference_qid,
+                occurrence_exists,
+                reference_metadata,
+                warnings,
+                taxon_candidates,
+                resolved_taxon_name,
+                parent_taxon_qid,
+                taxon_suggestions,
+                related_chemicals,
+                chemical_statements,
+            })
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::enrichment::EnrichedData;
+    use serde_json::json;
     use tokio;
+    use wiremock::matchers::{body_string_contains, method};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
 
     // Helper to create a basic EnrichedData for testing
     fn create_test_enriched_data() -> EnrichedData {
@@ -353,7 +1043,6 @@ mod tests {
             chemical_entity_name: "Test Compound".to_string(),
             input_smiles: "C".to_string(),
             sanitized_smiles: "C".to_string(),
-            smiles_were_sanitized: false,
             taxon_name: "Test Taxon".to_string(),
             reference_doi: "10.1234/test".to_string(),
             canonical_smiles: Some("C".to_string()),
@@ -362,7 +1051,7 @@ mod tests {
             inchikey: Some("VNWKTOKETHGBQD-UHFFFAOYSA-N".to_string()), // Methane InChIKey
             molecular_formula: Some("CH4".to_string()),
             exact_mass: Some(16.0),
-            other_descriptors: None,
+            ..Default::default()
         }
     }
 
@@ -378,7 +1067,9 @@ mod tests {
             .user_agent(USER_AGENT)
             .build()
             .unwrap();
-        let info = check_wikidata(&record, &client).await.unwrap();
+        let info = check_wikidata(&record, &CheckOptions::default(), &client)
+            .await
+            .unwrap();
 
         assert!(info.chemical_qid.is_some());
         // Note: QID might change, this is illustrative
@@ -400,7 +1091,9 @@ mod tests {
             .user_agent(USER_AGENT)
             .build()
             .unwrap();
-        let info = check_wikidata(&record, &client).await.unwrap();
+        let info = check_wikidata(&record, &CheckOptions::default(), &client)
+            .await
+            .unwrap();
         assert!(info.chemical_qid.is_none());
         // Occurrence check should be false as chemical_qid is None
         assert!(!info.occurrence_exists);
@@ -423,7 +1116,9 @@ mod tests {
             .user_agent(USER_AGENT)
             .build()
             .unwrap();
-        let info = check_wikidata(&record, &client).await.unwrap();
+        let info = check_wikidata(&record, &CheckOptions::default(), &client)
+            .await
+            .unwrap();
 
         // We display info for debugging
         println!("Chemical QID: {:?}", info.chemical_qid);
@@ -442,6 +1137,268 @@ mod tests {
         // println!("Occurrence exists: {}", info.occurrence_exists);
     }
 
-    // Add more tests for taxon, reference, occurrence, and error cases
-    // Consider using a mock SPARQL server (e.g., using wiremock-rs)
+    #[test]
+    fn test_sparql_literal_escapes_quotes() {
+        assert_eq!(sparql_literal("Aus bus"), r#""Aus bus""#);
+        assert_eq!(sparql_literal(r#"a"b\c"#), r#""a\"b\\c""#);
+    }
+
+    #[test]
+    fn test_collect_key_items_keeps_first_binding() {
+        let response: SparqlResponse = serde_json::from_str(
+            r#"{
+                "head": { "vars": ["key", "item"] },
+                "results": { "bindings": [
+                    { "key": { "type": "literal", "value": "Coffea arabica" },
+                      "item": { "type": "uri", "value": "http://www.wikidata.org/entity/Q1" } },
+                    { "key": { "type": "literal", "value": "Coffea arabica" },
+                      "item": { "type": "uri", "value": "http://www.wikidata.org/entity/Q2" } },
+                    { "key": { "type": "literal", "value": "Theobroma cacao" },
+                      "item": { "type": "uri", "value": "http://www.wikidata.org/entity/Q3" } }
+                ] }
+            }"#,
+        )
+        .unwrap();
+        let found = collect_key_items(&response);
+        assert_eq!(found.len(), 2);
+        assert_eq!(found["Coffea arabica"], "Q1");
+        assert_eq!(found["Theobroma cacao"], "Q3");
+    }
+
+    #[test]
+    fn test_preset_qids_follow_redirects_and_drop_missing_items() {
+        let response: SparqlResponse = serde_json::from_str(
+            r#"{
+                "results": { "bindings": [
+                    { "item": { "type": "uri", "value": "http://www.wikidata.org/entity/Q1" },
+                      "version": { "type": "literal", "value": "2114159112" } },
+                    { "item": { "type": "uri", "value": "http://www.wikidata.org/entity/Q2" },
+                      "target": { "type": "uri", "value": "http://www.wikidata.org/entity/Q3" } },
+                    { "item": { "type": "uri", "value": "http://www.wikidata.org/entity/Q4" } }
+                ] }
+            }"#,
+        )
+        .unwrap();
+        let statuses = BatchLookup {
+            found: collect_item_statuses(&response),
+            failed: HashMap::from([("Q5".to_string(), "timeout".to_string())]),
+        };
+        assert_eq!(
+            statuses.found["Q2"],
+            ItemStatus::RedirectsTo("Q3".to_string())
+        );
+
+        let mut warnings = Vec::new();
+        let preset = |qid: &str| Some(qid.to_string());
+        assert_eq!(
+            verify_preset("taxon", preset("Q1").as_ref(), &statuses, &mut warnings).unwrap(),
+            Some("Q1".to_string())
+        );
+        assert!(warnings.is_empty());
+        assert_eq!(
+            verify_preset("taxon", preset("Q2").as_ref(), &statuses, &mut warnings).unwrap(),
+            Some("Q3".to_string())
+        );
+        assert_eq!(
+            verify_preset("chemical", preset("Q4").as_ref(), &statuses, &mut warnings).unwrap(),
+            None
+        );
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[1].contains("Q4 does not exist"));
+        assert!(
+            verify_preset("reference", preset("Q5").as_ref(), &statuses, &mut warnings).is_err()
+        );
+    }
+
+    #[test]
+    fn test_batch_queries_use_values_blocks() {
+        let query = chemical_batch_query(r#""AAA" "BBB""#);
+        assert!(query.contains(r#"VALUES ?key { "AAA" "BBB" }"#));
+        assert!(query.contains("wdt:P235 ?key"));
+
+        let triples = vec![("Q1".to_string(), "Q2".to_string(), "Q3".to_string())];
+        let query = occurrence_batch_query(&triples);
+        assert!(query.contains("VALUES (?chemical ?taxon ?reference) { (wd:Q1 wd:Q2 wd:Q3) }"));
+    }
+
+    #[test]
+    fn test_batch_lookup_failures_are_per_key() {
+        let mut lookup: BatchLookup<String> = BatchLookup::default();
+        lookup.found.insert("A".to_string(), "Q1".to_string());
+        lookup.failed.insert("B".to_string(), "timeout".to_string());
+        assert_eq!(
+            lookup.resolve(&"A".to_string()).unwrap(),
+            Some("Q1".to_string())
+        );
+        assert_eq!(lookup.resolve(&"C".to_string()).unwrap(), None);
+        assert!(matches!(
+            lookup.resolve(&"B".to_string()),
+            Err(CrateError::SparqlBatchError(reason)) if reason == "timeout"
+        ));
+    }
+
+    #[tokio::test]
+    #[ignore] // Ignored by default to avoid hitting live Wikidata
+    async fn test_check_batch_matches_single_live() {
+        let mut record = create_test_enriched_data();
+        record.inchikey = Some("ULGZDMOVFRHVEP-RWJQBGPGSA-N".to_string());
+        record.taxon_name = "Streptomyces coelicolor".to_string();
+        record.reference_doi = "10.1021/BI965010K".to_string();
+        let client = reqwest::Client::builder()
+            .user_agent(USER_AGENT)
+            .build()
+            .unwrap();
+
+        let single = check_wikidata(&record, &CheckOptions::default(), &client)
+            .await
+            .unwrap();
+        let batch = check_wikidata_batch(
+            std::slice::from_ref(&record),
+            &CheckOptions::default(),
+            1,
+            &client,
+        )
+        .await;
+        let batched = batch.into_iter().next().unwrap().unwrap();
+        assert_eq!(batched.chemical_qid, single.chemical_qid);
+        assert_eq!(batched.taxon_qid, single.taxon_qid);
+        assert_eq!(batched.reference_qid, single.reference_qid);
+        assert_eq!(batched.occurrence_exists, single.occurrence_exists);
+    }
+
+    // Form-encodes a query fragment the way the batched POST body carries it.
+    fn sparql_form(fragment: &str) -> String {
+        fragment
+            .replace(':', "%3A")
+            .replace('?', "%3F")
+            .replace(' ', "+")
+    }
+
+    fn bindings(rows: serde_json::Value) -> ResponseTemplate {
+        ResponseTemplate::new(200).set_body_json(json!({ "results": { "bindings": rows } }))
+    }
+
+    fn uri(qid: &str) -> serde_json::Value {
+        json!({ "type": "uri", "value": format!("http://www.wikidata.org/entity/{qid}") })
+    }
+
+    fn literal(value: &str) -> serde_json::Value {
+        json!({ "type": "literal", "value": value })
+    }
+
+    fn crossref_metadata(doi: &str, issn: &str) -> ReferenceMetadata {
+        ReferenceMetadata {
+            doi: doi.to_string(),
+            title: format!("Title of {doi}"),
+            title_language: None,
+            language_qid: None,
+            entity_type_qid: "Q13442814".to_string(),
+            publication_date: None,
+            volume: None,
+            issue: None,
+            container_title: None,
+            issn: Some(issn.to_string()),
+            journal_qid: None,
+            authors: Vec::new(),
+            retrieved_on: chrono::NaiveDate::from_ymd_opt(2026, 1, 1).unwrap(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_check_wikidata_batch_against_mock_endpoint() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(body_string_contains(sparql_form("wdt:P235 ?key")))
+            .respond_with(bindings(json!([
+                { "key": literal("MOCKFOUNDKEYAA-UHFFFAOYSA-N"), "item": uri("Q101") }
+            ])))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(body_string_contains(sparql_form("wdt:P225 ?key")))
+            .respond_with(bindings(json!([
+                { "key": literal("Mockia batchensis"), "item": uri("Q102") }
+            ])))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(body_string_contains(sparql_form("wdt:P356 ?key")))
+            .respond_with(bindings(json!([
+                { "key": literal("10.9999/mock.found"), "item": uri("Q103") }
+            ])))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(body_string_contains(sparql_form("ps:P703 ?taxon")))
+            .respond_with(bindings(json!([
+                { "chemical": uri("Q101"), "taxon": uri("Q102"), "reference": uri("Q103") }
+            ])))
+            .mount(&server)
+            .await;
+        // Journal ISSN lookups (one per distinct missing DOI).
+        Mock::given(method("GET"))
+            .respond_with(bindings(json!([{ "item": uri("Q109") }])))
+            .expect(2)
+            .mount(&server)
+            .await;
+        // Related chemicals, statements, genus members: nothing.
+        Mock::given(method("POST"))
+            .respond_with(bindings(json!([])))
+            .with_priority(10)
+            .mount(&server)
+            .await;
+
+        for (doi, issn) in [
+            ("10.9999/mock.new-a", "9999-0001"),
+            ("10.9999/mock.new-b", "9999-0002"),
+        ] {
+            cache::store(
+                CacheKind::Crossref,
+                doi,
+                Some(&crossref_metadata(doi, issn)),
+            );
+        }
+        let record = |inchikey: &str, doi: &str| EnrichedData {
+            taxon_name: "Mockia batchensis".to_string(),
+            inchikey: Some(inchikey.to_string()),
+            reference_doi: doi.to_string(),
+            ..create_test_enriched_data()
+        };
+        let records = vec![
+            record("MOCKFOUNDKEYAA-UHFFFAOYSA-N", "10.9999/mock.found"),
+            record("MOCKMISSINGKEY-UHFFFAOYSA-N", "10.9999/mock.new-a"),
+            record("MOCKFOUNDKEYAA-UHFFFAOYSA-N", "10.9999/MOCK.NEW-B"),
+            record("MOCKFOUNDKEYAA-UHFFFAOYSA-N", "10.9999/mock.new-b"),
+        ];
+
+        configure_sparql_url(&format!("{}/sparql", server.uri()));
+        let results = check_wikidata_batch(
+            &records,
+            &CheckOptions::default(),
+            4,
+            &reqwest::Client::new(),
+        )
+        .await;
+        configure_sparql_url(WIKIDATA_SPARQL_URL);
+
+        let infos: Vec<WikidataInfo> = results.into_iter().map(|r| r.unwrap()).collect();
+        assert_eq!(infos[0].chemical_qid.as_deref(), Some("Q101"));
+        assert_eq!(infos[0].taxon_qid.as_deref(), Some("Q102"));
+        assert_eq!(infos[0].reference_qid.as_deref(), Some("Q103"));
+        assert!(infos[0].occurrence_exists);
+        assert!(infos[0].reference_metadata.is_none());
+
+        assert_eq!(infos[1].chemical_qid, None);
+        assert_eq!(infos[1].taxon_qid.as_deref(), Some("Q102"));
+        assert!(!infos[1].occurrence_exists);
+        for info in &infos[1..] {
+            assert_eq!(info.reference_qid, None);
+            let metadata = info.reference_metadata.as_ref().unwrap();
+            assert_eq!(metadata.journal_qid.as_deref(), Some("Q109"));
+        }
+        assert_eq!(
+            infos[2].reference_metadata.as_ref().unwrap().doi,
+            "10.9999/mock.new-b"
+        );
+    }
 }
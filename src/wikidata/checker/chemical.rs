@@ -0,0 +1,262 @@
+//! Near-duplicate detection for chemicals whose full InChIKey is not on Wikidata: items sharing
+//! the first (connectivity) block of the key are listed with how their stereo descriptors
+//! compare, so a stereo-undefined input is not created next to its defined stereoisomer (or
+//! the reverse) without a curator noticing.
+use super::{
+    BatchLookup, RecordEntities, SPARQL_BATCH_SIZE, binding_qid, binding_text, distinct,
+    execute_sparql_post, sparql_literal,
+};
+use crate::chemical_entity::stereo::{StereoLayers, StereoRelation};
+use crate::enrichment::EnrichedData;
+use crate::error::Result;
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+/// Length of the connectivity block plus its separator (`XXXXXXXXXXXXXX-`).
+const CONNECTIVITY_PREFIX_LEN: usize = 15;
+
+/// How an existing item sharing the connectivity block relates to the record's structure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChemicalRelation {
+    /// Same stereo descriptors; the keys differ only in isotope, charge or protonation layers.
+    Duplicate,
+    /// The record defines stereo that the existing item leaves open.
+    MoreSpecific,
+    /// The existing item defines stereo that the record leaves open.
+    LessSpecific,
+    /// Another stereoisomer of the same skeleton.
+    Stereoisomer,
+}
+
+impl ChemicalRelation {
+    /// Whether a new item would (nearly) duplicate the existing one.
+    pub fn is_near_duplicate(self) -> bool {
+        !matches!(self, ChemicalRelation::Stereoisomer)
+    }
+
+    /// Label used in the status report.
+    pub fn label(self) -> &'static str {
+        match self {
+            ChemicalRelation::Duplicate => "duplicate",
+            ChemicalRelation::MoreSpecific => "more specific",
+            ChemicalRelation::LessSpecific => "less specific",
+            ChemicalRelation::Stereoisomer => "stereoisomer",
+        }
+    }
+}
+
+/// An existing item whose InChIKey starts with the same connectivity block as the record's.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RelatedChemical {
+    pub qid: String,
+    pub inchikey: String,
+    pub relation: ChemicalRelation,
+}
+
+/// One P235 match of a connectivity block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct BlockMatch {
+    qid: String,
+    inchikey: String,
+    inchi: Option<String>,
+}
+
+fn connectivity_prefix(inchikey: &str) -> Option<&str> {
+    inchikey.get(..CONNECTIVITY_PREFIX_LEN)
+}
+
+fn connectivity_query(prefixes: &str) -> String {
+    format!(
+        "SELECT ?key ?item ?inchikey ?inchi WHERE {{
+            VALUES ?key {{ {prefixes} }}
+            ?item wdt:P235 ?inchikey .
+            FILTER(STRSTARTS(?inchikey, ?key))
+            OPTIONAL {{ ?item wdt:P234 ?inchi . }}
+        }}"
+    )
+}
+
+/// Fetches the items whose InChIKey starts with each connectivity prefix.
+async fn batch_lookup_connectivity(
+    prefixes: &[String],
+    client: &reqwest::Client,
+) -> BatchLookup<String, Vec<BlockMatch>> {
+    let mut lookup: BatchLookup<String, Vec<BlockMatch>> = BatchLookup::default();
+    for chunk in prefixes.chunks(SPARQL_BATCH_SIZE) {
+        let values = chunk
+            .iter()
+            .map(|prefix| sparql_literal(prefix))
+            .collect::<Vec<_>>()
+            .join(" ");
+        match execute_sparql_post(&connectivity_query(&values), client).await {
+            Ok(response) => {
+                for binding in response.results.iter().flat_map(|r| r.bindings.iter()) {
+                    let (Some(key), Some(qid), Some(inchikey)) = (
+                        binding_text(binding, "key"),
+                        binding_qid(binding, "item"),
+                        binding_text(binding, "inchikey"),
+                    ) else {
+                        continue;
+                    };
+                    let matches = lookup.found.entry(key.to_string()).or_default();
+                    if !matches.iter().any(|known| known.qid == qid) {
+                        matches.push(BlockMatch {
+                            qid,
+                            inchikey: inchikey.to_string(),
+                            inchi: binding_text(binding, "inchi").map(str::to_string),
+                        });
+                    }
+                }
+            }
+            Err(err) => {
+                warn!(
+                    "Connectivity lookup of {} InChIKey blocks failed: {}",
+                    chunk.len(),
+                    err
+                );
+                let reason = err.to_string();
+                for prefix in chunk {
+                    lookup.failed.insert(prefix.clone(), reason.clone());
+                }
+            }
+        }
+    }
+    lookup
+}
+
+fn stereo_layers(inchi: Option<&str>, inchikey: &str) -> Option<StereoLayers> {
+    inchi
+        .and_then(StereoLayers::from_inchi)
+        .or_else(|| StereoLayers::from_inchikey(inchikey))
+}
+
+/// Classifies an existing item against the record; stereo that cannot be read from either side
+/// counts as another stereoisomer.
+pub(super) fn classify_match(record: &EnrichedData, found: &BlockMatch) -> ChemicalRelation {
+    let ours = record
+        .inchikey
+        .as_deref()
+        .and_then(|inchikey| stereo_layers(record.inchi.as_deref(), inchikey));
+    let theirs = stereo_layers(found.inchi.as_deref(), &found.inchikey);
+    match ours
+        .zip(theirs)
+        .map(|(ours, theirs)| ours.relation_to(&theirs))
+    {
+        Some(StereoRelation::Same) => ChemicalRelation::Duplicate,
+        Some(StereoRelation::MoreSpecific) => ChemicalRelation::MoreSpecific,
+        Some(StereoRelation::LessSpecific) => ChemicalRelation::LessSpecific,
+        Some(StereoRelation::Different) | None => ChemicalRelation::Stereoisomer,
+    }
+}
+
+/// Lists the items sharing the connectivity block of every record whose chemical was not
+/// found. A failed lookup only adds a warning; the record is then planned as before.
+pub(super) async fn find_related_chemicals(
+    records: &[EnrichedData],
+    resolved: &mut [Result<RecordEntities>],
+    client: &reqwest::Client,
+) {
+    let prefixes: Vec<Option<&str>> = records
+        .iter()
+        .zip(resolved.iter())
+        .map(|(record, entities)| match entities {
+            Ok(entities) if entities.chemical.is_none() => {
+                record.inchikey.as_deref().and_then(connectivity_prefix)
+            }
+            _ => None,
+        })
+        .collect();
+    let distinct_prefixes = distinct(prefixes.iter().flatten().copied());
+    if distinct_prefixes.is_empty() {
+        return;
+    }
+    let matches = batch_lookup_connectivity(&distinct_prefixes, client).await;
+    for ((record, prefix), entities) in records.iter().zip(&prefixes).zip(resolved) {
+        let (Some(prefix), Ok(entities)) = (prefix, entities.as_mut()) else {
+            continue;
+        };
+        match matches.resolve(&prefix.to_string()) {
+            Ok(found) => {
+                entities.related_chemicals = found
+                    .unwrap_or_default()
+                    .iter()
+                    .filter(|found| Some(found.inchikey.as_str()) != record.inchikey.as_deref())
+                    .map(|found| RelatedChemical {
+                        qid: found.qid.clone(),
+                        inchikey: found.inchikey.clone(),
+                        relation: classify_match(record, found),
+                    })
+                    .collect();
+            }
+            Err(err) => entities.warnings.push(format!(
+                "Connectivity lookup for InChIKey block {} failed: {}",
+                prefix.trim_end_matches('-'),
+                err
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(inchi: &str, inchikey: &str) -> EnrichedData {
+        EnrichedData {
+            chemical_entity_name: "Alanine".to_string(),
+            input_smiles: "CC(N)C(=O)O".to_string(),
+            sanitized_smiles: "CC(N)C(=O)O".to_string(),
+            taxon_name: "Homo sapiens".to_string(),
+            reference_doi: "10.1000/a".to_string(),
+            inchi: Some(inchi.to_string()),
+            inchikey: Some(inchikey.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_connectivity_matches_are_classified_by_stereo() {
+        let racemic = record(
+            "InChI=1S/C3H7NO2/c1-2(4)3(5)6/h2H,4H2,1H3,(H,5,6)",
+            "QNAYBMKLOCPYGJ-UHFFFAOYSA-N",
+        );
+        let l_alanine = BlockMatch {
+            qid: "Q218642".to_string(),
+            inchikey: "QNAYBMKLOCPYGJ-REOHCLBHSA-N".to_string(),
+            inchi: Some("InChI=1S/C3H7NO2/c1-2(4)3(5)6/h2H,4H2,1H3,(H,5,6)/t2-/m0/s1".to_string()),
+        };
+        assert_eq!(
+            classify_match(&racemic, &l_alanine),
+            ChemicalRelation::LessSpecific
+        );
+        let without_inchi = BlockMatch {
+            inchi: None,
+            ..l_alanine.clone()
+        };
+        assert_eq!(
+            classify_match(&racemic, &without_inchi),
+            ChemicalRelation::Stereoisomer
+        );
+        let d_alanine = record(
+            "InChI=1S/C3H7NO2/c1-2(4)3(5)6/h2H,4H2,1H3,(H,5,6)/t2-/m1/s1",
+            "QNAYBMKLOCPYGJ-UWTATZPHSA-N",
+        );
+        assert_eq!(
+            classify_match(&d_alanine, &l_alanine),
+            ChemicalRelation::Stereoisomer
+        );
+        let racemic_item = BlockMatch {
+            qid: "Q27116".to_string(),
+            inchikey: "QNAYBMKLOCPYGJ-UHFFFAOYSA-N".to_string(),
+            inchi: None,
+        };
+        assert_eq!(
+            classify_match(&d_alanine, &racemic_item),
+            ChemicalRelation::MoreSpecific
+        );
+
+        let query = connectivity_query(r#""QNAYBMKLOCPYGJ-""#);
+        assert!(query.contains("FILTER(STRSTARTS(?inchikey, ?key))"));
+    }
+}
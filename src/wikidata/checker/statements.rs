@@ -0,0 +1,420 @@
+//! Statements already on matched chemical items. They are compared with the enrichment results
+//! so that only missing properties are added, and values that disagree are reported instead of
+//! being overwritten.
+use super::{
+    BatchLookup, RecordEntities, SPARQL_BATCH_SIZE, SparqlBinding, binding_qid, binding_text,
+    distinct, execute_sparql_post,
+};
+use crate::chemical_entity::comparison::{formula_difference, inchi_difference, smiles_difference};
+use crate::chemical_entity::composition::MASS_TOLERANCE;
+use crate::enrichment::EnrichedData;
+use crate::error::Result;
+use crate::wikidata::writer::DALTON_QID;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+/// Properties written on chemical items, identifier properties included.
+const CHEMICAL_PROPERTIES: [&str; 9] = [
+    "P2017", "P233", "P234", "P235", "P274", "P2067", "P662", "P683", "P592",
+];
+
+/// Values an existing chemical item holds for the properties written on new items.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ChemicalStatements {
+    /// Truthy (`wdt:`) values per property. Masses are decimal amounts, followed by the QID of
+    /// their unit when it is not the dalton (`194.08 Q199`).
+    pub values: BTreeMap<String, Vec<String>>,
+}
+
+/// A property whose values on Wikidata all differ from the enrichment result.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StatementConflict {
+    pub property: String,
+    pub ours: String,
+    pub theirs: Vec<DisputedValue>,
+}
+
+/// A value of an existing item and how it differs from ours.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DisputedValue {
+    pub value: String,
+    pub difference: String,
+}
+
+impl fmt::Display for StatementConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let theirs: Vec<String> = self
+            .theirs
+            .iter()
+            .map(|theirs| format!("{} [{}]", theirs.value, theirs.difference))
+            .collect();
+        write!(
+            f,
+            "{} {} (Wikidata: {})",
+            self.property,
+            self.ours,
+            theirs.join(", ")
+        )
+    }
+}
+
+/// Properties to add to an existing item, and the ones that disagree with it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StatementComparison {
+    pub missing: Vec<&'static str>,
+    pub conflicts: Vec<StatementConflict>,
+}
+
+impl ChemicalStatements {
+    /// Compares the values `data` would write with the item's. A property counts as missing
+    /// when the item has no value for it, and as a conflict when none of its values match;
+    /// structures are compared structurally (see [`crate::chemical_entity::comparison`]).
+    pub fn compare(&self, data: &EnrichedData) -> StatementComparison {
+        let mut comparison = StatementComparison::default();
+        for (property, ours) in our_values(data) {
+            let theirs = self
+                .values
+                .get(property)
+                .map(Vec::as_slice)
+                .unwrap_or_default();
+            if theirs.is_empty() {
+                if !comparison.missing.contains(&property) {
+                    comparison.missing.push(property);
+                }
+            } else if let Some(theirs) = theirs
+                .iter()
+                .map(|theirs| {
+                    value_difference(property, &ours, theirs).map(|difference| DisputedValue {
+                        value: theirs.clone(),
+                        difference,
+                    })
+                })
+                .collect::<Option<Vec<_>>>()
+            {
+                comparison.conflicts.push(StatementConflict {
+                    property: property.to_string(),
+                    ours,
+                    theirs,
+                });
+            }
+        }
+        comparison
+    }
+}
+
+/// Values of `data` per property, in the order they are written on new items.
+fn our_values(data: &EnrichedData) -> Vec<(&'static str, String)> {
+    let mut values = Vec::new();
+    let mut push = |property, value: Option<&String>| {
+        if let Some(value) = value {
+            values.push((property, value.clone()));
+        }
+    };
+    push("P2017", data.isomeric_smiles.as_ref());
+    push("P233", data.canonical_smiles.as_ref());
+    push("P234", data.inchi.as_ref());
+    push("P235", data.inchikey.as_ref());
+    push("P274", data.molecular_formula.as_ref());
+    push(
+        "P2067",
+        data.exact_mass.map(|mass| mass.to_string()).as_ref(),
+    );
+    for identifier in &data.identifiers {
+        if let Some(property) = identifier.source.property() {
+            values.push((property, identifier.id.clone()));
+        }
+    }
+    values
+}
+
+/// Replaces sub- and superscript digits and signs (`C₂H₃O₂⁻`) by plain ones.
+fn plain_formula(formula: &str) -> String {
+    formula
+        .chars()
+        .map(|ch| match ch {
+            '₀'..='₉' => char::from(b'0' + (ch as u32 - '₀' as u32) as u8),
+            '⁰' => '0',
+            '¹' => '1',
+            '²' => '2',
+            '³' => '3',
+            '⁴'..='⁹' => char::from(b'4' + (ch as u32 - '⁴' as u32) as u8),
+            '⁺' => '+',
+            '⁻' => '-',
+            _ => ch,
+        })
+        .collect()
+}
+
+/// How the item's value `theirs` differs from `ours`, or `None` when both say the same.
+fn value_difference(property: &str, ours: &str, theirs: &str) -> Option<String> {
+    match property {
+        "P2017" => smiles_difference(ours, theirs, true),
+        "P233" => smiles_difference(ours, theirs, false),
+        "P234" => inchi_difference(ours, theirs),
+        "P274" => formula_difference(&plain_formula(ours), &plain_formula(theirs)),
+        "P2067" => {
+            if let Some((_, unit)) = theirs.split_once(' ') {
+                return Some(format!("unit {} instead of dalton ({})", unit, DALTON_QID));
+            }
+            match (ours.parse::<f64>(), theirs.parse::<f64>()) {
+                (Ok(ours), Ok(theirs)) => ((ours - theirs).abs() > MASS_TOLERANCE)
+                    .then(|| format!("{:.4} Da apart", (ours - theirs).abs())),
+                _ => (ours != theirs).then(|| "different value".to_string()),
+            }
+        }
+        _ => (ours != theirs).then(|| "different value".to_string()),
+    }
+}
+
+/// Truthy values of the chemical properties; masses come from their best-ranked full values so
+/// that the unit can be checked too.
+fn statements_query(items: &str) -> String {
+    let properties = CHEMICAL_PROPERTIES
+        .iter()
+        .filter(|property| **property != "P2067")
+        .map(|property| format!("(\"{property}\" wdt:{property})"))
+        .collect::<Vec<_>>()
+        .join(" ");
+    format!(
+        "SELECT ?item ?property ?value ?unit WHERE {{
+            VALUES ?item {{ {items} }}
+            {{
+                VALUES (?property ?wdt) {{ {properties} }}
+                ?item ?wdt ?value .
+            }} UNION {{
+                BIND(\"P2067\" AS ?property)
+                ?item p:P2067 ?statement .
+                ?statement a wikibase:BestRank ;
+                           psv:P2067 ?mass .
+                ?mass wikibase:quantityAmount ?value ;
+                      wikibase:quantityUnit ?unit .
+            }}
+        }}"
+    )
+}
+
+/// A value as stored in [`ChemicalStatements`]: masses keep a unit other than the dalton.
+fn statement_value(binding: &HashMap<String, SparqlBinding>) -> Option<String> {
+    let value = binding_text(binding, "value")?;
+    Some(match binding_qid(binding, "unit") {
+        Some(unit) if unit != DALTON_QID => format!("{} {}", value, unit),
+        _ => value.to_string(),
+    })
+}
+
+/// Fetches the chemical statements of every item; items without any come back empty.
+async fn batch_lookup_statements(
+    qids: &[String],
+    client: &reqwest::Client,
+) -> BatchLookup<String, ChemicalStatements> {
+    let mut lookup: BatchLookup<String, ChemicalStatements> = BatchLookup::default();
+    for chunk in qids.chunks(SPARQL_BATCH_SIZE) {
+        let items = chunk
+            .iter()
+            .map(|qid| format!("wd:{}", qid))
+            .collect::<Vec<_>>()
+            .join(" ");
+        match execute_sparql_post(&statements_query(&items), client).await {
+            Ok(response) => {
+                for qid in chunk {
+                    lookup.found.entry(qid.clone()).or_default();
+                }
+                for binding in response.results.iter().flat_map(|r| r.bindings.iter()) {
+                    let (Some(qid), Some(property), Some(value)) = (
+                        binding_qid(binding, "item"),
+                        binding_text(binding, "property"),
+                        statement_value(binding),
+                    ) else {
+                        continue;
+                    };
+                    let values = lookup
+                        .found
+                        .entry(qid)
+                        .or_default()
+                        .values
+                        .entry(property.to_string())
+                        .or_default();
+                    if !values.contains(&value) {
+                        values.push(value);
+                    }
+                }
+            }
+            Err(err) => {
+                warn!(
+                    "Statement lookup of {} chemical items failed: {}",
+                    chunk.len(),
+                    err
+                );
+                let reason = err.to_string();
+                for qid in chunk {
+                    lookup.failed.insert(qid.clone(), reason.clone());
+                }
+            }
+        }
+    }
+    lookup
+}
+
+/// Fetches the statements of every resolved chemical item. A failed lookup only adds a
+/// warning; nothing is then added to that item.
+pub(super) async fn fetch_chemical_statements(
+    resolved: &mut [Result<RecordEntities>],
+    client: &reqwest::Client,
+) {
+    let qids = distinct(
+        resolved
+            .iter()
+            .filter_map(|entities| entities.as_ref().ok()?.chemical.as_deref()),
+    );
+    if qids.is_empty() {
+        return;
+    }
+    let statements = batch_lookup_statements(&qids, client).await;
+    for entities in resolved.iter_mut().flatten() {
+        let Some(qid) = &entities.chemical else {
+            continue;
+        };
+        match statements.resolve(qid) {
+            Ok(found) => entities.chemical_statements = Some(found.unwrap_or_default()),
+            Err(err) => entities.warnings.push(format!(
+                "Statement lookup for chemical {} failed: {}",
+                qid, err
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compare_finds_missing_and_conflicting_statements() {
+        let data = EnrichedData {
+            canonical_smiles: Some("CN1C=NC2=C1C(=O)N(C(=O)N2C)C".to_string()),
+            inchikey: Some("RYYVLZVUVIJVGH-UHFFFAOYSA-N".to_string()),
+            molecular_formula: Some("C8H10N4O2".to_string()),
+            exact_mass: Some(194.080376),
+            ..Default::default()
+        };
+        let existing = ChemicalStatements {
+            values: BTreeMap::from([
+                (
+                    "P235".to_string(),
+                    vec!["RYYVLZVUVIJVGH-UHFFFAOYSA-N".to_string()],
+                ),
+                ("P274".to_string(), vec!["C₈H₁₀N₄O₂".to_string()]),
+                ("P2067".to_string(), vec!["194.08".to_string()]),
+            ]),
+        };
+        let comparison = existing.compare(&data);
+        assert_eq!(comparison.missing, ["P233"]);
+        assert!(comparison.conflicts.is_empty());
+
+        let disputed = ChemicalStatements {
+            values: BTreeMap::from([
+                ("P274".to_string(), vec!["C₈H₉N₄O₂".to_string()]),
+                ("P2067".to_string(), vec!["193.07".to_string()]),
+            ]),
+        };
+        let comparison = disputed.compare(&data);
+        assert_eq!(comparison.missing, ["P233", "P235"]);
+        let described: Vec<String> = comparison
+            .conflicts
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            described,
+            [
+                "P274 C8H10N4O2 (Wikidata: C₈H₉N₄O₂ [H 10 vs 9])",
+                "P2067 194.080376 (Wikidata: 193.07 [1.0104 Da apart])"
+            ]
+        );
+
+        let query = statements_query("wd:Q60235");
+        assert!(query.contains(r#"("P2017" wdt:P2017)"#));
+        assert!(!query.contains("wdt:P2067"));
+        assert!(query.contains("wikibase:quantityUnit ?unit"));
+    }
+
+    #[test]
+    fn test_masses_in_other_units_conflict() {
+        let data = EnrichedData {
+            exact_mass: Some(194.080376),
+            ..Default::default()
+        };
+        let binding = |unit: &str| {
+            HashMap::from([
+                (
+                    "value".to_string(),
+                    SparqlBinding {
+                        datatype: "literal".to_string(),
+                        value: "194.08".to_string(),
+                    },
+                ),
+                (
+                    "unit".to_string(),
+                    SparqlBinding {
+                        datatype: "uri".to_string(),
+                        value: format!("http://www.wikidata.org/entity/{}", unit),
+                    },
+                ),
+            ])
+        };
+        assert_eq!(
+            statement_value(&binding("Q483261")).as_deref(),
+            Some("194.08")
+        );
+        let unitless = statement_value(&binding("Q199")).unwrap();
+        assert_eq!(unitless, "194.08 Q199");
+
+        let existing = ChemicalStatements {
+            values: BTreeMap::from([("P2067".to_string(), vec![unitless])]),
+        };
+        let comparison = existing.compare(&data);
+        assert!(comparison.missing.is_empty());
+        assert_eq!(
+            comparison.conflicts[0].to_string(),
+            "P2067 194.080376 (Wikidata: 194.08 Q199 [unit Q199 instead of dalton (Q483261)])"
+        );
+    }
+
+    #[test]
+    fn test_structures_are_compared_structurally() {
+        let data = EnrichedData {
+            isomeric_smiles: Some("C[C@@H](C(=O)O)N".to_string()),
+            canonical_smiles: Some("CC(C(=O)O)N".to_string()),
+            inchi: Some("InChI=1S/C3H7NO2/c1-2(4)3(5)6/h2H,4H2,1H3,(H,5,6)/t2-/m0/s1".to_string()),
+            ..Default::default()
+        };
+        let existing = ChemicalStatements {
+            values: BTreeMap::from([
+                ("P2017".to_string(), vec!["N[C@@H](C)C(O)=O".to_string()]),
+                ("P233".to_string(), vec!["NC(C)C(O)=O".to_string()]),
+                (
+                    "P234".to_string(),
+                    vec!["InChI=1S/C3H7NO2/c1-2(4)3(5)6/h2H,4H2,1H3,(H,5,6)/t2-/m1/s1".to_string()],
+                ),
+            ]),
+        };
+        let comparison = existing.compare(&data);
+        assert!(comparison.missing.is_empty());
+        assert_eq!(comparison.conflicts.len(), 1);
+        assert_eq!(comparison.conflicts[0].property, "P234");
+        assert_eq!(
+            comparison.conflicts[0].theirs[0].difference,
+            "different stereo"
+        );
+
+        let wrong_isomer = ChemicalStatements {
+            values: BTreeMap::from([("P2017".to_string(), vec!["C[C@H](C(=O)O)N".to_string()])]),
+        };
+        assert_eq!(
+            wrong_isomer.compare(&data).conflicts[0].to_string(),
+            "P2017 C[C@@H](C(=O)O)N (Wikidata: C[C@H](C(=O)O)N [1 stereocentre inverted])"
+        );
+    }
+}
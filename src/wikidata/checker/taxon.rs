@@ -0,0 +1,913 @@
+//! Taxon resolution: fetches every P225 match for a name and picks one of them, or none when
+//! the name stays ambiguous (homonyms across kingdoms, duplicate items). Synonym items are
+//! followed to their accepted taxon unless [`CheckOptions::keep_synonyms`] is set. A local
+//! backbone ([`CheckOptions::taxon_backbone`]) is consulted first and may answer directly or
+//! correct the name that is looked up. Names that are still not found get close names from
+//! the same genus as suggestions (accepted within [`CheckOptions::accept_fuzzy_taxa`] edits),
+//! and species that are not on Wikidata at all get their genus resolved, so they can be
+//! created under it.
+use super::{
+    BatchLookup, CheckOptions, RecordEntities, SPARQL_BATCH_SIZE, SparqlResponse, binding_qid,
+    binding_text, distinct, execute_sparql_post, sparql_literal,
+};
+use crate::cache::{self, CacheKind};
+use crate::enrichment::EnrichedData;
+use crate::error::Result;
+use crate::taxon::fuzzy::{damerau_levenshtein, fold_name};
+use crate::taxon::parser::parse_taxon_name;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Classes whose instances count as real taxa when narrowing down homonyms.
+const TAXON_CLASSES: [&str; 5] = [
+    "Q16521",    // taxon
+    "Q310890",   // monotypic taxon
+    "Q23038290", // fossil taxon
+    "Q98961713", // extinct taxon
+    "Q47487597", // monotypic fossil taxon
+];
+
+/// Names further than this from the input are not suggested.
+const SUGGESTION_MAX_DISTANCE: usize = 3;
+
+/// Number of close names listed in the status report.
+const MAX_SUGGESTIONS: usize = 3;
+
+/// P31 value of items that only carry a synonym name.
+const SYNONYM_CLASS: &str = "Q1040689";
+
+/// Accepted taxon a synonym item points to (P12763, or P1420 on the accepted item).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(super) struct AcceptedTaxon {
+    pub(super) qid: String,
+    pub(super) name: Option<String>,
+}
+
+/// One Wikidata item whose P225 equals the looked-up name.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub(super) struct TaxonCandidate {
+    pub(super) qid: String,
+    /// P105 values.
+    pub(super) ranks: Vec<String>,
+    /// P31 values.
+    pub(super) instance_of: Vec<String>,
+    /// Scientific names of the kingdom(s) reached through P171*.
+    pub(super) kingdoms: Vec<String>,
+    /// Input `higher_taxon` names found among the item's P171+ ancestors.
+    pub(super) higher_taxa: Vec<String>,
+    /// Accepted taxa when this item is a synonym.
+    pub(super) accepted: Vec<AcceptedTaxon>,
+}
+
+impl TaxonCandidate {
+    fn is_synonym(&self) -> bool {
+        !self.accepted.is_empty() || self.instance_of.iter().any(|c| c == SYNONYM_CLASS)
+    }
+
+    fn is_within(&self, higher_taxon: &str) -> bool {
+        self.kingdoms
+            .iter()
+            .chain(&self.higher_taxa)
+            .any(|name| name.eq_ignore_ascii_case(higher_taxon))
+    }
+
+    fn describe(&self) -> String {
+        if self.kingdoms.is_empty() {
+            self.qid.clone()
+        } else {
+            format!("{} ({})", self.qid, self.kingdoms.join("/"))
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub(super) enum TaxonMatch {
+    NotFound,
+    Unique(TaxonCandidate),
+    /// Several candidates remain; the row needs review.
+    Ambiguous(Vec<TaxonCandidate>),
+    /// No candidate sits under the given higher taxon; the row needs review.
+    HigherTaxonMismatch(Vec<TaxonCandidate>),
+}
+
+// `Plantae`, `plantae` and `PLANTAE` all name the same taxon; P225 values are capitalized.
+fn normalize_higher_taxon(name: &str) -> String {
+    let mut chars = name.trim().chars();
+    chars
+        .next()
+        .into_iter()
+        .flat_map(char::to_uppercase)
+        .chain(chars.flat_map(char::to_lowercase))
+        .collect()
+}
+
+fn taxon_candidates_query(names: &str, higher_taxa: &str) -> String {
+    let higher_taxon_block = if higher_taxa.is_empty() {
+        String::new()
+    } else {
+        format!(
+            "OPTIONAL {{ VALUES ?higherTaxon {{ {higher_taxa} }} ?item wdt:P171+/wdt:P225 ?higherTaxon . }}"
+        )
+    };
+    format!(
+        "SELECT ?key ?item ?rank ?type ?kingdomName ?higherTaxon ?accepted ?acceptedName WHERE {{
+            VALUES ?key {{ {names} }}
+            ?item wdt:P225 ?key .
+            OPTIONAL {{ ?item wdt:P105 ?rank . }}
+            OPTIONAL {{ ?item wdt:P31 ?type . }}
+            OPTIONAL {{ ?item wdt:P171* ?kingdom . ?kingdom wdt:P105 wd:Q36732 ; wdt:P225 ?kingdomName . }}
+            OPTIONAL {{
+                {{ ?item wdt:P12763 ?accepted . }} UNION {{ ?accepted wdt:P1420 ?item . }}
+                OPTIONAL {{ ?accepted wdt:P225 ?acceptedName . }}
+            }}
+            {higher_taxon_block}
+        }}"
+    )
+}
+
+fn push_unique(values: &mut Vec<String>, value: Option<String>) {
+    if let Some(value) = value
+        && !values.contains(&value)
+    {
+        values.push(value);
+    }
+}
+
+/// Groups the result rows by name, merging the rows of each item into one candidate.
+fn collect_taxon_candidates(response: &SparqlResponse) -> HashMap<String, Vec<TaxonCandidate>> {
+    let mut found: HashMap<String, Vec<TaxonCandidate>> = HashMap::new();
+    for binding in response.results.iter().flat_map(|r| r.bindings.iter()) {
+        let (Some(key), Some(qid)) = (binding_text(binding, "key"), binding_qid(binding, "item"))
+        else {
+            continue;
+        };
+        let candidates = found.entry(key.to_string()).or_default();
+        let index = match candidates.iter().position(|c| c.qid == qid) {
+            Some(index) => index,
+            None => {
+                candidates.push(TaxonCandidate {
+                    qid,
+                    ..Default::default()
+                });
+                candidates.len() - 1
+            }
+        };
+        let candidate = &mut candidates[index];
+        push_unique(&mut candidate.ranks, binding_qid(binding, "rank"));
+        push_unique(&mut candidate.instance_of, binding_qid(binding, "type"));
+        push_unique(
+            &mut candidate.kingdoms,
+            binding_text(binding, "kingdomName").map(String::from),
+        );
+        push_unique(
+            &mut candidate.higher_taxa,
+            binding_text(binding, "higherTaxon").map(String::from),
+        );
+        if let Some(qid) = binding_qid(binding, "accepted")
+            && !candidate.accepted.iter().any(|a| a.qid == qid)
+        {
+            candidate.accepted.push(AcceptedTaxon {
+                qid,
+                name: binding_text(binding, "acceptedName").map(String::from),
+            });
+        }
+    }
+    found
+}
+
+/// Distinct `higher_taxon` values of the records, normalized for the SPARQL `VALUES` block.
+pub(super) fn distinct_higher_taxa<'a>(
+    records: impl Iterator<Item = &'a EnrichedData>,
+) -> Vec<String> {
+    let mut higher_taxa: Vec<String> = Vec::new();
+    for record in records {
+        push_unique(
+            &mut higher_taxa,
+            record.higher_taxon.as_deref().map(normalize_higher_taxon),
+        );
+    }
+    higher_taxa
+}
+
+/// Cached P225 matches of a name, with the higher taxa their ancestors were checked against.
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedCandidates {
+    higher_taxa: Vec<String>,
+    candidates: Vec<TaxonCandidate>,
+}
+
+/// Fetches all P225 matches (with rank, P31 and kingdom) for each name.
+pub(super) async fn batch_lookup_taxa(
+    names: &[String],
+    higher_taxa: &[String],
+    client: &reqwest::Client,
+) -> BatchLookup<String, Vec<TaxonCandidate>> {
+    let mut lookup = BatchLookup::default();
+    let mut pending = Vec::new();
+    for name in names {
+        // An entry only answers the lookup if it covers every higher taxon asked about now.
+        match cache::lookup::<CachedCandidates>(CacheKind::TaxonName, name) {
+            Some(Some(cached))
+                if higher_taxa
+                    .iter()
+                    .all(|taxon| cached.higher_taxa.contains(taxon)) =>
+            {
+                lookup.found.insert(name.clone(), cached.candidates);
+            }
+            Some(None) => {}
+            _ => pending.push(name.clone()),
+        }
+    }
+    if pending.is_empty() {
+        return lookup;
+    }
+
+    let fetched = fetch_taxon_candidates(&pending, higher_taxa, client).await;
+    for name in &pending {
+        if let Some(candidates) = fetched.found.get(name) {
+            let cached = CachedCandidates {
+                higher_taxa: higher_taxa.to_vec(),
+                candidates: candidates.clone(),
+            };
+            cache::store(CacheKind::TaxonName, name, Some(&cached));
+        } else if !fetched.failed.contains_key(name) {
+            cache::store::<CachedCandidates>(CacheKind::TaxonName, name, None);
+        }
+    }
+    lookup.found.extend(fetched.found);
+    lookup.failed.extend(fetched.failed);
+    lookup
+}
+
+async fn fetch_taxon_candidates(
+    names: &[String],
+    higher_taxa: &[String],
+    client: &reqwest::Client,
+) -> BatchLookup<String, Vec<TaxonCandidate>> {
+    let higher_taxa = higher_taxa
+        .iter()
+        .map(|name| sparql_literal(name))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let mut lookup = BatchLookup::default();
+    for chunk in names.chunks(SPARQL_BATCH_SIZE) {
+        let values = chunk
+            .iter()
+            .map(|name| sparql_literal(name))
+            .collect::<Vec<_>>()
+            .join(" ");
+        match execute_sparql_post(&taxon_candidates_query(&values, &higher_taxa), client).await {
+            Ok(response) => lookup.found.extend(collect_taxon_candidates(&response)),
+            Err(err) => {
+                warn!(
+                    "Batched taxon lookup of {} names failed: {}",
+                    chunk.len(),
+                    err
+                );
+                let reason = err.to_string();
+                for name in chunk {
+                    lookup.failed.insert(name.clone(), reason.clone());
+                }
+            }
+        }
+    }
+    lookup
+}
+
+// Keeps only the candidates matching `keep`, unless that would leave none.
+fn narrow(pool: &mut Vec<&TaxonCandidate>, keep: impl Fn(&TaxonCandidate) -> bool) {
+    if pool.iter().any(|candidate| keep(candidate)) {
+        pool.retain(|candidate| keep(candidate));
+    }
+}
+
+/// Picks the candidate for `name`: the higher taxon (when given) must match, then candidates
+/// with the rank implied by the name, real taxon classes and accepted names are preferred.
+pub(super) fn choose_taxon(
+    name: &str,
+    higher_taxon: Option<&str>,
+    candidates: &[TaxonCandidate],
+) -> TaxonMatch {
+    if candidates.is_empty() {
+        return TaxonMatch::NotFound;
+    }
+    let mut pool: Vec<&TaxonCandidate> = candidates.iter().collect();
+    if let Some(higher_taxon) = higher_taxon {
+        pool.retain(|candidate| candidate.is_within(higher_taxon.trim()));
+        if pool.is_empty() {
+            return TaxonMatch::HigherTaxonMismatch(candidates.to_vec());
+        }
+    }
+    let expected_rank = parse_taxon_name(name).rank_qid();
+    narrow(&mut pool, |candidate| {
+        expected_rank.is_some_and(|rank| candidate.ranks.iter().any(|r| r == rank))
+    });
+    narrow(&mut pool, |candidate| {
+        candidate
+            .instance_of
+            .iter()
+            .any(|class| TAXON_CLASSES.contains(&class.as_str()))
+    });
+    narrow(&mut pool, |candidate| !candidate.is_synonym());
+    match pool.as_slice() {
+        [only] => TaxonMatch::Unique((*only).clone()),
+        _ => TaxonMatch::Ambiguous(pool.into_iter().cloned().collect()),
+    }
+}
+
+/// Name sent to the P225 lookup: the backbone's correction or accepted name, else the input.
+pub(super) fn taxon_lookup_name<'a>(
+    record: &'a EnrichedData,
+    entities: &'a RecordEntities,
+) -> &'a str {
+    entities
+        .taxon_lookup
+        .as_deref()
+        .unwrap_or(&record.taxon_name)
+}
+
+/// Resolves the record's name against the local backbone, if one is loaded. A listed QID is
+/// used as is; otherwise a fuzzy or accepted name replaces the input for the SPARQL lookup.
+pub(super) fn apply_backbone(
+    record: &EnrichedData,
+    options: &CheckOptions,
+    entities: &mut RecordEntities,
+) {
+    let Some(backbone) = &options.taxon_backbone else {
+        return;
+    };
+    if entities.taxon.is_some() {
+        return;
+    }
+    let Some(hit) = backbone.lookup(&record.taxon_name) else {
+        return;
+    };
+    if hit.distance > 0 {
+        entities.warnings.push(format!(
+            "Taxon '{}' is not in the backbone; using its closest name '{}' ({} edit(s) away).",
+            record.taxon_name, hit.name, hit.distance
+        ));
+    }
+    let (name, qid) = match hit.accepted_name {
+        Some(accepted) if !options.keep_synonyms => (accepted, hit.accepted_qid),
+        _ => (hit.name, hit.wikidata_qid),
+    };
+    if name != record.taxon_name {
+        if qid.is_some() {
+            entities.resolved_taxon_name = Some(name);
+        } else {
+            entities.taxon_lookup = Some(name);
+        }
+    }
+    entities.taxon = qid;
+}
+
+/// Applies [`choose_taxon`] to a record; ambiguous names leave the taxon unset and are listed
+/// as candidates plus a warning for the status report.
+pub(super) fn resolve_taxon(
+    record: &EnrichedData,
+    candidates: &[TaxonCandidate],
+    options: &CheckOptions,
+    entities: &mut RecordEntities,
+) {
+    let name = taxon_lookup_name(record, entities).to_string();
+    let higher_taxon = record.higher_taxon.as_deref();
+    let described = |candidates: &[TaxonCandidate]| {
+        candidates
+            .iter()
+            .map(TaxonCandidate::describe)
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    match choose_taxon(&name, higher_taxon, candidates) {
+        TaxonMatch::NotFound => {}
+        TaxonMatch::Unique(candidate) if candidate.is_synonym() && !options.keep_synonyms => {
+            follow_synonym(&name, candidate, entities)
+        }
+        TaxonMatch::Unique(candidate) => {
+            entities.taxon = Some(candidate.qid);
+            if name != record.taxon_name {
+                entities.resolved_taxon_name = Some(name);
+            }
+        }
+        TaxonMatch::Ambiguous(remaining) => {
+            entities.warnings.push(format!(
+                "Taxon name '{}' matches {} Wikidata items ({}); set higher_taxon (e.g. the kingdom) or taxon_qid to pick one.",
+                name,
+                remaining.len(),
+                described(&remaining)
+            ));
+            entities.taxon_candidates = remaining.into_iter().map(|c| c.qid).collect();
+        }
+        TaxonMatch::HigherTaxonMismatch(all) => {
+            entities.warnings.push(format!(
+                "No Wikidata item named '{}' ({}) belongs to '{}'; check higher_taxon or set taxon_qid.",
+                name,
+                described(&all),
+                higher_taxon.unwrap_or_default()
+            ));
+            entities.taxon_candidates = all.into_iter().map(|c| c.qid).collect();
+        }
+    }
+}
+
+/// Genus to look up for a record whose taxon was not found: only plain species names qualify
+/// for creation, so other names get no parent.
+fn missing_species_genus(record: &EnrichedData, entities: &RecordEntities) -> Option<String> {
+    if entities.taxon.is_some() || !entities.taxon_candidates.is_empty() {
+        return None;
+    }
+    let parsed = parse_taxon_name(&record.taxon_name);
+    parsed.is_plain_species().then_some(parsed.genus)
+}
+
+/// Records the genus QID a missing species would be created under. Ambiguous genera and
+/// synonym genera are left unset, which refuses the creation.
+fn resolve_parent_taxon(
+    record: &EnrichedData,
+    genus: &str,
+    candidates: &[TaxonCandidate],
+    entities: &mut RecordEntities,
+) {
+    if let TaxonMatch::Unique(candidate) =
+        choose_taxon(genus, record.higher_taxon.as_deref(), candidates)
+        && !candidate.is_synonym()
+    {
+        entities.parent_taxon = Some(candidate.qid);
+    }
+}
+
+/// Resolves, in one batched round, the genus of every species missing from Wikidata.
+pub(super) async fn resolve_parent_taxa(
+    records: &[EnrichedData],
+    resolved: &mut [Result<RecordEntities>],
+    client: &reqwest::Client,
+) {
+    let genera: Vec<Option<String>> = records
+        .iter()
+        .zip(resolved.iter())
+        .map(|(record, entities)| {
+            entities
+                .as_ref()
+                .ok()
+                .and_then(|entities| missing_species_genus(record, entities))
+        })
+        .collect();
+    let genus_names = distinct(genera.iter().flatten().map(String::as_str));
+    if genus_names.is_empty() {
+        return;
+    }
+    let higher_taxa = distinct_higher_taxa(
+        records
+            .iter()
+            .zip(&genera)
+            .filter(|(_, genus)| genus.is_some())
+            .map(|(record, _)| record),
+    );
+    let genus_items = batch_lookup_taxa(&genus_names, &higher_taxa, client).await;
+    for ((record, genus), entities) in records.iter().zip(&genera).zip(resolved) {
+        if let (Some(genus), Ok(entities)) = (genus, entities.as_mut()) {
+            match genus_items.resolve(genus) {
+                Ok(candidates) => {
+                    resolve_parent_taxon(record, genus, &candidates.unwrap_or_default(), entities)
+                }
+                Err(err) => entities
+                    .warnings
+                    .push(format!("Genus lookup for '{}' failed: {}", genus, err)),
+            }
+        }
+    }
+}
+
+// Children and grandchildren (e.g. below a subgenus) of the genera named in `?key`.
+fn genus_members_query(genera: &str) -> String {
+    format!(
+        "SELECT DISTINCT ?key ?name WHERE {{
+            VALUES ?key {{ {genera} }}
+            ?genus wdt:P225 ?key .
+            ?item wdt:P171/wdt:P171? ?genus ;
+                  wdt:P225 ?name .
+        }}"
+    )
+}
+
+/// Fetches the P225 names of the taxa below each genus.
+async fn batch_lookup_genus_members(
+    genera: &[String],
+    client: &reqwest::Client,
+) -> BatchLookup<String, Vec<String>> {
+    let mut lookup: BatchLookup<String, Vec<String>> = BatchLookup::default();
+    for chunk in genera.chunks(SPARQL_BATCH_SIZE) {
+        let values = chunk
+            .iter()
+            .map(|genus| sparql_literal(genus))
+            .collect::<Vec<_>>()
+            .join(" ");
+        match execute_sparql_post(&genus_members_query(&values), client).await {
+            Ok(response) => {
+                for binding in response.results.iter().flat_map(|r| r.bindings.iter()) {
+                    if let (Some(key), Some(name)) =
+                        (binding_text(binding, "key"), binding_text(binding, "name"))
+                    {
+                        push_unique(
+                            lookup.found.entry(key.to_string()).or_default(),
+                            Some(name.to_string()),
+                        );
+                    }
+                }
+            }
+            Err(err) => {
+                warn!(
+                    "Batched genus member lookup of {} genera failed: {}",
+                    chunk.len(),
+                    err
+                );
+                let reason = err.to_string();
+                for genus in chunk {
+                    lookup.failed.insert(genus.clone(), reason.clone());
+                }
+            }
+        }
+    }
+    lookup
+}
+
+/// Names within [`SUGGESTION_MAX_DISTANCE`] edits of `name`, closest first. Case and
+/// diacritics are ignored, so names differing only in those have distance 0.
+fn rank_suggestions(name: &str, members: &[String]) -> Vec<(String, usize)> {
+    let folded = fold_name(name);
+    let mut ranked: Vec<(String, usize)> = members
+        .iter()
+        .filter(|member| member.as_str() != name)
+        .map(|member| {
+            (
+                member.clone(),
+                damerau_levenshtein(&folded, &fold_name(member)),
+            )
+        })
+        .filter(|(_, distance)| *distance <= SUGGESTION_MAX_DISTANCE)
+        .collect();
+    ranked.sort_by(|a, b| a.1.cmp(&b.1).then_with(|| a.0.cmp(&b.0)));
+    ranked
+}
+
+/// The closest suggestion, when it is within `max_distance` and no other name is as close.
+fn accepted_suggestion(ranked: &[(String, usize)], max_distance: usize) -> Option<&str> {
+    match ranked {
+        [(best, distance), rest @ ..]
+            if *distance <= max_distance && rest.first().is_none_or(|(_, d)| d > distance) =>
+        {
+            Some(best)
+        }
+        _ => None,
+    }
+}
+
+// Genus to search for close names: the name was not found and has at least an epithet.
+fn unmatched_genus(record: &EnrichedData, entities: &RecordEntities) -> Option<String> {
+    if entities.taxon.is_some() || !entities.taxon_candidates.is_empty() {
+        return None;
+    }
+    let parsed = parse_taxon_name(taxon_lookup_name(record, entities));
+    parsed.specific_epithet.is_some().then_some(parsed.genus)
+}
+
+/// Looks for close names in the genus of every name that was not found. The closest ones are
+/// listed as a warning; with [`CheckOptions::accept_fuzzy_taxa`] a unique close match is
+/// looked up and resolved in place of the input name.
+pub(super) async fn suggest_close_taxa(
+    records: &[EnrichedData],
+    resolved: &mut [Result<RecordEntities>],
+    options: &CheckOptions,
+    client: &reqwest::Client,
+) {
+    let genera: Vec<Option<String>> = records
+        .iter()
+        .zip(resolved.iter())
+        .map(|(record, entities)| {
+            entities
+                .as_ref()
+                .ok()
+                .and_then(|entities| unmatched_genus(record, entities))
+        })
+        .collect();
+    let genus_names = distinct(genera.iter().flatten().map(String::as_str));
+    if genus_names.is_empty() {
+        return;
+    }
+    let members = batch_lookup_genus_members(&genus_names, client).await;
+
+    let mut accepted = vec![false; records.len()];
+    for (index, ((record, genus), entities)) in records
+        .iter()
+        .zip(&genera)
+        .zip(resolved.iter_mut())
+        .enumerate()
+    {
+        let (Some(genus), Ok(entities)) = (genus, entities.as_mut()) else {
+            continue;
+        };
+        let members = match members.resolve(genus) {
+            Ok(members) => members.unwrap_or_default(),
+            Err(err) => {
+                entities.warnings.push(format!(
+                    "Looking for names close to '{}' failed: {}",
+                    record.taxon_name, err
+                ));
+                continue;
+            }
+        };
+        let name = taxon_lookup_name(record, entities).to_string();
+        let ranked = rank_suggestions(&name, &members);
+        if let Some(max_distance) = options.accept_fuzzy_taxa
+            && let Some(best) = accepted_suggestion(&ranked, max_distance)
+        {
+            entities.warnings.push(format!(
+                "Taxon '{}' is not on Wikidata; using the close match '{}' ({} edit(s) away).",
+                name, best, ranked[0].1
+            ));
+            entities.taxon_lookup = Some(best.to_string());
+            accepted[index] = true;
+        } else if !ranked.is_empty() {
+            let closest = &ranked[..ranked.len().min(MAX_SUGGESTIONS)];
+            entities.warnings.push(format!(
+                "Taxon '{}' is not on Wikidata; closest names: {}.",
+                name,
+                closest
+                    .iter()
+                    .map(|(member, distance)| format!("{} ({} edit(s))", member, distance))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+            entities.taxon_suggestions = closest.iter().map(|(member, _)| member.clone()).collect();
+        }
+    }
+
+    let names = distinct(
+        records
+            .iter()
+            .zip(resolved.iter())
+            .zip(&accepted)
+            .filter(|(_, accepted)| **accepted)
+            .filter_map(|((record, entities), _)| {
+                entities
+                    .as_ref()
+                    .ok()
+                    .map(|entities| taxon_lookup_name(record, entities))
+            }),
+    );
+    if names.is_empty() {
+        return;
+    }
+    let higher_taxa = distinct_higher_taxa(
+        records
+            .iter()
+            .zip(&accepted)
+            .filter(|(_, accepted)| **accepted)
+            .map(|(record, _)| record),
+    );
+    let taxon_items = batch_lookup_taxa(&names, &higher_taxa, client).await;
+    for ((record, entities), accepted) in records.iter().zip(resolved).zip(accepted) {
+        if let (true, Ok(entities)) = (accepted, entities.as_mut()) {
+            match taxon_items.resolve(&taxon_lookup_name(record, entities).to_string()) {
+                Ok(candidates) => {
+                    resolve_taxon(record, &candidates.unwrap_or_default(), options, entities)
+                }
+                Err(err) => entities.warnings.push(format!(
+                    "Taxon lookup for the close match of '{}' failed: {}",
+                    record.taxon_name, err
+                )),
+            }
+        }
+    }
+}
+
+fn follow_synonym(name: &str, synonym: TaxonCandidate, entities: &mut RecordEntities) {
+    match synonym.accepted.as_slice() {
+        [accepted] => {
+            entities.taxon = Some(accepted.qid.clone());
+            entities.resolved_taxon_name = accepted.name.clone();
+        }
+        [] => {
+            entities.warnings.push(format!(
+                "Taxon '{}' ({}) is marked as a synonym but links no accepted taxon; using it as is.",
+                name, synonym.qid
+            ));
+            entities.taxon = Some(synonym.qid);
+        }
+        several => {
+            entities.warnings.push(format!(
+                "Taxon '{}' ({}) is a synonym of several accepted taxa ({}); set taxon_qid to pick one.",
+                name,
+                synonym.qid,
+                several
+                    .iter()
+                    .map(|a| a.qid.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+            entities.taxon_candidates = several.iter().map(|a| a.qid.clone()).collect();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(qid: &str, rank: &str, kingdom: &str) -> TaxonCandidate {
+        TaxonCandidate {
+            qid: qid.to_string(),
+            ranks: vec![rank.to_string()],
+            instance_of: vec!["Q16521".to_string()],
+            kingdoms: vec![kingdom.to_string()],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_collect_taxon_candidates_merges_rows_per_item() {
+        let response: SparqlResponse = serde_json::from_str(
+            r#"{
+                "results": { "bindings": [
+                    { "key": { "type": "literal", "value": "Morus" },
+                      "item": { "type": "uri", "value": "http://www.wikidata.org/entity/Q1" },
+                      "rank": { "type": "uri", "value": "http://www.wikidata.org/entity/Q34740" },
+                      "kingdomName": { "type": "literal", "value": "Plantae" } },
+                    { "key": { "type": "literal", "value": "Morus" },
+                      "item": { "type": "uri", "value": "http://www.wikidata.org/entity/Q1" },
+                      "type": { "type": "uri", "value": "http://www.wikidata.org/entity/Q16521" },
+                      "kingdomName": { "type": "literal", "value": "Plantae" } },
+                    { "key": { "type": "literal", "value": "Morus" },
+                      "item": { "type": "uri", "value": "http://www.wikidata.org/entity/Q2" },
+                      "kingdomName": { "type": "literal", "value": "Animalia" },
+                      "higherTaxon": { "type": "literal", "value": "Aves" } }
+                ] }
+            }"#,
+        )
+        .unwrap();
+        let found = collect_taxon_candidates(&response);
+        let morus = &found["Morus"];
+        assert_eq!(morus.len(), 2);
+        assert_eq!(morus[0].ranks, vec!["Q34740"]);
+        assert_eq!(morus[0].instance_of, vec!["Q16521"]);
+        assert_eq!(morus[0].kingdoms, vec!["Plantae"]);
+        assert_eq!(morus[1].higher_taxa, vec!["Aves"]);
+    }
+
+    #[test]
+    fn test_homonyms_need_a_higher_taxon() {
+        let homonyms = vec![
+            candidate("Q1", "Q34740", "Plantae"),
+            candidate("Q2", "Q34740", "Animalia"),
+        ];
+        assert!(matches!(
+            choose_taxon("Morus", None, &homonyms),
+            TaxonMatch::Ambiguous(c) if c.len() == 2
+        ));
+        assert_eq!(
+            choose_taxon("Morus", Some("plantae"), &homonyms),
+            TaxonMatch::Unique(homonyms[0].clone())
+        );
+        assert!(matches!(
+            choose_taxon("Morus", Some("Fungi"), &homonyms),
+            TaxonMatch::HigherTaxonMismatch(_)
+        ));
+        assert_eq!(choose_taxon("Morus", None, &[]), TaxonMatch::NotFound);
+    }
+
+    #[test]
+    fn test_rank_and_class_break_ties() {
+        let candidates = vec![
+            candidate("Q1", "Q34740", "Plantae"),
+            candidate("Q2", "Q7432", "Plantae"),
+        ];
+        assert_eq!(
+            choose_taxon("Aus bus", None, &candidates),
+            TaxonMatch::Unique(candidates[1].clone())
+        );
+
+        let mut not_a_taxon = candidate("Q3", "Q7432", "Plantae");
+        not_a_taxon.instance_of = vec!["Q1040689".to_string()];
+        let candidates = vec![candidate("Q2", "Q7432", "Plantae"), not_a_taxon];
+        assert_eq!(
+            choose_taxon("Aus bus", None, &candidates),
+            TaxonMatch::Unique(candidates[0].clone())
+        );
+        assert_eq!(normalize_higher_taxon(" PLANTAE "), "Plantae");
+    }
+
+    #[test]
+    fn test_synonyms_follow_the_accepted_taxon() {
+        let record = EnrichedData {
+            taxon_name: "Aus bus".to_string(),
+            ..Default::default()
+        };
+        let mut synonym = candidate("Q10", "Q7432", "Plantae");
+        synonym.instance_of = vec![SYNONYM_CLASS.to_string()];
+        synonym.accepted = vec![AcceptedTaxon {
+            qid: "Q20".to_string(),
+            name: Some("Cus bus".to_string()),
+        }];
+
+        let mut entities = RecordEntities::default();
+        resolve_taxon(
+            &record,
+            std::slice::from_ref(&synonym),
+            &CheckOptions::default(),
+            &mut entities,
+        );
+        assert_eq!(entities.taxon.as_deref(), Some("Q20"));
+        assert_eq!(entities.resolved_taxon_name.as_deref(), Some("Cus bus"));
+        assert!(entities.warnings.is_empty());
+
+        let mut entities = RecordEntities::default();
+        let keep = CheckOptions {
+            keep_synonyms: true,
+            ..Default::default()
+        };
+        resolve_taxon(
+            &record,
+            std::slice::from_ref(&synonym),
+            &keep,
+            &mut entities,
+        );
+        assert_eq!(entities.taxon.as_deref(), Some("Q10"));
+
+        synonym.accepted.push(AcceptedTaxon {
+            qid: "Q30".to_string(),
+            name: None,
+        });
+        let mut entities = RecordEntities::default();
+        resolve_taxon(
+            &record,
+            &[synonym, candidate("Q40", "Q34740", "Plantae")],
+            &CheckOptions::default(),
+            &mut entities,
+        );
+        assert_eq!(entities.taxon, None);
+        assert_eq!(entities.taxon_candidates, vec!["Q20", "Q30"]);
+    }
+
+    #[test]
+    fn test_backbone_answers_before_sparql() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("checklist.tsv");
+        std::fs::write(
+            &path,
+            "canonicalName\tacceptedNameUsage\twikidata\nAus bus\t\tQ1\nAus cus\tAus bus\t\nDus eus\t\t\n",
+        )
+        .unwrap();
+        let options = CheckOptions {
+            taxon_backbone: Some(std::sync::Arc::new(
+                crate::taxon::backbone::TaxonBackbone::load(&path).unwrap(),
+            )),
+            ..Default::default()
+        };
+        let record = |name: &str| EnrichedData {
+            taxon_name: name.to_string(),
+            ..Default::default()
+        };
+
+        let mut entities = RecordEntities::default();
+        apply_backbone(&record("Aus cus"), &options, &mut entities);
+        assert_eq!(entities.taxon.as_deref(), Some("Q1"));
+        assert_eq!(entities.resolved_taxon_name.as_deref(), Some("Aus bus"));
+
+        let mut entities = RecordEntities::default();
+        let typo = record("Dus eis");
+        apply_backbone(&typo, &options, &mut entities);
+        assert_eq!(entities.taxon, None);
+        assert_eq!(taxon_lookup_name(&typo, &entities), "Dus eus");
+        assert_eq!(entities.warnings.len(), 1);
+
+        resolve_taxon(
+            &typo,
+            &[candidate("Q9", "Q7432", "Plantae")],
+            &options,
+            &mut entities,
+        );
+        assert_eq!(entities.taxon.as_deref(), Some("Q9"));
+        assert_eq!(entities.resolved_taxon_name.as_deref(), Some("Dus eus"));
+    }
+
+    #[test]
+    fn test_close_names_are_ranked_and_accepted_when_unique() {
+        let members: Vec<String> = ["Coffea arabica", "Coffea racemosa", "Coffea arabicoides"]
+            .iter()
+            .map(|name| name.to_string())
+            .collect();
+        let ranked = rank_suggestions("Coffea arabia", &members);
+        assert_eq!(ranked, vec![("Coffea arabica".to_string(), 1),]);
+        assert_eq!(accepted_suggestion(&ranked, 1), Some("Coffea arabica"));
+        assert_eq!(accepted_suggestion(&ranked, 0), None);
+
+        // Case and diacritics do not count as edits.
+        let ranked = rank_suggestions("Coffea Arabicä", &members);
+        assert_eq!(ranked[0], ("Coffea arabica".to_string(), 0));
+
+        let tied = vec![("Aus bua".to_string(), 1), ("Aus bub".to_string(), 1)];
+        assert_eq!(accepted_suggestion(&tied, 2), None);
+    }
+}
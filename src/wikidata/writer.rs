@@ -1,42 +1,71 @@
-//! QuickStatements (QS) generation helpers.
+//! QuickStatements (QS) generation and direct Wikibase edit helpers.
+use crate::chemical_entity::stereo::StereoClass;
 use crate::enrichment::EnrichedData;
 use crate::error::{CrateError, Result};
-use crate::reference::{CROSSREF_QID, ReferenceMetadata, format_retrieved_date};
+use crate::reference::{CROSSREF_QID, ReferenceDate, ReferenceMetadata, format_retrieved_date};
+use crate::wikidata::api::{self, WikibaseSession};
 use crate::wikidata::checker::WikidataInfo;
-use log::warn;
-use std::collections::HashSet;
+use log::{info, warn};
+use serde_json::{Value, json};
+use std::collections::{HashMap, HashSet};
 use std::io::Write;
 
 const HEURISTIC_QID: &str = "Q113907573";
 const HEURISTIC_REFERENCE_PROPERTY: &str = "S887";
-const DALTON_QID: &str = "Q483261";
+const HEURISTIC_REFERENCE_PROPERTY_ID: &str = "P887";
+pub(crate) const DALTON_QID: &str = "Q483261";
+const SPECIES_QID: &str = "Q7432";
+const CHEMICAL_COMPOUND_QID: &str = "Q11173";
+const STEREOISOMER_GROUP_QID: &str = "Q59199015";
+const CHEMICAL_ENTITY_TYPE_QID: &str = "Q113145171";
+
+/// P31 class and English description of a new chemical item: fully defined structures are
+/// chemical compounds, structures with open stereo elements groups of stereoisomers; without a
+/// classification the generic "type of chemical entity" is kept.
+fn chemical_class(data: &EnrichedData) -> (&'static str, &'static str) {
+    match data.stereo {
+        Some(StereoClass::Defined) => (CHEMICAL_COMPOUND_QID, "chemical compound"),
+        Some(StereoClass::PartiallyDefined | StereoClass::Undefined) => {
+            (STEREOISOMER_GROUP_QID, "group of stereoisomers")
+        }
+        None => (CHEMICAL_ENTITY_TYPE_QID, "type of chemical entity"),
+    }
+}
 
 /// Generates QuickStatements commands for the provided records.
 pub fn generate_quickstatements(
     records: &[(EnrichedData, WikidataInfo)],
     chemical_creation_plan: &[bool],
     reference_creation_plan: &[bool],
+    taxon_creation_plan: &[bool],
     emit_occurrences: bool,
     writer: &mut dyn Write,
 ) -> Result<()> {
     let mut temp_qid_counter = 0;
     let mut emitted_references: HashSet<String> = HashSet::new();
+    let mut completed_chemicals: HashSet<String> = HashSet::new();
 
     for (idx, (data, info)) in records.iter().enumerate() {
         let should_create_chemical = *chemical_creation_plan.get(idx).unwrap_or(&false);
         let should_create_reference = *reference_creation_plan.get(idx).unwrap_or(&false);
+        let should_create_taxon = *taxon_creation_plan.get(idx).unwrap_or(&false);
         let mut commands = Vec::new();
         let mut current_chemical_qid = info.chemical_qid.clone();
 
-        if should_create_reference {
-            if let Some(metadata) = &info.reference_metadata {
-                let key = metadata.doi.to_lowercase();
-                if emitted_references.insert(key) {
-                    commands.extend(build_reference_commands(metadata));
-                }
+        if should_create_reference && let Some(metadata) = &info.reference_metadata {
+            let key = metadata.doi.to_lowercase();
+            if emitted_references.insert(key) {
+                commands.extend(build_reference_commands(metadata));
             }
         }
 
+        if should_create_taxon
+            && info.taxon_qid.is_none()
+            && let Some(genus_qid) = &info.parent_taxon_qid
+        {
+            commands.extend(build_taxon_commands(&data.taxon_name, genus_qid));
+        }
+
         // 1. Create Chemical Item if it doesn't exist
         if info.chemical_qid.is_none() && should_create_chemical {
             temp_qid_counter += 1;
@@ -45,42 +74,21 @@ pub fn generate_quickstatements(
             current_chemical_qid = Some(temp_qid.clone()); // Use temporary ID for subsequent commands
 
             // Add Label (L), Description (D), Alias (A)
+            let (class_qid, description) = chemical_class(data);
             commands.push(format!("LAST\tLen\t\"{}\"", data.chemical_entity_name));
-            // Description based on knowledge module preference
-            commands.push("LAST\tDen\t\"type of chemical entity\"".to_string());
+            // Description follows the stereo classification
+            commands.push(format!("LAST\tDen\t\"{}\"", description));
             // Potentially add aliases if needed
 
-            // Add P31 (instance of) -> Q113145171 (type of chemical entity)
-            commands.push("LAST\tP31\tQ113145171".to_string());
+            // Add P31 (instance of) -> compound, stereoisomer group or type of chemical entity
+            commands.push(format!("LAST\tP31\t{}", class_qid));
 
             // Add Chemical Properties
-            if let Some(smiles) = &data.isomeric_smiles {
-                commands.push(format!("LAST\tP2017\t\"{}\"", smiles));
-            }
-            if let Some(canonical) = &data.canonical_smiles {
-                commands.push(format!("LAST\tP233\t\"{}\"", canonical));
-            }
-            if let Some(inchi) = &data.inchi {
-                commands.push(format!("LAST\tP234\t\"{}\"", inchi));
-            }
-            if let Some(inchikey) = &data.inchikey {
-                commands.push(format!("LAST\tP235\t\"{}\"", inchikey));
-            }
-            if let Some(formula) = &data.molecular_formula {
-                let formatted = format_molecular_formula(formula);
-                commands.push(format!(
-                    "LAST\tP274\t\"{}\"\t{}\t{}",
-                    formatted, HEURISTIC_REFERENCE_PROPERTY, HEURISTIC_QID
-                ));
-            }
-            if let Some(mass) = data.exact_mass {
-                let mass_value = format_mass_quantity(mass);
-                let unit_id = DALTON_QID.trim_start_matches('Q');
-                commands.push(format!(
-                    "LAST\tP2067\t{}U{}\t{}\t{}",
-                    mass_value, unit_id, HEURISTIC_REFERENCE_PROPERTY, HEURISTIC_QID
-                ));
-            }
+            commands.extend(
+                chemical_statement_commands("LAST", data)
+                    .into_iter()
+                    .map(|(_, command)| command),
+            );
 
             if info.taxon_qid.is_some() && info.reference_qid.is_some() {
                 warn!(
@@ -102,6 +110,19 @@ pub fn generate_quickstatements(
             );
         }
 
+        // 1b. Add the properties an existing item lacks; conflicting values are only reported
+        if let (Some(chem_qid), Some(statements)) = (&info.chemical_qid, &info.chemical_statements)
+            && completed_chemicals.insert(chem_qid.clone())
+        {
+            let missing = statements.compare(data).missing;
+            commands.extend(
+                chemical_statement_commands(chem_qid, data)
+                    .into_iter()
+                    .filter(|(property, _)| missing.contains(property))
+                    .map(|(_, command)| command),
+            );
+        }
+
         // 2. Add Occurrence Statement if it doesn't exist and all QIDs are present
         if emit_occurrences && !info.occurrence_exists && info.chemical_qid.is_some() {
             match (&current_chemical_qid, &info.taxon_qid, &info.reference_qid) {
@@ -132,35 +153,548 @@ pub fn generate_quickstatements(
 
         // Write commands for this record to the writer
         for command in commands {
-            writeln!(writer, "{}", command).map_err(|e| CrateError::IoError(e))?;
+            writeln!(writer, "{}", command).map_err(CrateError::IoError)?;
         }
     }
 
     Ok(())
 }
 
-// --- Direct Wikidata Edit (Placeholder/Future Implementation) ---
-// This section would require handling authentication (OAuth or bot credentials)
-// and using a Wikidata edit API client (e.g., `wikidata` crate or custom `reqwest` calls).
-
-// pub async fn push_to_wikidata(
-//     records: &[(EnrichedData, WikidataInfo)],
-//     // auth_token: &str, // Or other auth mechanism
-//     client: &reqwest::Client,
-// ) -> Result<()> {
-//     // ... implementation for direct edits ...
-//     // - Create items if needed
-//     // - Add statements (P31, chemical props, P703)
-//     // - Handle edit conflicts, rate limits, etc.
-//     Err(CrateError::WikidataWriteError("Direct push not yet implemented".to_string()))
-// }
+/// QS statements for the chemical properties of `data` on `subject` (`LAST` or a QID), each
+/// with its property so that existing items only receive the missing ones.
+fn chemical_statement_commands(subject: &str, data: &EnrichedData) -> Vec<(&'static str, String)> {
+    let mut commands = Vec::new();
+    if let Some(smiles) = &data.isomeric_smiles {
+        commands.push(("P2017", format!("{}\tP2017\t\"{}\"", subject, smiles)));
+    }
+    if let Some(canonical) = &data.canonical_smiles {
+        commands.push(("P233", format!("{}\tP233\t\"{}\"", subject, canonical)));
+    }
+    if let Some(inchi) = &data.inchi {
+        commands.push(("P234", format!("{}\tP234\t\"{}\"", subject, inchi)));
+    }
+    if let Some(inchikey) = &data.inchikey {
+        commands.push(("P235", format!("{}\tP235\t\"{}\"", subject, inchikey)));
+    }
+    if let Some(formula) = &data.molecular_formula {
+        let formatted = format_molecular_formula(formula);
+        commands.push((
+            "P274",
+            format!(
+                "{}\tP274\t\"{}\"\t{}\t{}",
+                subject, formatted, HEURISTIC_REFERENCE_PROPERTY, HEURISTIC_QID
+            ),
+        ));
+    }
+    if let Some(mass) = data.exact_mass {
+        let mass_value = format_mass_quantity(mass);
+        let unit_id = DALTON_QID.trim_start_matches('Q');
+        commands.push((
+            "P2067",
+            format!(
+                "{}\tP2067\t{}U{}\t{}\t{}",
+                subject, mass_value, unit_id, HEURISTIC_REFERENCE_PROPERTY, HEURISTIC_QID
+            ),
+        ));
+    }
+    for identifier in &data.identifiers {
+        let (Some(property), Some(source_qid)) = (
+            identifier.source.property(),
+            identifier.source.stated_in_qid(),
+        ) else {
+            continue;
+        };
+        commands.push((
+            property,
+            format!(
+                "{}\t{}\t\"{}\"\tS248\t{}\tS813\t{}",
+                subject,
+                property,
+                identifier.id,
+                source_qid,
+                format_retrieved_date(identifier.retrieved_on)
+            ),
+        ));
+    }
+    commands
+}
+
+/// Creates QS commands for a species item placed under its genus.
+fn build_taxon_commands(name: &str, genus_qid: &str) -> Vec<String> {
+    let escaped_name = escape_literal(name);
+    vec![
+        "CREATE".to_string(),
+        format!("LAST\tLen\t\"{}\"", escaped_name),
+        "LAST\tP31\tQ16521".to_string(),
+        format!("LAST\tP225\t\"{}\"", escaped_name),
+        format!("LAST\tP105\t{}", SPECIES_QID),
+        format!("LAST\tP171\t{}", genus_qid),
+    ]
+}
+
+/// Creates QS commands to build a reference item from Crossref metadata.
+fn build_reference_commands(metadata: &ReferenceMetadata) -> Vec<String> {
+    let mut commands = Vec::new();
+    let retrieved_date = format_retrieved_date(metadata.retrieved_on);
+    let escaped_title = escape_literal(&metadata.title);
+    commands.push("CREATE".to_string());
+    commands.push(format!("LAST\tLmul\t\"{}\"", escaped_title));
+    commands.push("LAST\tDen\t\"scholarly reference\"".to_string());
+    commands.push(format!("LAST\tP31\t{}", metadata.entity_type_qid));
+
+    commands.push(format!(
+        "LAST\tP356\t\"{}\"\tS248\t{}\tS813\t{}",
+        escape_literal(&metadata.doi),
+        CROSSREF_QID,
+        retrieved_date
+    ));
+
+    let monolingual_lang = metadata.title_language.as_deref().unwrap_or("mul");
+    commands.push(format!(
+        "LAST\tP1476\t{lang}:\"{title}\"\tS248\t{source}\tS813\t{retrieved}",
+        lang = monolingual_lang,
+        title = escaped_title,
+        source = CROSSREF_QID,
+        retrieved = retrieved_date
+    ));
+
+    if let Some(language_qid) = &metadata.language_qid {
+        commands.push(format!(
+            "LAST\tP407\t{}\tS248\t{}\tS813\t{}",
+            language_qid, CROSSREF_QID, retrieved_date
+        ));
+    }
+
+    if let Some(date) = &metadata.publication_date {
+        commands.push(format!(
+            "LAST\tP577\t{}\tS248\t{}\tS813\t{}",
+            date.to_quickstatements_time(),
+            CROSSREF_QID,
+            retrieved_date
+        ));
+    }
+
+    if let Some(journal_qid) = &metadata.journal_qid {
+        commands.push(format!(
+            "LAST\tP1433\t{}\tS248\t{}\tS813\t{}",
+            journal_qid, CROSSREF_QID, retrieved_date
+        ));
+    }
+
+    if let Some(volume) = &metadata.volume {
+        commands.push(format!(
+            "LAST\tP478\t\"{}\"\tS248\t{}\tS813\t{}",
+            escape_literal(volume),
+            CROSSREF_QID,
+            retrieved_date
+        ));
+    }
+
+    if let Some(issue) = &metadata.issue {
+        commands.push(format!(
+            "LAST\tP433\t\"{}\"\tS248\t{}\tS813\t{}",
+            escape_literal(issue),
+            CROSSREF_QID,
+            retrieved_date
+        ));
+    }
+
+    for author in &metadata.authors {
+        commands.push(format!(
+            "LAST\tP2093\t\"{}\"\tP1545\t\"{}\"\tS248\t{}\tS813\t{}",
+            escape_literal(&author.full_name),
+            author.ordinal,
+            CROSSREF_QID,
+            retrieved_date
+        ));
+    }
+
+    commands
+}
+
+fn escape_literal(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('\"', "\\\"")
+        .replace('\n', " ")
+        .trim()
+        .to_string()
+}
+
+fn format_molecular_formula(formula: &str) -> String {
+    formula
+        .chars()
+        .map(|ch| match ch {
+            '0' => '₀',
+            '1' => '₁',
+            '2' => '₂',
+            '3' => '₃',
+            '4' => '₄',
+            '5' => '₅',
+            '6' => '₆',
+            '7' => '₇',
+            '8' => '₈',
+            '9' => '₉',
+            _ => ch,
+        })
+        .collect()
+}
+
+fn format_mass_quantity(value: f64) -> String {
+    let mut s = format!("{value:.9}");
+    if let Some(dot_pos) = s.find('.') {
+        while s.ends_with('0') {
+            s.pop();
+        }
+        if s.ends_with('.') {
+            s.push('0');
+        } else if s.len() == dot_pos {
+            s.push_str(".0");
+        }
+    } else {
+        s.push_str(".0");
+    }
+    s
+}
+
+// --- Direct Wikidata Edit ---
+// Mirrors the QuickStatements output above as Wikibase entity JSON. Because `wbeditentity`
+// returns the new QID immediately, occurrences can be written in the same run.
+
+const DIRECT_EDIT_SUMMARY: &str = "lotus-o3 natural product occurrence import";
+
+/// Counts and per-item failures from a direct push run.
+#[derive(Debug, Default)]
+pub struct DirectPushSummary {
+    pub created_chemicals: usize,
+    pub created_references: usize,
+    pub added_occurrences: usize,
+    pub added_statements: usize,
+    pub failures: Vec<String>,
+}
+
+/// Creates planned references and chemicals through the Wikibase API, adds the properties
+/// existing chemical items lack, then adds the P703 occurrences citing them. Created QIDs are written back into `records`.
+pub async fn push_to_wikidata(
+    records: &mut [(EnrichedData, WikidataInfo)],
+    chemical_creation_plan: &[bool],
+    reference_creation_plan: &[bool],
+    session: &WikibaseSession,
+) -> Result<DirectPushSummary> {
+    let mut summary = DirectPushSummary::default();
+    let mut created_references: HashMap<String, String> = HashMap::new();
+    let mut created_chemicals: HashMap<String, String> = HashMap::new();
+
+    for (idx, (_, info)) in records.iter().enumerate() {
+        if !*reference_creation_plan.get(idx).unwrap_or(&false) {
+            continue;
+        }
+        let Some(metadata) = &info.reference_metadata else {
+            continue;
+        };
+        let key = metadata.doi.trim().to_lowercase();
+        if created_references.contains_key(&key) {
+            continue;
+        }
+        match session
+            .create_item(&build_reference_entity(metadata), DIRECT_EDIT_SUMMARY)
+            .await
+        {
+            Ok(qid) => {
+                info!("Created reference {} for DOI {}", qid, metadata.doi);
+                summary.created_references += 1;
+                created_references.insert(key, qid);
+            }
+            Err(err) => summary.failures.push(format!(
+                "Reference creation failed for DOI {}: {}",
+                metadata.doi, err
+            )),
+        }
+    }
+
+    for (idx, (data, info)) in records.iter().enumerate() {
+        if info.chemical_qid.is_some() || !*chemical_creation_plan.get(idx).unwrap_or(&false) {
+            continue;
+        }
+        let Some(inchikey) = data.inchikey.clone() else {
+            continue;
+        };
+        match session
+            .create_item(&build_chemical_entity(data), DIRECT_EDIT_SUMMARY)
+            .await
+        {
+            Ok(qid) => {
+                info!("Created chemical {} for InChIKey {}", qid, inchikey);
+                summary.created_chemicals += 1;
+                created_chemicals.insert(inchikey, qid);
+            }
+            Err(err) => summary.failures.push(format!(
+                "Chemical creation failed for {} ({}): {}",
+                data.chemical_entity_name, inchikey, err
+            )),
+        }
+    }
+
+    let mut completed_chemicals: HashSet<String> = HashSet::new();
+    for (data, info) in records.iter() {
+        let (Some(chem_qid), Some(statements)) = (&info.chemical_qid, &info.chemical_statements)
+        else {
+            continue;
+        };
+        if !completed_chemicals.insert(chem_qid.clone()) {
+            continue;
+        }
+        let missing = statements.compare(data).missing;
+        for (property, claim) in chemical_claims(data) {
+            if !missing.contains(&property) {
+                continue;
+            }
+            match session
+                .add_claim(chem_qid, &claim, DIRECT_EDIT_SUMMARY)
+                .await
+            {
+                Ok(()) => {
+                    info!("Added missing {} to {}", property, chem_qid);
+                    summary.added_statements += 1;
+                }
+                Err(err) => summary.failures.push(format!(
+                    "Adding {} to {} failed: {}",
+                    property, chem_qid, err
+                )),
+            }
+        }
+    }
+
+    let mut emitted_occurrences: HashSet<(String, String, String)> = HashSet::new();
+    for (data, info) in records.iter_mut() {
+        if info.reference_qid.is_none() {
+            let key = data.reference_doi.trim().to_lowercase();
+            info.reference_qid = created_references.get(&key).cloned();
+        }
+        if info.chemical_qid.is_none() {
+            info.chemical_qid = data
+                .inchikey
+                .as_ref()
+                .and_then(|inchikey| created_chemicals.get(inchikey).cloned());
+        }
+        if info.occurrence_exists {
+            continue;
+        }
+        let (Some(chem_qid), Some(tax_qid), Some(ref_qid)) =
+            (&info.chemical_qid, &info.taxon_qid, &info.reference_qid)
+        else {
+            continue;
+        };
+        let triple = (chem_qid.clone(), tax_qid.clone(), ref_qid.clone());
+        if !emitted_occurrences.insert(triple) {
+            info.occurrence_exists = true;
+            continue;
+        }
+        match session
+            .add_claim(
+                chem_qid,
+                &build_occurrence_claim(tax_qid, ref_qid),
+                DIRECT_EDIT_SUMMARY,
+            )
+            .await
+        {
+            Ok(()) => {
+                info!(
+                    "Added occurrence {} P703 {} (S248 {})",
+                    chem_qid, tax_qid, ref_qid
+                );
+                summary.added_occurrences += 1;
+                info.occurrence_exists = true;
+            }
+            Err(err) => summary.failures.push(format!(
+                "Occurrence {} P703 {} (S248 {}) failed: {}",
+                chem_qid, tax_qid, ref_qid, err
+            )),
+        }
+    }
+
+    Ok(summary)
+}
+
+/// Builds the entity JSON for a new chemical item (same statements as the QS CREATE block).
+fn build_chemical_entity(data: &EnrichedData) -> Value {
+    let (class_qid, description) = chemical_class(data);
+    let mut claims = vec![api::statement(
+        api::item_snak("P31", class_qid),
+        Vec::new(),
+        Vec::new(),
+    )];
+    claims.extend(chemical_claims(data).into_iter().map(|(_, claim)| claim));
+
+    json!({
+        "labels": { "en": { "language": "en", "value": data.chemical_entity_name } },
+        "descriptions": { "en": { "language": "en", "value": description } },
+        "claims": claims
+    })
+}
+
+/// Statements for the chemical properties of `data`, mirroring `chemical_statement_commands`.
+fn chemical_claims(data: &EnrichedData) -> Vec<(&'static str, Value)> {
+    let heuristic_reference = || {
+        vec![api::item_snak(
+            HEURISTIC_REFERENCE_PROPERTY_ID,
+            HEURISTIC_QID,
+        )]
+    };
+    let mut claims = Vec::new();
+    let mut push_string = |property, value: Option<&String>| {
+        if let Some(value) = value {
+            claims.push((
+                property,
+                api::statement(api::string_snak(property, value), Vec::new(), Vec::new()),
+            ));
+        }
+    };
+    push_string("P2017", data.isomeric_smiles.as_ref());
+    push_string("P233", data.canonical_smiles.as_ref());
+    push_string("P234", data.inchi.as_ref());
+    push_string("P235", data.inchikey.as_ref());
+    if let Some(formula) = &data.molecular_formula {
+        claims.push((
+            "P274",
+            api::statement(
+                api::string_snak("P274", &format_molecular_formula(formula)),
+                Vec::new(),
+                heuristic_reference(),
+            ),
+        ));
+    }
+    if let Some(mass) = data.exact_mass {
+        claims.push((
+            "P2067",
+            api::statement(
+                api::quantity_snak("P2067", &format_mass_quantity(mass), DALTON_QID),
+                Vec::new(),
+                heuristic_reference(),
+            ),
+        ));
+    }
+    for identifier in &data.identifiers {
+        let (Some(property), Some(source_qid)) = (
+            identifier.source.property(),
+            identifier.source.stated_in_qid(),
+        ) else {
+            continue;
+        };
+        let retrieved = ReferenceDate::from(identifier.retrieved_on);
+        claims.push((
+            property,
+            api::statement(
+                api::string_snak(property, &identifier.id),
+                Vec::new(),
+                vec![
+                    api::item_snak("P248", source_qid),
+                    api::time_snak("P813", &retrieved.to_wikibase_time(), retrieved.precision()),
+                ],
+            ),
+        ));
+    }
+    claims
+}
+
+/// Builds the entity JSON for a new reference item from Crossref metadata.
+fn build_reference_entity(metadata: &ReferenceMetadata) -> Value {
+    let retrieved = ReferenceDate::from(metadata.retrieved_on);
+    let crossref_reference = || {
+        vec![
+            api::item_snak("P248", CROSSREF_QID),
+            api::time_snak("P813", &retrieved.to_wikibase_time(), retrieved.precision()),
+        ]
+    };
+    let title = metadata.title.replace('\n', " ").trim().to_string();
+    let mut claims = vec![
+        api::statement(
+            api::item_snak("P31", &metadata.entity_type_qid),
+            Vec::new(),
+            Vec::new(),
+        ),
+        api::statement(
+            api::string_snak("P356", &metadata.doi),
+            Vec::new(),
+            crossref_reference(),
+        ),
+        api::statement(
+            api::monolingual_snak(
+                "P1476",
+                metadata.title_language.as_deref().unwrap_or("mul"),
+                &title,
+            ),
+            Vec::new(),
+            crossref_reference(),
+        ),
+    ];
+    if let Some(language_qid) = &metadata.language_qid {
+        claims.push(api::statement(
+            api::item_snak("P407", language_qid),
+            Vec::new(),
+            crossref_reference(),
+        ));
+    }
+    if let Some(date) = &metadata.publication_date {
+        claims.push(api::statement(
+            api::time_snak("P577", &date.to_wikibase_time(), date.precision()),
+            Vec::new(),
+            crossref_reference(),
+        ));
+    }
+    if let Some(journal_qid) = &metadata.journal_qid {
+        claims.push(api::statement(
+            api::item_snak("P1433", journal_qid),
+            Vec::new(),
+            crossref_reference(),
+        ));
+    }
+    if let Some(volume) = &metadata.volume {
+        claims.push(api::statement(
+            api::string_snak("P478", volume),
+            Vec::new(),
+            crossref_reference(),
+        ));
+    }
+    if let Some(issue) = &metadata.issue {
+        claims.push(api::statement(
+            api::string_snak("P433", issue),
+            Vec::new(),
+            crossref_reference(),
+        ));
+    }
+    for author in &metadata.authors {
+        claims.push(api::statement(
+            api::string_snak("P2093", &author.full_name),
+            vec![api::string_snak("P1545", &author.ordinal.to_string())],
+            crossref_reference(),
+        ));
+    }
+
+    json!({
+        "labels": { "mul": { "language": "mul", "value": title } },
+        "descriptions": { "en": { "language": "en", "value": "scholarly reference" } },
+        "claims": claims
+    })
+}
+
+/// Builds a `found in taxon` (P703) statement cited with `stated in` (P248).
+fn build_occurrence_claim(taxon_qid: &str, reference_qid: &str) -> Value {
+    api::statement(
+        api::item_snak("P703", taxon_qid),
+        Vec::new(),
+        vec![api::item_snak("P248", reference_qid)],
+    )
+}
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::chemical_entity::identifiers::{ExternalIdentifier, IdentifierSource};
     use crate::enrichment::EnrichedData;
     use crate::reference::{ReferenceAuthor, ReferenceDate, ReferenceMetadata};
-    use crate::wikidata::checker::WikidataInfo;
+    use crate::wikidata::checker::{ChemicalStatements, WikidataInfo};
+    use std::collections::BTreeMap;
     use std::io::Cursor;
 
     fn create_test_data(
@@ -174,16 +708,14 @@ mod tests {
                 chemical_entity_name: "TestChem".to_string(),
                 input_smiles: "C".to_string(),
                 sanitized_smiles: "C".to_string(),
-                smiles_were_sanitized: false,
                 taxon_name: "TestTaxon".to_string(),
                 reference_doi: "10.1/test".to_string(),
                 canonical_smiles: Some("C".to_string()),
-                isomeric_smiles: None,
                 inchi: Some("InChI=1S/CH4/h1H4".to_string()),
                 inchikey: Some("VNWKTOKETHGBQD-UHFFFAOYSA-N".to_string()),
                 molecular_formula: Some("CH4".to_string()),
                 exact_mass: Some(16.0),
-                other_descriptors: None,
+                ..Default::default()
             },
             WikidataInfo {
                 chemical_qid: chem_qid.map(String::from),
@@ -191,6 +723,7 @@ mod tests {
                 reference_qid: ref_qid.map(String::from),
                 occurrence_exists,
                 reference_metadata: None,
+                ..Default::default()
             },
         )
     }
@@ -201,7 +734,7 @@ mod tests {
         let plan = vec![true];
         let ref_plan = vec![false];
         let mut buffer = Cursor::new(Vec::new());
-        generate_quickstatements(&records, &plan, &ref_plan, false, &mut buffer).unwrap();
+        generate_quickstatements(&records, &plan, &ref_plan, &[], false, &mut buffer).unwrap();
 
         let output = String::from_utf8(buffer.into_inner()).unwrap();
         let lines: Vec<&str> = output.trim().split('\n').collect();
@@ -240,7 +773,7 @@ mod tests {
         let plan = vec![false];
         let ref_plan = vec![false];
         let mut buffer = Cursor::new(Vec::new());
-        generate_quickstatements(&records, &plan, &ref_plan, true, &mut buffer).unwrap();
+        generate_quickstatements(&records, &plan, &ref_plan, &[], true, &mut buffer).unwrap();
 
         let output = String::from_utf8(buffer.into_inner()).unwrap();
         let lines: Vec<&str> = output.trim().split('\n').collect();
@@ -255,7 +788,7 @@ mod tests {
         let plan = vec![false];
         let ref_plan = vec![false];
         let mut buffer = Cursor::new(Vec::new());
-        generate_quickstatements(&records, &plan, &ref_plan, true, &mut buffer).unwrap();
+        generate_quickstatements(&records, &plan, &ref_plan, &[], true, &mut buffer).unwrap();
 
         let output = String::from_utf8(buffer.into_inner()).unwrap();
         assert!(output.trim().is_empty());
@@ -268,13 +801,39 @@ mod tests {
         let plan = vec![false];
         let ref_plan = vec![false];
         let mut buffer = Cursor::new(Vec::new());
-        generate_quickstatements(&records, &plan, &ref_plan, true, &mut buffer).unwrap();
+        generate_quickstatements(&records, &plan, &ref_plan, &[], true, &mut buffer).unwrap();
 
         let output = String::from_utf8(buffer.into_inner()).unwrap();
         // No occurrence command should be generated
         assert!(output.trim().is_empty());
         // Check stderr/log for the skip message (cannot check directly here)
     }
+
+    #[test]
+    fn test_generate_qs_create_taxon_under_genus() {
+        let mut record = create_test_data(Some("Q1"), None, Some("Q3"), false);
+        record.0.taxon_name = "Coffea nova".to_string();
+        record.1.parent_taxon_qid = Some("Q157811".to_string());
+        let records = vec![record];
+        let mut buffer = Cursor::new(Vec::new());
+        generate_quickstatements(&records, &[false], &[false], &[true], false, &mut buffer)
+            .unwrap();
+
+        let output = String::from_utf8(buffer.into_inner()).unwrap();
+        let lines: Vec<&str> = output.trim().split('\n').collect();
+        assert_eq!(
+            lines,
+            vec![
+                "CREATE",
+                r#"LAST	Len	"Coffea nova""#,
+                "LAST	P31	Q16521",
+                r#"LAST	P225	"Coffea nova""#,
+                "LAST	P105	Q7432",
+                "LAST	P171	Q157811",
+            ]
+        );
+    }
+
     #[test]
     fn test_generate_qs_multiple_records() {
         let records = vec![
@@ -285,7 +844,7 @@ mod tests {
         let plan = vec![true, false, false];
         let ref_plan = vec![false, false, false];
         let mut buffer = Cursor::new(Vec::new());
-        generate_quickstatements(&records, &plan, &ref_plan, true, &mut buffer).unwrap();
+        generate_quickstatements(&records, &plan, &ref_plan, &[], true, &mut buffer).unwrap();
 
         let output = String::from_utf8(buffer.into_inner()).unwrap();
         let lines: Vec<&str> = output.trim().split('\n').collect();
@@ -330,7 +889,7 @@ mod tests {
         let mut buffer = Cursor::new(Vec::new());
         let plan = vec![false];
         let ref_plan = vec![true];
-        generate_quickstatements(&records, &plan, &ref_plan, false, &mut buffer).unwrap();
+        generate_quickstatements(&records, &plan, &ref_plan, &[], false, &mut buffer).unwrap();
 
         let output = String::from_utf8(buffer.into_inner()).unwrap();
         assert!(output.contains("P356"));
@@ -338,131 +897,198 @@ mod tests {
         assert!(output.contains("Q13442814"));
         assert!(output.contains("P1433"));
     }
-}
-
-/// Creates QS commands to build a reference item from Crossref metadata.
-fn build_reference_commands(metadata: &ReferenceMetadata) -> Vec<String> {
-    let mut commands = Vec::new();
-    let retrieved_date = format_retrieved_date(metadata.retrieved_on);
-    let escaped_title = escape_literal(&metadata.title);
-    commands.push("CREATE".to_string());
-    commands.push(format!("LAST\tLmul\t\"{}\"", escaped_title));
-    commands.push("LAST\tDen\t\"scholarly reference\"".to_string());
-    commands.push(format!("LAST\tP31\t{}", metadata.entity_type_qid));
-
-    commands.push(format!(
-        "LAST\tP356\t\"{}\"\tS248\t{}\tS813\t{}",
-        escape_literal(&metadata.doi),
-        CROSSREF_QID,
-        retrieved_date
-    ));
 
-    let monolingual_lang = metadata.title_language.as_deref().unwrap_or("mul");
-    commands.push(format!(
-        "LAST\tP1476\t{lang}:\"{title}\"\tS248\t{source}\tS813\t{retrieved}",
-        lang = monolingual_lang,
-        title = escaped_title,
-        source = CROSSREF_QID,
-        retrieved = retrieved_date
-    ));
-
-    if let Some(language_qid) = &metadata.language_qid {
-        commands.push(format!(
-            "LAST\tP407\t{}\tS248\t{}\tS813\t{}",
-            language_qid, CROSSREF_QID, retrieved_date
-        ));
+    #[test]
+    fn test_chemical_entity_json_matches_qs_statements() {
+        let (data, _) = create_test_data(None, Some("Q2"), Some("Q3"), false);
+        let entity = build_chemical_entity(&data);
+        assert_eq!(entity["labels"]["en"]["value"], "TestChem");
+        let claims = entity["claims"].as_array().unwrap();
+        let properties: Vec<&str> = claims
+            .iter()
+            .map(|claim| claim["mainsnak"]["property"].as_str().unwrap())
+            .collect();
+        assert_eq!(properties, ["P31", "P233", "P234", "P235", "P274", "P2067"]);
+        let formula = &claims[4];
+        assert_eq!(formula["mainsnak"]["datavalue"]["value"], "CH₄");
+        assert_eq!(
+            formula["references"][0]["snaks"]["P887"][0]["datavalue"]["value"]["id"],
+            HEURISTIC_QID
+        );
     }
 
-    if let Some(date) = &metadata.publication_date {
-        commands.push(format!(
-            "LAST\tP577\t{}\tS248\t{}\tS813\t{}",
-            date.to_quickstatements_time(),
-            CROSSREF_QID,
-            retrieved_date
-        ));
-    }
+    #[test]
+    fn test_chemical_class_follows_stereo() {
+        let (mut data, info) = create_test_data(None, Some("Q2"), Some("Q3"), false);
+        data.stereo = Some(StereoClass::Undefined);
+        let mut buffer = Cursor::new(Vec::new());
+        generate_quickstatements(
+            &[(data.clone(), info)],
+            &[true],
+            &[false],
+            &[],
+            false,
+            &mut buffer,
+        )
+        .unwrap();
+        let output = String::from_utf8(buffer.into_inner()).unwrap();
+        assert!(output.contains("LAST\tDen\t\"group of stereoisomers\""));
+        assert!(output.contains("LAST\tP31\tQ59199015"));
 
-    if let Some(journal_qid) = &metadata.journal_qid {
-        commands.push(format!(
-            "LAST\tP1433\t{}\tS248\t{}\tS813\t{}",
-            journal_qid, CROSSREF_QID, retrieved_date
-        ));
+        data.stereo = Some(StereoClass::Defined);
+        let entity = build_chemical_entity(&data);
+        assert_eq!(entity["descriptions"]["en"]["value"], "chemical compound");
+        assert_eq!(
+            entity["claims"][0]["mainsnak"]["datavalue"]["value"]["id"],
+            "Q11173"
+        );
     }
 
-    if let Some(volume) = &metadata.volume {
-        commands.push(format!(
-            "LAST\tP478\t\"{}\"\tS248\t{}\tS813\t{}",
-            escape_literal(volume),
-            CROSSREF_QID,
-            retrieved_date
-        ));
-    }
+    #[test]
+    fn test_identifiers_are_cited_with_their_database() {
+        let (mut data, info) = create_test_data(None, Some("Q2"), Some("Q3"), false);
+        let retrieved_on = chrono::NaiveDate::from_ymd_opt(2026, 3, 2).unwrap();
+        data.identifiers = vec![
+            ExternalIdentifier {
+                source: IdentifierSource::Pubchem,
+                id: "297".to_string(),
+                retrieved_on,
+            },
+            ExternalIdentifier {
+                source: IdentifierSource::Coconut,
+                id: "CNP0000001".to_string(),
+                retrieved_on,
+            },
+        ];
+        let mut buffer = Cursor::new(Vec::new());
+        generate_quickstatements(
+            &[(data.clone(), info)],
+            &[true],
+            &[false],
+            &[],
+            false,
+            &mut buffer,
+        )
+        .unwrap();
+        let output = String::from_utf8(buffer.into_inner()).unwrap();
+        assert!(
+            output.contains("LAST\tP662\t\"297\"\tS248\tQ278487\tS813\t+2026-03-02T00:00:00Z/11")
+        );
+        assert!(!output.contains("CNP0000001"));
 
-    if let Some(issue) = &metadata.issue {
-        commands.push(format!(
-            "LAST\tP433\t\"{}\"\tS248\t{}\tS813\t{}",
-            escape_literal(issue),
-            CROSSREF_QID,
-            retrieved_date
-        ));
+        let entity = build_chemical_entity(&data);
+        let pubchem = entity["claims"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|claim| claim["mainsnak"]["property"] == "P662")
+            .unwrap();
+        assert_eq!(pubchem["mainsnak"]["datavalue"]["value"], "297");
+        let reference = &pubchem["references"][0]["snaks"];
+        assert_eq!(reference["P248"][0]["datavalue"]["value"]["id"], "Q278487");
+        assert_eq!(
+            reference["P813"][0]["datavalue"]["value"]["time"],
+            "+2026-03-02T00:00:00Z"
+        );
     }
 
-    for author in &metadata.authors {
-        commands.push(format!(
-            "LAST\tP2093\t\"{}\"\tP1545\t\"{}\"\tS248\t{}\tS813\t{}",
-            escape_literal(&author.full_name),
-            author.ordinal,
-            CROSSREF_QID,
-            retrieved_date
-        ));
+    #[test]
+    fn test_existing_chemical_only_receives_missing_statements() {
+        let (data, mut info) = create_test_data(Some("Q1"), Some("Q2"), Some("Q3"), true);
+        info.chemical_statements = Some(ChemicalStatements {
+            values: BTreeMap::from([
+                (
+                    "P235".to_string(),
+                    vec!["VNWKTOKETHGBQD-UHFFFAOYSA-N".to_string()],
+                ),
+                ("P274".to_string(), vec!["CH₃".to_string()]),
+            ]),
+        });
+        let records = vec![(data.clone(), info.clone()), (data, info)];
+        let mut buffer = Cursor::new(Vec::new());
+        generate_quickstatements(&records, &[false, false], &[], &[], true, &mut buffer).unwrap();
+        let output = String::from_utf8(buffer.into_inner()).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "Q1\tP233\t\"C\"",
+                "Q1\tP234\t\"InChI=1S/CH4/h1H4\"",
+                "Q1\tP2067\t16.0U483261\tS887\tQ113907573",
+            ]
+        );
+
+        let claims: Vec<&str> = chemical_claims(&records[0].0)
+            .into_iter()
+            .map(|(property, _)| property)
+            .collect();
+        assert_eq!(claims, ["P233", "P234", "P235", "P274", "P2067"]);
     }
 
-    commands
-}
+    #[tokio::test]
+    async fn test_push_creates_chemical_and_occurrence_in_same_run() {
+        use serde_json::json;
+        use wiremock::matchers::{body_string_contains, method, query_param};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
 
-fn escape_literal(value: &str) -> String {
-    value
-        .replace('\\', "\\\\")
-        .replace('\"', "\\\"")
-        .replace('\n', " ")
-        .trim()
-        .to_string()
-}
+        let server = MockServer::start().await;
+        for token_type in ["login", "csrf"] {
+            Mock::given(method("GET"))
+                .and(query_param("type", token_type))
+                .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                    "query": { "tokens": { format!("{}token", token_type): "token+\\" } }
+                })))
+                .mount(&server)
+                .await;
+        }
+        Mock::given(method("POST"))
+            .and(body_string_contains("action=login"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(json!({ "login": { "result": "Success" } })),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(body_string_contains("new=item"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(json!({ "entity": { "id": "Q100" } })),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(body_string_contains("id=Q100"))
+            .and(body_string_contains("P703"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(json!({ "entity": { "id": "Q100" } })),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
 
-fn format_molecular_formula(formula: &str) -> String {
-    formula
-        .chars()
-        .map(|ch| match ch {
-            '0' => '₀',
-            '1' => '₁',
-            '2' => '₂',
-            '3' => '₃',
-            '4' => '₄',
-            '5' => '₅',
-            '6' => '₆',
-            '7' => '₇',
-            '8' => '₈',
-            '9' => '₉',
-            _ => ch,
-        })
-        .collect()
-}
+        let api_url = format!("{}/w/api.php", server.uri());
+        let session = WikibaseSession::login(&api_url, "User@bot", "secret")
+            .await
+            .unwrap();
+        let mut records = vec![
+            create_test_data(None, Some("Q2"), Some("Q3"), false),
+            create_test_data(None, Some("Q2"), Some("Q3"), false),
+        ];
+        let summary = push_to_wikidata(&mut records, &[true, false], &[false, false], &session)
+            .await
+            .unwrap();
 
-fn format_mass_quantity(value: f64) -> String {
-    let mut s = format!("{value:.9}");
-    if let Some(dot_pos) = s.find('.') {
-        while s.ends_with('0') {
-            s.pop();
-        }
-        if s.ends_with('.') {
-            s.push('0');
-        } else if s.len() == dot_pos {
-            s.push_str(".0");
-        }
-    } else {
-        s.push_str(".0");
+        assert_eq!(summary.created_chemicals, 1);
+        assert_eq!(summary.added_occurrences, 1);
+        assert!(summary.failures.is_empty());
+        assert!(
+            records
+                .iter()
+                .all(|(_, info)| info.chemical_qid.as_deref() == Some("Q100"))
+        );
+        assert!(records.iter().all(|(_, info)| info.occurrence_exists));
     }
-    s
 }
 
 // helper removed: QuickStatements cannot reuse placeholders like LAST-1, so